@@ -1,16 +1,42 @@
-use crate::config::WebConfig;
-use crate::llm::Message;
+use crate::checkpoint::{Checkpoint, Checkpoints};
+use crate::config::{AgentConfig, Config, LlmConfig, ToolProtocol, WebConfig};
+use crate::context::{self, ContextBudget};
+use crate::edit::{self, Edit};
+use crate::index::{self, Index};
+use crate::llm::{
+    self, estimate_prompt_tokens, estimate_token_count, LlmError, Message, StreamEvent, TokenUsage,
+    ToolCallAccumulator, ToolCallRequest, ToolDefinition,
+};
+use crate::memory::{self, Memories, Scope};
+use crate::patch;
+use crate::permissions::{
+    Action, Approval, ApprovalRequest, Decision, Permissions, RuleAction, PERMISSIONS_FILE,
+};
+use crate::process::{self, RunOptions};
+use crate::read;
+use crate::repomap;
+use crate::search::{self, SortBy};
+use crate::semantic::{self, Corpus};
+use crate::verify;
+use crate::workspace::{Workspace, STATE_DIR};
 use futures_util::StreamExt;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::env;
-use std::fs;
 use std::io;
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::Output;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::sync::{oneshot, Mutex};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Serialize, Deserialize, Debug)]
 struct ToolCall {
@@ -23,7 +49,21 @@ impl ToolCall {
         match self.name.as_str() {
             "READ_FILE" => {
                 let path = self.parameters.get("path")?.as_str()?.to_string();
-                Some(Tool::ReadFile { path })
+                let offset = self
+                    .parameters
+                    .get("offset")
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v as usize);
+                let limit = self
+                    .parameters
+                    .get("limit")
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v as usize);
+                Some(Tool::ReadFile {
+                    path,
+                    offset,
+                    limit,
+                })
             }
             "WRITE_FILE" => {
                 let path = self.parameters.get("path")?.as_str()?.to_string();
@@ -43,8 +83,19 @@ impl ToolCall {
                     path,
                     old_string,
                     new_string,
+                    replace_all: self.flag("replace_all"),
+                    ignore_whitespace: self.flag("ignore_whitespace"),
                 })
             }
+            "MULTI_EDIT" => {
+                let path = self.parameters.get("path")?.as_str()?.to_string();
+                let edits = serde_json::from_value(self.parameters.get("edits")?.clone()).ok()?;
+                Some(Tool::MultiEdit { path, edits })
+            }
+            "APPLY_PATCH" => {
+                let patch = self.parameters.get("patch")?.as_str()?.to_string();
+                Some(Tool::ApplyPatch { patch })
+            }
             "DELETE_FILE" => {
                 let path = self.parameters.get("path")?.as_str()?.to_string();
                 Some(Tool::DeleteFile { path })
@@ -63,16 +114,25 @@ impl ToolCall {
             }
             "GREP_SEARCH" => {
                 let pattern = self.parameters.get("pattern")?.as_str()?.to_string();
-                let path = self
-                    .parameters
-                    .get("path")
-                    .and_then(|p| p.as_str())
-                    .map(|s| s.to_string());
-                Some(Tool::GrepSearch { pattern, path })
+                Some(Tool::GrepSearch {
+                    pattern,
+                    path: self.text("path"),
+                    glob: self.text("glob"),
+                    file_type: self.text("file_type"),
+                    context: self.number("context").unwrap_or(0),
+                    case_sensitive: self.flag("case_sensitive"),
+                    max_results: self.number("max_results"),
+                    sort: self.sort(),
+                })
             }
             "GLOB_SEARCH" => {
                 let pattern = self.parameters.get("pattern")?.as_str()?.to_string();
-                Some(Tool::GlobSearch { pattern })
+                Some(Tool::GlobSearch {
+                    pattern,
+                    path: self.text("path"),
+                    max_results: self.number("max_results"),
+                    sort: self.sort(),
+                })
             }
             "READ_URL" => {
                 let url = self.parameters.get("url")?.as_str()?.to_string();
@@ -84,16 +144,35 @@ impl ToolCall {
             }
             "REMEMBER" => {
                 let fact = self.parameters.get("fact")?.as_str()?.to_string();
-                Some(Tool::Remember { fact })
+                Some(Tool::Remember {
+                    fact,
+                    tags: self.tags(),
+                    scope: self.scope(),
+                })
+            }
+            "RECALL" => Some(Tool::Recall {
+                query: self.text("query").filter(|query| !query.trim().is_empty()),
+            }),
+            "FORGET" => {
+                let id = self.parameters.get("id")?.as_str()?.to_string();
+                Some(Tool::Forget { id })
             }
-            "RECALL" => Some(Tool::Recall),
             "INDEX_CODEBASE" => {
                 let path = self.parameters.get("path")?.as_str()?.to_string();
                 Some(Tool::IndexCodebase { path })
             }
             "SEARCH_INDEX" => {
                 let query = self.parameters.get("query")?.as_str()?.to_string();
-                Some(Tool::SearchIndex { query })
+                let callers = self.flag("callers");
+                Some(Tool::SearchIndex { query, callers })
+            }
+            "SEMANTIC_SEARCH" => {
+                let query = self.parameters.get("query")?.as_str()?.to_string();
+                Some(Tool::SemanticSearch {
+                    query,
+                    path: self.text("path"),
+                    max_results: self.number("max_results"),
+                })
             }
             "FUZZY_FIND" => {
                 let pattern = self.parameters.get("pattern")?.as_str()?.to_string();
@@ -191,6 +270,567 @@ impl ToolCall {
             _ => None,
         }
     }
+
+    /// An optional boolean parameter, false when absent
+    fn flag(&self, name: &str) -> bool {
+        self.parameters
+            .get(name)
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    fn text(&self, name: &str) -> Option<String> {
+        self.parameters
+            .get(name)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+    }
+
+    fn number(&self, name: &str) -> Option<usize> {
+        self.parameters
+            .get(name)
+            .and_then(|v| v.as_u64())
+            .map(|n| n as usize)
+    }
+
+    /// `sort` is "path" or "modified"; anything else sorts by path.
+    fn sort(&self) -> SortBy {
+        self.parameters
+            .get("sort")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// `tags` as a list of strings, or as one comma-separated string.
+    fn tags(&self) -> Vec<String> {
+        match self.parameters.get("tags") {
+            Some(serde_json::Value::Array(tags)) => tags
+                .iter()
+                .filter_map(|tag| tag.as_str())
+                .map(|tag| tag.to_string())
+                .collect(),
+            Some(serde_json::Value::String(tags)) => {
+                tags.split(',').map(|tag| tag.trim().to_string()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// `scope` is "project" or "user" ("global" also works); anything else
+    /// is the project.
+    fn scope(&self) -> Scope {
+        match self.text("scope").as_deref() {
+            Some("user" | "global") => Scope::User,
+            _ => Scope::Project,
+        }
+    }
+}
+
+/// Function-calling schemas for every tool, offered to the model when the
+/// native tool protocol is active. Names and parameters mirror `ToolCall::into_tool`.
+pub fn tool_definitions() -> Vec<ToolDefinition> {
+    fn object(properties: serde_json::Value, required: &[&str]) -> serde_json::Value {
+        json!({ "type": "object", "properties": properties, "required": required })
+    }
+    fn string(description: &str) -> serde_json::Value {
+        json!({ "type": "string", "description": description })
+    }
+    fn integer(description: &str) -> serde_json::Value {
+        json!({ "type": "integer", "description": description })
+    }
+    fn boolean(description: &str) -> serde_json::Value {
+        json!({ "type": "boolean", "description": description })
+    }
+    let no_params = || object(json!({}), &[]);
+
+    vec![
+        // Planning and Task Management
+        ToolDefinition::function(
+            "CREATE_PLAN",
+            "Create a structured plan in plan.md breaking a complex task into steps",
+            object(
+                json!({
+                    "task": string("Short description of the overall task"),
+                    "steps": { "type": "array", "items": { "type": "string" }, "description": "Ordered list of steps" }
+                }),
+                &["task", "steps"],
+            ),
+        ),
+        ToolDefinition::function(
+            "UPDATE_PLAN",
+            "Mark a step of plan.md as completed",
+            object(
+                json!({ "completed_step": integer("1-based step number") }),
+                &["completed_step"],
+            ),
+        ),
+        ToolDefinition::function(
+            "CLEAR_PLAN",
+            "Remove plan.md once the task is fully completed",
+            no_params(),
+        ),
+        // File Operations
+        ToolDefinition::function(
+            "READ_FILE",
+            "Read a file with numbered lines, 2000 lines at a time by default. Binary files are summarised instead",
+            object(
+                json!({
+                    "path": string("Path of the file to read"),
+                    "offset": integer("Optional line to start from, counting from 1"),
+                    "limit": integer("Optional number of lines to read")
+                }),
+                &["path"],
+            ),
+        ),
+        ToolDefinition::function(
+            "WRITE_FILE",
+            "Create or overwrite a file, creating parent directories as needed",
+            object(
+                json!({ "path": string("Path of the file to write"), "content": string("Full file content") }),
+                &["path", "content"],
+            ),
+        ),
+        ToolDefinition::function(
+            "APPEND_FILE",
+            "Append content to the end of an existing file",
+            object(
+                json!({ "path": string("Path of the file"), "content": string("Content to append") }),
+                &["path", "content"],
+            ),
+        ),
+        ToolDefinition::function(
+            "SEARCH_REPLACE",
+            "Replace an exact string in a file. It must match exactly once unless replace_all is set",
+            object(
+                json!({
+                    "path": string("Path of the file to edit"),
+                    "old_string": string("Exact text to find"),
+                    "new_string": string("Replacement text"),
+                    "replace_all": boolean("Replace every match instead of requiring exactly one"),
+                    "ignore_whitespace": boolean("If there is no exact match, match text that differs only in whitespace")
+                }),
+                &["path", "old_string", "new_string"],
+            ),
+        ),
+        ToolDefinition::function(
+            "MULTI_EDIT",
+            "Apply several replacements to one file, in order. If any fails, none are applied",
+            object(
+                json!({
+                    "path": string("Path of the file to edit"),
+                    "edits": {
+                        "type": "array",
+                        "description": "Replacements, each seeing the result of the ones before it",
+                        "items": object(
+                            json!({
+                                "old_string": string("Exact text to find"),
+                                "new_string": string("Replacement text"),
+                                "replace_all": boolean("Replace every match instead of requiring exactly one"),
+                                "ignore_whitespace": boolean("If there is no exact match, match text that differs only in whitespace")
+                            }),
+                            &["old_string", "new_string"],
+                        )
+                    }
+                }),
+                &["path", "edits"],
+            ),
+        ),
+        ToolDefinition::function(
+            "APPLY_PATCH",
+            "Apply a unified diff (as from diff -u or git diff) that may create, change or delete several files. Either every file changes or none do",
+            object(
+                json!({ "patch": string("The diff, with ---/+++ file headers and @@ hunks") }),
+                &["patch"],
+            ),
+        ),
+        ToolDefinition::function(
+            "DELETE_FILE",
+            "Delete a file, or a directory recursively",
+            object(json!({ "path": string("Path to delete") }), &["path"]),
+        ),
+        // Directory Operations
+        ToolDefinition::function(
+            "LIST_FILES",
+            "List files and directories in a directory",
+            object(json!({ "path": string("Directory to list") }), &["path"]),
+        ),
+        ToolDefinition::function(
+            "LIST_FILES_RECURSIVE",
+            "Recursively list all files in a directory tree",
+            object(json!({ "path": string("Root directory") }), &["path"]),
+        ),
+        ToolDefinition::function(
+            "CREATE_DIRECTORY",
+            "Create a directory and any missing parents",
+            object(json!({ "path": string("Directory to create") }), &["path"]),
+        ),
+        // Search & Navigation
+        ToolDefinition::function(
+            "GREP_SEARCH",
+            "Search file contents for a regex pattern (case-insensitive by default), skipping files ignored by .gitignore",
+            object(
+                json!({
+                    "pattern": string("Regex to search for"),
+                    "path": string("Directory or file to search, defaults to '.'"),
+                    "glob": string("Only search files matching this glob, e.g. *.rs or src/**/*.ts"),
+                    "file_type": string("Only search files of this type, e.g. rust, py, js, ts, go"),
+                    "context": integer("Lines to show before and after each match"),
+                    "case_sensitive": boolean("Match case exactly"),
+                    "max_results": integer("Stop after this many matching lines, default 200"),
+                    "sort": string("'path' (default) or 'modified' for recently changed files first")
+                }),
+                &["pattern"],
+            ),
+        ),
+        ToolDefinition::function(
+            "GLOB_SEARCH",
+            "Find files matching a glob such as *.rs or src/**/test_*.py, skipping files ignored by .gitignore",
+            object(
+                json!({
+                    "pattern": string("Glob; '*' stays within a directory and '**' spans any number. A glob without '/' matches file names anywhere"),
+                    "path": string("Directory to search, defaults to '.'"),
+                    "max_results": integer("Most files to list, default 200"),
+                    "sort": string("'path' (default) or 'modified' for recently changed files first")
+                }),
+                &["pattern"],
+            ),
+        ),
+        ToolDefinition::function(
+            "FUZZY_FIND",
+            "Find files whose path contains a fragment, e.g. 'user' matches src/user_model.rs",
+            object(
+                json!({ "pattern": string("Path fragment"), "path": string("Directory to search, defaults to '.'") }),
+                &["pattern"],
+            ),
+        ),
+        ToolDefinition::function(
+            "INDEX_CODEBASE",
+            "Parse the source files in a directory (Rust, Python, JavaScript, TypeScript, Go, Java, C) and index their definitions and call sites, skipping files ignored by .gitignore. Running it again only re-reads files that changed",
+            object(
+                json!({ "path": string("Root directory to index") }),
+                &["path"],
+            ),
+        ),
+        ToolDefinition::function(
+            "SEARCH_INDEX",
+            "Search the symbol index built by INDEX_CODEBASE: where a symbol is defined (file:line hits, best matches first), or with callers, where it is called from",
+            object(
+                json!({
+                    "query": string("Symbol name or fragment, or Type::method"),
+                    "callers": boolean("List the call sites of the function, method or macro named by query instead of its definitions (default false)"),
+                }),
+                &["query"],
+            ),
+        ),
+        ToolDefinition::function(
+            "SEMANTIC_SEARCH",
+            "Find the code or docs that answer a natural-language question such as 'where do we parse the config'. Files are split into chunks by definition and ranked by word matches (BM25), re-ranked by meaning when an embedding model is configured. Needs no prior indexing",
+            object(
+                json!({
+                    "query": string("What to look for, in plain words"),
+                    "path": string("Directory to search (default: workspace root)"),
+                    "max_results": integer("Chunks to return (default 10)"),
+                }),
+                &["query"],
+            ),
+        ),
+        ToolDefinition::function(
+            "READ_URL",
+            "Fetch a web page and return its text",
+            object(json!({ "url": string("URL to fetch") }), &["url"]),
+        ),
+        ToolDefinition::function(
+            "SEARCH_WEB",
+            "Search the web",
+            object(json!({ "query": string("Search query") }), &["query"]),
+        ),
+        ToolDefinition::function(
+            "REMEMBER",
+            "Save an important fact or decision to memory. Memories that match a later prompt are shown with it automatically",
+            object(
+                json!({
+                    "fact": string("Fact to remember, complete enough to make sense on its own"),
+                    "tags": { "type": "array", "items": { "type": "string" }, "description": "Keywords to find it by, e.g. [\"build\", \"testing\"]" },
+                    "scope": string("\"project\" for facts about this workspace (default), \"user\" for the user's preferences that hold in every project"),
+                }),
+                &["fact"],
+            ),
+        ),
+        ToolDefinition::function(
+            "RECALL",
+            "Search saved memories, project and user, best matches first; without a query, list the most recently used",
+            object(
+                json!({ "query": string("Words to look for in the facts and their tags") }),
+                &[],
+            ),
+        ),
+        ToolDefinition::function(
+            "FORGET",
+            "Delete a memory that is wrong or out of date",
+            object(
+                json!({ "id": string("Id of the memory, as shown by RECALL (e.g. p3 or u1)") }),
+                &["id"],
+            ),
+        ),
+        // Code Execution & Compilation
+        ToolDefinition::function(
+            "EXECUTE_CODE",
+            "Execute a code snippet (python, javascript, bash, rust, go, java, c, cpp)",
+            object(
+                json!({ "language": string("Language of the snippet"), "code": string("Source code to run") }),
+                &["language", "code"],
+            ),
+        ),
+        ToolDefinition::function(
+            "RUN_COMMAND",
+            "Run a shell command",
+            object(
+                json!({ "command": string("Command line to execute") }),
+                &["command"],
+            ),
+        ),
+        // Development Workflow
+        ToolDefinition::function("GIT_STATUS", "Show git repository status", no_params()),
+        ToolDefinition::function("GIT_DIFF", "Show unstaged changes", no_params()),
+        ToolDefinition::function(
+            "GIT_COMMIT",
+            "Stage all changes and commit them",
+            object(json!({ "message": string("Commit message") }), &["message"]),
+        ),
+        ToolDefinition::function(
+            "GIT_LOG",
+            "Show recent commit history",
+            object(
+                json!({ "count": integer("Number of commits to show") }),
+                &[],
+            ),
+        ),
+        // Quality Assurance
+        ToolDefinition::function(
+            "RUN_LINT",
+            "Run the linter for a language (rust, python, javascript)",
+            object(
+                json!({ "language": string("Language to lint"), "path": string("Project directory, defaults to '.'") }),
+                &["language"],
+            ),
+        ),
+        ToolDefinition::function(
+            "RUN_TESTS",
+            "Run a test suite (cargo, pytest, jest)",
+            object(
+                json!({ "framework": string("Test framework"), "path": string("Project directory, defaults to '.'") }),
+                &["framework"],
+            ),
+        ),
+        // Package Management
+        ToolDefinition::function(
+            "INSTALL_PACKAGE",
+            "Install a package with npm, yarn, cargo, pip or go",
+            object(
+                json!({ "manager": string("Package manager"), "package": string("Package name") }),
+                &["manager", "package"],
+            ),
+        ),
+        // System Information & Time
+        ToolDefinition::function(
+            "GET_TIME",
+            "Get the current date, time and timezone",
+            no_params(),
+        ),
+        ToolDefinition::function(
+            "GET_OS_INFO",
+            "Get operating system, architecture, shell and path separator details",
+            no_params(),
+        ),
+        // Enhanced File Operations
+        ToolDefinition::function(
+            "COPY_FILE",
+            "Copy a file",
+            object(
+                json!({ "source": string("File to copy"), "destination": string("Destination path") }),
+                &["source", "destination"],
+            ),
+        ),
+        ToolDefinition::function(
+            "MOVE_FILE",
+            "Move a file or directory",
+            object(
+                json!({ "source": string("Path to move"), "destination": string("Destination path") }),
+                &["source", "destination"],
+            ),
+        ),
+        ToolDefinition::function(
+            "RENAME_FILE",
+            "Rename a file or directory",
+            object(
+                json!({ "old_name": string("Current path"), "new_name": string("New path") }),
+                &["old_name", "new_name"],
+            ),
+        ),
+    ]
+}
+
+/// Everything a tool needs besides its own arguments.
+#[derive(Debug, Clone)]
+pub struct ToolContext {
+    pub web: WebConfig,
+    /// Where relative paths point and commands run
+    pub workspace: Workspace,
+    /// Shell that runs RUN_COMMAND commands
+    pub shell: String,
+    /// Check files after writing them
+    pub post_write_verify: bool,
+    /// How long a tool's commands may run
+    pub timeout: Option<Duration>,
+    /// Overrides of `timeout` by tool name
+    pub tool_timeouts: HashMap<String, Option<Duration>>,
+    /// Characters of command output kept from the start and the end
+    pub output_head_chars: usize,
+    pub output_tail_chars: usize,
+    /// Receives command output line by line while it runs
+    pub output_lines: Option<UnboundedSender<String>>,
+    /// Where files are snapshotted before tools change them
+    pub checkpoints: Option<Checkpoints>,
+    /// Groups the checkpoints of one user turn
+    pub turn: u64,
+    /// Fired when the user cancels the run (Esc)
+    pub cancel: CancellationToken,
+    /// The endpoint SEMANTIC_SEARCH gets embeddings from, if an
+    /// `embedding_model` is configured
+    pub embeddings: Option<LlmConfig>,
+    /// The user-level memory store, unless `user_memory` is off
+    pub user_memory: Option<PathBuf>,
+}
+
+impl Default for ToolContext {
+    fn default() -> Self {
+        let agent = AgentConfig::default();
+        Self {
+            web: WebConfig::default(),
+            workspace: Workspace::default(),
+            shell: agent.shell,
+            post_write_verify: false,
+            timeout: None,
+            tool_timeouts: HashMap::new(),
+            output_head_chars: agent.output_head_chars,
+            output_tail_chars: agent.output_tail_chars,
+            output_lines: None,
+            checkpoints: None,
+            turn: 0,
+            cancel: CancellationToken::new(),
+            embeddings: None,
+            user_memory: None,
+        }
+    }
+}
+
+impl ToolContext {
+    /// A context for one user turn.
+    pub fn from_config(config: &Config, cancel: CancellationToken) -> io::Result<Self> {
+        let agent = &config.agent;
+        let seconds = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        let workspace = Workspace::from_config(agent)?;
+        Ok(Self {
+            web: config.web.clone(),
+            checkpoints: Some(Checkpoints::new(workspace.root())),
+            workspace,
+            shell: agent.shell.clone(),
+            post_write_verify: agent.post_write_verify,
+            timeout: seconds(agent.command_timeout_secs),
+            tool_timeouts: agent
+                .tool_timeouts
+                .iter()
+                .map(|(tool, secs)| (tool.to_uppercase(), seconds(*secs)))
+                .collect(),
+            output_head_chars: agent.output_head_chars,
+            output_tail_chars: agent.output_tail_chars,
+            output_lines: None,
+            turn: chrono::Utc::now().timestamp_millis() as u64,
+            cancel,
+            embeddings: config
+                .llm
+                .embedding_model
+                .is_some()
+                .then(|| config.llm.clone()),
+            user_memory: user_memory_file(agent),
+        })
+    }
+
+    /// The project and user memory stores.
+    fn memories(&self) -> Memories {
+        Memories::new(self.workspace.root(), self.user_memory.clone())
+    }
+
+    /// Runs a command for `tool` with its timeout, streaming its output.
+    async fn run(&self, tool: &str, command: &mut Command) -> io::Result<Output> {
+        let options = RunOptions {
+            timeout: self
+                .tool_timeouts
+                .get(tool)
+                .copied()
+                .unwrap_or(self.timeout),
+            lines: self.output_lines.clone(),
+        };
+        process::run(command, &self.cancel, &options).await
+    }
+
+    /// Runs the first of `commands` whose program is installed, moving on
+    /// only when one is missing. `None` if none of them is.
+    async fn run_first_installed(
+        &self,
+        tool: &str,
+        commands: impl IntoIterator<Item = Command>,
+    ) -> io::Result<Option<Output>> {
+        for mut command in commands {
+            match self.run(tool, &mut command).await {
+                Ok(output) => return Ok(Some(output)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Cuts long command output down to its beginning and end.
+    fn clip(&self, output: &[u8]) -> String {
+        process::clip(
+            &String::from_utf8_lossy(output),
+            self.output_head_chars,
+            self.output_tail_chars,
+        )
+    }
+
+    /// `command` run through the configured shell, in the workspace root.
+    fn shell_command(&self, command: &str) -> Command {
+        let program = Path::new(&self.shell)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let flag = match program.as_str() {
+            "cmd" => "/C",
+            "powershell" | "pwsh" => "-Command",
+            _ => "-c",
+        };
+        let mut cmd = Command::new(&self.shell);
+        cmd.arg(flag)
+            .arg(command)
+            .current_dir(self.workspace.root());
+        cmd
+    }
+
+    /// Result of `post_write_verify` for files just written, to append to
+    /// the tool result.
+    async fn verification_note(&self, files: &[(&Path, Option<&str>)]) -> String {
+        if !self.post_write_verify {
+            return String::new();
+        }
+        verify::verify_written_files(files, self.workspace.root(), self.timeout, &self.cancel)
+            .await
+            .iter()
+            .map(|note| format!("\n\n{}", note))
+            .collect()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -198,6 +838,11 @@ pub enum Tool {
     // File Operations
     ReadFile {
         path: String,
+        /// First line to show, counting from 1
+        #[serde(default)]
+        offset: Option<usize>,
+        #[serde(default)]
+        limit: Option<usize>,
     },
     WriteFile {
         path: String,
@@ -211,6 +856,17 @@ pub enum Tool {
         path: String,
         old_string: String,
         new_string: String,
+        #[serde(default)]
+        replace_all: bool,
+        #[serde(default)]
+        ignore_whitespace: bool,
+    },
+    MultiEdit {
+        path: String,
+        edits: Vec<Edit>,
+    },
+    ApplyPatch {
+        patch: String,
     },
     DeleteFile {
         path: String,
@@ -231,15 +887,43 @@ pub enum Tool {
     GrepSearch {
         pattern: String,
         path: Option<String>,
+        #[serde(default)]
+        glob: Option<String>,
+        #[serde(default)]
+        file_type: Option<String>,
+        /// Lines shown around each match
+        #[serde(default)]
+        context: usize,
+        #[serde(default)]
+        case_sensitive: bool,
+        #[serde(default)]
+        max_results: Option<usize>,
+        #[serde(default)]
+        sort: SortBy,
     },
     GlobSearch {
         pattern: String,
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default)]
+        max_results: Option<usize>,
+        #[serde(default)]
+        sort: SortBy,
     },
     IndexCodebase {
         path: String,
     },
     SearchIndex {
         query: String,
+        #[serde(default)]
+        callers: bool,
+    },
+    SemanticSearch {
+        query: String,
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default)]
+        max_results: Option<usize>,
     },
     FuzzyFind {
         pattern: String,
@@ -253,8 +937,18 @@ pub enum Tool {
     },
     Remember {
         fact: String,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        scope: Scope,
+    },
+    Recall {
+        #[serde(default)]
+        query: Option<String>,
+    },
+    Forget {
+        id: String,
     },
-    Recall,
 
     // Code Execution & Compilation
     ExecuteCode {
@@ -321,58 +1015,464 @@ pub enum Tool {
 }
 
 impl Tool {
-    pub fn execute(&self, web_config: &WebConfig) -> Result<String, io::Error> {
+    /// Builds a tool from a native function call. `arguments` is the JSON
+    /// object produced by the model; an empty string means no arguments.
+    pub fn from_call(name: &str, arguments: &str) -> Option<Tool> {
+        let parameters = if arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(arguments).ok()?
+        };
+        ToolCall {
+            name: name.to_string(),
+            parameters,
+        }
+        .into_tool()
+    }
+
+    /// Tools that only observe the workspace or the outside world. They are
+    /// safe to run concurrently with each other. SEARCH_INDEX,
+    /// SEMANTIC_SEARCH and RECALL are not: they save their index files or
+    /// when memories were used.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Tool::ReadFile { .. }
+                | Tool::ListFiles { .. }
+                | Tool::ListFilesRecursive { .. }
+                | Tool::GrepSearch { .. }
+                | Tool::GlobSearch { .. }
+                | Tool::FuzzyFind { .. }
+                | Tool::ReadUrl { .. }
+                | Tool::SearchWeb { .. }
+                | Tool::GitStatus
+                | Tool::GitDiff
+                | Tool::GitLog { .. }
+                | Tool::GetTime
+                | Tool::GetOsInfo
+        )
+    }
+
+    /// Describes tools that delete or move files, run arbitrary programs or
+    /// change the repository, which must be approved before they run.
+    pub fn permission_action(&self, workspace: &Workspace) -> Option<Action> {
+        // Otherwise the model could write itself an allow-everything rule
+        let mut changed = self.changed_paths().unwrap_or_default();
+        if let Tool::CreateDirectory { path } = self {
+            changed.push(path.clone());
+        }
+        if changed.iter().any(|path| workspace.is_state_path(path)) {
+            let paths: Vec<&str> = changed.iter().map(String::as_str).collect();
+            return Some(Action::state_paths(
+                self.name(),
+                workspace,
+                &paths,
+                format!("Change rct's own files in {}/: {}", STATE_DIR, self.label()),
+            ));
+        }
+
+        let action = match self {
+            Tool::DeleteFile { path } => {
+                let what = if workspace.resolve(path).is_ok_and(|path| path.is_dir()) {
+                    "Delete directory and everything in it"
+                } else {
+                    "Delete file"
+                };
+                Action::paths(
+                    "DELETE_FILE",
+                    workspace,
+                    &[path],
+                    format!("{}: {}", what, path),
+                )
+            }
+            Tool::MoveFile {
+                source,
+                destination,
+            } => Action::paths(
+                "MOVE_FILE",
+                workspace,
+                &[source, destination],
+                format!("Move {} to {}", source, destination),
+            ),
+            Tool::CopyFile {
+                source,
+                destination,
+            } => {
+                // Copying to a new path destroys nothing
+                if !workspace
+                    .resolve(destination)
+                    .is_ok_and(|path| path.exists())
+                {
+                    return None;
+                }
+                Action::paths(
+                    "COPY_FILE",
+                    workspace,
+                    &[destination],
+                    format!("Copy {} over existing {}", source, destination),
+                )
+            }
+            Tool::RenameFile { old_name, new_name } => Action::paths(
+                "RENAME_FILE",
+                workspace,
+                &[old_name, new_name],
+                format!("Rename {} to {}", old_name, new_name),
+            ),
+            Tool::RunCommand { command } => {
+                Action::shell("RUN_COMMAND", command, format!("Run: {}", command))
+            }
+            Tool::ExecuteCode { language, code } => {
+                let description = format!("Run {} code:\n{}", language, code);
+                match language.to_lowercase().as_str() {
+                    "bash" | "sh" => Action::shell("EXECUTE_CODE", code, description),
+                    _ => Action::script("EXECUTE_CODE", code, description),
+                }
+            }
+            // Rules match the action, not how the model words the message
+            Tool::GitCommit { message } => Action::command(
+                "GIT_COMMIT",
+                "git add . && git commit",
+                format!("Stage all changes (git add .) and commit: {}", message),
+            ),
+            Tool::InstallPackage { manager, package } => Action::command(
+                "INSTALL_PACKAGE",
+                &format!("{} {}", manager, package),
+                format!("Install {} package: {}", manager, package),
+            ),
+            Tool::ApplyPatch { patch } => {
+                let files = patch::parse(patch).ok()?;
+                let mut paths = Vec::new();
+                let mut changes = Vec::new();
+                for file in &files {
+                    match (&file.old_path, &file.new_path) {
+                        (Some(old), None) => {
+                            paths.push(old.as_str());
+                            changes.push(format!("Delete file: {}", old));
+                        }
+                        (Some(old), Some(new)) if old != new => {
+                            paths.extend([old.as_str(), new.as_str()]);
+                            changes.push(format!("Move {} to {}", old, new));
+                        }
+                        _ => {}
+                    }
+                }
+                if changes.is_empty() {
+                    return None;
+                }
+                Action::paths(
+                    "APPLY_PATCH",
+                    workspace,
+                    &paths,
+                    format!("Apply a patch that will:\n{}", changes.join("\n")),
+                )
+            }
+            _ => return None,
+        };
+        Some(action)
+    }
+
+    /// Name of the tool as the model calls it
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::ReadFile { .. } => "READ_FILE",
+            Tool::WriteFile { .. } => "WRITE_FILE",
+            Tool::AppendFile { .. } => "APPEND_FILE",
+            Tool::SearchReplace { .. } => "SEARCH_REPLACE",
+            Tool::MultiEdit { .. } => "MULTI_EDIT",
+            Tool::ApplyPatch { .. } => "APPLY_PATCH",
+            Tool::DeleteFile { .. } => "DELETE_FILE",
+            Tool::ListFiles { .. } => "LIST_FILES",
+            Tool::ListFilesRecursive { .. } => "LIST_FILES_RECURSIVE",
+            Tool::CreateDirectory { .. } => "CREATE_DIRECTORY",
+            Tool::GrepSearch { .. } => "GREP_SEARCH",
+            Tool::GlobSearch { .. } => "GLOB_SEARCH",
+            Tool::FuzzyFind { .. } => "FUZZY_FIND",
+            Tool::IndexCodebase { .. } => "INDEX_CODEBASE",
+            Tool::SearchIndex { .. } => "SEARCH_INDEX",
+            Tool::SemanticSearch { .. } => "SEMANTIC_SEARCH",
+            Tool::ReadUrl { .. } => "READ_URL",
+            Tool::SearchWeb { .. } => "SEARCH_WEB",
+            Tool::Remember { .. } => "REMEMBER",
+            Tool::Recall { .. } => "RECALL",
+            Tool::Forget { .. } => "FORGET",
+            Tool::ExecuteCode { .. } => "EXECUTE_CODE",
+            Tool::RunCommand { .. } => "RUN_COMMAND",
+            Tool::GitStatus => "GIT_STATUS",
+            Tool::GitDiff => "GIT_DIFF",
+            Tool::GitCommit { .. } => "GIT_COMMIT",
+            Tool::GitLog { .. } => "GIT_LOG",
+            Tool::RunLint { .. } => "RUN_LINT",
+            Tool::RunTests { .. } => "RUN_TESTS",
+            Tool::InstallPackage { .. } => "INSTALL_PACKAGE",
+            Tool::CreatePlan { .. } => "CREATE_PLAN",
+            Tool::UpdatePlan { .. } => "UPDATE_PLAN",
+            Tool::ClearPlan => "CLEAR_PLAN",
+            Tool::GetTime => "GET_TIME",
+            Tool::GetOsInfo => "GET_OS_INFO",
+            Tool::CopyFile { .. } => "COPY_FILE",
+            Tool::MoveFile { .. } => "MOVE_FILE",
+            Tool::RenameFile { .. } => "RENAME_FILE",
+        }
+    }
+
+    /// Short human-readable description used in the tool logs
+    pub fn label(&self) -> String {
+        match self {
+            Tool::ReadFile {
+                path,
+                offset: None,
+                limit: None,
+            } => format!("READ_FILE {}", path),
+            Tool::ReadFile {
+                path,
+                offset,
+                limit,
+            } => {
+                let start = offset.unwrap_or(1);
+                match limit {
+                    Some(limit) => format!(
+                        "READ_FILE {} (lines {}-{})",
+                        path,
+                        start,
+                        start + limit.saturating_sub(1)
+                    ),
+                    None => format!("READ_FILE {} (from line {})", path, start),
+                }
+            }
+            Tool::WriteFile { path, content: _ } => format!("WRITE_FILE {}", path),
+            Tool::AppendFile { path, content: _ } => format!("APPEND_FILE {}", path),
+            Tool::SearchReplace { path, .. } => format!("SEARCH_REPLACE {}", path),
+            Tool::MultiEdit { path, edits } => {
+                format!("MULTI_EDIT {} ({} edits)", path, edits.len())
+            }
+            Tool::ApplyPatch { patch } => match patch::parse(patch) {
+                Ok(files) => format!(
+                    "APPLY_PATCH {}",
+                    files
+                        .iter()
+                        .map(|f| f.path())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                Err(_) => "APPLY_PATCH".to_string(),
+            },
+            Tool::DeleteFile { path } => format!("DELETE_FILE {}", path),
+            Tool::ListFiles { path } => format!("LIST_FILES {}", path),
+            Tool::ListFilesRecursive { path } => format!("LIST_FILES_RECURSIVE {}", path),
+            Tool::CreateDirectory { path } => format!("CREATE_DIRECTORY {}", path),
+            Tool::GrepSearch { pattern, .. } => format!("GREP_SEARCH {}", pattern),
+            Tool::GlobSearch { pattern, .. } => format!("GLOB_SEARCH {}", pattern),
+            Tool::FuzzyFind { pattern, .. } => format!("FUZZY_FIND {}", pattern),
+            Tool::IndexCodebase { path } => format!("INDEX_CODEBASE {}", path),
+            Tool::SearchIndex { query, callers } => {
+                if *callers {
+                    format!("SEARCH_INDEX callers of {}", query)
+                } else {
+                    format!("SEARCH_INDEX {}", query)
+                }
+            }
+            Tool::SemanticSearch { query, .. } => format!("SEMANTIC_SEARCH '{}'", query),
+            Tool::ReadUrl { url } => format!("READ_URL {}", url),
+            Tool::SearchWeb { query } => format!("SEARCH_WEB '{}'", query),
+            Tool::Remember { fact, .. } => {
+                format!("REMEMBER ({})", fact.chars().take(50).collect::<String>())
+            }
+            Tool::Recall { query: None } => "RECALL".to_string(),
+            Tool::Recall { query: Some(query) } => format!("RECALL ({})", query),
+            Tool::Forget { id } => format!("FORGET {}", id),
+            Tool::ExecuteCode { language, code: _ } => format!("EXECUTE_CODE {}", language),
+            Tool::RunCommand { command } => format!("RUN_COMMAND {}", command),
+            Tool::GitStatus => "GIT_STATUS".to_string(),
+            Tool::GitDiff => "GIT_DIFF".to_string(),
+            Tool::GitCommit { message } => format!("GIT_COMMIT \"{}\"", message),
+            Tool::GitLog { count } => format!(
+                "GIT_LOG {}",
+                count.map_or("all".to_string(), |n| n.to_string())
+            ),
+            Tool::RunLint { language, path: _ } => format!("RUN_LINT {}", language),
+            Tool::RunTests { framework, path: _ } => format!("RUN_TESTS {}", framework),
+            Tool::InstallPackage { manager, package } => {
+                format!("INSTALL_PACKAGE {} {}", manager, package)
+            }
+            Tool::CreatePlan { task, steps } => {
+                format!("CREATE_PLAN \"{}\" ({} steps)", task, steps.len())
+            }
+            Tool::UpdatePlan { completed_step } => {
+                format!("UPDATE_PLAN step {}", completed_step)
+            }
+            Tool::ClearPlan => "CLEAR_PLAN".to_string(),
+            Tool::GetTime => "GET_TIME".to_string(),
+            Tool::GetOsInfo => "GET_OS_INFO".to_string(),
+            Tool::CopyFile {
+                source,
+                destination,
+            } => format!("COPY_FILE {} -> {}", source, destination),
+            Tool::MoveFile {
+                source,
+                destination,
+            } => format!("MOVE_FILE {} -> {}", source, destination),
+            Tool::RenameFile { old_name, new_name } => {
+                format!("RENAME_FILE {} -> {}", old_name, new_name)
+            }
+        }
+    }
+
+    /// Runs the tool without a way to cancel it.
+    #[allow(dead_code)]
+    pub async fn execute(&self, web_config: &WebConfig) -> Result<String, io::Error> {
+        self.execute_with(&ToolContext {
+            web: web_config.clone(),
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Runs the tool. Child processes are killed when `ctx.cancel` fires or
+    /// the returned future is dropped. Directory walks run on the blocking
+    /// pool; everything else is async I/O.
+    ///
+    /// Files the tool is about to change are snapshotted first, and the
+    /// tool doesn't run if that fails.
+    pub async fn execute_with(&self, ctx: &ToolContext) -> Result<String, io::Error> {
+        let (Some(checkpoints), Some(paths)) = (&ctx.checkpoints, self.changed_paths()) else {
+            return self.perform(ctx).await;
+        };
+        let paths = paths
+            .into_iter()
+            .map(|path| ctx.workspace.resolve(&path))
+            .collect::<io::Result<Vec<_>>>()?;
+        let (store, turn, label) = (checkpoints.clone(), ctx.turn, self.label());
+        let checkpoint = blocking(move || store.save(turn, &label, &paths))
+            .await
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Could not save a checkpoint, so nothing was changed: {}", e),
+                )
+            })?;
+
+        let result = self.perform(ctx).await;
+        if result.is_err() {
+            // A tool can fail after changing files, e.g. partway through
+            // deleting a directory, and those changes must stay undoable
+            let (store, id) = (checkpoints.clone(), checkpoint.id);
+            let unchanged = blocking(move || store.unchanged(&checkpoint))
+                .await
+                .unwrap_or(false);
+            if unchanged {
+                let _ = checkpoints.discard(id);
+            }
+        }
+        result
+    }
+
+    /// Paths a tool changes, for tools that edit, move or delete files.
+    pub fn changed_paths(&self) -> Option<Vec<String>> {
+        let paths = match self {
+            Tool::WriteFile { path, .. }
+            | Tool::AppendFile { path, .. }
+            | Tool::SearchReplace { path, .. }
+            | Tool::MultiEdit { path, .. }
+            | Tool::DeleteFile { path } => vec![path.clone()],
+            Tool::MoveFile {
+                source,
+                destination,
+            } => vec![source.clone(), destination.clone()],
+            Tool::CopyFile { destination, .. } => vec![destination.clone()],
+            Tool::RenameFile { old_name, new_name } => vec![old_name.clone(), new_name.clone()],
+            Tool::ApplyPatch { patch } => patch::parse(patch)
+                .ok()?
+                .into_iter()
+                .flat_map(|file| file.old_path.into_iter().chain(file.new_path))
+                .collect(),
+            _ => return None,
+        };
+        Some(paths)
+    }
+
+    async fn perform(&self, ctx: &ToolContext) -> Result<String, io::Error> {
+        let web_config = &ctx.web;
+        let name = self.name();
         match self {
             // File Operations
-            Tool::ReadFile { path } => {
-                let content = fs::read_to_string(path)?;
-                Ok(format!("File: {}\n\n{}", path, content))
+            Tool::ReadFile { path, offset, limit } => {
+                let target = ctx.workspace.resolve(path)?;
+                let (path, offset, limit) = (path.clone(), *offset, *limit);
+                blocking(move || read::read_file(&path, &target, offset, limit)).await
             }
             Tool::WriteFile { path, content } => {
+                let target = ctx.workspace.resolve(path)?;
                 // Create parent directories if they don't exist
-                if let Some(parent) = Path::new(path).parent() {
-                    fs::create_dir_all(parent)?;
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).await?;
                 }
-                fs::write(path, content)?;
-                Ok(format!("File '{}' written successfully ({} bytes).", path, content.len()))
+                fs::write(&target, content).await?;
+                let note = ctx.verification_note(&[(&target, Some(content))]).await;
+                Ok(format!("File '{}' written successfully ({} bytes).{}", path, content.len(), note))
             }
             Tool::AppendFile { path, content } => {
-                if !Path::new(path).exists() {
+                let target = ctx.workspace.resolve(path)?;
+                if !fs::try_exists(&target).await? {
                     return Err(io::Error::new(io::ErrorKind::NotFound, format!("File '{}' does not exist", path)));
                 }
-                let mut file = fs::OpenOptions::new().append(true).open(path)?;
-                use std::io::Write;
-                file.write_all(content.as_bytes())?;
-                Ok(format!("Content appended to '{}' successfully ({} bytes added).", path, content.len()))
-            }
-            Tool::SearchReplace { path, old_string, new_string } => {
-                let content = fs::read_to_string(path)?;
-                if !content.contains(old_string) {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData,
-                        format!("Old string '{}' not found in file '{}'", old_string, path)));
+                let mut file = fs::OpenOptions::new().append(true).open(&target).await?;
+                file.write_all(content.as_bytes()).await?;
+                file.flush().await?;
+                drop(file);
+                let note = ctx.verification_note(&[(&target, None)]).await;
+                Ok(format!("Content appended to '{}' successfully ({} bytes added).{}", path, content.len(), note))
+            }
+            Tool::SearchReplace { path, old_string, new_string, replace_all, ignore_whitespace } => {
+                let target = ctx.workspace.resolve(path)?;
+                let content = fs::read_to_string(&target).await?;
+                let (new_content, replaced) = edit::replace(&content, old_string, new_string, *replace_all, *ignore_whitespace)
+                    .map_err(|e| io::Error::new(e.kind(), format!("Nothing was changed in '{}': {}", path, e)))?;
+                fs::write(&target, &new_content).await?;
+                let note = ctx.verification_note(&[(&target, Some(&new_content))]).await;
+                Ok(format!("Replaced {} in '{}'.{}", replaced.describe(), path, note))
+            }
+            Tool::MultiEdit { path, edits } => {
+                if edits.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "MULTI_EDIT needs at least one edit"));
                 }
-                let new_content = content.replace(old_string, new_string);
-                fs::write(path, new_content)?;
-                Ok(format!("Successfully replaced '{}' with '{}' in '{}'", old_string, new_string, path))
+                let target = ctx.workspace.resolve(path)?;
+                let mut content = fs::read_to_string(&target).await?;
+                let mut summary = Vec::new();
+                // All edits are applied in memory first, so a failing one leaves the file untouched
+                for (i, edit) in edits.iter().enumerate() {
+                    let (next, replaced) = edit.apply(&content).map_err(|e| {
+                        io::Error::new(e.kind(), format!("Edit {} of {} failed, so nothing was changed in '{}': {}", i + 1, edits.len(), path, e))
+                    })?;
+                    content = next;
+                    summary.push(format!("{}. Replaced {}", i + 1, replaced.describe()));
+                }
+                fs::write(&target, &content).await?;
+                let note = ctx.verification_note(&[(&target, Some(&content))]).await;
+                Ok(format!("Applied {} edits to '{}':\n{}{}", edits.len(), path, summary.join("\n"), note))
             }
+            Tool::ApplyPatch { patch } => apply_patch(ctx, patch).await,
             Tool::DeleteFile { path } => {
-                if Path::new(path).is_dir() {
-                    fs::remove_dir_all(path)?;
+                let target = ctx.workspace.resolve(path)?;
+                if target == ctx.workspace.root() {
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Refusing to delete the workspace root"));
+                }
+                // symlink_metadata so a link to a directory is removed, not emptied
+                if fs::symlink_metadata(&target).await?.is_dir() {
+                    fs::remove_dir_all(&target).await?;
                     Ok(format!("Directory '{}' deleted successfully.", path))
                 } else {
-                    fs::remove_file(path)?;
+                    fs::remove_file(&target).await?;
                     Ok(format!("File '{}' deleted successfully.", path))
                 }
             }
 
             // Directory Operations
             Tool::ListFiles { path } => {
-                let entries = fs::read_dir(path)?;
+                let mut entries = fs::read_dir(ctx.workspace.resolve(path)?).await?;
                 let mut files = Vec::new();
-                for entry in entries {
-                    let entry = entry?;
+                while let Some(entry) = entries.next_entry().await? {
                     let name = entry.file_name().to_string_lossy().to_string();
-                    let is_dir = entry.file_type()?.is_dir();
+                    let is_dir = entry.file_type().await?.is_dir();
                     files.push(format!("{}{}", if is_dir { "[DIR] " } else { "[FILE] " }, name));
                 }
                 files.sort();
@@ -381,7 +1481,7 @@ impl Tool {
             Tool::ListFilesRecursive { path } => {
                 fn collect_files(dir: &Path, prefix: &str) -> Result<Vec<String>, io::Error> {
                     let mut results = Vec::new();
-                    let entries = fs::read_dir(dir)?;
+                    let entries = std::fs::read_dir(dir)?;
                     for entry in entries {
                         let entry = entry?;
                         let name = entry.file_name().to_string_lossy().to_string();
@@ -398,74 +1498,71 @@ impl Tool {
                     }
                     Ok(results)
                 }
-                let files = collect_files(Path::new(path), "")?;
+                let root = ctx.workspace.resolve(path)?;
+                let files = blocking(move || collect_files(&root, "")).await?;
                 Ok(format!("Recursive contents of '{}':\n{}", path, files.join("\n")))
             }
             Tool::CreateDirectory { path } => {
-                fs::create_dir_all(path)?;
+                fs::create_dir_all(ctx.workspace.resolve(path)?).await?;
                 Ok(format!("Directory '{}' created successfully.", path))
             }
 
             // Search & Navigation
-            Tool::GrepSearch { pattern, path } => {
-                let search_path = path.as_ref().map(|s| s.as_str()).unwrap_or(".");
-                let mut cmd = Command::new("grep");
-                cmd.arg("-r").arg("-n").arg("-i").arg(pattern).arg(search_path);
-                let output = cmd.output()?;
-
-                if output.status.success() {
-                    Ok(String::from_utf8_lossy(&output.stdout).to_string())
-                } else {
-                    Ok(format!("No matches found for pattern '{}' in '{}'", pattern, search_path))
+            Tool::GrepSearch { pattern, path, glob, file_type, context, case_sensitive, max_results, sort } => {
+                let search_path = path.as_deref().unwrap_or(".");
+                let start = ctx.workspace.resolve(search_path)?;
+                let regex = RegexBuilder::new(pattern)
+                    .case_insensitive(!case_sensitive)
+                    .build()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid regex '{}': {}", pattern, e)))?;
+                let filter = search::Filter { glob: glob.clone(), file_type: file_type.clone(), sort: *sort };
+                let root = ctx.workspace.root().to_path_buf();
+                let (context, max) = (*context, max_results.unwrap_or(search::DEFAULT_MAX_RESULTS).max(1));
+                let found = blocking(move || search::grep(&root, &start, &regex, context, max, &filter)).await?;
+
+                if found.matches == 0 {
+                    return Ok(format!("No matches found for pattern '{}' in '{}'", pattern, search_path));
+                }
+                let mut result = format!("{} matches in {} files for '{}':\n{}", found.matches, found.files, pattern, found.lines.join("\n"));
+                if found.truncated {
+                    result.push_str(&format!("\n... stopped after {} matches. Narrow the search with path, glob or file_type, or raise max_results", max));
                 }
+                Ok(result)
             }
-            Tool::GlobSearch { pattern } => {
-                let mut cmd = Command::new("find");
-                cmd.arg(".").arg("-name").arg(pattern);
-                let output = cmd.output()?;
+            Tool::GlobSearch { pattern, path, max_results, sort } => {
+                let search_path = path.as_deref().unwrap_or(".");
+                let start = ctx.workspace.resolve(search_path)?;
+                let filter = search::Filter { glob: Some(pattern.clone()), file_type: None, sort: *sort };
+                let root = ctx.workspace.root().to_path_buf();
+                let files = blocking(move || search::files(&root, &start, &filter)).await?;
 
-                if output.status.success() {
-                    let stdout_str = String::from_utf8_lossy(&output.stdout);
-                    let files_vec: Vec<_> = stdout_str
-                        .lines()
-                        .filter(|line| !line.is_empty())
-                        .collect();
-                    Ok(format!("Files matching '{}':\n{}", pattern, files_vec.join("\n")))
-                } else {
-                    Ok(format!("No files found matching pattern '{}'", pattern))
+                if files.is_empty() {
+                    return Ok(format!("No files found matching pattern '{}'", pattern));
                 }
+                Ok(format!("Files matching '{}':\n{}", pattern, list_files(&files, *max_results)))
             }
             Tool::FuzzyFind { pattern, path } => {
-                let search_path = path.as_ref().map(|s| s.as_str()).unwrap_or(".");
-                let mut cmd = Command::new("find");
-                cmd.arg(search_path).arg("-type").arg("f");
-                let output = cmd.output()?;
-
-                if output.status.success() {
-                    let stdout_str = String::from_utf8_lossy(&output.stdout);
-                    let pattern_lower = pattern.to_lowercase();
-                    let files_vec: Vec<_> = stdout_str
-                        .lines()
-                        .filter(|line| !line.is_empty())
-                        .filter(|line| line.to_lowercase().contains(&pattern_lower))
-                        .collect();
-                    if files_vec.is_empty() {
-                        Ok(format!("No files found matching fuzzy pattern '{}' in '{}'", pattern, search_path))
-                    } else {
-                        Ok(format!("Files matching fuzzy pattern '{}':\n{}", pattern, files_vec.join("\n")))
-                    }
+                let search_path = path.as_deref().unwrap_or(".");
+                let start = ctx.workspace.resolve(search_path)?;
+                let root = ctx.workspace.root().to_path_buf();
+                let files = blocking(move || search::files(&root, &start, &search::Filter::default())).await?;
+                let pattern_lower = pattern.to_lowercase();
+                let files: Vec<String> = files.into_iter().filter(|file| file.to_lowercase().contains(&pattern_lower)).collect();
+
+                if files.is_empty() {
+                    Ok(format!("No files found matching fuzzy pattern '{}' in '{}'", pattern, search_path))
                 } else {
-                    Ok(format!("Failed to search files in '{}'", search_path))
+                    Ok(format!("Files matching fuzzy pattern '{}':\n{}", pattern, list_files(&files, None)))
                 }
             }
             Tool::ReadUrl { url } => {
-                let response = reqwest::blocking::get(url)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to fetch URL: {}", e)))?;
+                let response = reqwest::get(url).await
+                    .map_err(|e| io::Error::other(format!("Failed to fetch URL: {}", e)))?;
                 if !response.status().is_success() {
-                    return Err(io::Error::new(io::ErrorKind::Other, format!("HTTP Error: {}", response.status())));
+                    return Err(io::Error::other(format!("HTTP Error: {}", response.status())));
                 }
-                let html = response.text()
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to read response text: {}", e)))?;
+                let html = response.text().await
+                    .map_err(|e| io::Error::other(format!("Failed to read response text: {}", e)))?;
                 let text = html2text::from_read(html.as_bytes(), 80);
                 Ok(format!("Content of {}:\n\n{}", url, text))
             }
@@ -476,20 +1573,20 @@ impl Tool {
                 let provider = web_config.provider.to_lowercase();
                 if provider == "duckduckgo" {
                      let url = format!("https://html.duckduckgo.com/html/?q={}", urlencoding::encode(query));
-                     let client = reqwest::blocking::Client::builder()
+                     let client = reqwest::Client::builder()
                         .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
                         .build()
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                        .map_err(io::Error::other)?;
 
-                     let response = client.get(&url).send()
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to search: {}", e)))?;
+                     let response = client.get(&url).send().await
+                        .map_err(|e| io::Error::other(format!("Failed to search: {}", e)))?;
 
                      if !response.status().is_success() {
-                         return Err(io::Error::new(io::ErrorKind::Other, format!("Search failed with status: {}", response.status())));
+                         return Err(io::Error::other(format!("Search failed with status: {}", response.status())));
                      }
 
-                     let html = response.text()
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                     let html = response.text().await
+                        .map_err(io::Error::other)?;
                      // Simple parsing for DDG HTML (this is brittle but works for basic scraping)
                      // We look for class="result__a"
                      let regex = Regex::new(r#"class="result__a"\s+href="([^"]+)">([^<]+)</a>"#).unwrap();
@@ -518,187 +1615,217 @@ impl Tool {
                 }
             }
 
-            Tool::Remember { fact } => {
-                let memory_file = ".agent_memory.md";
-                let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
-                let entry = format!("\n## [{}]\n{}\n", timestamp, fact);
+            Tool::Remember { fact, tags, scope } => {
+                let memories = ctx.memories();
+                let (fact, tags, scope) = (fact.clone(), tags.clone(), *scope);
+                let memory =
+                    blocking(move || memories.remember(&fact, &tags, scope, "REMEMBER")).await?;
+                Ok(format!("Remembered in the {} memory: {}", scope, memory))
+            }
 
-                if Path::new(memory_file).exists() {
-                    let mut file = fs::OpenOptions::new().append(true).open(memory_file)?;
-                    use std::io::Write;
-                    file.write_all(entry.as_bytes())?;
+            Tool::Recall { query } => {
+                let memories = ctx.memories();
+                let query = query.clone().unwrap_or_default();
+                let recalled = {
+                    let query = query.clone();
+                    blocking(move || memories.recall(&query, memory::DEFAULT_RECALL_LIMIT)).await?
+                };
+                if recalled.is_empty() && query.is_empty() {
+                    return Ok(
+                        "No memories saved yet. Use REMEMBER to save important facts.".to_string(),
+                    );
+                }
+                if recalled.is_empty() {
+                    return Ok(format!("No memories match '{}'.", query));
+                }
+                let mut output = if query.is_empty() {
+                    format!("{} memories, most recently used first:\n", recalled.len())
                 } else {
-                    fs::write(memory_file, format!("# Agent Memory\n{}", entry))?;
+                    format!("{} memories matching '{}':\n", recalled.len(), query)
+                };
+                for (scope, memory) in &recalled {
+                    output.push_str(&format!(
+                        "- {} ({}, saved {} by {})\n",
+                        memory, scope, memory.created, memory.source
+                    ));
                 }
-
-                Ok(format!("Remembered: '{}' (saved to {})", fact, memory_file))
+                Ok(output)
             }
 
-            Tool::Recall => {
-                let memory_file = ".agent_memory.md";
-                if Path::new(memory_file).exists() {
-                    let content = fs::read_to_string(memory_file)?;
-                    Ok(format!("Project Memory:\n\n{}", content))
-                } else {
-                    Ok("No project memory found. Use REMEMBER to save important facts.".to_string())
+            Tool::Forget { id } => {
+                let memories = ctx.memories();
+                let id = id.trim().to_string();
+                let forgotten = {
+                    let id = id.clone();
+                    blocking(move || memories.forget(&id)).await?
+                };
+                match forgotten {
+                    Some(memory) => Ok(format!("Forgot {}", memory)),
+                    None => Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("No memory with id '{}'; RECALL lists them", id),
+                    )),
                 }
             }
 
             Tool::IndexCodebase { path } => {
-                let root_path = Path::new(path);
-                if !root_path.exists() {
+                let start = ctx.workspace.resolve(path)?;
+                if !fs::try_exists(&start).await? {
                     return Err(io::Error::new(io::ErrorKind::NotFound, format!("Path '{}' does not exist", path)));
                 }
+                let root = ctx.workspace.root().to_path_buf();
+                let (update, files, symbols) = blocking(move || {
+                    let mut index = Index::load(&root);
+                    let update = index.update(&root, &start)?;
+                    index.save(&root)?;
+                    Ok((update, index.files.len(), index.symbol_count()))
+                })
+                .await?;
 
-                let mut index_data: HashMap<String, Vec<String>> = HashMap::new();
-                let mut file_count = 0;
-                let mut symbol_count = 0;
-
-                // Regex patterns for different languages
-                let rust_fn = Regex::new(r"fn\s+([a-zA-Z_][a-zA-Z0-9_]*)").unwrap();
-                let rust_struct = Regex::new(r"(struct|enum|trait)\s+([a-zA-Z_][a-zA-Z0-9_]*)").unwrap();
-                let py_def = Regex::new(r"def\s+([a-zA-Z_][a-zA-Z0-9_]*)").unwrap();
-                let py_class = Regex::new(r"class\s+([a-zA-Z_][a-zA-Z0-9_]*)").unwrap();
-                let js_func = Regex::new(r"(?:function\s+([a-zA-Z_][a-zA-Z0-9_]*)|([a-zA-Z_][a-zA-Z0-9_]*)\s*=\s*function)").unwrap();
-                let js_class = Regex::new(r"class\s+([a-zA-Z_][a-zA-Z0-9_]*)").unwrap();
-                let js_const = Regex::new(r"(?:const|let|var)\s+([a-zA-Z_][a-zA-Z0-9_]*)\s*=").unwrap();
-
-                fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&Path)) -> io::Result<()> {
-                    if dir.is_dir() {
-                        for entry in fs::read_dir(dir)? {
-                            let entry = entry?;
-                            let path = entry.path();
-                            if path.is_dir() {
-                                if !path.file_name().unwrap().to_string_lossy().starts_with('.') {
-                                     visit_dirs(&path, cb)?;
-                                }
-                            } else {
-                                cb(&path);
-                            }
-                        }
+                Ok(format!(
+                    "Indexed {} files with {} symbols ({} new, {} changed, {} removed, {} unchanged). Index saved to {}",
+                    files, symbols, update.added, update.changed, update.removed, update.unchanged, index::INDEX_FILE
+                ))
+            }
+
+            Tool::SearchIndex { query, callers } => {
+                let root = ctx.workspace.root().to_path_buf();
+                if !fs::try_exists(root.join(index::INDEX_FILE)).await? {
+                    return Ok("Index not found. Please run INDEX_CODEBASE first.".to_string());
+                }
+                let query = query.clone();
+                let callers = *callers;
+                blocking(move || {
+                    // Files edited since the last INDEX_CODEBASE are picked up here
+                    let mut index = Index::load(&root);
+                    let update = index.refresh(&root);
+                    if update.changed + update.removed > 0 {
+                        index.save(&root)?;
                     }
-                    Ok(())
-                }
-                visit_dirs(root_path, &mut |file_path| {
-                    if let Ok(content) = fs::read_to_string(file_path) {
-                        let ext = file_path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                        let file_str = file_path.to_string_lossy().to_string();
-                        let mut symbols = Vec::new();
-                        match ext {
-                            "rs" => {
-                                for cap in rust_fn.captures_iter(&content) {
-                                    symbols.push(format!("Function: {}", &cap[1]));
-                                }
-                                for cap in rust_struct.captures_iter(&content) {
-                                    symbols.push(format!("{}: {}", &cap[1], &cap[2]));
-                                }
-                            },
-                            "py" => {
-                                for cap in py_def.captures_iter(&content) {
-                                    symbols.push(format!("Function: {}", &cap[1]));
-                                }
-                                for cap in py_class.captures_iter(&content) {
-                                    symbols.push(format!("Class: {}", &cap[1]));
-                                }
-                            },
-                            "js" | "ts" | "jsx" | "tsx" => {
-                                for cap in js_func.captures_iter(&content) {
-                                    if let Some(name) = cap.get(1).or(cap.get(2)) {
-                                        symbols.push(format!("Function: {}", name.as_str()));
-                                    }
-                                }
-                                for cap in js_class.captures_iter(&content) {
-                                    symbols.push(format!("Class: {}", &cap[1]));
-                                }
-                                for cap in js_const.captures_iter(&content) {
-                                    symbols.push(format!("Variable: {}", &cap[1]));
-                                }
-                            },
-                            _ => {}
+                    if callers {
+                        let calls = index.callers(&query);
+                        if calls.is_empty() {
+                            return Ok(format!("No calls of '{}' found", query));
                         }
-
-                        if !symbols.is_empty() {
-                            index_data.insert(file_str, symbols);
-                            file_count += 1;
-                            symbol_count += index_data.values().last().unwrap().len();
+                        let mut result = format!("{} calls of '{}':", calls.len(), query);
+                        for call in calls.iter().take(MAX_INDEX_HITS) {
+                            result.push_str(&format!("\n{}", call));
+                        }
+                        if calls.len() > MAX_INDEX_HITS {
+                            result.push_str(&format!("\n... {} more not shown", calls.len() - MAX_INDEX_HITS));
                         }
+                        return Ok(result);
                     }
-                })?;
-
-                // Save index to file
-                let json = serde_json::to_string_pretty(&index_data).unwrap();
-                fs::write(".agent_index.json", json)?;
-
-                Ok(format!("Indexed {} files and found {} symbols. Index saved to .agent_index.json", file_count, symbol_count))
+                    let hits = index.search(&query);
+                    if hits.is_empty() {
+                        return Ok(format!("No symbols found matching '{}'", query));
+                    }
+                    let mut result = format!("Found {} matches for '{}':", hits.len(), query);
+                    for hit in hits.iter().take(MAX_INDEX_HITS) {
+                        result.push_str(&format!("\n{}", hit));
+                    }
+                    if hits.len() > MAX_INDEX_HITS {
+                        result.push_str(&format!("\n... {} more not shown. Use a more specific query", hits.len() - MAX_INDEX_HITS));
+                    }
+                    Ok(result)
+                })
+                .await
             }
 
-            Tool::SearchIndex { query } => {
-                if !Path::new(".agent_index.json").exists() {
-                    return Ok("Index not found. Please run INDEX_CODEBASE first.".to_string());
+            Tool::SemanticSearch {
+                query,
+                path,
+                max_results,
+            } => {
+                let start = ctx.workspace.resolve(path.as_deref().unwrap_or("."))?;
+                if !fs::try_exists(&start).await? {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Path '{}' does not exist", start.display()),
+                    ));
+                }
+                let root = ctx.workspace.root().to_path_buf();
+                let limit = max_results.unwrap_or(semantic::DEFAULT_MAX_RESULTS).max(1);
+                let candidates = match &ctx.embeddings {
+                    Some(_) => limit.max(semantic::RERANK_CANDIDATES),
+                    None => limit,
+                };
+                let wanted = query.clone();
+                let mut hits = blocking(move || {
+                    let mut corpus = Corpus::load(&root);
+                    let update = corpus.update(&root, &start)?;
+                    if update.added + update.changed + update.removed > 0 {
+                        corpus.save(&root)?;
+                    }
+                    Ok(corpus.search(&root, &start, &wanted, candidates))
+                })
+                .await?;
+                if hits.is_empty() {
+                    return Ok(format!("No code or docs found matching '{}'", query));
                 }
-                let content = fs::read_to_string(".agent_index.json")?;
-                let index: HashMap<String, Vec<String>> = serde_json::from_str(&content).unwrap_or_default();
-                let query_lower = query.to_lowercase();
-                let mut results = Vec::new();
-                for (file, symbols) in index {
-                    for symbol in symbols {
-                        if symbol.to_lowercase().contains(&query_lower) {
-                            results.push(format!("{} -> {}", symbol, file));
+
+                let mut ranking = "BM25".to_string();
+                if let Some(config) = ctx.embeddings.as_ref().filter(|_| hits.len() > 1) {
+                    let inputs: Vec<String> = std::iter::once(query.clone())
+                        .chain(hits.iter().map(|hit| hit.embedding_input()))
+                        .collect();
+                    match llm::embed(config, &inputs).await {
+                        Ok(vectors) if vectors.len() == inputs.len() => {
+                            semantic::rerank(&mut hits, &vectors[0], &vectors[1..]);
+                            ranking = "BM25, re-ranked with embeddings".to_string();
                         }
+                        Ok(_) => ranking = "BM25; the embedding model returned too few vectors".to_string(),
+                        Err(e) => ranking = format!("BM25; embeddings unavailable: {}", e),
                     }
                 }
+                hits.truncate(limit);
 
-                if results.is_empty() {
-                    Ok(format!("No symbols found matching '{}'", query))
-                } else {
-                    results.sort();
-                    Ok(format!("Found {} matches for '{}':\n{}", results.len(), query, results.join("\n")))
+                let mut result = format!("Top {} chunks for '{}' ({}):", hits.len(), query, ranking);
+                for hit in &hits {
+                    result.push_str(&format!("\n\n{}:{}-{}", hit.path, hit.start, hit.end));
+                    if let Some(title) = &hit.title {
+                        result.push_str(&format!(" {}", title));
+                    }
+                    result.push_str(&format!(" (score {:.2})", hit.score));
+                    for line in hit.excerpt(query, SEMANTIC_EXCERPT_LINES) {
+                        result.push_str(&format!("\n    {}", line));
+                    }
                 }
+                Ok(result)
             }
 
             // Code Execution & Compilation
             Tool::ExecuteCode { language, code } => {
                 match language.to_lowercase().as_str() {
-                    "python" | "py" => Self::execute_python(code),
-                    "javascript" | "js" | "node" => Self::execute_javascript(code),
-                    "bash" | "sh" => Self::execute_bash(code),
-                    "rust" => Self::execute_rust(code),
-                    "go" => Self::execute_go(code),
-                    "java" => Self::execute_java(code),
-                    "c" | "cpp" | "c++" => Self::execute_c_cpp(code, language),
+                    "python" | "py" => Self::execute_python(code, ctx).await,
+                    "javascript" | "js" | "node" => Self::execute_javascript(code, ctx).await,
+                    "bash" | "sh" => Self::execute_bash(code, ctx).await,
+                    "rust" => Self::execute_rust(code, ctx).await,
+                    "go" => Self::execute_go(code, ctx).await,
+                    "java" => Self::execute_java(code, ctx).await,
+                    "c" | "cpp" | "c++" => Self::execute_c_cpp(code, language, ctx).await,
                     _ => Ok(format!("Unsupported language: {}. Supported: python, javascript, bash, rust, go, java, c, cpp", language))
                 }
             }
             Tool::RunCommand { command } => {
-                // OS-adaptive command execution
-                let output = if cfg!(target_os = "windows") {
-                    Command::new("cmd")
-                        .arg("/C")
-                        .arg(command)
-                        .output()?
-                } else {
-                    Command::new("sh")
-                        .arg("-c")
-                        .arg(command)
-                        .output()?
-                };
+                let output = ctx.run(name, &mut ctx.shell_command(command)).await?;
 
                 if output.status.success() {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    let stdout = ctx.clip(&output.stdout);
+                    let stderr = ctx.clip(&output.stderr);
                     if stdout.is_empty() && stderr.is_empty() {
                         Ok(format!("Command '{}' executed successfully.", command))
                     } else {
                         Ok(format!("Command: {}\n\nSTDOUT:\n{}\n\nSTDERR:\n{}", command, stdout, stderr))
                     }
                 } else {
-                    Ok(format!("Command '{}' failed:\n\nSTDERR:\n{}", command, String::from_utf8_lossy(&output.stderr)))
+                    Ok(format!("Command '{}' failed:\n\nSTDERR:\n{}", command, ctx.clip(&output.stderr)))
                 }
             }
 
             // Development Workflow
             Tool::GitStatus => {
-                let output = Command::new("git").arg("status").arg("--porcelain").output()?;
+                let output = ctx.run(name, Command::new("git").arg("status").arg("--porcelain").current_dir(ctx.workspace.root())).await?;
                 if output.status.success() {
                     let status = String::from_utf8_lossy(&output.stdout);
                     if status.trim().is_empty() {
@@ -711,7 +1838,7 @@ impl Tool {
                 }
             }
             Tool::GitDiff => {
-                let output = Command::new("git").arg("diff").output()?;
+                let output = ctx.run(name, Command::new("git").arg("diff").current_dir(ctx.workspace.root())).await?;
                 if output.status.success() {
                     let diff = String::from_utf8_lossy(&output.stdout);
                     if diff.trim().is_empty() {
@@ -725,8 +1852,8 @@ impl Tool {
             }
             Tool::GitCommit { message } => {
                 // First add all changes
-                let _ = Command::new("git").arg("add").arg(".").output()?;
-                let output = Command::new("git").arg("commit").arg("-m").arg(message).output()?;
+                let _ = ctx.run(name, Command::new("git").arg("add").arg(".").current_dir(ctx.workspace.root())).await?;
+                let output = ctx.run(name, Command::new("git").arg("commit").arg("-m").arg(message).current_dir(ctx.workspace.root())).await?;
                 if output.status.success() {
                     Ok(format!("Successfully committed changes with message: '{}'", message))
                 } else {
@@ -735,11 +1862,11 @@ impl Tool {
             }
             Tool::GitLog { count } => {
                 let mut cmd = Command::new("git");
-                cmd.arg("log").arg("--oneline");
+                cmd.arg("log").arg("--oneline").current_dir(ctx.workspace.root());
                 if let Some(n) = count {
                     cmd.arg(format!("-{}", n));
                 }
-                let output = cmd.output()?;
+                let output = ctx.run(name, &mut cmd).await?;
                 if output.status.success() {
                     Ok(format!("Git log:\n{}", String::from_utf8_lossy(&output.stdout)))
                 } else {
@@ -749,108 +1876,74 @@ impl Tool {
 
             // Quality Assurance
             Tool::RunLint { language, path } => {
-                let target_path = path.as_ref().map(|s| s.as_str()).unwrap_or(".");
+                let target_path = &ctx.workspace.resolve(path.as_deref().unwrap_or("."))?;
                 match language.to_lowercase().as_str() {
                     "rust" => {
                         let mut cmd = Command::new("cargo");
                         cmd.arg("clippy").current_dir(target_path);
-                        let output = cmd.output()?;
+                        let output = ctx.run(name, &mut cmd).await?;
                         if output.status.success() {
                             Ok("Cargo clippy passed - no linting issues found.".to_string())
                         } else {
-                            Ok(format!("Linting issues found:\n{}", String::from_utf8_lossy(&output.stderr)))
+                            Ok(format!("Linting issues found:\n{}", ctx.clip(&output.stderr)))
                         }
                     }
                     "python" => {
-                        let output = if let Ok(out) = Command::new("flake8").arg(target_path).output() {
-                            out
-                        } else if let Ok(out) = Command::new("pylint").arg(target_path).output() {
-                            out
-                        } else {
-                            match Command::new("python").arg("-m").arg("pycodestyle").arg(target_path).output() {
-                                Ok(out) => out,
-                                Err(_) => std::process::Output {
-                                    status: std::process::ExitStatus::default(),
-                                    stdout: Vec::new(),
-                                    stderr: b"Python linter not available".to_vec(),
-                                }
-                            }
-                        };
-                        if output.status.success() {
-                            Ok("Python linting passed.".to_string())
-                        } else {
-                            Ok(format!("Python linting issues:\n{}", String::from_utf8_lossy(&output.stderr)))
+                        let mut flake8 = Command::new("flake8");
+                        flake8.arg(target_path);
+                        let mut pylint = Command::new("pylint");
+                        pylint.arg(target_path);
+                        let mut pycodestyle = Command::new("python");
+                        pycodestyle.arg("-m").arg("pycodestyle").arg(target_path);
+                        match ctx.run_first_installed(name, [flake8, pylint, pycodestyle]).await? {
+                            None => Ok("Python linter not available.".to_string()),
+                            Some(output) if output.status.success() => Ok("Python linting passed.".to_string()),
+                            Some(output) => Ok(format!("Python linting issues:\n{}", ctx.clip(&output.stderr))),
                         }
                     }
                     "javascript" | "js" => {
-                        let output = match Command::new("eslint").arg(target_path).output() {
-                            Ok(out) => out,
-                            Err(_) => std::process::Output {
-                                status: std::process::ExitStatus::default(),
-                                stdout: Vec::new(),
-                                stderr: b"ESLint not available".to_vec(),
-                            }
-                        };
-                        if output.status.success() {
-                            Ok("JavaScript linting passed.".to_string())
-                        } else {
-                            Ok(format!("JavaScript linting issues:\n{}", String::from_utf8_lossy(&output.stderr)))
+                        let mut eslint = Command::new("eslint");
+                        eslint.arg(target_path);
+                        match ctx.run_first_installed(name, [eslint]).await? {
+                            None => Ok("ESLint not available.".to_string()),
+                            Some(output) if output.status.success() => Ok("JavaScript linting passed.".to_string()),
+                            Some(output) => Ok(format!("JavaScript linting issues:\n{}", ctx.clip(&output.stderr))),
                         }
                     }
                     _ => Ok(format!("Linting not supported for language: {}", language))
                 }
             }
             Tool::RunTests { framework, path } => {
-                let target_path = path.as_ref().map(|s| s.as_str()).unwrap_or(".");
+                let target_path = &ctx.workspace.resolve(path.as_deref().unwrap_or("."))?;
                 match framework.to_lowercase().as_str() {
                     "cargo" |                     "rust" => {
                         let mut cmd = Command::new("cargo");
                         cmd.arg("test").current_dir(target_path);
-                        let output = cmd.output()?;
+                        let output = ctx.run(name, &mut cmd).await?;
                         if output.status.success() {
-                            Ok(format!("Tests passed!\n{}", String::from_utf8_lossy(&output.stdout)))
+                            Ok(format!("Tests passed!\n{}", ctx.clip(&output.stdout)))
                         } else {
-                            Ok(format!("Tests failed:\n{}", String::from_utf8_lossy(&output.stderr)))
+                            Ok(format!("Tests failed:\n{}", ctx.clip(&output.stderr)))
                         }
                     }
                     "pytest" | "python" => {
-                        let mut cmd = Command::new("python");
-                        cmd.arg("-m").arg("pytest").current_dir(target_path);
-                        let output = if let Ok(out) = cmd.output() {
-                            out
-                        } else {
-                            let mut cmd2 = Command::new("python");
-                            cmd2.arg("-m").arg("unittest").arg("discover").current_dir(target_path);
-                            match cmd2.output() {
-                                Ok(out) => out,
-                                Err(_) => std::process::Output {
-                                    status: std::process::ExitStatus::default(),
-                                    stdout: Vec::new(),
-                                    stderr: b"Python testing framework not available".to_vec(),
-                                }
-                            }
-                        };
-                        if output.status.success() {
-                            Ok(format!("Python tests passed!\n{}", String::from_utf8_lossy(&output.stdout)))
-                        } else {
-                            Ok(format!("Python tests failed:\n{}", String::from_utf8_lossy(&output.stderr)))
+                        let mut pytest = Command::new("python");
+                        pytest.arg("-m").arg("pytest").current_dir(target_path);
+                        let mut unittest = Command::new("python");
+                        unittest.arg("-m").arg("unittest").arg("discover").current_dir(target_path);
+                        match ctx.run_first_installed(name, [pytest, unittest]).await? {
+                            None => Ok("Python testing framework not available.".to_string()),
+                            Some(output) if output.status.success() => Ok(format!("Python tests passed!\n{}", ctx.clip(&output.stdout))),
+                            Some(output) => Ok(format!("Python tests failed:\n{}", ctx.clip(&output.stderr))),
                         }
                     }
                     "jest" | "javascript" | "js" => {
-                        let mut cmd = Command::new("npx");
-                        cmd.arg("jest").current_dir(target_path);
-                        let output = match cmd.output() {
-                            Ok(out) => out,
-                            Err(_) => std::process::Output {
-                                status: std::process::ExitStatus::default(),
-                                stdout: Vec::new(),
-                                stderr: b"Jest testing framework not available".to_vec(),
-                            }
-                        };
-                        if output.status.success() {
-                            Ok(format!("JavaScript tests passed!\n{}", String::from_utf8_lossy(&output.stdout)))
-                        } else {
-                            Ok(format!("JavaScript tests failed:\n{}", String::from_utf8_lossy(&output.stderr)))
+                        let mut jest = Command::new("npx");
+                        jest.arg("jest").current_dir(target_path);
+                        match ctx.run_first_installed(name, [jest]).await? {
+                            None => Ok("Jest testing framework not available.".to_string()),
+                            Some(output) if output.status.success() => Ok(format!("JavaScript tests passed!\n{}", ctx.clip(&output.stdout))),
+                            Some(output) => Ok(format!("JavaScript tests failed:\n{}", ctx.clip(&output.stderr))),
                         }
                     }
                     _ => Ok(format!("Testing framework '{}' not supported.", framework))
@@ -862,7 +1955,7 @@ impl Tool {
                 match manager.to_lowercase().as_str() {
                     "npm" | "yarn" => {
                         let cmd = if manager == "yarn" { "yarn" } else { "npm" };
-                        let output = Command::new(cmd).arg("install").arg(package).output()?;
+                        let output = ctx.run(name, Command::new(cmd).arg("install").arg(package).current_dir(ctx.workspace.root())).await?;
                         if output.status.success() {
                             Ok(format!("Successfully installed {} package: {}", manager, package))
                         } else {
@@ -870,7 +1963,7 @@ impl Tool {
                         }
                     }
                     "cargo" | "rust" => {
-                        let output = Command::new("cargo").arg("add").arg(package).output()?;
+                        let output = ctx.run(name, Command::new("cargo").arg("add").arg(package).current_dir(ctx.workspace.root())).await?;
                         if output.status.success() {
                             Ok(format!("Successfully added Rust crate: {}", package))
                         } else {
@@ -878,7 +1971,7 @@ impl Tool {
                         }
                     }
                     "pip" | "python" => {
-                        let output = Command::new("pip").arg("install").arg(package).output()?;
+                        let output = ctx.run(name, Command::new("pip").arg("install").arg(package).current_dir(ctx.workspace.root())).await?;
                         if output.status.success() {
                             Ok(format!("Successfully installed Python package: {}", package))
                         } else {
@@ -886,7 +1979,7 @@ impl Tool {
                         }
                     }
                     "go" => {
-                        let output = Command::new("go").arg("get").arg(package).output()?;
+                        let output = ctx.run(name, Command::new("go").arg("get").arg(package).current_dir(ctx.workspace.root())).await?;
                         if output.status.success() {
                             Ok(format!("Successfully installed Go package: {}", package))
                         } else {
@@ -909,12 +2002,13 @@ impl Tool {
                 content.push_str("- Completed: 0\n");
                 content.push_str("- Remaining: 0\n");
 
-                fs::write("plan.md", content)?;
+                fs::write(ctx.workspace.root().join("plan.md"), content).await?;
                 Ok(format!("Created plan.md with {} steps for task: {}", steps.len(), task))
             }
 
             Tool::UpdatePlan { completed_step } => {
-                let content = fs::read_to_string("plan.md")?;
+                let plan_path = ctx.workspace.root().join("plan.md");
+                let content = fs::read_to_string(&plan_path).await?;
                 let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
 
                 // Update the checklist
@@ -939,13 +2033,14 @@ impl Tool {
                 }
 
                 let new_content = lines.join("\n");
-                fs::write("plan.md", new_content)?;
+                fs::write(&plan_path, new_content).await?;
                 Ok(format!("Updated plan.md: Step {} completed. Progress: {}/{}", completed_step, completed_steps, total_steps))
             }
 
             Tool::ClearPlan => {
-                if Path::new("plan.md").exists() {
-                    fs::remove_file("plan.md")?;
+                let plan_path = ctx.workspace.root().join("plan.md");
+                if plan_path.exists() {
+                    fs::remove_file(&plan_path).await?;
                     Ok("Cleared plan.md - task completed!".to_string())
                 } else {
                     Ok("No plan.md file found to clear".to_string())
@@ -959,7 +2054,7 @@ impl Tool {
                 let datetime = chrono::Local::now();
                 let unix_timestamp = now.duration_since(SystemTime::UNIX_EPOCH)
                     .map(|d| d.as_secs())
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("System time is before UNIX epoch: {}", e)))?;
+                    .map_err(|e| io::Error::other(format!("System time is before UNIX epoch: {}", e)))?;
                 Ok(format!(
                     "Current Date & Time:\n\
                      • Date: {}\n\
@@ -982,7 +2077,7 @@ impl Tool {
                 let os_details = match os {
                     "linux" => {
                         // Try to get distribution info
-                        let distro = fs::read_to_string("/etc/os-release")
+                        let distro = std::fs::read_to_string("/etc/os-release")
                             .ok()
                             .and_then(|content| {
                                 content.lines()
@@ -1023,83 +2118,90 @@ impl Tool {
 
             // Enhanced File Operations
             Tool::CopyFile { source, destination } => {
-                fs::copy(source, destination)?;
+                fs::copy(ctx.workspace.resolve(source)?, ctx.workspace.resolve(destination)?).await?;
                 Ok(format!("Successfully copied '{}' to '{}'", source, destination))
             }
 
             Tool::MoveFile { source, destination } => {
-                fs::rename(source, destination)?;
+                fs::rename(ctx.workspace.resolve(source)?, ctx.workspace.resolve(destination)?).await?;
                 Ok(format!("Successfully moved '{}' to '{}'", source, destination))
             }
 
             Tool::RenameFile { old_name, new_name } => {
-                fs::rename(old_name, new_name)?;
+                fs::rename(ctx.workspace.resolve(old_name)?, ctx.workspace.resolve(new_name)?).await?;
                 Ok(format!("Successfully renamed '{}' to '{}'", old_name, new_name))
             }
         }
     }
 
     // Helper methods for code execution
-    fn execute_python(code: &str) -> Result<String, io::Error> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-        let temp_file = format!("/tmp/temp_code_{}.py", timestamp);
-        fs::write(&temp_file, code)?;
-        let output = Command::new("python3").arg(&temp_file).output()?;
-        let _ = fs::remove_file(temp_file);
+    async fn execute_python(code: &str, ctx: &ToolContext) -> Result<String, io::Error> {
+        let dir = code_dir()?;
+        let temp_file = dir.path().join("main.py");
+        fs::write(&temp_file, code).await?;
+        let output = ctx
+            .run(
+                "EXECUTE_CODE",
+                Command::new("python3")
+                    .arg(&temp_file)
+                    .current_dir(ctx.workspace.root()),
+            )
+            .await?;
         if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            Ok(ctx.clip(&output.stdout))
         } else {
             Ok(format!(
                 "Python execution failed:\n{}",
-                String::from_utf8_lossy(&output.stderr)
+                ctx.clip(&output.stderr)
             ))
         }
     }
 
-    fn execute_javascript(code: &str) -> Result<String, io::Error> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-        let temp_file = format!("/tmp/temp_code_{}.js", timestamp);
-        fs::write(&temp_file, code)?;
-        let output = Command::new("node").arg(&temp_file).output()?;
-        let _ = fs::remove_file(temp_file);
+    async fn execute_javascript(code: &str, ctx: &ToolContext) -> Result<String, io::Error> {
+        let dir = code_dir()?;
+        let temp_file = dir.path().join("main.js");
+        fs::write(&temp_file, code).await?;
+        let output = ctx
+            .run(
+                "EXECUTE_CODE",
+                Command::new("node")
+                    .arg(&temp_file)
+                    .current_dir(ctx.workspace.root()),
+            )
+            .await?;
         if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            Ok(ctx.clip(&output.stdout))
         } else {
             Ok(format!(
                 "JavaScript execution failed:\n{}",
-                String::from_utf8_lossy(&output.stderr)
+                ctx.clip(&output.stderr)
             ))
         }
     }
 
-    fn execute_bash(code: &str) -> Result<String, io::Error> {
-        let output = Command::new("bash").arg("-c").arg(code).output()?;
+    async fn execute_bash(code: &str, ctx: &ToolContext) -> Result<String, io::Error> {
+        let output = ctx
+            .run(
+                "EXECUTE_CODE",
+                Command::new("bash")
+                    .arg("-c")
+                    .arg(code)
+                    .current_dir(ctx.workspace.root()),
+            )
+            .await?;
         if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            Ok(ctx.clip(&output.stdout))
         } else {
             Ok(format!(
                 "Bash execution failed:\n{}",
-                String::from_utf8_lossy(&output.stderr)
+                ctx.clip(&output.stderr)
             ))
         }
     }
 
-    fn execute_rust(code: &str) -> Result<String, io::Error> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-        let temp_dir = format!("/tmp/rust_code_{}", timestamp);
-        fs::create_dir_all(&temp_dir)?;
-        let main_rs = format!("{}/src/main.rs", temp_dir);
-        let cargo_toml = format!("{}/Cargo.toml", temp_dir);
-
+    async fn execute_rust(code: &str, ctx: &ToolContext) -> Result<String, io::Error> {
+        let dir = code_dir()?;
+        let cargo_toml = dir.path().join("Cargo.toml");
         fs::write(
             &cargo_toml,
             r#"[package]
@@ -1108,86 +2210,95 @@ version = "0.1.0"
 edition = "2021"
 [dependencies]
 "#,
-        )?;
-        fs::create_dir_all(format!("{}/src", temp_dir))?;
-        fs::write(&main_rs, code)?;
+        )
+        .await?;
+        fs::create_dir_all(dir.path().join("src")).await?;
+        fs::write(dir.path().join("src").join("main.rs"), code).await?;
         let mut cmd = Command::new("cargo");
-        cmd.arg("run").current_dir(&temp_dir);
-        let output = cmd.output()?;
-        let _ = fs::remove_dir_all(temp_dir);
+        cmd.arg("run")
+            .arg("--manifest-path")
+            .arg(&cargo_toml)
+            .current_dir(ctx.workspace.root());
+        let output = ctx.run("EXECUTE_CODE", &mut cmd).await?;
         if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            Ok(ctx.clip(&output.stdout))
         } else {
             Ok(format!(
                 "Rust execution failed:\n{}",
-                String::from_utf8_lossy(&output.stderr)
+                ctx.clip(&output.stderr)
             ))
         }
     }
 
-    fn execute_go(code: &str) -> Result<String, io::Error> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-        let temp_file = format!("/tmp/temp_code_{}.go", timestamp);
-        fs::write(&temp_file, format!("package main\n\n{}", code))?;
-        let output = Command::new("go").arg("run").arg(&temp_file).output()?;
-        let _ = fs::remove_file(temp_file);
+    async fn execute_go(code: &str, ctx: &ToolContext) -> Result<String, io::Error> {
+        let dir = code_dir()?;
+        let temp_file = dir.path().join("main.go");
+        fs::write(&temp_file, format!("package main\n\n{}", code)).await?;
+        let output = ctx
+            .run(
+                "EXECUTE_CODE",
+                Command::new("go")
+                    .arg("run")
+                    .arg(&temp_file)
+                    .current_dir(ctx.workspace.root()),
+            )
+            .await?;
         if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            Ok(ctx.clip(&output.stdout))
         } else {
             Ok(format!(
                 "Go execution failed:\n{}",
-                String::from_utf8_lossy(&output.stderr)
+                ctx.clip(&output.stderr)
             ))
         }
     }
 
-    fn execute_java(code: &str) -> Result<String, io::Error> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-        let temp_file = format!("/tmp/temp_code_{}.java", timestamp);
+    async fn execute_java(code: &str, ctx: &ToolContext) -> Result<String, io::Error> {
+        let dir = code_dir()?;
         let class_name = "TempCode";
+        // javac wants a public class in a file of the same name
+        let temp_file = dir.path().join(format!("{}.java", class_name));
         let full_code = format!("public class {} {{\n    public static void main(String[] args) {{\n        {}\n    }}\n}}", class_name, code);
-        fs::write(&temp_file, full_code)?;
-        let compile_output = Command::new("javac").arg(&temp_file).output()?;
-        if compile_output.status.success() {
-            let run_output = Command::new("java")
-                .arg("-cp")
-                .arg("/tmp")
-                .arg(class_name)
-                .output()?;
-            let _ = fs::remove_file(temp_file);
-            let _ = fs::remove_file(format!("/tmp/{}.class", class_name));
-            if run_output.status.success() {
-                Ok(String::from_utf8_lossy(&run_output.stdout).to_string())
-            } else {
-                Ok(format!(
-                    "Java execution failed:\n{}",
-                    String::from_utf8_lossy(&run_output.stderr)
-                ))
-            }
+        fs::write(&temp_file, full_code).await?;
+        let compile_output = ctx
+            .run("EXECUTE_CODE", Command::new("javac").arg(&temp_file))
+            .await?;
+        if !compile_output.status.success() {
+            return Ok(format!(
+                "Java compilation failed:\n{}",
+                ctx.clip(&compile_output.stderr)
+            ));
+        }
+        let run_output = ctx
+            .run(
+                "EXECUTE_CODE",
+                Command::new("java")
+                    .arg("-cp")
+                    .arg(dir.path())
+                    .arg(class_name)
+                    .current_dir(ctx.workspace.root()),
+            )
+            .await?;
+        if run_output.status.success() {
+            Ok(ctx.clip(&run_output.stdout))
         } else {
-            let _ = fs::remove_file(temp_file);
             Ok(format!(
-                "Java compilation failed:\n{}",
-                String::from_utf8_lossy(&compile_output.stderr)
+                "Java execution failed:\n{}",
+                ctx.clip(&run_output.stderr)
             ))
         }
     }
 
-    fn execute_c_cpp(code: &str, language: &str) -> Result<String, io::Error> {
+    async fn execute_c_cpp(
+        code: &str,
+        language: &str,
+        ctx: &ToolContext,
+    ) -> Result<String, io::Error> {
         let is_cpp = matches!(language.to_lowercase().as_str(), "cpp" | "c++");
         let extension = if is_cpp { "cpp" } else { "c" };
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-        let temp_source = format!("/tmp/temp_code_{}.{}", timestamp, extension);
-        let temp_exe = format!("/tmp/temp_exe_{}", timestamp);
+        let dir = code_dir()?;
+        let temp_source = dir.path().join(format!("main.{}", extension));
+        let temp_exe = dir.path().join("main");
 
         let full_code = if is_cpp {
             format!(
@@ -1198,56 +2309,438 @@ edition = "2021"
             format!("#include <stdio.h>\nint main() {{\n{}\nreturn 0;\n}}", code)
         };
 
-        fs::write(&temp_source, full_code)?;
+        fs::write(&temp_source, full_code).await?;
         let compiler = if is_cpp { "g++" } else { "gcc" };
-        let compile_output = Command::new(compiler)
-            .arg(&temp_source)
-            .arg("-o")
-            .arg(&temp_exe)
-            .output()?;
-        if compile_output.status.success() {
-            let run_output = Command::new(&temp_exe).output()?;
-            let _ = fs::remove_file(temp_source);
-            let _ = fs::remove_file(temp_exe);
-            if run_output.status.success() {
-                Ok(String::from_utf8_lossy(&run_output.stdout).to_string())
-            } else {
-                Ok(format!(
-                    "{} execution failed:\n{}",
-                    if is_cpp { "C++" } else { "C" },
-                    String::from_utf8_lossy(&run_output.stderr)
-                ))
-            }
+        let compile_output = ctx
+            .run(
+                "EXECUTE_CODE",
+                Command::new(compiler)
+                    .arg(&temp_source)
+                    .arg("-o")
+                    .arg(&temp_exe),
+            )
+            .await?;
+        if !compile_output.status.success() {
+            return Ok(format!(
+                "{} compilation failed:\n{}",
+                if is_cpp { "C++" } else { "C" },
+                ctx.clip(&compile_output.stderr)
+            ));
+        }
+        let run_output = ctx
+            .run(
+                "EXECUTE_CODE",
+                Command::new(&temp_exe).current_dir(ctx.workspace.root()),
+            )
+            .await?;
+        if run_output.status.success() {
+            Ok(ctx.clip(&run_output.stdout))
         } else {
-            let _ = fs::remove_file(temp_source);
             Ok(format!(
-                "{} compilation failed:\n{}",
+                "{} execution failed:\n{}",
                 if is_cpp { "C++" } else { "C" },
-                String::from_utf8_lossy(&compile_output.stderr)
+                ctx.clip(&run_output.stderr)
             ))
         }
     }
 }
 
-#[derive(Clone)]
-pub struct Agent {
-    messages: Vec<Message>,
-}
-
-impl Default for Agent {
-    fn default() -> Self {
-        Self::new()
-    }
+/// A fresh directory in the system temp dir for the files of one
+/// EXECUTE_CODE run. It is removed when dropped, so also when the run fails,
+/// times out or is cancelled.
+fn code_dir() -> io::Result<tempfile::TempDir> {
+    tempfile::Builder::new().prefix("rct_code_").tempdir()
 }
 
-impl Agent {
-    pub fn new() -> Self {
-        Self { messages: vec![] }
-    }
+/// Patches every file of a unified diff in memory, then writes them all.
+/// Nothing is written unless every hunk of every file applies.
+async fn apply_patch(ctx: &ToolContext, text: &str) -> io::Result<String> {
+    let files = patch::parse(text)?;
+    let mut changes: Vec<(PathBuf, Option<String>)> = Vec::new();
+    let mut summary = Vec::new();
+    let mut failures = Vec::new();
+    for file in &files {
+        let old = match &file.old_path {
+            Some(path) => match fs::read_to_string(ctx.workspace.resolve(path)?).await {
+                Ok(content) => content,
+                Err(e) => {
+                    failures.push(format!("{}: could not read it: {}", path, e));
+                    continue;
+                }
+            },
+            None => String::new(),
+        };
+        if let Some(path) = file
+            .new_path
+            .as_ref()
+            .filter(|&new| file.old_path.as_ref() != Some(new))
+        {
+            if fs::try_exists(ctx.workspace.resolve(path)?).await? {
+                failures.push(format!("{}: already exists", path));
+                continue;
+            }
+        }
+        let patched = match file.apply(&old) {
+            Ok(patched) => patched,
+            Err(rejected) => {
+                let hunks: Vec<String> = rejected.iter().map(|hunk| hunk.to_string()).collect();
+                failures.push(format!(
+                    "{}: {} of {} hunks rejected\n\n{}",
+                    file.path(),
+                    rejected.len(),
+                    file.hunks.len(),
+                    hunks.join("\n\n")
+                ));
+                continue;
+            }
+        };
 
-    fn get_system_prompt() -> String {
-        // Load custom prompt if it exists
-        let custom_prompt = fs::read_to_string("prompt.md")
+        let mut notes = Vec::new();
+        if patched.moved > 0 {
+            notes.push(format!("{} at an offset", patched.moved));
+        }
+        if patched.fuzzy > 0 {
+            notes.push(format!("{} with fuzz", patched.fuzzy));
+        }
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!(", {}", notes.join(", "))
+        };
+        match (&file.old_path, &file.new_path) {
+            (None, Some(new)) => {
+                summary.push(format!("  A {}", new));
+                changes.push((ctx.workspace.resolve(new)?, Some(patched.content)));
+            }
+            (Some(old), None) => {
+                summary.push(format!("  D {}", old));
+                changes.push((ctx.workspace.resolve(old)?, None));
+            }
+            (Some(old), Some(new)) if old != new => {
+                summary.push(format!(
+                    "  R {} -> {} ({} hunks{})",
+                    old,
+                    new,
+                    file.hunks.len(),
+                    notes
+                ));
+                changes.push((ctx.workspace.resolve(new)?, Some(patched.content)));
+                changes.push((ctx.workspace.resolve(old)?, None));
+            }
+            _ => {
+                summary.push(format!(
+                    "  M {} ({} hunks{})",
+                    file.path(),
+                    file.hunks.len(),
+                    notes
+                ));
+                changes.push((ctx.workspace.resolve(file.path())?, Some(patched.content)));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "The patch was not applied, so no files were changed. {} of {} files failed:\n\n{}",
+                failures.len(),
+                files.len(),
+                failures.join("\n\n")
+            ),
+        ));
+    }
+
+    write_all_or_nothing(&changes).await?;
+    let written: Vec<_> = changes
+        .iter()
+        .filter_map(|(path, content)| Some((path.as_path(), Some(content.as_deref()?))))
+        .collect();
+    let note = ctx.verification_note(&written).await;
+    Ok(format!(
+        "Patched {} files:\n{}{}",
+        files.len(),
+        summary.join("\n"),
+        note
+    ))
+}
+
+/// Writes every file, or removes it for `None`. If one fails, the files
+/// already changed are put back the way they were, and the directories
+/// created for new files are removed again.
+async fn write_all_or_nothing(changes: &[(PathBuf, Option<String>)]) -> io::Result<()> {
+    let mut done: Vec<(&Path, Option<Vec<u8>>)> = Vec::new();
+    // Outermost first, so they can be removed in reverse
+    let mut created: Vec<&Path> = Vec::new();
+    for (path, content) in changes {
+        let before = fs::read(path).await.ok();
+        let result = match content {
+            Some(content) => match path.parent() {
+                Some(parent) => {
+                    let mut missing = Vec::new();
+                    for dir in parent.ancestors() {
+                        if fs::metadata(dir).await.is_ok() {
+                            break;
+                        }
+                        missing.push(dir);
+                    }
+                    created.extend(missing.into_iter().rev());
+                    match fs::create_dir_all(parent).await {
+                        Ok(()) => fs::write(path, content).await,
+                        Err(e) => Err(e),
+                    }
+                }
+                None => fs::write(path, content).await,
+            },
+            None => fs::remove_file(path).await,
+        };
+        if let Err(e) = result {
+            for (path, before) in done.into_iter().rev() {
+                let _ = match before {
+                    Some(before) => fs::write(path, before).await,
+                    None => fs::remove_file(path).await,
+                };
+            }
+            // Only empty directories go, so nothing else put there is lost
+            for dir in created.into_iter().rev() {
+                let _ = fs::remove_dir(dir).await;
+            }
+            return Err(e);
+        }
+        done.push((path, before));
+    }
+    Ok(())
+}
+
+/// One path per line, up to `max` (by default `search::DEFAULT_MAX_RESULTS`).
+fn list_files(files: &[String], max: Option<usize>) -> String {
+    let max = max.unwrap_or(search::DEFAULT_MAX_RESULTS).max(1);
+    let mut listed = files
+        .iter()
+        .take(max)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if files.len() > max {
+        listed.push_str(&format!(
+            "\n... {} more files not shown (max_results: {})",
+            files.len() - max,
+            max
+        ));
+    }
+    listed
+}
+
+/// Where the user-level memory store lives, unless `user_memory` is off.
+fn user_memory_file(agent: &AgentConfig) -> Option<PathBuf> {
+    agent.user_memory.then(Memories::user_file).flatten()
+}
+
+/// The context budget of the model requests are sent to. The model is only
+/// resolved when the config doesn't give the window size; if the endpoint
+/// can't name it, the configured name is guessed from as before.
+async fn context_budget(config: &LlmConfig) -> ContextBudget {
+    if config.context_window.is_some() || config.ollama.num_ctx.is_some() {
+        return ContextBudget::for_config(config, &config.model_name);
+    }
+    let model = llm::active_model(config)
+        .await
+        .unwrap_or_else(|_| config.model_name.clone());
+    ContextBudget::for_config(config, &model)
+}
+
+/// Runs file system walks and other long synchronous work off the runtime.
+async fn blocking<T, F>(work: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)))
+}
+
+/// SEARCH_INDEX results shown before the rest are counted.
+const MAX_INDEX_HITS: usize = 50;
+
+/// Lines of each SEMANTIC_SEARCH result shown.
+const SEMANTIC_EXCERPT_LINES: usize = 3;
+
+/// User turns kept verbatim when the conversation is compacted.
+const RECENT_TURNS: usize = 2;
+
+/// Older tool results are cut down to this many characters first.
+const OLD_TOOL_RESULT_CHARS: usize = 2_000;
+
+/// Live output lines shown in the tool log per tool run.
+const MAX_LIVE_LINES: usize = 1_000;
+
+/// Longer live output lines are cut to this many characters.
+const MAX_LIVE_LINE_CHARS: usize = 500;
+
+const TEXT_TOOL_FORMAT: &str = r#"## TOOL CALLING FORMAT
+
+**PRIMARY FORMAT: Use JSON tool calls for maximum reliability:**
+```json
+TOOL: {"name": "READ_FILE", "parameters": {"path": "/path/to/file.txt"}}
+TOOL: {"name": "CREATE_PLAN", "parameters": {"task": "Task description", "steps": ["Step 1", "Step 2", "Step 3"]}}
+```
+
+**Legacy format (still supported):**
+```
+TOOL: READ_FILE /path/to/file.txt
+TOOL: CREATE_PLAN "Task description" "Step 1" "Step 2" "Step 3"
+```
+
+**CRITICAL RULES FOR TOOL EXECUTION:**
+1. Put ONLY the tool call on a line starting with "TOOL:"
+2. Do NOT add "ACTION:" or any prefix before "TOOL:"
+3. Do NOT show placeholder text like "[Tool executes and returns result]"
+4. The tool will execute automatically - you don't need to describe the execution
+5. After the tool executes, you will receive the result and can reason about it
+6. Independent calls (e.g. reading several files) can go on separate TOOL: lines in one response - they run in order and all results come back together
+
+**CORRECT:**
+```
+TOOL: {"name": "GET_TIME", "parameters": {}}
+```
+
+**INCORRECT:**
+```
+ACTION: TOOL: {"name": "GET_TIME", "parameters": {}}
+```
+
+**INCORRECT:**
+```
+TOOL: {"name": "GET_TIME", "parameters": {}}
+[Tool executes and returns result]
+```
+
+"#;
+
+const NATIVE_TOOL_FORMAT: &str = r#"## TOOL CALLING FORMAT
+
+**Tools are provided through the function-calling interface.** Call them as functions with JSON arguments. Do NOT write `TOOL:` lines in your reply - the `TOOL: {...}` snippets in this prompt only illustrate tool names and their parameters.
+
+**CRITICAL RULES FOR TOOL EXECUTION:**
+1. Invoke tools only through function calls, never as text
+2. Do NOT show placeholder text like "[Tool executes and returns result]"
+3. The tool will execute automatically - you don't need to describe the execution
+4. After the tool executes, you will receive the result and can reason about it
+5. Independent calls (e.g. reading several files) can be made together in one turn - they run in order and all results come back together
+
+"#;
+
+#[derive(Clone)]
+pub struct Agent {
+    messages: Vec<Message>,
+    permissions: Permissions,
+    /// The symbol index the repository map is built from, for the
+    /// workspace it was built for; kept between turns so only changed
+    /// files are parsed again
+    repo_index: Option<(PathBuf, Index)>,
+}
+
+impl Default for Agent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Agent {
+    pub fn new() -> Self {
+        // The rules are read from the workspace on the first run
+        Self {
+            messages: vec![],
+            permissions: Permissions::default(),
+            repo_index: None,
+        }
+    }
+
+    /// Tells the model that the user rolled back some of its changes, so it
+    /// doesn't assume they are still there.
+    pub fn note_undone(&mut self, undone: &[Checkpoint]) {
+        // A new conversation has no changes to forget
+        if self.messages.is_empty() {
+            return;
+        }
+        let changes: Vec<String> = undone.iter().map(|c| format!("- {}", c.label)).collect();
+        self.messages.push(Message {
+            role: "user".to_string(),
+            content: format!(
+                "[Note] I undid these tool calls; the files are back to how they were before them:\n{}",
+                changes.join("\n")
+            ),
+            ..Default::default()
+        });
+    }
+
+    /// The repository map for the system prompt, within `repo_map_tokens`
+    /// and a tenth of the context window. None if it is turned off or can't
+    /// be built, or if `cancel` fires first.
+    async fn repo_map(&mut self, config: &Config, cancel: &CancellationToken) -> Option<String> {
+        let budget = context_budget(&config.llm).await;
+        let tokens = config
+            .agent
+            .repo_map_tokens
+            .min((budget.limit() / 10) as usize);
+        if tokens == 0 {
+            return None;
+        }
+        let root = Workspace::from_config(&config.agent)
+            .ok()?
+            .root()
+            .to_path_buf();
+        // Starts from the index INDEX_CODEBASE saved, if any, but never
+        // writes one into the workspace itself
+        let mut index = match self.repo_index.take() {
+            Some((indexed, index)) if indexed == root => index,
+            _ => Index::load(&root),
+        };
+        let build = blocking(move || {
+            let map = repomap::refresh(&root, &mut index, tokens);
+            Ok((root, index, map))
+        });
+        let (root, index, map) = tokio::select! {
+            built = build => built.ok()?,
+            _ = cancel.cancelled() => return None,
+        };
+        self.repo_index = Some((root, index));
+        map.ok()?
+    }
+
+    /// The saved memories that best match `prompt`, to put in front of it.
+    /// None if none match or `recall_memories` is 0.
+    async fn recall_memories(config: &Config, prompt: &str) -> Option<String> {
+        let limit = config.agent.recall_memories;
+        if limit == 0 || prompt.trim().is_empty() {
+            return None;
+        }
+        let root = Workspace::from_config(&config.agent)
+            .ok()?
+            .root()
+            .to_path_buf();
+        let memories = Memories::new(&root, user_memory_file(&config.agent));
+        let prompt = prompt.to_string();
+        let recalled = blocking(move || memories.recall(&prompt, limit))
+            .await
+            .ok()?;
+        if recalled.is_empty() {
+            return None;
+        }
+        let mut message = format!(
+            "{}\n\nSaved facts that may bear on this request. Use FORGET on any that are no longer true:\n",
+            memory::RECALL_HEADER
+        );
+        for (_, memory) in &recalled {
+            message.push_str(&format!("- {}\n", memory));
+        }
+        Some(message)
+    }
+
+    /// The system prompt, with `prompt.md` appended when there is one.
+    pub fn get_system_prompt(protocol: ToolProtocol) -> String {
+        // Load custom prompt if it exists
+        let custom_prompt = std::fs::read_to_string("prompt.md")
             .ok()
             .map(|content| format!("\n\n## CUSTOM USER INSTRUCTIONS\n\n{}\n", content))
             .unwrap_or_default();
@@ -1282,30 +2775,36 @@ The tool will execute automatically and you will receive the result. Then you ca
 3. **CLEAR_PLAN** - Remove plan.md when task is fully completed
 
 ### File Operations
-4. **READ_FILE** `<path>` - Read and display file contents with line numbers
+4. **READ_FILE** `<path> [offset] [limit]` - Read a file with numbered lines, 2000 at a time; use offset (first line, from 1) and limit for large files. The numbers are not part of the file, so leave them out of SEARCH_REPLACE text
 5. **WRITE_FILE** `<path> <content>` - Create or overwrite files (creates parent directories automatically)
 6. **APPEND_FILE** `<path> <content>` - Add content to the end of existing files
-7. **SEARCH_REPLACE** `<path> <old_string> <new_string>` - Replace text in files with exact matching
-8. **DELETE_FILE** `<path>` - Remove files or directories (recursive for directories)
+7. **SEARCH_REPLACE** `<path> <old_string> <new_string> [replace_all] [ignore_whitespace]` - Replace text in files with exact matching. old_string must match exactly once; include enough surrounding lines to make it unique, or set replace_all to change every match. ignore_whitespace matches text that differs only in indentation or spacing
+8. **MULTI_EDIT** `<path> <edits>` - Apply several SEARCH_REPLACE edits (old_string, new_string, optional replace_all and ignore_whitespace) to one file in order; if any edit fails, none are applied
+9. **APPLY_PATCH** `<patch>` - Apply a unified diff (---/+++ headers, @@ hunks) that may create, change or delete several files. Hunks may be a few lines off; if any hunk fails, no file changes and the rejected hunks come back with the file lines around them
+10. **DELETE_FILE** `<path>` - Remove files or directories (recursive for directories)
 
 ### Directory Operations
-9. **LIST_FILES** `<path>` - List files and directories in a given path
-10. **LIST_FILES_RECURSIVE** `<path>` - Recursively list all files in directory tree
-11. **CREATE_DIRECTORY** `<path>` - Create directories (recursive)
+11. **LIST_FILES** `<path>` - List files and directories in a given path
+12. **LIST_FILES_RECURSIVE** `<path>` - Recursively list all files in directory tree
+13. **CREATE_DIRECTORY** `<path>` - Create directories (recursive)
 
 ### Search & Navigation
-12. **GREP_SEARCH** `<pattern> [path]` - Search for text patterns using ripgrep (fast, regex support, case-insensitive)
-13. **GLOB_SEARCH** `<pattern>` - Find files matching glob patterns (*.rs, **/test/**, etc.)
-14. **FUZZY_FIND** `<pattern> [path]` - Fuzzy search for file paths (e.g. "user" matches "src/user_model.rs")
-15. **INDEX_CODEBASE** `<path>` - Scan directory and build a symbol index (functions, classes)
-16. **SEARCH_INDEX** `<query>` - Search the built index for symbols
-17. **READ_URL** `<url>` - Fetch and read the content of a web page
-18. **SEARCH_WEB** `<query>` - Search the web for information (default: DuckDuckGo)
-19. **REMEMBER** `<fact>` - Save an important fact or decision to project memory (.agent_memory.md)
-20. **RECALL** - Retrieve all saved project memory
+14. **GREP_SEARCH** `<pattern> [path] [glob] [file_type] [context] [case_sensitive] [max_results] [sort]` - Regex search of file contents, case-insensitive by default; skips .gitignored files such as target/ and node_modules/
+15. **GLOB_SEARCH** `<pattern> [path] [max_results] [sort]` - Find files matching glob patterns (*.rs, src/**/test_*.py, etc.); sort "modified" lists recently changed files first
+16. **FUZZY_FIND** `<pattern> [path]` - Fuzzy search for file paths (e.g. "user" matches "src/user_model.rs")
+17. **SEMANTIC_SEARCH** `<query> [path] [max_results]` - Find code or docs for a plain-language question ("where do we parse the config"); returns the best-matching definitions as file:line ranges with their key lines. Use it when you don't know a symbol's name; use SEARCH_INDEX or GREP_SEARCH when you do
+18. **INDEX_CODEBASE** `<path>` - Parse a directory's source files (Rust, Python, JS/TS, Go, Java, C) and index definitions and call sites (honours .gitignore; re-runs only re-read changed files)
+19. **SEARCH_INDEX** `<query> [callers]` - Where a symbol is defined (name, fragment or Type::method; file:line hits, best first), or with callers=true, who calls it
+20. **READ_URL** `<url>` - Fetch and read the content of a web page
+21. **SEARCH_WEB** `<query>` - Search the web for information (default: DuckDuckGo)
+
+### Memory
+22. **REMEMBER** `<fact> [tags] [scope]` - Save an important fact or decision, with tags to find it by; scope "user" keeps the user's preferences for every project. Memories matching a prompt are shown with it
+23. **RECALL** `[query]` - Search saved memories, best matches first; without a query, list the most recently used
+24. **FORGET** `<id>` - Delete a memory (id as shown by RECALL, e.g. p3) that is wrong or out of date
 
 ### Code Execution & Compilation
-21. **EXECUTE_CODE** `<language> <code>` - Execute code in multiple languages:
+25. **EXECUTE_CODE** `<language> <code>` - Execute code in multiple languages:
     - Python (python, py)
     - JavaScript/Node.js (javascript, js, node)
     - Bash/Shell (bash, sh)
@@ -1313,41 +2812,41 @@ The tool will execute automatically and you will receive the result. Then you ca
     - Go (go)
     - Java (java)
     - C/C++ (c, cpp, c++)
-22. **RUN_COMMAND** `<command>` - Execute shell commands with full environment access
+26. **RUN_COMMAND** `<command>` - Execute shell commands with full environment access
 
 ### Development Workflow
-23. **GIT_STATUS** - Show git repository status
-24. **GIT_DIFF** - Show unstaged changes
-25. **GIT_COMMIT** `<message>` - Commit changes with message
-26. **GIT_LOG** `[count]` - Show recent commit history
+27. **GIT_STATUS** - Show git repository status
+28. **GIT_DIFF** - Show unstaged changes
+29. **GIT_COMMIT** `<message>` - Commit changes with message
+30. **GIT_LOG** `[count]` - Show recent commit history
 
 ### Quality Assurance
-27. **RUN_LINT** `<language> [path]` - Run linters for code quality:
+31. **RUN_LINT** `<language> [path]` - Run linters for code quality:
     - Rust: cargo clippy
     - Python: flake8/pylint
     - JavaScript: eslint
     - Go: golangci-lint
-28. **RUN_TESTS** `<framework> [path]` - Run test suites:
+32. **RUN_TESTS** `<framework> [path]` - Run test suites:
     - Rust: cargo test
     - Python: pytest/unittest
     - JavaScript: jest/mocha
     - Go: go test
 
 ### Package Management
-29. **INSTALL_PACKAGE** `<manager> <package>` - Install packages:
+33. **INSTALL_PACKAGE** `<manager> <package>` - Install packages:
     - npm install <package>
     - cargo add <package>
     - pip install <package>
     - go get <package>
 
 ### System Information
-30. **GET_TIME** - Get current date, time, and timezone information from the system
-31. **GET_OS_INFO** - Get operating system details (OS type, architecture, shell, path separators)
+34. **GET_TIME** - Get current date, time, and timezone information from the system
+35. **GET_OS_INFO** - Get operating system details (OS type, architecture, shell, path separators)
 
 ### Enhanced File Operations
-32. **COPY_FILE** `<source> <destination>` - Copy a file from source to destination
-33. **MOVE_FILE** `<source> <destination>` - Move/relocate a file or directory
-34. **RENAME_FILE** `<old_name> <new_name>` - Rename a file or directory
+36. **COPY_FILE** `<source> <destination>` - Copy a file from source to destination
+37. **MOVE_FILE** `<source> <destination>` - Move/relocate a file or directory
+38. **RENAME_FILE** `<old_name> <new_name>` - Rename a file or directory
 
 ## PLANNING WORKFLOW - REQUIRED FOR COMPLEX TASKS
 
@@ -1408,44 +2907,7 @@ Complex: "Add authentication" -> CREATE_PLAN first
 
 **CRITICAL: If you receive a complex task, your FIRST tool call MUST be CREATE_PLAN. Do not execute any other tools until the plan is created.**
 
-## TOOL CALLING FORMAT
-
-**PRIMARY FORMAT: Use JSON tool calls for maximum reliability:**
-```json
-TOOL: {{"name": "READ_FILE", "parameters": {{"path": "/path/to/file.txt"}}}}
-TOOL: {{"name": "CREATE_PLAN", "parameters": {{"task": "Task description", "steps": ["Step 1", "Step 2", "Step 3"]}}}}
-```
-
-**Legacy format (still supported):**
-```
-TOOL: READ_FILE /path/to/file.txt
-TOOL: CREATE_PLAN "Task description" "Step 1" "Step 2" "Step 3"
-```
-
-**CRITICAL RULES FOR TOOL EXECUTION:**
-1. Put ONLY the tool call on a line starting with "TOOL:"
-2. Do NOT add "ACTION:" or any prefix before "TOOL:"
-3. Do NOT show placeholder text like "[Tool executes and returns result]"
-4. The tool will execute automatically - you don't need to describe the execution
-5. After the tool executes, you will receive the result and can reason about it
-
-**CORRECT:**
-```
-TOOL: {{"name": "GET_TIME", "parameters": {{}}}}
-```
-
-**INCORRECT:**
-```
-ACTION: TOOL: {{"name": "GET_TIME", "parameters": {{}}}}
-```
-
-**INCORRECT:**
-```
-TOOL: {{"name": "GET_TIME", "parameters": {{}}}}
-[Tool executes and returns result]
-```
-
-## CODING WORKFLOW PRINCIPLES
+{}## CODING WORKFLOW PRINCIPLES
 
 ### 1. Task Assessment Phase
 - **Determine task complexity**: If task requires 3+ steps or multiple files -> USE CREATE_PLAN
@@ -1459,6 +2921,7 @@ TOOL: {{"name": "GET_TIME", "parameters": {{}}}}
 
 ### 3. Code Understanding Phase
 - Use GREP_SEARCH to find code definitions, references, or TODOs
+- Use SEMANTIC_SEARCH when you can describe the code but don't know its names
 - Use FUZZY_FIND to jump to specific files
 - READ_FILE to examine the code context and logic
 - Analyze the code structure before making changes
@@ -1471,6 +2934,7 @@ TOOL: {{"name": "GET_TIME", "parameters": {{}}}}
 
 ### 5. Implementation Phase
 - Use SEARCH_REPLACE for precise edits (prefer over WRITE_FILE for modifications)
+- Use MULTI_EDIT for several edits to one file, APPLY_PATCH for changes across files
 - APPEND_FILE for adding to existing files
 - Verify changes with READ_FILE
 - Test modifications with EXECUTE_CODE or RUN_COMMAND
@@ -1486,7 +2950,7 @@ TOOL: {{"name": "GET_TIME", "parameters": {{}}}}
 - Use CLEAR_PLAN when all steps are done
 
 ### 8. Error Recovery
-- If SEARCH_REPLACE fails, check exact string matching
+- If SEARCH_REPLACE fails, READ_FILE again and copy the text exactly; if it matched several times, add surrounding lines
 - If EXECUTE_CODE fails, try RUN_COMMAND with compilation
 - If RUN_COMMAND fails, simplify the command or check permissions
 - Always verify file existence with LIST_FILES before operations
@@ -1521,6 +2985,7 @@ TOOL: {{"name": "GET_OS_INFO", "parameters": {{}}}}
 TOOL: {{"name": "COPY_FILE", "parameters": {{"source": "file.txt", "destination": "backup/file.txt"}}}}
 TOOL: {{"name": "MOVE_FILE", "parameters": {{"source": "old/path/file.txt", "destination": "new/path/file.txt"}}}}
 TOOL: {{"name": "RENAME_FILE", "parameters": {{"old_name": "oldname.txt", "new_name": "newname.txt"}}}}
+TOOL: {{"name": "MULTI_EDIT", "parameters": {{"path": "src/lib.rs", "edits": [{{"old_string": "fn old_name(", "new_string": "fn new_name("}}, {{"old_string": "old_name(1)", "new_string": "new_name(1)", "replace_all": true}}]}}}}
 
 ## OS-ADAPTIVE EXECUTION
 
@@ -1547,7 +3012,7 @@ TOOL: {{"name": "GET_OS_INFO", "parameters": {{}}}}
 
 - **PLAN FIRST for complex tasks** - Always use CREATE_PLAN as your FIRST tool call for tasks requiring 3+ steps
 - **ALWAYS use tools** - Never describe actions without executing them
-- **Use JSON format** - Prefer `TOOL: {{"name": "TOOL_NAME", "parameters": {{...}}}}` over legacy format
+{}
 - **Execute immediately** - Do not explain what you would do, just call the tool
 - **WORK UNTIL COMPLETE** - After each tool result, immediately determine the next action and execute it. Continue this loop until the user's request is fully satisfied. Do NOT stop after just one tool call.
 - **No placeholders** - Never write text like "[Tool executes]" or "[Result will appear]"
@@ -1567,242 +3032,293 @@ TOOL: {{"name": "GET_OS_INFO", "parameters": {{}}}}
 3. MOST IMPORTANTLY: After receiving a tool result, IMMEDIATELY proceed with the next step. Do not wait for user input. Continue working through the task until it is COMPLETE.
 **{}
 "#,
+            Self::tool_format_section(protocol),
+            Self::tool_call_guideline(protocol),
             custom_prompt
         )
     }
 
-    fn parse_tool_call(&self, response: &str) -> Option<Tool> {
-        let lines: Vec<&str> = response.lines().collect();
-        for line in lines {
-            let trimmed_line = line.trim();
-            if trimmed_line.starts_with("TOOL:") {
-                let tool_part = trimmed_line.strip_prefix("TOOL:").unwrap().trim();
-
-                // Try JSON format first
-                if tool_part.starts_with('{') {
-                    if let Ok(tool_call) = serde_json::from_str::<ToolCall>(tool_part) {
-                        if let Some(tool) = tool_call.into_tool() {
-                            return Some(tool);
-                        }
-                    }
+    fn tool_format_section(protocol: ToolProtocol) -> &'static str {
+        match protocol {
+            ToolProtocol::Native => NATIVE_TOOL_FORMAT,
+            ToolProtocol::Text => TEXT_TOOL_FORMAT,
+        }
+    }
+
+    /// The line of the guidelines that says how to write a tool call.
+    fn tool_call_guideline(protocol: ToolProtocol) -> &'static str {
+        match protocol {
+            ToolProtocol::Native => {
+                "- **Call tools as functions** - Never write `TOOL:` lines; the `TOOL: {...}` snippets in this prompt only show tool names and parameters"
+            }
+            ToolProtocol::Text => {
+                r#"- **Use JSON format** - Prefer `TOOL: {"name": "TOOL_NAME", "parameters": {...}}` over legacy format"#
+            }
+        }
+    }
+
+    /// Collects every `TOOL:` line of a text-protocol response, in order.
+    pub fn parse_tool_calls(response: &str) -> Vec<Tool> {
+        response
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("TOOL:"))
+            .filter_map(|tool_part| Self::parse_tool_line(tool_part.trim()))
+            .collect()
+    }
+
+    fn parse_tool_line(tool_part: &str) -> Option<Tool> {
+        // Try JSON format first
+        if tool_part.starts_with('{') {
+            if let Ok(tool_call) = serde_json::from_str::<ToolCall>(tool_part) {
+                if let Some(tool) = tool_call.into_tool() {
+                    return Some(tool);
                 }
+            }
+        }
 
-                // Fall back to legacy format
-                let parts: Vec<&str> = tool_part.splitn(2, ' ').collect();
-                if parts.len() >= 2 {
-                    let tool_name = parts[0];
-                    let params = parts[1];
-
-                    return match tool_name {
-                        "READ_FILE" => Some(Tool::ReadFile {
-                            path: params.to_string(),
-                        }),
-                        "WRITE_FILE" => {
-                            if let Some(space_pos) = params.find(' ') {
-                                let path = &params[..space_pos];
-                                let content = &params[space_pos + 1..];
-                                Some(Tool::WriteFile {
-                                    path: path.to_string(),
-                                    content: content.to_string(),
-                                })
-                            } else {
-                                None
-                            }
-                        }
-                        "APPEND_FILE" => {
-                            if let Some(space_pos) = params.find(' ') {
-                                let path = &params[..space_pos];
-                                let content = &params[space_pos + 1..];
-                                Some(Tool::AppendFile {
-                                    path: path.to_string(),
-                                    content: content.to_string(),
-                                })
-                            } else {
-                                None
-                            }
-                        }
-                        "SEARCH_REPLACE" => {
-                            let params_str = params.to_string();
-                            // Parse: "path old_string new_string" - need to split carefully
-                            if let Some(first_space) = params_str.find(' ') {
-                                let path = &params_str[..first_space];
-                                let remaining = &params_str[first_space + 1..];
-                                // Find the boundary between old_string and new_string
-                                // This is tricky with the legacy format, but we'll assume old_string comes first
-                                if let Some(last_space) = remaining.rfind(' ') {
-                                    let old_string = &remaining[..last_space];
-                                    let new_string = &remaining[last_space + 1..];
-                                    Some(Tool::SearchReplace {
-                                        path: path.to_string(),
-                                        old_string: old_string.to_string(),
-                                        new_string: new_string.to_string(),
-                                    })
-                                } else {
-                                    None
-                                }
-                            } else {
-                                None
-                            }
-                        }
-                        "RUN_COMMAND" => Some(Tool::RunCommand {
-                            command: params.to_string(),
-                        }),
-                        "LIST_FILES" => Some(Tool::ListFiles {
-                            path: params.to_string(),
-                        }),
-                        "LIST_FILES_RECURSIVE" => Some(Tool::ListFilesRecursive {
-                            path: params.to_string(),
-                        }),
-                        "CREATE_DIRECTORY" => Some(Tool::CreateDirectory {
-                            path: params.to_string(),
-                        }),
-                        "DELETE_FILE" => Some(Tool::DeleteFile {
-                            path: params.to_string(),
-                        }),
-                        "EXECUTE_CODE" => {
-                            if let Some(space_pos) = params.find(' ') {
-                                let language = &params[..space_pos];
-                                let code = &params[space_pos + 1..];
-                                Some(Tool::ExecuteCode {
-                                    language: language.to_string(),
-                                    code: code.to_string(),
-                                })
-                            } else {
-                                None
-                            }
-                        }
-                        "GREP_SEARCH" => {
-                            // Parse: "pattern [path]"
-                            let search_parts: Vec<&str> = params.splitn(2, ' ').collect();
-                            let pattern = search_parts[0].to_string();
-                            let path = search_parts.get(1).map(|s| s.to_string());
-                            Some(Tool::GrepSearch { pattern, path })
-                        }
-                        "GLOB_SEARCH" => Some(Tool::GlobSearch {
-                            pattern: params.to_string(),
-                        }),
-                        "FUZZY_FIND" => {
-                            let parts: Vec<&str> = params.splitn(2, ' ').collect();
-                            let pattern = parts[0].to_string();
-                            let path = parts.get(1).map(|s| s.to_string());
-                            Some(Tool::FuzzyFind { pattern, path })
-                        }
-                        "INDEX_CODEBASE" => Some(Tool::IndexCodebase {
-                            path: params.to_string(),
-                        }),
-                        "SEARCH_INDEX" => Some(Tool::SearchIndex {
-                            query: params.to_string(),
-                        }),
-                        "READ_URL" => Some(Tool::ReadUrl {
-                            url: params.to_string(),
-                        }),
-                        "SEARCH_WEB" => Some(Tool::SearchWeb {
-                            query: params.to_string(),
-                        }),
-                        "REMEMBER" => Some(Tool::Remember {
-                            fact: params.to_string(),
-                        }),
-                        "RECALL" => Some(Tool::Recall),
-                        "GIT_STATUS" => Some(Tool::GitStatus),
-                        "GIT_DIFF" => Some(Tool::GitDiff),
-                        "GIT_COMMIT" => Some(Tool::GitCommit {
-                            message: params.to_string(),
-                        }),
-                        "GIT_LOG" => {
-                            let count = if params.is_empty() {
-                                None
-                            } else {
-                                params.parse::<usize>().ok()
-                            };
-                            Some(Tool::GitLog { count })
-                        }
-                        "RUN_LINT" => {
-                            let lint_parts: Vec<&str> = params.splitn(2, ' ').collect();
-                            let language = lint_parts[0].to_string();
-                            let path = lint_parts.get(1).map(|s| s.to_string());
-                            Some(Tool::RunLint { language, path })
-                        }
-                        "RUN_TESTS" => {
-                            let test_parts: Vec<&str> = params.splitn(2, ' ').collect();
-                            let framework = test_parts[0].to_string();
-                            let path = test_parts.get(1).map(|s| s.to_string());
-                            Some(Tool::RunTests { framework, path })
-                        }
-                        "INSTALL_PACKAGE" => {
-                            let package_parts: Vec<&str> = params.splitn(2, ' ').collect();
-                            if package_parts.len() == 2 {
-                                Some(Tool::InstallPackage {
-                                    manager: package_parts[0].to_string(),
-                                    package: package_parts[1].to_string(),
-                                })
-                            } else {
-                                None
-                            }
-                        }
-                        "CREATE_PLAN" => {
-                            // Parse: "task description" "step1" "step2" "step3"
-                            let parts: Vec<&str> = params
-                                .split('"')
-                                .filter(|s| !s.is_empty() && !s.trim().is_empty())
-                                .collect();
-                            if parts.len() >= 2 {
-                                let task = parts[0].trim().to_string();
-                                let steps: Vec<String> =
-                                    parts[1..].iter().map(|s| s.trim().to_string()).collect();
-                                Some(Tool::CreatePlan { task, steps })
-                            } else {
-                                None
-                            }
-                        }
-                        "UPDATE_PLAN" => {
-                            if let Ok(step) = params.trim().parse::<usize>() {
-                                Some(Tool::UpdatePlan {
-                                    completed_step: step,
-                                })
-                            } else {
-                                None
-                            }
-                        }
-                        "CLEAR_PLAN" => Some(Tool::ClearPlan),
-                        "GET_TIME" => Some(Tool::GetTime),
-                        "GET_OS_INFO" => Some(Tool::GetOsInfo),
-                        "COPY_FILE" => {
-                            let parts: Vec<&str> = params.splitn(2, ' ').collect();
-                            if parts.len() == 2 {
-                                Some(Tool::CopyFile {
-                                    source: parts[0].to_string(),
-                                    destination: parts[1].to_string(),
-                                })
-                            } else {
-                                None
-                            }
-                        }
-                        "MOVE_FILE" => {
-                            let parts: Vec<&str> = params.splitn(2, ' ').collect();
-                            if parts.len() == 2 {
-                                Some(Tool::MoveFile {
-                                    source: parts[0].to_string(),
-                                    destination: parts[1].to_string(),
-                                })
-                            } else {
-                                None
-                            }
-                        }
-                        "RENAME_FILE" => {
-                            let parts: Vec<&str> = params.splitn(2, ' ').collect();
-                            if parts.len() == 2 {
-                                Some(Tool::RenameFile {
-                                    old_name: parts[0].to_string(),
-                                    new_name: parts[1].to_string(),
-                                })
-                            } else {
-                                None
-                            }
+        // Fall back to legacy format
+        let parts: Vec<&str> = tool_part.splitn(2, ' ').collect();
+        if parts.len() >= 2 {
+            let tool_name = parts[0];
+            let params = parts[1];
+
+            return match tool_name {
+                "READ_FILE" => Some(Tool::ReadFile {
+                    path: params.to_string(),
+                    offset: None,
+                    limit: None,
+                }),
+                "WRITE_FILE" => {
+                    if let Some(space_pos) = params.find(' ') {
+                        let path = &params[..space_pos];
+                        let content = &params[space_pos + 1..];
+                        Some(Tool::WriteFile {
+                            path: path.to_string(),
+                            content: content.to_string(),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                "APPEND_FILE" => {
+                    if let Some(space_pos) = params.find(' ') {
+                        let path = &params[..space_pos];
+                        let content = &params[space_pos + 1..];
+                        Some(Tool::AppendFile {
+                            path: path.to_string(),
+                            content: content.to_string(),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                "SEARCH_REPLACE" => {
+                    let params_str = params.to_string();
+                    // Parse: "path old_string new_string" - need to split carefully
+                    if let Some(first_space) = params_str.find(' ') {
+                        let path = &params_str[..first_space];
+                        let remaining = &params_str[first_space + 1..];
+                        // Find the boundary between old_string and new_string
+                        // This is tricky with the legacy format, but we'll assume old_string comes first
+                        if let Some(last_space) = remaining.rfind(' ') {
+                            let old_string = &remaining[..last_space];
+                            let new_string = &remaining[last_space + 1..];
+                            Some(Tool::SearchReplace {
+                                path: path.to_string(),
+                                old_string: old_string.to_string(),
+                                new_string: new_string.to_string(),
+                                replace_all: false,
+                                ignore_whitespace: false,
+                            })
+                        } else {
+                            None
                         }
-                        _ => None,
+                    } else {
+                        None
+                    }
+                }
+                "RUN_COMMAND" => Some(Tool::RunCommand {
+                    command: params.to_string(),
+                }),
+                "LIST_FILES" => Some(Tool::ListFiles {
+                    path: params.to_string(),
+                }),
+                "LIST_FILES_RECURSIVE" => Some(Tool::ListFilesRecursive {
+                    path: params.to_string(),
+                }),
+                "CREATE_DIRECTORY" => Some(Tool::CreateDirectory {
+                    path: params.to_string(),
+                }),
+                "DELETE_FILE" => Some(Tool::DeleteFile {
+                    path: params.to_string(),
+                }),
+                "EXECUTE_CODE" => {
+                    if let Some(space_pos) = params.find(' ') {
+                        let language = &params[..space_pos];
+                        let code = &params[space_pos + 1..];
+                        Some(Tool::ExecuteCode {
+                            language: language.to_string(),
+                            code: code.to_string(),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                "GREP_SEARCH" => {
+                    // Parse: "pattern [path]"
+                    let search_parts: Vec<&str> = params.splitn(2, ' ').collect();
+                    let pattern = search_parts[0].to_string();
+                    let path = search_parts.get(1).map(|s| s.to_string());
+                    Some(Tool::GrepSearch {
+                        pattern,
+                        path,
+                        glob: None,
+                        file_type: None,
+                        context: 0,
+                        case_sensitive: false,
+                        max_results: None,
+                        sort: SortBy::Path,
+                    })
+                }
+                "GLOB_SEARCH" => Some(Tool::GlobSearch {
+                    pattern: params.to_string(),
+                    path: None,
+                    max_results: None,
+                    sort: SortBy::Path,
+                }),
+                "FUZZY_FIND" => {
+                    let parts: Vec<&str> = params.splitn(2, ' ').collect();
+                    let pattern = parts[0].to_string();
+                    let path = parts.get(1).map(|s| s.to_string());
+                    Some(Tool::FuzzyFind { pattern, path })
+                }
+                "INDEX_CODEBASE" => Some(Tool::IndexCodebase {
+                    path: params.to_string(),
+                }),
+                "SEARCH_INDEX" => Some(Tool::SearchIndex {
+                    query: params.to_string(),
+                    callers: false,
+                }),
+                "SEMANTIC_SEARCH" => Some(Tool::SemanticSearch {
+                    query: params.to_string(),
+                    path: None,
+                    max_results: None,
+                }),
+                "READ_URL" => Some(Tool::ReadUrl {
+                    url: params.to_string(),
+                }),
+                "SEARCH_WEB" => Some(Tool::SearchWeb {
+                    query: params.to_string(),
+                }),
+                "REMEMBER" => Some(Tool::Remember {
+                    fact: params.to_string(),
+                    tags: Vec::new(),
+                    scope: Scope::Project,
+                }),
+                "RECALL" => Some(Tool::Recall {
+                    query: (!params.is_empty()).then(|| params.to_string()),
+                }),
+                "FORGET" => Some(Tool::Forget {
+                    id: params.to_string(),
+                }),
+                "GIT_STATUS" => Some(Tool::GitStatus),
+                "GIT_DIFF" => Some(Tool::GitDiff),
+                "GIT_COMMIT" => Some(Tool::GitCommit {
+                    message: params.to_string(),
+                }),
+                "GIT_LOG" => {
+                    let count = if params.is_empty() {
+                        None
+                    } else {
+                        params.parse::<usize>().ok()
                     };
+                    Some(Tool::GitLog { count })
                 }
-            }
+                "RUN_LINT" => {
+                    let lint_parts: Vec<&str> = params.splitn(2, ' ').collect();
+                    let language = lint_parts[0].to_string();
+                    let path = lint_parts.get(1).map(|s| s.to_string());
+                    Some(Tool::RunLint { language, path })
+                }
+                "RUN_TESTS" => {
+                    let test_parts: Vec<&str> = params.splitn(2, ' ').collect();
+                    let framework = test_parts[0].to_string();
+                    let path = test_parts.get(1).map(|s| s.to_string());
+                    Some(Tool::RunTests { framework, path })
+                }
+                "INSTALL_PACKAGE" => {
+                    let package_parts: Vec<&str> = params.splitn(2, ' ').collect();
+                    if package_parts.len() == 2 {
+                        Some(Tool::InstallPackage {
+                            manager: package_parts[0].to_string(),
+                            package: package_parts[1].to_string(),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                "CREATE_PLAN" => {
+                    // Parse: "task description" "step1" "step2" "step3"
+                    let parts: Vec<&str> = params
+                        .split('"')
+                        .filter(|s| !s.is_empty() && !s.trim().is_empty())
+                        .collect();
+                    if parts.len() >= 2 {
+                        let task = parts[0].trim().to_string();
+                        let steps: Vec<String> =
+                            parts[1..].iter().map(|s| s.trim().to_string()).collect();
+                        Some(Tool::CreatePlan { task, steps })
+                    } else {
+                        None
+                    }
+                }
+                "UPDATE_PLAN" => {
+                    if let Ok(step) = params.trim().parse::<usize>() {
+                        Some(Tool::UpdatePlan {
+                            completed_step: step,
+                        })
+                    } else {
+                        None
+                    }
+                }
+                "CLEAR_PLAN" => Some(Tool::ClearPlan),
+                "GET_TIME" => Some(Tool::GetTime),
+                "GET_OS_INFO" => Some(Tool::GetOsInfo),
+                "COPY_FILE" => {
+                    let parts: Vec<&str> = params.splitn(2, ' ').collect();
+                    if parts.len() == 2 {
+                        Some(Tool::CopyFile {
+                            source: parts[0].to_string(),
+                            destination: parts[1].to_string(),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                "MOVE_FILE" => {
+                    let parts: Vec<&str> = params.splitn(2, ' ').collect();
+                    if parts.len() == 2 {
+                        Some(Tool::MoveFile {
+                            source: parts[0].to_string(),
+                            destination: parts[1].to_string(),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                "RENAME_FILE" => {
+                    let parts: Vec<&str> = params.splitn(2, ' ').collect();
+                    if parts.len() == 2 {
+                        Some(Tool::RenameFile {
+                            old_name: parts[0].to_string(),
+                            new_name: parts[1].to_string(),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
         }
         None
     }
@@ -1812,22 +3328,121 @@ TOOL: {{"name": "GET_OS_INFO", "parameters": {{}}}}
         config: &crate::config::Config,
         user_prompt: String,
         app: Arc<Mutex<crate::app::App>>,
+        cancel: CancellationToken,
     ) -> Result<(String, Vec<String>), Box<dyn std::error::Error + Send + Sync>> {
-        self.run_with_streaming(config, user_prompt, app).await
+        self.run_with_streaming(config, user_prompt, app, cancel)
+            .await
     }
 
+    /// Brings the conversation back under the context budget. Old tool
+    /// results are shortened first; if that isn't enough, everything before
+    /// the recent turns is replaced by a summary written by the model. The
+    /// system prompt and the plan survive. With `force` (the `/compact`
+    /// command) both steps run regardless of size. Returns a log line if
+    /// anything changed.
+    pub async fn compact(
+        &mut self,
+        config: &crate::config::Config,
+        app: &Arc<Mutex<crate::app::App>>,
+        force: bool,
+    ) -> Result<Option<String>, LlmError> {
+        let budget = context_budget(&config.llm).await;
+        // Tool schemas are sent with every native request
+        let overhead = if config.llm.tool_protocol == ToolProtocol::Native {
+            estimate_token_count(&serde_json::to_string(&tool_definitions()).unwrap_or_default())
+        } else {
+            0
+        };
+        let size = |messages: &[Message]| estimate_prompt_tokens(messages) + overhead;
+
+        let before = size(&self.messages);
+        if !force && before <= budget.threshold() {
+            return Ok(None);
+        }
+
+        let recent = context::recent_turns_start(&self.messages, RECENT_TURNS);
+        let mut truncated =
+            context::truncate_tool_results(&mut self.messages, recent, OLD_TOOL_RESULT_CHARS);
+
+        let start = context::preamble_len(&self.messages);
+        let mut summarized = 0;
+        if (force || size(&self.messages) > budget.threshold()) && recent > start {
+            let request = context::summary_request(&self.messages[start..recent]);
+            let (summary, usage) = crate::llm::ask_llm_with_messages(&config.llm, &request).await?;
+            {
+                let mut app_guard = app.lock().await;
+                app_guard.increment_requests();
+                app_guard.record_usage(usage);
+            }
+
+            let mut content = format!("Summary of the earlier conversation:\n\n{}", summary.trim());
+            let plan_path = Workspace::from_config(&config.agent)
+                .map(|workspace| workspace.root().join("plan.md"))
+                .unwrap_or_else(|_| "plan.md".into());
+            if let Ok(plan) = fs::read_to_string(plan_path).await {
+                content.push_str(&format!("\n\nCurrent plan (plan.md):\n{}", plan));
+            }
+            summarized = recent - start;
+            context::replace_with_summary(&mut self.messages, start, recent, content);
+        }
+
+        if size(&self.messages) > budget.limit() {
+            // The current turn alone is too big, so shorten its tool results
+            // too, except the ones the model hasn't seen yet
+            let newest = self
+                .messages
+                .iter()
+                .rposition(|m| m.role == "assistant")
+                .unwrap_or(0);
+            truncated +=
+                context::truncate_tool_results(&mut self.messages, newest, OLD_TOOL_RESULT_CHARS);
+        }
+
+        if truncated == 0 && summarized == 0 {
+            return Ok(force.then(|| "[CONTEXT] Nothing to compact".to_string()));
+        }
+        Ok(Some(format!(
+            "[CONTEXT] Compacted conversation from ~{} to ~{} tokens ({} tool results shortened, {} messages summarized)",
+            before,
+            size(&self.messages),
+            truncated,
+            summarized
+        )))
+    }
+
+    /// Runs one user turn. When `cancel` fires the current request or tool
+    /// is abandoned (child processes are killed) and the turn ends early;
+    /// every tool call already sent to the model still gets a result, so
+    /// the conversation can continue normally afterwards.
     pub async fn run_with_streaming(
         &mut self,
         config: &crate::config::Config,
         user_prompt: String,
         app: Arc<Mutex<crate::app::App>>,
+        cancel: CancellationToken,
     ) -> Result<(String, Vec<String>), Box<dyn std::error::Error + Send + Sync>> {
-        // Add system message if this is the first interaction
-        if self.messages.is_empty() {
-            self.messages.push(Message {
+        let native_tools = config.llm.tool_protocol == ToolProtocol::Native;
+        let tool_definitions = if native_tools {
+            tool_definitions()
+        } else {
+            Vec::new()
+        };
+
+        // Add system message if this is the first interaction. The repository
+        // map in it is rebuilt every turn, so it follows the files the
+        // previous turns changed
+        let mut system_prompt = Self::get_system_prompt(config.llm.tool_protocol);
+        if let Some(map) = self.repo_map(config, &cancel).await {
+            system_prompt.push_str(&format!("\n\n{}", map));
+        }
+        match self.messages.first_mut() {
+            None => self.messages.push(Message {
                 role: "system".to_string(),
-                content: Self::get_system_prompt(),
-            });
+                content: system_prompt,
+                ..Default::default()
+            }),
+            Some(first) if first.role == "system" => first.content = system_prompt,
+            Some(_) => {}
         }
 
         // Add planning reminder for complex tasks
@@ -1836,6 +3451,7 @@ TOOL: {{"name": "GET_OS_INFO", "parameters": {{}}}}
             self.messages.push(Message {
                 role: "system".to_string(),
                 content: "REMINDER: If the user's request is complex (3+ steps, multiple files), start with CREATE_PLAN. For simple tasks, use tools directly.".to_string(),
+                ..Default::default()
             });
         }
 
@@ -1868,22 +3484,57 @@ TOOL: {{"name": "GET_OS_INFO", "parameters": {{}}}}
             self.messages.push(Message {
                 role: "system".to_string(),
                 content: "This appears to be a complex task. Please start with CREATE_PLAN to break it down into manageable steps.".to_string(),
+                ..Default::default()
             });
         }
 
+        // The memories recalled for the previous prompt make way for the
+        // ones that match this one
+        for message in self.messages.iter_mut().filter(|m| m.role == "user") {
+            let prompt = memory::strip_recalled(&message.content);
+            if prompt.len() != message.content.len() {
+                message.content = prompt.to_string();
+            }
+        }
+        let content = match Self::recall_memories(config, &user_prompt).await {
+            Some(recalled) => memory::prepend_recalled(&recalled, &user_prompt),
+            None => user_prompt.clone(),
+        };
+
         // Add user message
         self.messages.push(Message {
             role: "user".to_string(),
-            content: user_prompt.clone(),
+            content,
+            ..Default::default()
         });
 
         let mut all_tool_logs = Vec::new();
         let mut attempts = 0;
-        const MAX_ATTEMPTS: usize = 8;
+        let mut compacted_after_overflow = false;
+        let tool_context = ToolContext::from_config(config, cancel.clone())?;
+        // Without readable rules every guarded action is asked about
+        let rules_file = tool_context.workspace.root().join(PERMISSIONS_FILE);
+        if self.permissions.path() != Some(rules_file.as_path()) {
+            self.permissions = Permissions::load(&rules_file).unwrap_or_default();
+        }
+        let max_attempts = config.agent.max_attempts.max(1);
 
         loop {
             attempts += 1;
 
+            let compacted = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => {
+                    return Self::finish_cancelled(&app, String::new(), all_tool_logs).await;
+                }
+                compacted = self.compact(config, &app, false) => compacted,
+            };
+            match compacted {
+                Ok(Some(log)) => all_tool_logs.push(log),
+                Ok(None) => {}
+                Err(e) => all_tool_logs.push(format!("[WARNING] Compaction failed: {}", e)),
+            }
+
             // Start streaming for this response
             {
                 let mut app_guard = app.lock().await;
@@ -1892,27 +3543,80 @@ TOOL: {{"name": "GET_OS_INFO", "parameters": {{}}}}
 
             // Create a string to collect the full response
             let mut full_response = String::new();
+            let mut tool_call_parts = ToolCallAccumulator::default();
+            let mut reported_usage: Option<TokenUsage> = None;
 
             // Get streaming response from LLM
-            let mut stream = match crate::llm::stream_llm_response(&config.llm, &self.messages)
-                .await
-            {
+            let opened = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => {
+                    return Self::finish_cancelled(&app, String::new(), all_tool_logs).await;
+                }
+                opened = crate::llm::stream_llm_response(
+                    &config.llm,
+                    &self.messages,
+                    &tool_definitions,
+                ) => opened,
+            };
+            let mut stream = match opened {
                 Ok(stream) => stream,
+                Err(LlmError::ContextLengthExceeded(_)) if !compacted_after_overflow => {
+                    // Our estimate was off; compact as far as possible and try once more
+                    compacted_after_overflow = true;
+                    attempts -= 1;
+                    let compacted = tokio::select! {
+                        biased;
+                        _ = cancel.cancelled() => {
+                            return Self::finish_cancelled(&app, String::new(), all_tool_logs).await;
+                        }
+                        compacted = self.compact(config, &app, true) => compacted,
+                    };
+                    match compacted {
+                        Ok(Some(log)) => all_tool_logs.push(log),
+                        Ok(None) => {}
+                        Err(e) => {
+                            let mut app_guard = app.lock().await;
+                            app_guard.finish_streaming(format!(
+                                "Error: The conversation is too long and could not be compacted: {}",
+                                e
+                            ));
+                            return Err(Box::new(e));
+                        }
+                    }
+                    continue;
+                }
                 Err(e) => {
                     let mut app_guard = app.lock().await;
-                    app_guard
-                        .finish_streaming("Error: Failed to start streaming response".to_string());
+                    app_guard.finish_streaming(format!(
+                        "Error: Failed to start streaming response: {}",
+                        e
+                    ));
                     return Err(Box::new(e));
                 }
             };
 
-            // Collect tokens from the stream
-            let mut token_stream = String::new();
-            while let Some(chunk_result) = stream.next().await {
-                match chunk_result {
-                    Ok(chunk) => {
+            // Collect tokens and tool call fragments from the stream
+            loop {
+                let event = tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => {
+                        // Keep the text seen so far; half-streamed tool calls
+                        // are dropped, so none is left without a result
+                        if !full_response.is_empty() {
+                            self.messages.push(Message {
+                                role: "assistant".to_string(),
+                                content: full_response.clone(),
+                                ..Default::default()
+                            });
+                        }
+                        return Self::finish_cancelled(&app, full_response, all_tool_logs).await;
+                    }
+                    event = stream.next() => event,
+                };
+                let Some(event) = event else { break };
+                match event {
+                    Ok(StreamEvent::Content(chunk)) => {
                         if !chunk.is_empty() {
-                            token_stream.push_str(&chunk);
                             full_response.push_str(&chunk);
                             // Update streaming message with brief lock
                             {
@@ -1921,6 +3625,10 @@ TOOL: {{"name": "GET_OS_INFO", "parameters": {{}}}}
                             }
                         }
                     }
+                    Ok(StreamEvent::ToolCall(delta)) => tool_call_parts.push(delta),
+                    Ok(StreamEvent::Usage(usage)) => {
+                        reported_usage = Some(reported_usage.unwrap_or_default() + usage);
+                    }
                     Err(e) => {
                         let mut app_guard = app.lock().await;
                         app_guard.finish_streaming(format!("Error in streaming: {}", e));
@@ -1928,152 +3636,389 @@ TOOL: {{"name": "GET_OS_INFO", "parameters": {{}}}}
                     }
                 }
             }
+            let tool_calls = tool_call_parts.finish();
 
+            // Fall back to the tokenizer when the server didn't report usage
+            let usage = reported_usage.unwrap_or_else(|| {
+                let reply = Message {
+                    role: "assistant".to_string(),
+                    content: full_response.clone(),
+                    tool_calls: Some(tool_calls.clone()),
+                    ..Default::default()
+                };
+                crate::llm::estimate_usage(&self.messages, &reply)
+            });
             {
                 let mut app_guard = app.lock().await;
                 app_guard.increment_requests();
+                app_guard.record_usage(usage);
             }
 
-            // Check if response contains a tool call
-            if let Some(tool) = self.parse_tool_call(&full_response) {
-                let mut tool_logs = Vec::new();
-
-                // Log the tool execution
-                let tool_name = match &tool {
-                    Tool::ReadFile { path } => format!("READ_FILE {}", path),
-                    Tool::WriteFile { path, content: _ } => format!("WRITE_FILE {}", path),
-                    Tool::AppendFile { path, content: _ } => format!("APPEND_FILE {}", path),
-                    Tool::SearchReplace {
-                        path,
-                        old_string: _,
-                        new_string: _,
-                    } => format!("SEARCH_REPLACE {}", path),
-                    Tool::DeleteFile { path } => format!("DELETE_FILE {}", path),
-                    Tool::ListFiles { path } => format!("LIST_FILES {}", path),
-                    Tool::ListFilesRecursive { path } => format!("LIST_FILES_RECURSIVE {}", path),
-                    Tool::CreateDirectory { path } => format!("CREATE_DIRECTORY {}", path),
-                    Tool::GrepSearch { pattern, path: _ } => format!("GREP_SEARCH {}", pattern),
-                    Tool::GlobSearch { pattern } => format!("GLOB_SEARCH {}", pattern),
-                    Tool::FuzzyFind { pattern, path: _ } => format!("FUZZY_FIND {}", pattern),
-                    Tool::IndexCodebase { path } => format!("INDEX_CODEBASE {}", path),
-                    Tool::SearchIndex { query } => format!("SEARCH_INDEX {}", query),
-                    Tool::ReadUrl { url } => format!("READ_URL {}", url),
-                    Tool::SearchWeb { query } => format!("SEARCH_WEB '{}'", query),
-                    Tool::Remember { fact } => {
-                        format!("REMEMBER ({})", fact.chars().take(50).collect::<String>())
-                    }
-                    Tool::Recall => "RECALL".to_string(),
-                    Tool::ExecuteCode { language, code: _ } => format!("EXECUTE_CODE {}", language),
-                    Tool::RunCommand { command } => format!("RUN_COMMAND {}", command),
-                    Tool::GitStatus => "GIT_STATUS".to_string(),
-                    Tool::GitDiff => "GIT_DIFF".to_string(),
-                    Tool::GitCommit { message } => format!("GIT_COMMIT \"{}\"", message),
-                    Tool::GitLog { count } => format!(
-                        "GIT_LOG {}",
-                        count.map_or("all".to_string(), |n| n.to_string())
-                    ),
-                    Tool::RunLint { language, path: _ } => format!("RUN_LINT {}", language),
-                    Tool::RunTests { framework, path: _ } => format!("RUN_TESTS {}", framework),
-                    Tool::InstallPackage { manager, package } => {
-                        format!("INSTALL_PACKAGE {} {}", manager, package)
-                    }
-                    Tool::CreatePlan { task, steps } => {
-                        format!("CREATE_PLAN \"{}\" ({} steps)", task, steps.len())
-                    }
-                    Tool::UpdatePlan { completed_step } => {
-                        format!("UPDATE_PLAN step {}", completed_step)
-                    }
-                    Tool::ClearPlan => "CLEAR_PLAN".to_string(),
-                    Tool::GetTime => "GET_TIME".to_string(),
-                    Tool::GetOsInfo => "GET_OS_INFO".to_string(),
-                    Tool::CopyFile {
-                        source,
-                        destination,
-                    } => format!("COPY_FILE {} -> {}", source, destination),
-                    Tool::MoveFile {
-                        source,
-                        destination,
-                    } => format!("MOVE_FILE {} -> {}", source, destination),
-                    Tool::RenameFile { old_name, new_name } => {
-                        format!("RENAME_FILE {} -> {}", old_name, new_name)
-                    }
-                };
-                tool_logs.push(format!("[ATTEMPT {}] Executing {}", attempts, tool_name));
-
-                // Execute the tool
-                let tool_result = match tool.execute(&config.web) {
-                    Ok(result) => {
-                        {
-                            let mut app_guard = app.lock().await;
-                            app_guard.increment_tools_executed();
-                        }
-                        tool_logs.push(format!("[SUCCESS] {}", result));
-                        result
-                    }
-                    Err(e) => {
-                        let error_msg = format!("[ERROR] {}", e);
-                        tool_logs.push(error_msg.clone());
-                        format!("Tool failed: {}. Please try a different approach or check if the path/command is correct.", e)
-                    }
-                };
-                all_tool_logs.extend(tool_logs);
+            // Native calls come back structured; with the text protocol they
+            // are scraped from the reply itself
+            let requested: Vec<Result<Tool, String>> = if native_tools {
+                tool_calls
+                    .iter()
+                    .map(|call| {
+                        Tool::from_call(&call.function.name, &call.function.arguments)
+                            .ok_or_else(|| Self::invalid_call_result(call))
+                    })
+                    .collect()
+            } else {
+                Self::parse_tool_calls(&full_response)
+                    .into_iter()
+                    .map(Ok)
+                    .collect()
+            };
 
-                // Add assistant message and tool result to conversation
+            if requested.is_empty() {
+                // No tool call, task appears to be complete
                 self.messages.push(Message {
                     role: "assistant".to_string(),
                     content: full_response.clone(),
+                    ..Default::default()
                 });
 
+                if attempts > 1 {
+                    all_tool_logs.push(format!(
+                        "[COMPLETE] Task completed after {} attempts",
+                        attempts
+                    ));
+                }
+
+                {
+                    let mut app_guard = app.lock().await;
+                    app_guard.finish_streaming(full_response.clone());
+                }
+                return Ok((full_response, all_tool_logs));
+            }
+
+            // Add assistant message; native tool calls ride along with it so
+            // each call id can be answered below
+            self.messages.push(Message {
+                role: "assistant".to_string(),
+                content: full_response.clone(),
+                tool_calls: native_tools.then(|| tool_calls.clone()),
+                ..Default::default()
+            });
+
+            let labels: Vec<String> = requested
+                .iter()
+                .map(|call| match call {
+                    Ok(tool) => tool.label(),
+                    Err(_) => "INVALID_TOOL_CALL".to_string(),
+                })
+                .collect();
+            let results = Self::execute_tools(
+                requested,
+                &tool_context,
+                &mut self.permissions,
+                &app,
+                attempts,
+                &mut all_tool_logs,
+            )
+            .await;
+
+            // Return every result to the model in one follow-up
+            if native_tools {
+                for (call, result) in tool_calls.iter().zip(results) {
+                    self.messages.push(Self::tool_message(call, result));
+                }
+            } else {
                 self.messages.push(Message {
                     role: "user".to_string(),
-                    content: format!("Tool result: {}", tool_result),
+                    content: Self::combine_tool_results(&labels, results),
+                    ..Default::default()
                 });
+            }
 
-                // Check if we should continue or if the task is complete
-                if attempts >= MAX_ATTEMPTS {
-                    // Get final response after max attempts (non-streaming for final response)
-                    let (final_response, final_tokens) =
-                        crate::llm::ask_llm_with_messages(&config.llm, &self.messages).await?;
-                    {
-                        let mut app_guard = app.lock().await;
-                        app_guard.increment_tokens(final_tokens);
-                        app_guard.finish_streaming(final_response.clone());
-                    }
-
-                    self.messages.push(Message {
-                        role: "assistant".to_string(),
-                        content: final_response.clone(),
-                    });
+            if cancel.is_cancelled() {
+                return Self::finish_cancelled(&app, full_response, all_tool_logs).await;
+            }
 
-                    all_tool_logs.push(format!(
-                        "[WARNING] Reached maximum attempts ({})",
-                        MAX_ATTEMPTS
-                    ));
-                    return Ok((final_response, all_tool_logs));
+            // Check if we should continue or if the task is complete
+            if attempts >= max_attempts {
+                // Get final response after max attempts (non-streaming for final response).
+                // No tools are offered, so the tool turns go along as text
+                let mut request = crate::llm::flatten_tool_calls(&self.messages);
+                request.push(Message {
+                    role: "user".to_string(),
+                    content: format!(
+                        "You have used all {} tool rounds for this request. Don't call any more tools; give your final answer now, including what is left to do.",
+                        max_attempts
+                    ),
+                    ..Default::default()
+                });
+                let (final_response, final_usage) = tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => {
+                        return Self::finish_cancelled(&app, full_response, all_tool_logs).await;
+                    }
+                    reply = crate::llm::ask_llm_with_messages(&config.llm, &request) => reply?,
+                };
+                {
+                    let mut app_guard = app.lock().await;
+                    app_guard.increment_requests();
+                    app_guard.record_usage(final_usage);
+                    app_guard.finish_streaming(final_response.clone());
                 }
 
-                // Continue to next iteration to see if more tools are needed
-                continue;
-            } else {
-                // No tool call, task appears to be complete
                 self.messages.push(Message {
                     role: "assistant".to_string(),
-                    content: full_response.clone(),
+                    content: final_response.clone(),
+                    ..Default::default()
                 });
 
-                if attempts > 1 {
-                    all_tool_logs.push(format!(
-                        "[COMPLETE] Task completed after {} attempts",
-                        attempts
+                all_tool_logs.push(format!(
+                    "[WARNING] Reached maximum attempts ({})",
+                    max_attempts
+                ));
+                return Ok((final_response, all_tool_logs));
+            }
+
+            // Continue to next iteration to see if more tools are needed
+        }
+    }
+
+    /// Runs the requested tools in order and returns one result per call.
+    /// Consecutive read-only tools can't affect each other, so they run
+    /// concurrently.
+    async fn execute_tools(
+        calls: Vec<Result<Tool, String>>,
+        ctx: &ToolContext,
+        permissions: &mut Permissions,
+        app: &Arc<Mutex<crate::app::App>>,
+        attempt: usize,
+        tool_logs: &mut Vec<String>,
+    ) -> Vec<String> {
+        let mut results = Vec::with_capacity(calls.len());
+        let mut calls = calls.into_iter().peekable();
+
+        while let Some(call) = calls.next() {
+            if ctx.cancel.is_cancelled() {
+                // Every call still needs an answer for the conversation to stay valid
+                results.push("Tool not run: cancelled by user.".to_string());
+                continue;
+            }
+            let tool = match call {
+                Ok(tool) => tool,
+                Err(error) => {
+                    tool_logs.push(format!("[ERROR] {}", error));
+                    results.push(error);
+                    continue;
+                }
+            };
+
+            let mut batch = vec![tool];
+            if batch[0].is_read_only() {
+                while let Some(Ok(next)) =
+                    calls.next_if(|call| matches!(call, Ok(tool) if tool.is_read_only()))
+                {
+                    batch.push(next);
+                }
+            }
+
+            if batch.len() == 1 {
+                let tool = &batch[0];
+                if let Some(action) = tool.permission_action(&ctx.workspace) {
+                    if let Err(reason) =
+                        Self::authorize(action, permissions, &ctx.cancel, app, tool_logs).await
+                    {
+                        tool_logs.push(format!("[DENIED] {}: {}", tool.label(), reason));
+                        results.push(format!(
+                            "Tool not run: {}. Do not retry it; choose another approach or ask the user.",
+                            reason
+                        ));
+                        continue;
+                    }
+                }
+                tool_logs.push(format!("[ATTEMPT {}] Executing {}", attempt, tool.label()));
+                let outcome = Self::run_tool(tool, ctx, Some(app)).await;
+                results.push(Self::record_outcome(outcome, app, tool_logs).await);
+                continue;
+            }
+
+            tool_logs.push(format!(
+                "[ATTEMPT {}] Executing {} read-only tools in parallel",
+                attempt,
+                batch.len()
+            ));
+            let runs = batch.iter().map(|tool| {
+                tool_logs.push(format!("  - {}", tool.label()));
+                Self::run_tool(tool, ctx, None)
+            });
+            let runs: Vec<_> = runs.collect();
+            for outcome in futures_util::future::join_all(runs).await {
+                results.push(Self::record_outcome(outcome, app, tool_logs).await);
+            }
+        }
+
+        results
+    }
+
+    /// Checks `action` against the permission rules and asks the user when
+    /// none applies. Returns why the action may not run.
+    async fn authorize(
+        action: Action,
+        permissions: &mut Permissions,
+        cancel: &CancellationToken,
+        app: &Arc<Mutex<crate::app::App>>,
+        tool_logs: &mut Vec<String>,
+    ) -> Result<(), String> {
+        match permissions.check(&action) {
+            Decision::Allow => return Ok(()),
+            Decision::Deny(rule) => return Err(format!("blocked by permission rule {}", rule)),
+            Decision::Ask => {}
+        }
+
+        let (reply, answer) = oneshot::channel();
+        app.lock()
+            .await
+            .request_approval(ApprovalRequest { action, reply });
+        let answer = tokio::select! {
+            answer = answer => answer,
+            _ = cancel.cancelled() => {
+                app.lock().await.approval = None;
+                return Err("cancelled by user".to_string());
+            }
+        };
+
+        match answer {
+            Ok(Approval::AllowOnce) => Ok(()),
+            Ok(Approval::Deny(reason)) if reason.is_empty() => {
+                Err("the user denied it".to_string())
+            }
+            Ok(Approval::Deny(reason)) => Err(format!("the user denied it: {}", reason)),
+            Ok(Approval::Save(rule)) => {
+                let action = rule.action;
+                tool_logs.push(format!("[PERMISSIONS] Saved rule: {}", rule));
+                if let Err(e) = permissions.add(rule) {
+                    tool_logs.push(format!("[WARNING] Could not save permission rules: {}", e));
+                }
+                match action {
+                    RuleAction::Allow => Ok(()),
+                    RuleAction::Deny => Err("the user denied it".to_string()),
+                }
+            }
+            Err(_) => Err("the approval prompt was closed".to_string()),
+        }
+    }
+
+    /// Runs a tool until it finishes or the user cancels, in which case the
+    /// tool is dropped midway along with any child processes it started.
+    ///
+    /// With `app`, whatever the tool's commands print shows up in the tool
+    /// log as it happens.
+    async fn run_tool(
+        tool: &Tool,
+        ctx: &ToolContext,
+        app: Option<&Arc<Mutex<crate::app::App>>>,
+    ) -> Result<String, io::Error> {
+        let mut task_ctx = ctx.clone();
+        let (lines_tx, mut lines) = mpsc::unbounded_channel();
+        if let Some(app) = app {
+            app.lock()
+                .await
+                .add_tool_log(format!("[RUNNING] {}", tool.label()));
+            task_ctx.output_lines = Some(lines_tx);
+        }
+        let run = tool.execute_with(&task_ctx);
+        tokio::pin!(run);
+
+        let mut shown = 0;
+        loop {
+            tokio::select! {
+                // Lines first, so none are lost when the tool finishes
+                biased;
+                _ = ctx.cancel.cancelled() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Interrupted,
+                        "cancelled by user",
                     ));
                 }
+                Some(line) = lines.recv() => {
+                    let Some(app) = app else { continue };
+                    shown += 1;
+                    let log = match shown.cmp(&MAX_LIVE_LINES) {
+                        Ordering::Less if line.chars().count() > MAX_LIVE_LINE_CHARS => {
+                            let start: String = line.chars().take(MAX_LIVE_LINE_CHARS).collect();
+                            format!("  > {} [...]", start)
+                        }
+                        Ordering::Less => format!("  > {}", line),
+                        Ordering::Equal => "  > [... more output, see the tool result ...]".to_string(),
+                        Ordering::Greater => continue,
+                    };
+                    app.lock().await.add_tool_log(log);
+                }
+                result = &mut run => return result,
+            }
+        }
+    }
+
+    /// Ends a cancelled turn, showing whatever was streamed so far.
+    async fn finish_cancelled(
+        app: &Arc<Mutex<crate::app::App>>,
+        partial: String,
+        mut tool_logs: Vec<String>,
+    ) -> Result<(String, Vec<String>), Box<dyn std::error::Error + Send + Sync>> {
+        tool_logs.push("[CANCELLED] Stopped by user".to_string());
+        let mut app_guard = app.lock().await;
+        app_guard.finish_streaming(partial.clone());
+        app_guard
+            .conversation
+            .push("System: Cancelled.".to_string());
+        Ok((partial, tool_logs))
+    }
 
+    /// Records a tool outcome in the logs and usage stats, and returns the
+    /// text sent back to the model.
+    async fn record_outcome(
+        outcome: Result<String, io::Error>,
+        app: &Arc<Mutex<crate::app::App>>,
+        tool_logs: &mut Vec<String>,
+    ) -> String {
+        match outcome {
+            Ok(result) => {
                 {
                     let mut app_guard = app.lock().await;
-                    app_guard.finish_streaming(full_response.clone());
+                    app_guard.increment_tools_executed();
                 }
-                return Ok((full_response, all_tool_logs));
+                tool_logs.push(format!("[SUCCESS] {}", result));
+                result
             }
+            Err(e) => {
+                tool_logs.push(format!("[ERROR] {}", e));
+                format!("Tool failed: {}. Please try a different approach or check if the path/command is correct.", e)
+            }
+        }
+    }
+
+    fn combine_tool_results(labels: &[String], results: Vec<String>) -> String {
+        if results.len() == 1 {
+            return format!("Tool result: {}", results[0]);
+        }
+        let sections: Vec<String> = labels
+            .iter()
+            .zip(results)
+            .enumerate()
+            .map(|(i, (label, result))| format!("[{}] {}\n{}", i + 1, label, result))
+            .collect();
+        format!("Tool results:\n\n{}", sections.join("\n\n"))
+    }
+
+    fn invalid_call_result(call: &ToolCallRequest) -> String {
+        format!(
+            "Tool failed: unknown tool '{}' or missing/invalid arguments: {}. Check the tool schema and try again.",
+            call.function.name, call.function.arguments
+        )
+    }
+
+    fn tool_message(call: &ToolCallRequest, content: String) -> Message {
+        Message {
+            role: "tool".to_string(),
+            content,
+            tool_call_id: Some(call.id.clone()),
+            ..Default::default()
         }
     }
 }
@@ -1,3 +1,24 @@
+use crate::llm::TokenUsage;
+use crate::permissions::{Approval, ApprovalRequest, Rule, RuleAction};
+use crossterm::event::KeyCode;
+
+/// What the approval modal is currently collecting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApprovalInput {
+    /// Waiting for y/n/a/d
+    Choosing,
+    /// Typing the reason for a denial
+    Reason(String),
+    /// Editing the pattern of a rule to save
+    Pattern(RuleAction, String),
+}
+
+#[derive(Debug)]
+pub struct ApprovalPrompt {
+    pub request: ApprovalRequest,
+    pub input: ApprovalInput,
+}
+
 pub struct App {
     pub user_input: String,
     pub conversation: Vec<String>,
@@ -8,6 +29,8 @@ pub struct App {
     // Usage tracking
     pub session_start_time: std::time::Instant,
     pub tokens_used: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
     pub total_requests: u64,
     pub total_tools_executed: u64,
     // Scrolling and streaming state
@@ -15,6 +38,8 @@ pub struct App {
     pub tool_logs_scroll_position: usize,
     pub is_streaming: bool,
     pub current_streaming_message: String,
+    // Tool call waiting for the user's approval
+    pub approval: Option<ApprovalPrompt>,
 }
 
 impl Default for App {
@@ -29,13 +54,15 @@ impl App {
             user_input: String::new(),
             conversation: Vec::new(),
             status_message:
-                "Commands: /quit, /stats | Keys: Up/Down (scroll chat), Left/Right (scroll logs), PgUp/PgDn, Home/End".to_string(),
+                "Commands: /quit, /stats, /compact, /models, /model <id>, /undo [turn], /checkpoints | Keys: Esc (cancel), Up/Down (scroll chat), Left/Right (scroll logs), PgUp/PgDn, Home/End".to_string(),
             tool_logs: Vec::new(),
             is_executing_tool: false,
             current_tool: String::new(),
             // Initialize usage tracking
             session_start_time: std::time::Instant::now(),
             tokens_used: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
             total_requests: 0,
             total_tools_executed: 0,
             // Initialize scrolling and streaming state
@@ -43,6 +70,7 @@ impl App {
             tool_logs_scroll_position: 0,
             is_streaming: false,
             current_streaming_message: String::new(),
+            approval: None,
         }
     }
 
@@ -55,6 +83,12 @@ impl App {
         self.tokens_used += tokens;
     }
 
+    pub fn record_usage(&mut self, usage: TokenUsage) {
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.increment_tokens(usage.total());
+    }
+
     pub fn increment_requests(&mut self) {
         self.total_requests += 1;
     }
@@ -76,7 +110,7 @@ impl App {
         format!(
             "Session Summary:\n\
              • Duration: {:02}:{:02}:{:02}\n\
-             • Tokens Used: {}\n\
+             • Tokens Used: {} ({} prompt, {} completion)\n\
              • LLM Requests: {}\n\
              • Tools Executed: {}\n\
              • Average Tokens/Request: {}",
@@ -84,13 +118,13 @@ impl App {
             minutes,
             seconds,
             self.tokens_used,
+            self.prompt_tokens,
+            self.completion_tokens,
             self.total_requests,
             self.total_tools_executed,
-            if self.total_requests > 0 {
-                self.tokens_used / self.total_requests
-            } else {
-                0
-            }
+            self.tokens_used
+                .checked_div(self.total_requests)
+                .unwrap_or(0)
         )
     }
 
@@ -149,4 +183,85 @@ impl App {
         self.status_message = "Done.".to_string();
         self.scroll_conversation_to_bottom();
     }
+
+    // Approval modal
+    pub fn request_approval(&mut self, request: ApprovalRequest) {
+        self.status_message = if request.action.always_asks() {
+            "Approval needed: y allow once, n deny, d always deny, Esc cancel"
+        } else {
+            "Approval needed: y allow once, n deny, a always allow, d always deny, Esc cancel"
+        }
+        .to_string();
+        self.approval = Some(ApprovalPrompt {
+            request,
+            input: ApprovalInput::Choosing,
+        });
+    }
+
+    /// Handles a key press while an approval prompt is open. Returns false
+    /// for keys the prompt leaves alone (Esc while choosing cancels the run).
+    pub fn handle_approval_key(&mut self, key: KeyCode) -> bool {
+        let Some(prompt) = self.approval.as_mut() else {
+            return false;
+        };
+        let answer = match (&mut prompt.input, key) {
+            (ApprovalInput::Choosing, KeyCode::Esc) => return false,
+            (ApprovalInput::Choosing, KeyCode::Char('y')) => Some(Approval::AllowOnce),
+            (ApprovalInput::Choosing, KeyCode::Char('n')) => {
+                prompt.input = ApprovalInput::Reason(String::new());
+                None
+            }
+            (ApprovalInput::Choosing, KeyCode::Char('a'))
+                if prompt.request.action.always_asks() =>
+            {
+                self.status_message =
+                    "Code, rct's own files and commands that chain, pipe or redirect can only be allowed once"
+                        .to_string();
+                None
+            }
+            (ApprovalInput::Choosing, KeyCode::Char(c @ ('a' | 'd'))) => {
+                let action = if c == 'a' {
+                    RuleAction::Allow
+                } else {
+                    RuleAction::Deny
+                };
+                let pattern = prompt.request.action.suggested_pattern();
+                prompt.input = ApprovalInput::Pattern(action, pattern);
+                None
+            }
+            (ApprovalInput::Choosing, _) => None,
+            (_, KeyCode::Esc) => {
+                prompt.input = ApprovalInput::Choosing;
+                None
+            }
+            (ApprovalInput::Reason(text) | ApprovalInput::Pattern(_, text), KeyCode::Char(c)) => {
+                text.push(c);
+                None
+            }
+            (ApprovalInput::Reason(text) | ApprovalInput::Pattern(_, text), KeyCode::Backspace) => {
+                text.pop();
+                None
+            }
+            (ApprovalInput::Reason(reason), KeyCode::Enter) => {
+                Some(Approval::Deny(reason.trim().to_string()))
+            }
+            (ApprovalInput::Pattern(action, pattern), KeyCode::Enter) => {
+                Some(Approval::Save(Rule {
+                    action: *action,
+                    tool: prompt.request.action.tool.to_string(),
+                    pattern: pattern.trim().to_string(),
+                }))
+            }
+            _ => None,
+        };
+
+        if let Some(answer) = answer {
+            if let Some(prompt) = self.approval.take() {
+                // The run may have been cancelled meanwhile; nobody is listening then
+                let _ = prompt.request.reply.send(answer);
+            }
+            self.status_message = "Thinking...".to_string();
+        }
+        true
+    }
 }
@@ -0,0 +1,383 @@
+use crate::workspace;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where snapshots live, relative to the workspace.
+pub const CHECKPOINT_DIR: &str = ".rct/checkpoints";
+
+/// Older checkpoints are dropped once there are more than this.
+const MAX_CHECKPOINTS: usize = 200;
+
+/// A tool call whose files are bigger than this, together, is refused
+/// rather than copied, e.g. deleting `target/` or `node_modules/`.
+const MAX_SNAPSHOT_FILES: usize = 10_000;
+const MAX_SNAPSHOT_BYTES: u64 = 256 * 1024 * 1024;
+
+const MANIFEST: &str = "checkpoint.json";
+
+/// The files one tool call was about to change, as they were before it ran.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub id: u64,
+    /// Checkpoints of the same user turn share this
+    pub turn: u64,
+    /// The tool call, as shown in the tool logs
+    pub label: String,
+    pub created: String,
+    pub entries: Vec<Entry>,
+}
+
+/// One path in a checkpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Relative to the workspace root, or absolute outside of it
+    pub path: PathBuf,
+    pub state: State,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    /// Nothing was there; restoring removes whatever is there now
+    Missing,
+    Dir,
+    /// Contents saved under this name in the checkpoint's directory
+    File(String),
+    Symlink(PathBuf),
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} [{}] {}", self.id, self.created, self.label)
+    }
+}
+
+/// Snapshots taken before mutating tools run, so their changes can be
+/// undone later. Undoing a checkpoint also undoes every later one, newest
+/// first, so files always go back to a state they were really in.
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    root: PathBuf,
+    dir: PathBuf,
+    max_files: usize,
+    max_bytes: u64,
+}
+
+impl Checkpoints {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            dir: root.join(CHECKPOINT_DIR),
+            max_files: MAX_SNAPSHOT_FILES,
+            max_bytes: MAX_SNAPSHOT_BYTES,
+        }
+    }
+
+    /// Caps how many files, and how many bytes, one checkpoint may copy.
+    #[allow(dead_code)]
+    pub fn with_limits(mut self, max_files: usize, max_bytes: u64) -> Self {
+        self.max_files = max_files;
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Snapshots `paths`, directories with everything in them except the
+    /// checkpoints themselves. Fails without copying anything when they are
+    /// over the size limits.
+    pub fn save(&self, turn: u64, label: &str, paths: &[PathBuf]) -> io::Result<Checkpoint> {
+        let (mut files, mut bytes) = (0, 0);
+        for path in paths {
+            self.measure(path, &mut files, &mut bytes)?;
+        }
+
+        let id = self.list()?.last().map_or(1, |last| last.id + 1);
+        let dir = self.checkpoint_dir(id);
+        workspace::create_state_dir(&dir)?;
+
+        let mut checkpoint = Checkpoint {
+            id,
+            turn,
+            label: label.to_string(),
+            created: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            entries: Vec::new(),
+        };
+        let saved = paths
+            .iter()
+            .try_for_each(|path| self.snapshot(path, &dir, &mut checkpoint.entries));
+        if let Err(e) = saved.and_then(|_| write_manifest(&dir, &checkpoint)) {
+            let _ = fs::remove_dir_all(&dir);
+            return Err(e);
+        }
+        self.prune()?;
+        Ok(checkpoint)
+    }
+
+    /// All checkpoints, oldest first. Unreadable ones are skipped.
+    pub fn list(&self) -> io::Result<Vec<Checkpoint>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut checkpoints = Vec::new();
+        for entry in entries {
+            let manifest = entry?.path().join(MANIFEST);
+            if let Ok(text) = fs::read_to_string(&manifest) {
+                if let Ok(checkpoint) = serde_json::from_str::<Checkpoint>(&text) {
+                    checkpoints.push(checkpoint);
+                }
+            }
+        }
+        checkpoints.sort_by_key(|checkpoint| checkpoint.id);
+        Ok(checkpoints)
+    }
+
+    /// Forgets a checkpoint without restoring it, e.g. when its tool failed.
+    pub fn discard(&self, id: u64) -> io::Result<()> {
+        fs::remove_dir_all(self.checkpoint_dir(id))
+    }
+
+    /// Whether the files are all still as `checkpoint` saved them, so there
+    /// is nothing to undo.
+    pub fn unchanged(&self, checkpoint: &Checkpoint) -> io::Result<bool> {
+        let dir = self.checkpoint_dir(checkpoint.id);
+        for entry in &checkpoint.entries {
+            let path = self.root.join(&entry.path);
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(metadata) => Some(metadata),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            let same = match (&entry.state, metadata) {
+                (State::Missing, metadata) => metadata.is_none(),
+                (State::Dir, Some(metadata)) if metadata.is_dir() => {
+                    // A file added inside it has no entry of its own
+                    let children = checkpoint
+                        .entries
+                        .iter()
+                        .filter(|child| child.path.parent() == Some(entry.path.as_path()))
+                        .count();
+                    fs::read_dir(&path)?
+                        .filter(|child| {
+                            child
+                                .as_ref()
+                                .map_or(true, |child| !child.path().starts_with(&self.dir))
+                        })
+                        .count()
+                        == children
+                }
+                (State::File(blob), Some(metadata)) if metadata.is_file() => {
+                    fs::read(&path)? == fs::read(dir.join(blob))?
+                }
+                (State::Symlink(target), Some(metadata)) if metadata.file_type().is_symlink() => {
+                    fs::read_link(&path)? == *target
+                }
+                _ => false,
+            };
+            if !same {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Undoes the last tool call. Returns what was undone.
+    pub fn undo_last(&self) -> io::Result<Vec<Checkpoint>> {
+        match self.list()?.last() {
+            Some(last) => self.restore(last.id),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Undoes every tool call of the latest turn.
+    pub fn undo_turn(&self) -> io::Result<Vec<Checkpoint>> {
+        let checkpoints = self.list()?;
+        let Some(turn) = checkpoints.last().map(|last| last.turn) else {
+            return Ok(Vec::new());
+        };
+        let first = checkpoints
+            .iter()
+            .rev()
+            .take_while(|checkpoint| checkpoint.turn == turn)
+            .last()
+            .map_or(0, |checkpoint| checkpoint.id);
+        self.restore(first)
+    }
+
+    /// Puts files back the way they were before checkpoint `id`, undoing it
+    /// and everything after it. Returns what was undone, newest first.
+    pub fn restore(&self, id: u64) -> io::Result<Vec<Checkpoint>> {
+        let mut undone: Vec<Checkpoint> = self
+            .list()?
+            .into_iter()
+            .filter(|checkpoint| checkpoint.id >= id)
+            .collect();
+        if undone.first().map(|first| first.id) != Some(id) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No checkpoint #{}", id),
+            ));
+        }
+        undone.reverse();
+        for checkpoint in &undone {
+            self.apply(checkpoint)?;
+            self.discard(checkpoint.id)?;
+        }
+        Ok(undone)
+    }
+
+    fn checkpoint_dir(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{:06}", id))
+    }
+
+    /// Counts the files and bytes under `path`, failing as soon as either
+    /// is over its limit.
+    fn measure(&self, path: &Path, files: &mut usize, bytes: &mut u64) -> io::Result<()> {
+        if path.starts_with(&self.dir) {
+            return Ok(());
+        }
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if metadata.is_dir() {
+            for child in fs::read_dir(path)? {
+                self.measure(&child?.path(), files, bytes)?;
+            }
+            return Ok(());
+        }
+        *files += 1;
+        if metadata.is_file() {
+            *bytes += metadata.len();
+        }
+        if *files > self.max_files || *bytes > self.max_bytes {
+            return Err(io::Error::other(format!(
+                "'{}' is too big to checkpoint (more than {} files or {} MB)",
+                path.strip_prefix(&self.root).unwrap_or(path).display(),
+                self.max_files,
+                self.max_bytes / (1024 * 1024)
+            )));
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self, path: &Path, dir: &Path, entries: &mut Vec<Entry>) -> io::Result<()> {
+        if path.starts_with(&self.dir) {
+            return Ok(());
+        }
+        let relative = path.strip_prefix(&self.root).unwrap_or(path).to_path_buf();
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                entries.push(Entry {
+                    path: relative,
+                    state: State::Missing,
+                });
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        if metadata.file_type().is_symlink() {
+            entries.push(Entry {
+                path: relative,
+                state: State::Symlink(fs::read_link(path)?),
+            });
+        } else if metadata.is_dir() {
+            entries.push(Entry {
+                path: relative,
+                state: State::Dir,
+            });
+            for child in fs::read_dir(path)? {
+                self.snapshot(&child?.path(), dir, entries)?;
+            }
+        } else {
+            let blob = entries.len().to_string();
+            fs::copy(path, dir.join(&blob))?;
+            entries.push(Entry {
+                path: relative,
+                state: State::File(blob),
+            });
+        }
+        Ok(())
+    }
+
+    fn apply(&self, checkpoint: &Checkpoint) -> io::Result<()> {
+        let dir = self.checkpoint_dir(checkpoint.id);
+        // Parents come before their children in a snapshot
+        for entry in &checkpoint.entries {
+            let path = self.root.join(&entry.path);
+            match &entry.state {
+                State::Missing => remove(&path)?,
+                State::Dir => {
+                    if !path.is_dir() || is_symlink(&path) {
+                        remove(&path)?;
+                    }
+                    fs::create_dir_all(&path)?;
+                }
+                State::File(blob) => {
+                    if path.is_dir() || is_symlink(&path) {
+                        remove(&path)?;
+                    }
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::copy(dir.join(blob), &path)?;
+                }
+                State::Symlink(target) => {
+                    remove(&path)?;
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    symlink(target, &path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let checkpoints = self.list()?;
+        let excess = checkpoints.len().saturating_sub(MAX_CHECKPOINTS);
+        for checkpoint in &checkpoints[..excess] {
+            self.discard(checkpoint.id)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_manifest(dir: &Path, checkpoint: &Checkpoint) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(checkpoint).map_err(io::Error::other)?;
+    fs::write(dir.join(MANIFEST), json)
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+/// Removes whatever is at `path`, if anything. Links are removed, not followed.
+fn remove(path: &Path) -> io::Result<()> {
+    let result = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, path: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, path)
+}
@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 use std::io;
 
@@ -7,6 +8,80 @@ pub struct Config {
     pub llm: LlmConfig,
     #[serde(default)]
     pub web: WebConfig,
+    #[serde(default)]
+    pub agent: AgentConfig,
+    /// Problems worth telling the user about, such as keys in an old place
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+/// Keys that used to be read from `[llm]` and now belong to `[agent]`.
+const MOVED_TO_AGENT: &[&str] = &[
+    "max_attempts",
+    "shell",
+    "post_write_verify",
+    "workspace_root",
+    "safe_fs",
+];
+
+/// How the agent works through a prompt (`[agent]`).
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AgentConfig {
+    /// Tool rounds per prompt before the model must give its final answer
+    pub max_attempts: usize,
+    /// Shell that runs RUN_COMMAND commands
+    pub shell: String,
+    /// Re-read written files and run a quick syntax check on them
+    pub post_write_verify: bool,
+    /// Directory the tools work in; the current directory when empty
+    pub workspace_root: String,
+    /// Reject tool paths that lead outside `workspace_root`
+    pub safe_fs: bool,
+    /// Seconds a tool's commands may run before they are killed; 0 for no limit
+    pub command_timeout_secs: u64,
+    /// Per-tool overrides of `command_timeout_secs`, e.g. `RUN_COMMAND = 900`
+    pub tool_timeouts: HashMap<String, u64>,
+    /// Characters kept from the start of long command output
+    pub output_head_chars: usize,
+    /// Characters kept from the end of long command output
+    pub output_tail_chars: usize,
+    /// Tokens the repository map in the system prompt may use; 0 leaves
+    /// the map out
+    pub repo_map_tokens: usize,
+    /// Keep a user-level memory store in `~/.rct/memory.json` next to the
+    /// one in the workspace
+    pub user_memory: bool,
+    /// Memories most relevant to the prompt added to each user turn; 0
+    /// leaves recall to RECALL
+    pub recall_memories: usize,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 12,
+            shell: default_shell().to_string(),
+            post_write_verify: true,
+            workspace_root: String::new(),
+            safe_fs: true,
+            command_timeout_secs: 300,
+            tool_timeouts: HashMap::new(),
+            output_head_chars: 6_000,
+            output_tail_chars: 4_000,
+            repo_map_tokens: 1_500,
+            user_memory: true,
+            recall_memories: 5,
+        }
+    }
+}
+
+pub fn default_shell() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "cmd"
+    } else {
+        "sh"
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, Default)]
@@ -27,13 +102,111 @@ pub struct LlmConfig {
     pub api_key: String,
     pub api_base_url: String,
     pub model_name: String,
+    #[serde(default)]
+    pub tool_protocol: ToolProtocol,
+    /// Upper bound on generated tokens; required by the Anthropic API
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Context window of the model in tokens; guessed from the model name
+    /// when unset
+    #[serde(default)]
+    pub context_window: Option<u64>,
+    /// Embedding model on the same endpoint that SEMANTIC_SEARCH re-ranks
+    /// its results with; BM25 ranking alone when unset
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    #[serde(default)]
+    pub ollama: OllamaConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+/// Retries for rate limits, timeouts, 5xx responses and dropped connections.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+/// Options for the native Ollama backend (`provider = "ollama"`).
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct OllamaConfig {
+    /// Context window to load the model with; Ollama's default is small
+    pub num_ctx: Option<u32>,
+    /// How long the model stays loaded after a request, e.g. "30m" or "-1"
+    pub keep_alive: Option<String>,
+    /// Extra model options passed through as-is (temperature, top_p, ...)
+    #[serde(default)]
+    pub options: HashMap<String, serde_json::Value>,
+}
+
+/// How tools are offered to the model and how its tool calls are read back.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolProtocol {
+    /// OpenAI-style function calling via the `tools` request field
+    #[default]
+    Native,
+    /// `TOOL: {...}` lines scraped from the reply text, for servers without
+    /// function calling support
+    Text,
 }
 
 impl Config {
     pub fn from_file(path: &str) -> Result<Self, io::Error> {
         let contents = fs::read_to_string(path)?;
-        let config: Config =
-            toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Self::parse(&contents)
+    }
+
+    /// Reads a config. Keys that moved from `[llm]` to `[agent]` are still
+    /// taken from `[llm]` when `[agent]` doesn't set them, with a warning.
+    pub fn parse(contents: &str) -> Result<Self, io::Error> {
+        let invalid = |e: toml::de::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut table: toml::Table = toml::from_str(contents).map_err(invalid)?;
+        let moved: Vec<(&str, toml::Value)> = match table.get_mut("llm") {
+            Some(toml::Value::Table(llm)) => MOVED_TO_AGENT
+                .iter()
+                .filter_map(|&key| llm.remove(key).map(|value| (key, value)))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut warnings = Vec::new();
+        if !moved.is_empty() {
+            let agent = table
+                .entry("agent")
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            for (key, value) in moved {
+                match agent {
+                    toml::Value::Table(agent) if !agent.contains_key(key) => {
+                        agent.insert(key.to_string(), value);
+                        warnings.push(format!(
+                            "`{}` belongs under [agent] now; it was read from [llm]",
+                            key
+                        ));
+                    }
+                    _ => warnings.push(format!(
+                        "`{}` under [llm] is ignored; the one under [agent] is used",
+                        key
+                    )),
+                }
+            }
+        }
+
+        let mut config: Config = toml::Value::Table(table).try_into().map_err(invalid)?;
+        config.warnings = warnings;
         Ok(config)
     }
 }
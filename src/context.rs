@@ -0,0 +1,214 @@
+use crate::config::LlmConfig;
+use crate::llm::Message;
+
+/// Used when neither the config nor the model name tells us the window size.
+const DEFAULT_CONTEXT_WINDOW: u64 = 32_768;
+
+/// Tokens kept free for the reply when `max_tokens` isn't configured.
+const DEFAULT_REPLY_RESERVE: u64 = 4_096;
+
+/// Compaction starts once the prompt uses this share of the usable budget.
+const COMPACT_AT_PERCENT: u64 = 80;
+
+/// Longest message content quoted verbatim in a summary request.
+const SUMMARY_MESSAGE_CHARS: usize = 4_000;
+
+/// Upper bound on the length of the "characters removed" marker.
+const MARKER_CHARS: usize = 80;
+
+/// Known context windows, matched as substrings of the lowercased model
+/// name. More specific names must come before their prefixes.
+const MODEL_WINDOWS: &[(&str, u64)] = &[
+    ("claude", 200_000),
+    ("gpt-4.1", 1_000_000),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5", 16_385),
+    ("gpt-5", 400_000),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+    ("gemini", 1_000_000),
+    ("llama3.1", 128_000),
+    ("llama3.2", 128_000),
+    ("llama3.3", 128_000),
+    ("llama3", 8_192),
+    ("qwen2.5", 32_768),
+    ("qwen3", 40_960),
+    ("deepseek", 64_000),
+    ("mistral", 32_768),
+    ("codestral", 32_768),
+];
+
+/// Guesses the context window of `model` from its name.
+pub fn context_window_for_model(model: &str) -> u64 {
+    let model = model.to_lowercase();
+    MODEL_WINDOWS
+        .iter()
+        .find(|(name, _)| model.contains(name))
+        .map(|(_, window)| *window)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+/// How many prompt tokens a request may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextBudget {
+    /// Total context window of the model
+    pub window: u64,
+    /// Part of the window kept free for the reply
+    pub reserve: u64,
+}
+
+impl ContextBudget {
+    /// `context_window` from config wins, then Ollama's `num_ctx`, then a
+    /// guess from the name of `model`, the one requests are sent to (see
+    /// `llm::active_model`; `model_name` may be `AUTODETECT`).
+    pub fn for_config(config: &LlmConfig, model: &str) -> Self {
+        let window = config
+            .context_window
+            .or(config.ollama.num_ctx.map(u64::from))
+            .unwrap_or_else(|| context_window_for_model(model));
+        let reserve = config
+            .max_tokens
+            .map(u64::from)
+            .unwrap_or(DEFAULT_REPLY_RESERVE)
+            .min(window / 4);
+        Self { window, reserve }
+    }
+
+    /// Prompt tokens that fit next to the reply.
+    pub fn limit(&self) -> u64 {
+        self.window - self.reserve
+    }
+
+    /// Prompt size above which the conversation should be compacted.
+    pub fn threshold(&self) -> u64 {
+        self.limit() * COMPACT_AT_PERCENT / 100
+    }
+}
+
+/// Text-protocol tool results are sent back as user messages; they are part
+/// of the turn that requested them, not the start of a new one.
+pub fn is_tool_result(message: &Message) -> bool {
+    message.role == "tool" || (message.role == "user" && message.content.starts_with("Tool result"))
+}
+
+fn is_user_prompt(message: &Message) -> bool {
+    message.role == "user" && !is_tool_result(message)
+}
+
+/// Number of leading system messages (the system prompt and its reminders),
+/// which are never compacted.
+pub fn preamble_len(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .take_while(|message| message.role == "system")
+        .count()
+}
+
+/// Index of the first message of the last `turns` user turns. Cutting here
+/// never separates a tool call from its result.
+pub fn recent_turns_start(messages: &[Message], turns: usize) -> usize {
+    let preamble = preamble_len(messages);
+    let starts: Vec<usize> = messages
+        .iter()
+        .enumerate()
+        .skip(preamble)
+        .filter(|(_, message)| is_user_prompt(message))
+        .map(|(i, _)| i)
+        .collect();
+    match starts.len().checked_sub(turns) {
+        Some(i) => starts[i],
+        None => preamble,
+    }
+}
+
+/// Keeps the beginning and end of `text` if it's longer than `max_chars`.
+fn shorten(text: &str, max_chars: usize) -> Option<String> {
+    let total = text.chars().count();
+    if total <= max_chars {
+        return None;
+    }
+    // Leave room for the marker so shortened text is never shortened again
+    let keep = max_chars.saturating_sub(MARKER_CHARS);
+    let head_len = keep * 3 / 4;
+    let tail_len = keep - head_len;
+    let head: String = text.chars().take(head_len).collect();
+    let tail: String = text.chars().skip(total - tail_len).collect();
+    Some(format!(
+        "{}\n[... {} characters removed to save context ...]\n{}",
+        head,
+        total - head_len - tail_len,
+        tail
+    ))
+}
+
+/// Shortens every tool result in `messages[..end]` longer than `max_chars`
+/// to its beginning and end. Returns how many were shortened.
+pub fn truncate_tool_results(messages: &mut [Message], end: usize, max_chars: usize) -> usize {
+    let mut truncated = 0;
+    for message in messages[..end].iter_mut().filter(|m| is_tool_result(m)) {
+        if let Some(short) = shorten(&message.content, max_chars) {
+            message.content = short;
+            truncated += 1;
+        }
+    }
+    truncated
+}
+
+/// Builds the request asking the model to summarize `messages`. Long
+/// messages are shortened so the request itself fits in the window.
+pub fn summary_request(messages: &[Message]) -> Vec<Message> {
+    let mut transcript = String::new();
+    for message in messages {
+        transcript.push_str(&format!("[{}]\n", message.role));
+        if !message.content.is_empty() {
+            let content = shorten(&message.content, SUMMARY_MESSAGE_CHARS);
+            transcript.push_str(content.as_deref().unwrap_or(&message.content));
+            transcript.push('\n');
+        }
+        for call in message.tool_calls.iter().flatten() {
+            transcript.push_str(&format!(
+                "(called {} {})\n",
+                call.function.name, call.function.arguments
+            ));
+        }
+        transcript.push('\n');
+    }
+
+    vec![
+        Message {
+            role: "system".to_string(),
+            content: "You compress coding-assistant conversations. Summarize the transcript \
+                      so the assistant can continue the work without it. Keep: the user's \
+                      requests and constraints, decisions made, files created or changed and \
+                      why, commands run and their important results, errors still open, and \
+                      what was about to happen next. Be concise and factual; use bullet points."
+                .to_string(),
+            ..Default::default()
+        },
+        Message {
+            role: "user".to_string(),
+            content: format!("Transcript to summarize:\n\n{}", transcript),
+            ..Default::default()
+        },
+    ]
+}
+
+/// Replaces `messages[start..end]` with a single summary message.
+pub fn replace_with_summary(
+    messages: &mut Vec<Message>,
+    start: usize,
+    end: usize,
+    summary: String,
+) {
+    messages.splice(
+        start..end,
+        std::iter::once(Message {
+            role: "user".to_string(),
+            content: summary,
+            ..Default::default()
+        }),
+    );
+}
@@ -0,0 +1,178 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Matches listed in messages before the rest are summarised.
+const MAX_LISTED_LINES: usize = 10;
+
+/// Characters of `old_string` quoted in error messages.
+const PREVIEW_CHARS: usize = 80;
+
+/// One old/new pair of a MULTI_EDIT.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub old_string: String,
+    pub new_string: String,
+    /// Replace every match instead of requiring exactly one
+    #[serde(default)]
+    pub replace_all: bool,
+    /// Fall back to a match that differs only in whitespace
+    #[serde(default)]
+    pub ignore_whitespace: bool,
+}
+
+impl Edit {
+    pub fn apply(&self, content: &str) -> io::Result<(String, Replaced)> {
+        replace(
+            content,
+            &self.old_string,
+            &self.new_string,
+            self.replace_all,
+            self.ignore_whitespace,
+        )
+    }
+}
+
+/// Where an edit matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replaced {
+    /// 1-based line of each match, in the text the edit was applied to
+    pub lines: Vec<usize>,
+    /// Only matched once whitespace was ignored
+    pub loose: bool,
+}
+
+impl Replaced {
+    /// E.g. "2 occurrences (lines 3, 9)".
+    pub fn describe(&self) -> String {
+        let count = self.lines.len();
+        let what = if count == 1 {
+            "1 occurrence".to_string()
+        } else {
+            format!("{} occurrences", count)
+        };
+        let loose = if self.loose {
+            ", ignoring whitespace"
+        } else {
+            ""
+        };
+        format!("{} ({}{})", what, list_lines(&self.lines), loose)
+    }
+}
+
+/// Replaces `old` with `new` in `content`.
+///
+/// `old` must match exactly once unless `replace_all` is set; an ambiguous
+/// match is an error listing where the matches are. When nothing matches
+/// exactly and `ignore_whitespace` is set, text that differs from `old` only
+/// in the amount or kind of whitespace matches instead.
+pub fn replace(
+    content: &str,
+    old: &str,
+    new: &str,
+    replace_all: bool,
+    ignore_whitespace: bool,
+) -> io::Result<(String, Replaced)> {
+    if old.is_empty() {
+        return Err(invalid("old_string is empty".to_string()));
+    }
+
+    let mut spans: Vec<(usize, usize)> = content
+        .match_indices(old)
+        .map(|(start, matched)| (start, start + matched.len()))
+        .collect();
+    let mut loose = false;
+    if spans.is_empty() {
+        let loose_spans = loose_matches(content, old);
+        if loose_spans.is_empty() {
+            return Err(invalid(format!("'{}' was not found", preview(old))));
+        }
+        if !ignore_whitespace {
+            return Err(invalid(format!(
+                "'{}' was not found exactly, but text that differs only in whitespace is at {}. \
+                 Copy the text exactly or set ignore_whitespace: true",
+                preview(old),
+                list_lines(&line_numbers(content, &loose_spans))
+            )));
+        }
+        spans = loose_spans;
+        loose = true;
+    }
+
+    let lines = line_numbers(content, &spans);
+    if spans.len() > 1 && !replace_all {
+        return Err(invalid(format!(
+            "'{}' matches {} times ({}). Include more surrounding text so it matches once, \
+             or set replace_all: true",
+            preview(old),
+            spans.len(),
+            list_lines(&lines)
+        )));
+    }
+
+    let mut replaced = String::with_capacity(content.len());
+    let mut last = 0;
+    for (start, end) in spans {
+        replaced.push_str(&content[last..start]);
+        replaced.push_str(new);
+        last = end;
+    }
+    replaced.push_str(&content[last..]);
+    Ok((replaced, Replaced { lines, loose }))
+}
+
+/// Matches of `old` where every run of whitespace may be any other run of
+/// whitespace, e.g. after indentation changed or a line was re-wrapped.
+fn loose_matches(content: &str, old: &str) -> Vec<(usize, usize)> {
+    let words: Vec<String> = old.split_whitespace().map(regex::escape).collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+    match Regex::new(&words.join(r"\s+")) {
+        Ok(pattern) => pattern
+            .find_iter(content)
+            .map(|found| (found.start(), found.end()))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn line_numbers(content: &str, spans: &[(usize, usize)]) -> Vec<usize> {
+    let mut line = 1;
+    let mut counted = 0;
+    spans
+        .iter()
+        .map(|&(start, _)| {
+            line += content[counted..start].matches('\n').count();
+            counted = start;
+            line
+        })
+        .collect()
+}
+
+fn list_lines(lines: &[usize]) -> String {
+    let mut listed: Vec<String> = lines
+        .iter()
+        .take(MAX_LISTED_LINES)
+        .map(|line| line.to_string())
+        .collect();
+    if lines.len() > MAX_LISTED_LINES {
+        listed.push(format!("and {} more", lines.len() - MAX_LISTED_LINES));
+    }
+    let noun = if lines.len() == 1 { "line" } else { "lines" };
+    format!("{} {}", noun, listed.join(", "))
+}
+
+/// First line of `old`, shortened, for error messages.
+fn preview(old: &str) -> String {
+    let first = old.trim().lines().next().unwrap_or("");
+    let mut shown: String = first.chars().take(PREVIEW_CHARS).collect();
+    if shown.len() < old.trim().len() {
+        shown.push_str("...");
+    }
+    shown
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
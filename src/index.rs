@@ -0,0 +1,359 @@
+use crate::search;
+use crate::symbols::{self, Reference, Symbol};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Where INDEX_CODEBASE keeps its index, relative to the workspace root.
+pub const INDEX_FILE: &str = ".agent_index.json";
+
+/// Bumped whenever the layout of the index or what goes into it changes,
+/// so that an old index is rebuilt instead of misread.
+const VERSION: u32 = 3;
+
+/// What the index knows about one file. The file is read again only when
+/// its size or mtime changed, and its symbols are extracted again only
+/// when its content did too.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileEntry {
+    /// Milliseconds since the Unix epoch
+    pub modified: u64,
+    pub size: u64,
+    pub hash: String,
+    pub symbols: Vec<Symbol>,
+    /// Calls made in the file
+    #[serde(default)]
+    pub references: Vec<Reference>,
+}
+
+/// A call site of a symbol.
+#[derive(Debug, Clone)]
+pub struct Caller<'a> {
+    pub path: &'a str,
+    pub reference: &'a Reference,
+}
+
+impl fmt::Display for Caller<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path, self.reference.line)?;
+        match &self.reference.caller {
+            Some(caller) => write!(f, " in {}", caller),
+            None => write!(f, " at top level"),
+        }
+    }
+}
+
+/// Counts from an index update.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Update {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+/// A search result.
+#[derive(Debug, Clone)]
+pub struct Hit<'a> {
+    pub path: &'a str,
+    pub symbol: &'a Symbol,
+    pub score: u32,
+}
+
+impl fmt::Display for Hit<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{} {} {}",
+            self.path, self.symbol.line, self.symbol.kind, self.symbol.name
+        )?;
+        if let Some(parent) = &self.symbol.parent {
+            write!(f, " (in {})", parent)?;
+        }
+        write!(f, ": {}", self.symbol.signature)
+    }
+}
+
+/// The symbol index of a workspace, keyed by path relative to its root.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Index {
+    version: u32,
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+enum Status {
+    Added,
+    Changed,
+    Unchanged,
+}
+
+impl Index {
+    /// The saved index, or an empty one if there is none or it was written
+    /// by an older version.
+    pub fn load(root: &Path) -> Index {
+        fs::read_to_string(root.join(INDEX_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Index>(&json).ok())
+            .filter(|index| index.version == VERSION)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(root.join(INDEX_FILE), json)
+    }
+
+    pub fn symbol_count(&self) -> usize {
+        self.files.values().map(|file| file.symbols.len()).sum()
+    }
+
+    /// Brings the index up to date with the source files under `start`,
+    /// skipping whatever .gitignore leaves out. Files that are gone are
+    /// dropped; files elsewhere in the workspace are left alone.
+    pub fn update(&mut self, root: &Path, start: &Path) -> io::Result<Update> {
+        let files = search::files(root, start, &search::Filter::default())?;
+        Ok(self.update_files(root, start, &files))
+    }
+
+    /// Like `update`, for callers that already listed the files under
+    /// `start`, relative to `root`.
+    pub fn update_files(&mut self, root: &Path, start: &Path, files: &[String]) -> Update {
+        let mut update = Update::default();
+        let mut seen = HashSet::new();
+        for path in files {
+            if !symbols::is_supported(path) {
+                continue;
+            }
+            match self.refresh_file(root, path) {
+                Ok(Status::Added) => update.added += 1,
+                Ok(Status::Changed) => update.changed += 1,
+                Ok(Status::Unchanged) => update.unchanged += 1,
+                Err(_) => continue,
+            }
+            seen.insert(path.as_str());
+        }
+
+        let below = start
+            .strip_prefix(root)
+            .unwrap_or(start)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let gone: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| !seen.contains(path.as_str()) && is_below(path, &below))
+            .cloned()
+            .collect();
+        update.removed = gone.len();
+        for path in gone {
+            self.files.remove(&path);
+        }
+        update
+    }
+
+    /// Re-reads the files already in the index that changed since they
+    /// were indexed, and drops the ones that were deleted.
+    pub fn refresh(&mut self, root: &Path) -> Update {
+        let mut update = Update::default();
+        let paths: Vec<String> = self.files.keys().cloned().collect();
+        for path in paths {
+            match self.refresh_file(root, &path) {
+                Ok(Status::Unchanged) => update.unchanged += 1,
+                Ok(_) => update.changed += 1,
+                Err(_) => {
+                    self.files.remove(&path);
+                    update.removed += 1;
+                }
+            }
+        }
+        update
+    }
+
+    fn refresh_file(&mut self, root: &Path, path: &str) -> io::Result<Status> {
+        let full = root.join(path);
+        let metadata = fs::metadata(&full)?;
+        let modified = modified_millis(&metadata);
+        let size = metadata.len();
+        let known = self.files.get_mut(path);
+        if let Some(entry) = &known {
+            if entry.modified == modified && entry.size == size {
+                return Ok(Status::Unchanged);
+            }
+        }
+
+        let bytes = fs::read(&full)?;
+        let hash = format!("{:016x}", fnv1a(&bytes));
+        let status = match known {
+            Some(entry) if entry.hash == hash => {
+                entry.modified = modified;
+                entry.size = size;
+                return Ok(Status::Unchanged);
+            }
+            Some(_) => Status::Changed,
+            None => Status::Added,
+        };
+        let extracted = symbols::extract(path, &String::from_utf8_lossy(&bytes));
+        self.files.insert(
+            path.to_string(),
+            FileEntry {
+                modified,
+                size,
+                hash,
+                symbols: extracted.symbols,
+                references: extracted.references,
+            },
+        );
+        Ok(status)
+    }
+
+    /// Symbols matching `query`, best first. Exact names rank above
+    /// prefixes, whole words of a name (`read` in `read_file` or
+    /// `readFile`) above other substrings, and those above names that only
+    /// contain the query's letters in order. `Type::name` and `Type.name`
+    /// match methods by their parent too.
+    pub fn search(&self, query: &str) -> Vec<Hit<'_>> {
+        let query = query.trim();
+        let mut hits: Vec<Hit> = self
+            .files
+            .iter()
+            .flat_map(|(path, file)| file.symbols.iter().map(move |symbol| (path, symbol)))
+            .filter_map(|(path, symbol)| {
+                score(symbol, query).map(|score| Hit {
+                    path,
+                    symbol,
+                    score,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(a.symbol.name.len().cmp(&b.symbol.name.len()))
+                .then(a.path.cmp(b.path))
+                .then(a.symbol.line.cmp(&b.symbol.line))
+        });
+        hits
+    }
+
+    /// Where the function, method or macro `name` is called, by path and
+    /// line. Calls are matched by name alone, so for `Type::name` or
+    /// `Type.name` every call of a `name` is included.
+    pub fn callers(&self, name: &str) -> Vec<Caller<'_>> {
+        let name = name.trim();
+        let name = name
+            .rsplit_once("::")
+            .or_else(|| name.rsplit_once('.'))
+            .map_or(name, |(_, name)| name)
+            .trim_end_matches("()")
+            .trim_end_matches('!');
+        self.files
+            .iter()
+            .flat_map(|(path, file)| {
+                file.references
+                    .iter()
+                    .filter(|reference| reference.name == name)
+                    .map(move |reference| Caller { path, reference })
+            })
+            .collect()
+    }
+}
+
+/// When a file was last modified, in milliseconds since the Unix epoch.
+pub(crate) fn modified_millis(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+pub(crate) fn is_below(path: &str, dir: &str) -> bool {
+    dir.is_empty()
+        || dir == "."
+        || path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn score(symbol: &Symbol, query: &str) -> Option<u32> {
+    if query.is_empty() {
+        return None;
+    }
+    if let Some((parent, name)) = query.rsplit_once("::").or_else(|| query.rsplit_once('.')) {
+        let parent_matches = symbol
+            .parent
+            .as_deref()
+            .is_some_and(|p| p.eq_ignore_ascii_case(parent));
+        return (parent_matches && symbol.name.eq_ignore_ascii_case(name)).then_some(100);
+    }
+
+    let name = symbol.name.to_lowercase();
+    let lower = query.to_lowercase();
+    let score = if symbol.name == query {
+        100
+    } else if name == lower {
+        90
+    } else if name.starts_with(&lower) {
+        70
+    } else if words(&symbol.name).contains(&lower) {
+        60
+    } else if name.contains(&lower) {
+        40
+    } else if is_subsequence(&lower, &name) {
+        20
+    } else if symbol.signature.to_lowercase().contains(&lower) {
+        10
+    } else {
+        return None;
+    };
+    Some(score)
+}
+
+/// The lowercase words of a snake_case or camelCase name.
+pub(crate) fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        let boundary = c == '_' || c == '-' || (c.is_uppercase() && previous_lower);
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if c != '_' && c != '-' {
+            word.extend(c.to_lowercase());
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut rest = haystack.chars();
+    needle.chars().all(|c| rest.any(|h| h == c))
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is the same in every build.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -1,4 +1,19 @@
 pub mod agent;
 pub mod app;
+pub mod checkpoint;
 pub mod config;
+pub mod context;
+pub mod edit;
+pub mod index;
 pub mod llm;
+pub mod memory;
+pub mod patch;
+pub mod permissions;
+pub mod process;
+pub mod read;
+pub mod repomap;
+pub mod search;
+pub mod semantic;
+pub mod symbols;
+pub mod verify;
+pub mod workspace;
@@ -1,30 +1,148 @@
-use crate::config::LlmConfig;
-use futures_util::StreamExt;
-use reqwest::Client;
+pub mod anthropic;
+pub mod ollama;
+pub mod openai;
+pub mod sse;
+
+use crate::config::{LlmConfig, RetryConfig};
+use anthropic::AnthropicProvider;
+use async_trait::async_trait;
+use futures_util::{stream, Stream, StreamExt};
+use ollama::OllamaProvider;
+use once_cell::sync::Lazy;
+use openai::OpenAiProvider;
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
-
-// Token estimation function (rough approximation based on GPT tokenization)
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+
+/// Counts tokens with the cl100k BPE vocabulary embedded in the binary. It
+/// is exact for OpenAI models and close enough for others; only used when
+/// the server doesn't report usage itself.
 pub fn estimate_token_count(text: &str) -> u64 {
-    // Rough approximation: ~4 characters per token for English text
-    // This is not exact but provides a reasonable estimate
-    let char_count = text.chars().count() as f64;
-    let estimated_tokens = (char_count / 4.0).ceil() as u64;
-
-    // Ensure minimum of 1 token for non-empty text
-    if estimated_tokens == 0 && !text.trim().is_empty() {
-        1
-    } else {
-        estimated_tokens
+    tiktoken_rs::cl100k_base_singleton()
+        .encode_ordinary(text)
+        .len() as u64
+}
+
+/// Tokenizer fallback for the prompt side of a request.
+pub fn estimate_prompt_tokens(messages: &[Message]) -> u64 {
+    messages
+        .iter()
+        .map(|message| {
+            let calls: u64 = message
+                .tool_calls
+                .iter()
+                .flatten()
+                .map(|call| {
+                    estimate_token_count(&call.function.name)
+                        + estimate_token_count(&call.function.arguments)
+                })
+                .sum();
+            // Role and message framing cost a few tokens on top of the content
+            MESSAGE_OVERHEAD_TOKENS + estimate_token_count(&message.content) + calls
+        })
+        .sum()
+}
+
+/// Tokenizer fallback for a reply that came back without usage numbers.
+pub fn estimate_usage(messages: &[Message], reply: &Message) -> TokenUsage {
+    TokenUsage {
+        prompt_tokens: estimate_prompt_tokens(messages),
+        completion_tokens: estimate_prompt_tokens(std::slice::from_ref(reply))
+            - MESSAGE_OVERHEAD_TOKENS,
     }
 }
 
+const MESSAGE_OVERHEAD_TOKENS: u64 = 4;
+
 #[derive(Debug)]
 pub enum LlmError {
     RequestFailed(reqwest::Error),
     ApiError(String),
     ParseError(String),
+    /// HTTP 429; `retry_after` comes from the `Retry-After` header
+    RateLimited {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    /// Missing, invalid or unauthorized API key (HTTP 401/403)
+    Auth(String),
+    /// The prompt no longer fits in the model's context window
+    ContextLengthExceeded(String),
+    Timeout(String),
+    /// 5xx responses, including overloaded servers
+    Server {
+        status: u16,
+        message: String,
+    },
+}
+
+impl LlmError {
+    /// Builds a typed error from a non-success response.
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(e) => return LlmError::RequestFailed(e),
+        };
+        Self::from_status(status.as_u16(), retry_after, body)
+    }
+
+    pub fn from_status(status: u16, retry_after: Option<Duration>, body: String) -> Self {
+        let lower = body.to_lowercase();
+        let message = format!("HTTP {}: {}", status, body);
+        match status {
+            401 | 403 => LlmError::Auth(message),
+            429 => LlmError::RateLimited {
+                retry_after,
+                message,
+            },
+            408 | 504 => LlmError::Timeout(message),
+            400 | 413
+                if [
+                    "context_length",
+                    "context length",
+                    "prompt is too long",
+                    "too many tokens",
+                ]
+                .iter()
+                .any(|marker| lower.contains(marker)) =>
+            {
+                LlmError::ContextLengthExceeded(message)
+            }
+            500..=599 => LlmError::Server { status, message },
+            _ => LlmError::ApiError(message),
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LlmError::RateLimited { .. } | LlmError::Timeout(_) | LlmError::Server { .. } => true,
+            LlmError::RequestFailed(e) => !e.is_builder() && !e.is_status(),
+            _ => false,
+        }
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<f64>() {
+        return (seconds >= 0.0).then(|| Duration::from_secs_f64(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
 }
 
 impl fmt::Display for LlmError {
@@ -33,6 +151,11 @@ impl fmt::Display for LlmError {
             LlmError::RequestFailed(e) => write!(f, "Request failed: {}", e),
             LlmError::ApiError(msg) => write!(f, "API error: {}", msg),
             LlmError::ParseError(msg) => write!(f, "Parse error: {}", msg),
+            LlmError::RateLimited { message, .. } => write!(f, "Rate limited: {}", message),
+            LlmError::Auth(msg) => write!(f, "Authentication failed: {}", msg),
+            LlmError::ContextLengthExceeded(msg) => write!(f, "Context length exceeded: {}", msg),
+            LlmError::Timeout(msg) => write!(f, "Timed out: {}", msg),
+            LlmError::Server { message, .. } => write!(f, "Server error: {}", message),
         }
     }
 }
@@ -41,250 +164,461 @@ impl Error for LlmError {}
 
 impl From<reqwest::Error> for LlmError {
     fn from(err: reqwest::Error) -> Self {
-        LlmError::RequestFailed(err)
+        if err.is_timeout() {
+            LlmError::Timeout(err.to_string())
+        } else {
+            LlmError::RequestFailed(err)
+        }
     }
 }
 
-#[derive(Deserialize, Debug)]
-struct ModelsResponse {
-    data: Vec<ModelInfo>,
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Message {
+    pub role: String,
+    // Assistant messages that only carry tool calls come back with `"content": null`
+    #[serde(default, deserialize_with = "deserialize_nullable_string")]
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallRequest>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
-struct ModelInfo {
-    id: String,
+fn deserialize_nullable_string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
 }
 
-#[derive(Serialize, Debug)]
-struct ChatCompletionRequest {
-    model: String,
-    messages: Vec<Message>,
-    stream: bool,
+/// A function call requested by the model, in the OpenAI wire format.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ToolCallRequest {
+    pub id: String,
+    #[serde(rename = "type", default = "default_tool_type")]
+    pub kind: String,
+    pub function: FunctionCall,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Message {
-    pub role: String,
-    pub content: String,
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments, exactly as the model produced them
+    #[serde(default)]
+    pub arguments: String,
 }
 
-#[derive(Deserialize, Debug)]
-struct ChatCompletionResponse {
-    choices: Vec<Choice>,
+fn default_tool_type() -> String {
+    "function".to_string()
 }
 
-#[derive(Deserialize, Debug)]
-struct ChatCompletionStreamResponse {
-    choices: Vec<StreamChoice>,
+/// A tool offered to the model through the `tools` array of a request.
+#[derive(Serialize, Debug, Clone)]
+pub struct ToolDefinition {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: FunctionDefinition,
 }
 
-#[derive(Deserialize, Debug)]
-struct StreamChoice {
-    delta: StreamDelta,
+#[derive(Serialize, Debug, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
 }
 
-#[derive(Deserialize, Debug)]
-struct StreamDelta {
-    content: Option<String>,
+impl ToolDefinition {
+    pub fn function(name: &str, description: &str, parameters: serde_json::Value) -> Self {
+        Self {
+            kind: default_tool_type(),
+            function: FunctionDefinition {
+                name: name.to_string(),
+                description: description.to_string(),
+                parameters,
+            },
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct Choice {
-    message: Message,
+/// A piece of a streamed assistant turn.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    Content(String),
+    ToolCall(ToolCallDelta),
+    /// Token counts reported by the server, usually at the end of the stream
+    Usage(TokenUsage),
 }
 
-pub async fn ask_llm_with_messages(
-    config: &LlmConfig,
-    messages: &[Message],
-) -> Result<(String, u64), LlmError> {
-    let client = Client::new();
-    let _provider = config.provider.as_deref().unwrap_or("openai");
-
-    // Calculate input tokens
-    let mut input_tokens = 0u64;
-    for message in messages {
-        input_tokens += estimate_token_count(&message.role);
-        input_tokens += estimate_token_count(&message.content);
+/// Token counts for one request, as reported by the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
     }
+}
 
-    // Determine model to use (support AUTODETECT from /models endpoint for OpenAI-compatible APIs)
-    let model_to_use = if config.model_name.eq_ignore_ascii_case("AUTODETECT")
-        || config.model_name.trim().is_empty()
-    {
-        // Fetch available models and use the first one
-        let models_url = format!("{}/models", config.api_base_url.trim_end_matches('/'));
+impl std::ops::Add for TokenUsage {
+    type Output = TokenUsage;
 
-        let mut models_request = client.get(&models_url);
-        if !config.api_key.is_empty() {
-            models_request = models_request.bearer_auth(&config.api_key);
+    fn add(self, other: TokenUsage) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
         }
-        let response = models_request.send().await?;
+    }
+}
 
-        let status = response.status();
-        let response_text = response.text().await?;
-        if !status.is_success() {
-            return Err(LlmError::ApiError(format!(
-                "HTTP {} while fetching models: {}",
-                status, response_text
-            )));
-        }
+/// A fragment of a tool call. The id and name usually arrive in the first
+/// fragment for an `index`, while `arguments` is spread over many of them.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ToolCallDelta {
+    #[serde(default)]
+    pub index: usize,
+    pub id: Option<String>,
+    pub function: Option<FunctionCallDelta>,
+}
 
-        let parsed_models: ModelsResponse = serde_json::from_str(&response_text).map_err(|e| {
-            LlmError::ParseError(format!("Failed to parse /models response: {}", e))
-        })?;
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FunctionCallDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+/// Reassembles streamed `ToolCallDelta`s into complete tool calls.
+#[derive(Debug, Default)]
+pub struct ToolCallAccumulator {
+    calls: Vec<ToolCallRequest>,
+}
 
-        match parsed_models.data.first() {
-            Some(first) => first.id.clone(),
-            None => {
-                return Err(LlmError::ApiError(
-                    "No models returned by /models".to_string(),
-                ))
+impl ToolCallAccumulator {
+    pub fn push(&mut self, delta: ToolCallDelta) {
+        while self.calls.len() <= delta.index {
+            self.calls.push(ToolCallRequest {
+                kind: default_tool_type(),
+                ..Default::default()
+            });
+        }
+        let call = &mut self.calls[delta.index];
+        if let Some(id) = delta.id.filter(|id| !id.is_empty()) {
+            call.id = id;
+        }
+        if let Some(function) = delta.function {
+            // Some servers repeat the name in every fragment, so only take the first one
+            if let Some(name) = function.name.filter(|n| !n.is_empty()) {
+                if call.function.name.is_empty() {
+                    call.function.name = name;
+                }
+            }
+            if let Some(arguments) = function.arguments {
+                call.function.arguments.push_str(&arguments);
             }
         }
-    } else {
-        config.model_name.clone()
-    };
-
-    let request_body = ChatCompletionRequest {
-        model: model_to_use,
-        messages: messages.to_vec(),
-        stream: false,
-    };
+    }
 
-    // First, get the raw response to debug
-    let mut completion_request = client
-        .post(format!(
-            "{}/chat/completions",
-            config.api_base_url.trim_end_matches('/')
-        ))
-        .json(&request_body);
-    if !config.api_key.is_empty() {
-        completion_request = completion_request.bearer_auth(&config.api_key);
+    /// Returns the finished calls, dropping nameless fragments and filling in
+    /// ids for servers that don't send them.
+    pub fn finish(self) -> Vec<ToolCallRequest> {
+        self.calls
+            .into_iter()
+            .enumerate()
+            .filter(|(_, call)| !call.function.name.is_empty())
+            .map(|(i, mut call)| {
+                if call.id.is_empty() {
+                    call.id = format!("call_{}", i);
+                }
+                call
+            })
+            .collect()
     }
-    let response = completion_request.send().await?;
-
-    // Check if the request was successful
-    let status = response.status();
-    if !status.is_success() {
-        let error_text = response.text().await?;
-        return Err(LlmError::ApiError(format!(
-            "HTTP {}: {}",
-            status, error_text
-        )));
+}
+
+/// Boxed stream of events produced by `LlmProvider::stream`.
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<StreamEvent, LlmError>> + Send>>;
+
+/// Splits a byte stream into lines. Network chunks can end anywhere, even in
+/// the middle of a UTF-8 sequence, so incomplete lines are kept until the
+/// rest arrives.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Appends `chunk` and returns every line it completed, without the
+    /// trailing `\n` or `\r\n`.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(pos) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            lines.push(Self::decode(&line[..pos]));
+        }
+        lines
     }
 
-    // Get the raw response text for debugging
-    let response_text = response.text().await?;
-
-    // Try to parse the response
-    match serde_json::from_str::<ChatCompletionResponse>(&response_text) {
-        Ok(parsed_response) => {
-            if let Some(choice) = parsed_response.choices.into_iter().next() {
-                let response_content = choice.message.content;
-                let output_tokens = estimate_token_count(&response_content);
-                let total_tokens = input_tokens + output_tokens;
-                Ok((response_content, total_tokens))
-            } else {
-                let response_content = "No response content available.".to_string();
-                let output_tokens = estimate_token_count(&response_content);
-                let total_tokens = input_tokens + output_tokens;
-                Ok((response_content, total_tokens))
-            }
+    /// Returns whatever is left once the stream has ended.
+    pub fn finish(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
         }
-        Err(e) => Err(LlmError::ParseError(format!(
-            "Failed to parse API response: {}",
-            e
-        ))),
+        let rest = std::mem::take(&mut self.pending);
+        Some(Self::decode(&rest))
+    }
+
+    fn decode(line: &[u8]) -> String {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        String::from_utf8_lossy(line).into_owned()
     }
 }
 
-/// Stream LLM responses in real-time using Server-Sent Events (SSE)
-pub async fn stream_llm_response(
-    config: &LlmConfig,
-    messages: &[Message],
-) -> Result<impl futures_util::Stream<Item = Result<String, LlmError>>, LlmError> {
-    let client = Client::new();
+/// Turns the raw chunks of a streaming response body into `StreamEvent`s,
+/// keeping whatever state it needs between chunks.
+pub trait ChunkDecoder {
+    fn decode(&mut self, chunk: &[u8]) -> Vec<Result<StreamEvent, LlmError>>;
 
-    // Determine model to use
-    let model_to_use = if config.model_name.eq_ignore_ascii_case("AUTODETECT")
-        || config.model_name.trim().is_empty()
-    {
-        // For streaming, we'll use the provided model or default to a common one
-        "gpt-3.5-turbo".to_string()
-    } else {
-        config.model_name.clone()
-    };
+    /// Called once after the last chunk to flush any buffered input.
+    fn finish(&mut self) -> Vec<Result<StreamEvent, LlmError>>;
+}
 
-    let request_body = ChatCompletionRequest {
-        model: model_to_use,
-        messages: messages.to_vec(),
-        stream: true,
-    };
+/// Drives `decoder` over a response body, flushing it when the body ends.
+pub fn decode_body<S, B, D>(body: S, decoder: D) -> EventStream
+where
+    S: Stream<Item = Result<B, reqwest::Error>> + Send + 'static,
+    B: AsRef<[u8]>,
+    D: ChunkDecoder + Send + 'static,
+{
+    let events = stream::unfold(
+        (Box::pin(body), decoder, false),
+        |(mut body, mut decoder, finished)| async move {
+            if finished {
+                return None;
+            }
+            match body.next().await {
+                Some(Ok(bytes)) => Some((decoder.decode(bytes.as_ref()), (body, decoder, false))),
+                Some(Err(e)) => {
+                    Some((vec![Err(LlmError::RequestFailed(e))], (body, decoder, true)))
+                }
+                None => Some((decoder.finish(), (body, decoder, true))),
+            }
+        },
+    )
+    .flat_map(stream::iter);
+    Box::pin(events)
+}
 
-    // Create streaming request
-    let mut request = client
-        .post(format!(
-            "{}/chat/completions",
-            config.api_base_url.trim_end_matches('/')
+/// A chat backend. Each implementation translates our OpenAI-shaped
+/// `Message`s and `ToolDefinition`s into its own wire format.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Ids of the models the endpoint offers, in the order it lists them.
+    async fn list_models(&self) -> Result<Vec<String>, LlmError>;
+
+    /// Single non-streaming completion returning the assistant message and
+    /// the tokens used, estimated if the server didn't say.
+    async fn complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<(Message, TokenUsage), LlmError>;
+
+    /// Streaming completion yielding content and tool call fragments, plus a
+    /// `StreamEvent::Usage` if the server reports token counts.
+    async fn stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<EventStream, LlmError>;
+
+    /// One embedding per input, in the same order.
+    async fn embed(&self, _model: &str, _inputs: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+        Err(LlmError::ApiError(
+            "This provider has no embeddings endpoint".to_string(),
         ))
-        .json(&request_body);
+    }
+}
 
-    if !config.api_key.is_empty() {
-        request = request.bearer_auth(&config.api_key);
+/// Models picked for `AUTODETECT`, keyed by provider and endpoint, so the
+/// model list is fetched once per session rather than once per request.
+static DETECTED_MODELS: Lazy<StdMutex<HashMap<String, String>>> = Lazy::new(Default::default);
+
+/// The configured model, or for `AUTODETECT` (or an empty name) the first
+/// model the endpoint lists.
+pub async fn resolve_model<P>(provider: &P, config: &LlmConfig) -> Result<String, LlmError>
+where
+    P: LlmProvider + ?Sized,
+{
+    let model = config.model_name.trim();
+    if !model.eq_ignore_ascii_case("AUTODETECT") && !model.is_empty() {
+        return Ok(model.to_string());
     }
 
-    let response = request.send().await?;
+    let key = format!(
+        "{}|{}",
+        config.provider.as_deref().unwrap_or("openai"),
+        config.api_base_url
+    );
+    if let Some(model) = DETECTED_MODELS.lock().unwrap().get(&key) {
+        return Ok(model.clone());
+    }
+    let model = provider
+        .list_models()
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| LlmError::ApiError("The endpoint returned no models".to_string()))?;
+    DETECTED_MODELS.lock().unwrap().insert(key, model.clone());
+    Ok(model)
+}
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await?;
-        return Err(LlmError::ApiError(format!(
-            "HTTP {}: {}",
-            status, error_text
-        )));
+/// The model requests are currently sent to, with `AUTODETECT` resolved.
+pub async fn active_model(config: &LlmConfig) -> Result<String, LlmError> {
+    resolve_model(provider_for(config).as_ref(), config).await
+}
+
+/// Models offered by the configured endpoint.
+pub async fn list_models(config: &LlmConfig) -> Result<Vec<String>, LlmError> {
+    let provider = provider_for(config);
+    with_retry(&config.retry, || provider.list_models()).await
+}
+
+/// Picks the backend named by `provider` in config.toml. Anything that isn't
+/// a dedicated backend is treated as an OpenAI-compatible endpoint.
+pub fn provider_for(config: &LlmConfig) -> Box<dyn LlmProvider> {
+    match config
+        .provider
+        .as_deref()
+        .unwrap_or("openai")
+        .to_lowercase()
+        .as_str()
+    {
+        "anthropic" | "claude" => Box::new(AnthropicProvider::new(config.clone())),
+        "ollama" => Box::new(OllamaProvider::new(config.clone())),
+        _ => Box::new(OpenAiProvider::new(config.clone())),
     }
+}
 
-    // Get the response body as a stream
-    let stream = response.bytes_stream();
-
-    // Convert the byte stream to a stream of String chunks
-    let string_stream = stream.map(move |result| {
-        match result {
-            Ok(bytes) => {
-                // Try to parse each chunk as SSE data
-                let chunk_str = String::from_utf8_lossy(&bytes);
-
-                // SSE format: "data: {...}\n\n"
-                // Process ALL data lines in this chunk, not just the first one
-                let mut collected_content = String::new();
-
-                for line in chunk_str.lines() {
-                    if let Some(data_line) = line.strip_prefix("data: ") {
-                        if data_line == "[DONE]" {
-                            continue; // End of stream marker
-                        } else if !data_line.is_empty() {
-                            // Parse the JSON chunk
-                            match serde_json::from_str::<ChatCompletionStreamResponse>(data_line) {
-                                Ok(parsed) => {
-                                    if let Some(choice) = parsed.choices.first() {
-                                        if let Some(content) = &choice.delta.content {
-                                            collected_content.push_str(content);
-                                        }
-                                    }
-                                }
-                                Err(_) => {
-                                    // Skip unparseable chunks silently
-                                }
-                            }
-                        }
-                    }
+/// `messages` with native tool calls and results turned into plain text,
+/// for a request that offers no tools. Providers reject tool calls and
+/// results unless the tool definitions are sent along.
+pub fn flatten_tool_calls(messages: &[Message]) -> Vec<Message> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut content = message.content.clone();
+            for call in message.tool_calls.iter().flatten() {
+                if !content.is_empty() {
+                    content.push('\n');
                 }
+                content.push_str(&format!(
+                    "(called {} {})",
+                    call.function.name, call.function.arguments
+                ));
+            }
+            let role = match message.role.as_str() {
+                "tool" => {
+                    content = format!("Tool result: {}", content);
+                    "user"
+                }
+                role => role,
+            };
+            Message {
+                role: role.to_string(),
+                content,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+pub async fn ask_llm_with_messages(
+    config: &LlmConfig,
+    messages: &[Message],
+) -> Result<(String, TokenUsage), LlmError> {
+    let (message, usage) = ask_llm_with_tools(config, messages, &[]).await?;
+    Ok((message.content, usage))
+}
+
+/// Non-streaming completion that offers `tools` to the model and returns the
+/// full assistant message, including any `tool_calls`.
+pub async fn ask_llm_with_tools(
+    config: &LlmConfig,
+    messages: &[Message],
+    tools: &[ToolDefinition],
+) -> Result<(Message, TokenUsage), LlmError> {
+    let provider = provider_for(config);
+    with_retry(&config.retry, || provider.complete(messages, tools)).await
+}
+
+/// Stream LLM responses in real-time from the configured provider
+pub async fn stream_llm_response(
+    config: &LlmConfig,
+    messages: &[Message],
+    tools: &[ToolDefinition],
+) -> Result<EventStream, LlmError> {
+    // Only opening the stream is retried; once tokens have been shown to the
+    // user a failure has to be handled by the caller
+    let provider = provider_for(config);
+    with_retry(&config.retry, || provider.stream(messages, tools)).await
+}
 
-                Ok(collected_content)
+/// Embeddings of `inputs` from the configured `embedding_model`.
+pub async fn embed(config: &LlmConfig, inputs: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+    let model = config
+        .embedding_model
+        .as_deref()
+        .ok_or_else(|| LlmError::ApiError("No embedding_model is configured".to_string()))?;
+    let provider = provider_for(config);
+    with_retry(&config.retry, || provider.embed(model, inputs)).await
+}
+
+/// Runs `request` until it succeeds, fails with a non-retryable error or
+/// runs out of retries, sleeping between attempts. A `Retry-After` longer
+/// than `max_backoff_ms` (a daily quota, say) ends the retries at once
+/// rather than leaving the agent asleep.
+pub async fn with_retry<T, F, Fut>(policy: &RetryConfig, mut request: F) -> Result<T, LlmError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, LlmError>>,
+{
+    let mut retries = 0;
+    loop {
+        match request().await {
+            Err(e) if e.is_retryable() && retries < policy.max_retries => {
+                let delay = match &e {
+                    LlmError::RateLimited {
+                        retry_after: Some(wait),
+                        ..
+                    } => *wait,
+                    _ => backoff_delay(policy, retries),
+                };
+                if delay > Duration::from_millis(policy.max_backoff_ms) {
+                    return Err(e);
+                }
+                retries += 1;
+                tokio::time::sleep(delay).await;
             }
-            Err(e) => Err(LlmError::RequestFailed(e)),
+            result => return result,
         }
-    });
+    }
+}
 
-    Ok(string_stream)
+/// Exponential backoff with "equal jitter": half the delay is fixed, the
+/// other half random, so concurrent clients don't retry in lockstep.
+pub fn backoff_delay(policy: &RetryConfig, retry: u32) -> Duration {
+    let base = policy
+        .initial_backoff_ms
+        .saturating_mul(1u64 << retry.min(20))
+        .min(policy.max_backoff_ms);
+    let half = base / 2;
+    let jitter = if half == 0 {
+        0
+    } else {
+        RandomState::new().build_hasher().finish() % (half + 1)
+    };
+    Duration::from_millis(half + jitter)
 }
@@ -0,0 +1,434 @@
+use super::sse::SseDecoder;
+use super::{
+    decode_body, estimate_usage, resolve_model, ChunkDecoder, EventStream, FunctionCall,
+    FunctionCallDelta, LlmError, LlmProvider, Message, StreamEvent, TokenUsage, ToolCallDelta,
+    ToolCallRequest, ToolDefinition,
+};
+use crate::config::LlmConfig;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const DEFAULT_MAX_TOKENS: u32 = 8192;
+
+#[derive(Deserialize, Debug)]
+struct ModelsResponse {
+    data: Vec<ModelInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelInfo {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: Option<UsageBody>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct UsageBody {
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_creation_input_tokens: Option<u64>,
+    cache_read_input_tokens: Option<u64>,
+}
+
+impl UsageBody {
+    /// Cached prompt tokens are reported separately from `input_tokens`
+    fn prompt_tokens(&self) -> u64 {
+        self.input_tokens
+            + self.cache_creation_input_tokens.unwrap_or(0)
+            + self.cache_read_input_tokens.unwrap_or(0)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageStart {
+    #[serde(default)]
+    usage: UsageBody,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Server-sent events of the Messages streaming API that we care about.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamingEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    /// Carries the cumulative `output_tokens` for the whole reply
+    MessageDelta {
+        #[serde(default)]
+        usage: Option<UsageBody>,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: BlockDelta,
+    },
+    Error {
+        error: ApiErrorBody,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiErrorBody {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+impl ApiErrorBody {
+    /// Errors can also arrive mid-stream, after the 200 status line
+    fn into_llm_error(self) -> LlmError {
+        let message = format!("{}: {}", self.kind, self.message);
+        match self.kind.as_str() {
+            "rate_limit_error" => LlmError::RateLimited {
+                retry_after: None,
+                message,
+            },
+            "authentication_error" | "permission_error" => LlmError::Auth(message),
+            "overloaded_error" => LlmError::Server {
+                status: 529,
+                message,
+            },
+            "api_error" => LlmError::Server {
+                status: 500,
+                message,
+            },
+            _ => LlmError::ApiError(message),
+        }
+    }
+}
+
+/// Anthropic Messages API (`/v1/messages`).
+pub struct AnthropicProvider {
+    config: LlmConfig,
+    client: Client,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: LlmConfig) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.config.api_base_url.trim_end_matches('/'),
+            path
+        )
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        request
+            .header("x-api-key", &self.config.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    async fn request_body(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        stream: bool,
+    ) -> Result<Value, LlmError> {
+        let (system, messages) = convert_messages(messages);
+        let mut body = json!({
+            "model": resolve_model(self, &self.config).await?,
+            "max_tokens": self.config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            "messages": messages,
+            "stream": stream,
+        });
+        if let Some(system) = system {
+            body["system"] = json!(system);
+        }
+        if !tools.is_empty() {
+            body["tools"] = tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "name": tool.function.name,
+                        "description": tool.function.description,
+                        "input_schema": tool.function.parameters,
+                    })
+                })
+                .collect();
+        }
+        Ok(body)
+    }
+
+    async fn send(&self, body: &Value) -> Result<reqwest::Response, LlmError> {
+        let response = self
+            .authorize(self.client.post(self.url("messages")))
+            .json(body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(LlmError::from_response(response).await);
+        }
+        Ok(response)
+    }
+}
+
+/// Converts OpenAI-style messages into the Messages API shape: system
+/// messages are split out, tool calls become `tool_use` blocks, tool results
+/// become `tool_result` blocks in a user turn, and consecutive turns of the
+/// same role are merged since the API requires alternating roles.
+pub fn convert_messages(messages: &[Message]) -> (Option<String>, Vec<Value>) {
+    let mut system_parts = Vec::new();
+    let mut converted: Vec<(String, Vec<Value>)> = Vec::new();
+
+    for message in messages {
+        let (role, blocks) = match message.role.as_str() {
+            "system" => {
+                system_parts.push(message.content.clone());
+                continue;
+            }
+            "tool" => (
+                "user",
+                vec![json!({
+                    "type": "tool_result",
+                    "tool_use_id": message.tool_call_id.clone().unwrap_or_default(),
+                    "content": message.content,
+                })],
+            ),
+            "assistant" => {
+                let mut blocks = Vec::new();
+                if !message.content.trim().is_empty() {
+                    blocks.push(json!({ "type": "text", "text": message.content }));
+                }
+                for call in message.tool_calls.iter().flatten() {
+                    let input: Value = serde_json::from_str(&call.function.arguments)
+                        .unwrap_or_else(|_| json!({}));
+                    blocks.push(json!({
+                        "type": "tool_use",
+                        "id": call.id,
+                        "name": call.function.name,
+                        "input": input,
+                    }));
+                }
+                if blocks.is_empty() {
+                    // Empty text blocks are rejected by the API
+                    blocks.push(json!({ "type": "text", "text": "(no content)" }));
+                }
+                ("assistant", blocks)
+            }
+            _ => (
+                "user",
+                vec![json!({ "type": "text", "text": message.content })],
+            ),
+        };
+
+        match converted.last_mut() {
+            Some((last_role, last_blocks)) if last_role == role => last_blocks.extend(blocks),
+            _ => converted.push((role.to_string(), blocks)),
+        }
+    }
+
+    let system = (!system_parts.is_empty()).then(|| system_parts.join("\n\n"));
+    let messages = converted
+        .into_iter()
+        .map(|(role, content)| json!({ "role": role, "content": content }))
+        .collect();
+    (system, messages)
+}
+
+/// Decodes the SSE body of a streaming Messages API call.
+#[derive(Debug, Default)]
+pub struct AnthropicDecoder {
+    sse: SseDecoder,
+}
+
+impl AnthropicDecoder {
+    fn decode_data(data: &str) -> Option<Result<StreamEvent, LlmError>> {
+        let event = match serde_json::from_str::<StreamingEvent>(data) {
+            Ok(event) => event,
+            Err(e) => {
+                return Some(Err(LlmError::ParseError(format!(
+                    "Failed to parse stream event: {}",
+                    e
+                ))))
+            }
+        };
+        match event {
+            StreamingEvent::ContentBlockStart {
+                index,
+                content_block: ContentBlock::ToolUse { id, name, .. },
+            } => Some(Ok(StreamEvent::ToolCall(ToolCallDelta {
+                index,
+                id: Some(id),
+                function: Some(FunctionCallDelta {
+                    name: Some(name),
+                    arguments: None,
+                }),
+            }))),
+            StreamingEvent::ContentBlockDelta {
+                delta: BlockDelta::TextDelta { text },
+                ..
+            } => Some(Ok(StreamEvent::Content(text))),
+            StreamingEvent::ContentBlockDelta {
+                index,
+                delta: BlockDelta::InputJsonDelta { partial_json },
+            } => Some(Ok(StreamEvent::ToolCall(ToolCallDelta {
+                index,
+                id: None,
+                function: Some(FunctionCallDelta {
+                    name: None,
+                    arguments: Some(partial_json),
+                }),
+            }))),
+            StreamingEvent::MessageStart { message } => Some(Ok(StreamEvent::Usage(TokenUsage {
+                prompt_tokens: message.usage.prompt_tokens(),
+                completion_tokens: 0,
+            }))),
+            StreamingEvent::MessageDelta { usage: Some(usage) } => {
+                Some(Ok(StreamEvent::Usage(TokenUsage {
+                    prompt_tokens: 0,
+                    completion_tokens: usage.output_tokens,
+                })))
+            }
+            StreamingEvent::Error { error } => Some(Err(error.into_llm_error())),
+            _ => None,
+        }
+    }
+}
+
+impl ChunkDecoder for AnthropicDecoder {
+    fn decode(&mut self, chunk: &[u8]) -> Vec<Result<StreamEvent, LlmError>> {
+        self.sse
+            .push(chunk)
+            .iter()
+            .filter_map(|event| Self::decode_data(&event.data))
+            .collect()
+    }
+
+    fn finish(&mut self) -> Vec<Result<StreamEvent, LlmError>> {
+        self.sse
+            .finish()
+            .iter()
+            .filter_map(|event| Self::decode_data(&event.data))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let response = self
+            .authorize(self.client.get(self.url("models")))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(LlmError::from_response(response).await);
+        }
+        let response_text = response.text().await?;
+
+        let parsed_models: ModelsResponse = serde_json::from_str(&response_text).map_err(|e| {
+            LlmError::ParseError(format!("Failed to parse /models response: {}", e))
+        })?;
+        Ok(parsed_models
+            .data
+            .into_iter()
+            .map(|model| model.id)
+            .collect())
+    }
+
+    async fn complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<(Message, TokenUsage), LlmError> {
+        let body = self.request_body(messages, tools, false).await?;
+        let response_text = self.send(&body).await?.text().await?;
+        let parsed: MessagesResponse = serde_json::from_str(&response_text)
+            .map_err(|e| LlmError::ParseError(format!("Failed to parse API response: {}", e)))?;
+
+        let mut message = Message {
+            role: "assistant".to_string(),
+            ..Default::default()
+        };
+        let mut tool_calls = Vec::new();
+        for block in parsed.content {
+            match block {
+                ContentBlock::Text { text } => message.content.push_str(&text),
+                ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCallRequest {
+                    id,
+                    kind: "function".to_string(),
+                    function: FunctionCall {
+                        name,
+                        arguments: input.to_string(),
+                    },
+                }),
+                ContentBlock::Other => {}
+            }
+        }
+
+        if !tool_calls.is_empty() {
+            message.tool_calls = Some(tool_calls);
+        }
+        let usage = match parsed.usage {
+            Some(usage) => TokenUsage {
+                prompt_tokens: usage.prompt_tokens(),
+                completion_tokens: usage.output_tokens,
+            },
+            None => estimate_usage(messages, &message),
+        };
+        Ok((message, usage))
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<EventStream, LlmError> {
+        let body = self.request_body(messages, tools, true).await?;
+        let response = self.send(&body).await?;
+
+        Ok(decode_body(
+            response.bytes_stream(),
+            AnthropicDecoder::default(),
+        ))
+    }
+}
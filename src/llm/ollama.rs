@@ -0,0 +1,318 @@
+use super::{
+    decode_body, estimate_usage, resolve_model, ChunkDecoder, EventStream, FunctionCall,
+    FunctionCallDelta, LineBuffer, LlmError, LlmProvider, Message, StreamEvent, TokenUsage,
+    ToolCallDelta, ToolCallRequest, ToolDefinition,
+};
+use crate::config::LlmConfig;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Deserialize, Debug)]
+struct TagsResponse {
+    models: Vec<TagInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TagInfo {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+/// One `/api/chat` response object. Non-streaming requests get a single one;
+/// streaming requests get one per line, the last with `done: true` and the
+/// token counts.
+#[derive(Deserialize, Debug, Default)]
+struct ChatChunk {
+    #[serde(default)]
+    message: Option<ChunkMessage>,
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ChunkMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaToolCall {
+    function: OllamaFunction,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaFunction {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+impl ChatChunk {
+    fn usage(&self) -> Option<TokenUsage> {
+        if self.prompt_eval_count.is_none() && self.eval_count.is_none() {
+            return None;
+        }
+        Some(TokenUsage {
+            prompt_tokens: self.prompt_eval_count.unwrap_or(0),
+            completion_tokens: self.eval_count.unwrap_or(0),
+        })
+    }
+}
+
+/// Ollama's native API (`/api/chat`, `/api/tags`), which unlike the `/v1`
+/// shim accepts Ollama-specific options and reports real token counts.
+pub struct OllamaProvider {
+    config: LlmConfig,
+    client: Client,
+}
+
+impl OllamaProvider {
+    pub fn new(config: LlmConfig) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+        }
+    }
+
+    /// The default config points at the OpenAI shim (`.../v1`), so accept
+    /// that and talk to the server root instead.
+    fn url(&self, path: &str) -> String {
+        let base = self.config.api_base_url.trim_end_matches('/');
+        let base = base.strip_suffix("/v1").unwrap_or(base);
+        format!("{}/{}", base, path)
+    }
+
+    async fn request_body(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        stream: bool,
+    ) -> Result<Value, LlmError> {
+        let ollama = &self.config.ollama;
+        let mut body = json!({
+            "model": resolve_model(self, &self.config).await?,
+            "messages": convert_messages(messages),
+            "stream": stream,
+        });
+        if !tools.is_empty() {
+            body["tools"] = json!(tools);
+        }
+
+        let mut options = serde_json::Map::new();
+        for (key, value) in &ollama.options {
+            options.insert(key.clone(), value.clone());
+        }
+        if let Some(num_ctx) = ollama.num_ctx {
+            options.insert("num_ctx".to_string(), json!(num_ctx));
+        }
+        if !options.is_empty() {
+            body["options"] = Value::Object(options);
+        }
+        if let Some(keep_alive) = &ollama.keep_alive {
+            body["keep_alive"] = json!(keep_alive);
+        }
+        Ok(body)
+    }
+
+    async fn send(&self, body: &Value) -> Result<reqwest::Response, LlmError> {
+        let response = self
+            .client
+            .post(self.url("api/chat"))
+            .json(body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(LlmError::from_response(response).await);
+        }
+        Ok(response)
+    }
+}
+
+/// Converts OpenAI-style messages for `/api/chat`, where tool call arguments
+/// are JSON objects rather than encoded strings.
+pub fn convert_messages(messages: &[Message]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut converted = json!({ "role": message.role, "content": message.content });
+            if let Some(calls) = &message.tool_calls {
+                converted["tool_calls"] = calls
+                    .iter()
+                    .map(|call| {
+                        let arguments: Value = serde_json::from_str(&call.function.arguments)
+                            .unwrap_or_else(|_| json!({}));
+                        json!({ "function": { "name": call.function.name, "arguments": arguments } })
+                    })
+                    .collect();
+            }
+            converted
+        })
+        .collect()
+}
+
+/// Decodes the newline-delimited JSON body of a streaming `/api/chat` call.
+#[derive(Debug, Default)]
+pub struct OllamaDecoder {
+    lines: LineBuffer,
+    next_tool_index: usize,
+}
+
+impl OllamaDecoder {
+    fn decode_line(&mut self, line: &str) -> Vec<Result<StreamEvent, LlmError>> {
+        if line.trim().is_empty() {
+            return Vec::new();
+        }
+        let chunk: ChatChunk = match serde_json::from_str(line) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                return vec![Err(LlmError::ParseError(format!(
+                    "Failed to parse stream line: {}",
+                    e
+                )))]
+            }
+        };
+        if let Some(error) = chunk.error {
+            return vec![Err(LlmError::ApiError(error))];
+        }
+
+        let usage = chunk.usage();
+        let mut events = Vec::new();
+        if let Some(message) = chunk.message {
+            if !message.content.is_empty() {
+                events.push(Ok(StreamEvent::Content(message.content)));
+            }
+            // Ollama sends each tool call whole, so every one gets its own index
+            for call in message.tool_calls {
+                events.push(Ok(StreamEvent::ToolCall(ToolCallDelta {
+                    index: self.next_tool_index,
+                    id: None,
+                    function: Some(FunctionCallDelta {
+                        name: Some(call.function.name),
+                        arguments: Some(call.function.arguments.to_string()),
+                    }),
+                })));
+                self.next_tool_index += 1;
+            }
+        }
+        if let Some(usage) = usage {
+            events.push(Ok(StreamEvent::Usage(usage)));
+        }
+        events
+    }
+}
+
+impl ChunkDecoder for OllamaDecoder {
+    fn decode(&mut self, chunk: &[u8]) -> Vec<Result<StreamEvent, LlmError>> {
+        self.lines
+            .push(chunk)
+            .into_iter()
+            .flat_map(|line| self.decode_line(&line))
+            .collect()
+    }
+
+    fn finish(&mut self) -> Vec<Result<StreamEvent, LlmError>> {
+        match self.lines.finish() {
+            Some(line) => self.decode_line(&line),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let response = self.client.get(self.url("api/tags")).send().await?;
+        if !response.status().is_success() {
+            return Err(LlmError::from_response(response).await);
+        }
+        let response_text = response.text().await?;
+
+        let tags: TagsResponse = serde_json::from_str(&response_text).map_err(|e| {
+            LlmError::ParseError(format!("Failed to parse /api/tags response: {}", e))
+        })?;
+        Ok(tags.models.into_iter().map(|model| model.name).collect())
+    }
+
+    async fn complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<(Message, TokenUsage), LlmError> {
+        let body = self.request_body(messages, tools, false).await?;
+        let response_text = self.send(&body).await?.text().await?;
+        let chunk: ChatChunk = serde_json::from_str(&response_text)
+            .map_err(|e| LlmError::ParseError(format!("Failed to parse API response: {}", e)))?;
+        if let Some(error) = chunk.error {
+            return Err(LlmError::ApiError(error));
+        }
+
+        let usage = chunk.usage();
+        let reply = chunk.message.unwrap_or_default();
+        let tool_calls: Vec<ToolCallRequest> = reply
+            .tool_calls
+            .into_iter()
+            .enumerate()
+            .map(|(i, call)| ToolCallRequest {
+                id: format!("call_{}", i),
+                kind: "function".to_string(),
+                function: FunctionCall {
+                    name: call.function.name,
+                    arguments: call.function.arguments.to_string(),
+                },
+            })
+            .collect();
+
+        let message = Message {
+            role: "assistant".to_string(),
+            content: reply.content,
+            tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+            ..Default::default()
+        };
+        let usage = usage.unwrap_or_else(|| estimate_usage(messages, &message));
+        Ok((message, usage))
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<EventStream, LlmError> {
+        let body = self.request_body(messages, tools, true).await?;
+        let response = self.send(&body).await?;
+        Ok(decode_body(
+            response.bytes_stream(),
+            OllamaDecoder::default(),
+        ))
+    }
+
+    async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+        let response = self
+            .client
+            .post(self.url("api/embed"))
+            .json(&json!({ "model": model, "input": inputs }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(LlmError::from_response(response).await);
+        }
+        let response_text = response.text().await?;
+
+        let parsed: EmbedResponse = serde_json::from_str(&response_text).map_err(|e| {
+            LlmError::ParseError(format!("Failed to parse /api/embed response: {}", e))
+        })?;
+        Ok(parsed.embeddings)
+    }
+}
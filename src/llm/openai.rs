@@ -0,0 +1,334 @@
+use super::sse::SseDecoder;
+use super::{
+    decode_body, estimate_usage, resolve_model, ChunkDecoder, EventStream, LlmError, LlmProvider,
+    Message, StreamEvent, TokenUsage, ToolCallDelta, ToolDefinition,
+};
+use crate::config::LlmConfig;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Deserialize, Debug)]
+struct ModelsResponse {
+    data: Vec<ModelInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelInfo {
+    id: String,
+}
+
+#[derive(Serialize, Debug)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize, Debug)]
+struct ChatCompletionRequest {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ToolDefinition>,
+}
+
+#[derive(Serialize, Debug)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct UsageBody {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+impl From<UsageBody> for TokenUsage {
+    fn from(usage: UsageBody) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionResponse {
+    choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<UsageBody>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatCompletionStreamResponse {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    /// Only set on the final chunk, which has no choices
+    #[serde(default)]
+    usage: Option<UsageBody>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: StreamDelta,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct StreamDelta {
+    content: Option<String>,
+    tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Choice {
+    message: Message,
+}
+
+/// Any server speaking the OpenAI `/chat/completions` API (OpenAI, vLLM,
+/// llama.cpp, LM Studio, Ollama's `/v1` shim, ...).
+pub struct OpenAiProvider {
+    config: LlmConfig,
+    client: Client,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: LlmConfig) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.config.api_base_url.trim_end_matches('/'),
+            path
+        )
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if self.config.api_key.is_empty() {
+            request
+        } else {
+            request.bearer_auth(&self.config.api_key)
+        }
+    }
+}
+
+/// Decodes the SSE body of a streaming `/chat/completions` call.
+#[derive(Debug, Default)]
+pub struct OpenAiDecoder {
+    sse: SseDecoder,
+}
+
+impl OpenAiDecoder {
+    fn decode_data(data: &str) -> Vec<Result<StreamEvent, LlmError>> {
+        let value: Value = match serde_json::from_str(data) {
+            Ok(value) => value,
+            Err(e) => {
+                return vec![Err(LlmError::ParseError(format!(
+                    "Failed to parse stream chunk: {}",
+                    e
+                )))]
+            }
+        };
+        // Errors after the 200 status line arrive as a regular data event
+        if let Some(error) = value.get("error") {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(|| error.to_string());
+            return vec![Err(LlmError::ApiError(message))];
+        }
+
+        let parsed: ChatCompletionStreamResponse = match serde_json::from_value(value) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                return vec![Err(LlmError::ParseError(format!(
+                    "Unexpected stream chunk: {}",
+                    e
+                )))]
+            }
+        };
+        let mut events = Vec::new();
+        if let Some(choice) = parsed.choices.into_iter().next() {
+            if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                events.push(Ok(StreamEvent::Content(content)));
+            }
+            for delta in choice.delta.tool_calls.into_iter().flatten() {
+                events.push(Ok(StreamEvent::ToolCall(delta)));
+            }
+        }
+        if let Some(usage) = parsed.usage {
+            events.push(Ok(StreamEvent::Usage(usage.into())));
+        }
+        events
+    }
+}
+
+impl ChunkDecoder for OpenAiDecoder {
+    fn decode(&mut self, chunk: &[u8]) -> Vec<Result<StreamEvent, LlmError>> {
+        self.sse
+            .push(chunk)
+            .iter()
+            .flat_map(|event| Self::decode_data(&event.data))
+            .collect()
+    }
+
+    fn finish(&mut self) -> Vec<Result<StreamEvent, LlmError>> {
+        self.sse
+            .finish()
+            .iter()
+            .flat_map(|event| Self::decode_data(&event.data))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let response = self
+            .authorize(self.client.get(self.url("models")))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(LlmError::from_response(response).await);
+        }
+        let response_text = response.text().await?;
+
+        let parsed_models: ModelsResponse = serde_json::from_str(&response_text).map_err(|e| {
+            LlmError::ParseError(format!("Failed to parse /models response: {}", e))
+        })?;
+        Ok(parsed_models
+            .data
+            .into_iter()
+            .map(|model| model.id)
+            .collect())
+    }
+
+    async fn complete(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<(Message, TokenUsage), LlmError> {
+        let request_body = ChatCompletionRequest {
+            model: resolve_model(self, &self.config).await?,
+            messages: messages.to_vec(),
+            stream: false,
+            stream_options: None,
+            tools: tools.to_vec(),
+        };
+
+        let response = self
+            .authorize(self.client.post(self.url("chat/completions")))
+            .json(&request_body)
+            .send()
+            .await?;
+
+        // Check if the request was successful
+        if !response.status().is_success() {
+            return Err(LlmError::from_response(response).await);
+        }
+
+        // Get the raw response text for debugging
+        let response_text = response.text().await?;
+
+        // Try to parse the response
+        match serde_json::from_str::<ChatCompletionResponse>(&response_text) {
+            Ok(parsed_response) => {
+                let message = match parsed_response.choices.into_iter().next() {
+                    Some(choice) => choice.message,
+                    None => Message {
+                        role: "assistant".to_string(),
+                        content: "No response content available.".to_string(),
+                        ..Default::default()
+                    },
+                };
+                let usage = match parsed_response.usage {
+                    Some(usage) => usage.into(),
+                    None => estimate_usage(messages, &message),
+                };
+                Ok((message, usage))
+            }
+            Err(e) => Err(LlmError::ParseError(format!(
+                "Failed to parse API response: {}",
+                e
+            ))),
+        }
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> Result<EventStream, LlmError> {
+        let request_body = ChatCompletionRequest {
+            model: resolve_model(self, &self.config).await?,
+            messages: messages.to_vec(),
+            stream: true,
+            // Without this the stream carries no token counts at all
+            stream_options: Some(StreamOptions {
+                include_usage: true,
+            }),
+            tools: tools.to_vec(),
+        };
+
+        // Create streaming request
+        let response = self
+            .authorize(self.client.post(self.url("chat/completions")))
+            .json(&request_body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(LlmError::from_response(response).await);
+        }
+
+        Ok(decode_body(
+            response.bytes_stream(),
+            OpenAiDecoder::default(),
+        ))
+    }
+
+    async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, LlmError> {
+        let response = self
+            .authorize(self.client.post(self.url("embeddings")))
+            .json(&EmbeddingRequest {
+                model,
+                input: inputs,
+            })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(LlmError::from_response(response).await);
+        }
+        let response_text = response.text().await?;
+
+        let mut parsed: EmbeddingResponse = serde_json::from_str(&response_text).map_err(|e| {
+            LlmError::ParseError(format!("Failed to parse /embeddings response: {}", e))
+        })?;
+        parsed.data.sort_by_key(|data| data.index);
+        Ok(parsed.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
@@ -0,0 +1,85 @@
+use super::LineBuffer;
+
+/// One dispatched server-sent event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub id: Option<String>,
+    /// All `data:` lines of the event joined with `\n`
+    pub data: String,
+}
+
+/// Incremental `text/event-stream` parser. Feed it network chunks as they
+/// arrive; events are only returned once their terminating blank line has
+/// been seen, so nothing is lost when an event spans several chunks.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    lines: LineBuffer,
+    event: Option<String>,
+    id: Option<String>,
+    data: Vec<String>,
+    done: bool,
+}
+
+impl SseDecoder {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        let lines = self.lines.push(chunk);
+        lines
+            .into_iter()
+            .filter_map(|line| self.process_line(&line))
+            .collect()
+    }
+
+    /// Flushes an event left unterminated when the stream closed.
+    pub fn finish(&mut self) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        if let Some(line) = self.lines.finish() {
+            events.extend(self.process_line(&line));
+        }
+        events.extend(self.dispatch());
+        events
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if self.done {
+            // Nothing after the `[DONE]` marker belongs to the response
+            return None;
+        }
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // Comment, used by some servers as a keep-alive
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => self.data.push(value.to_string()),
+            "event" => self.event = Some(value.to_string()),
+            "id" => self.id = Some(value.to_string()),
+            _ => {} // `retry` and unknown fields
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let data = std::mem::take(&mut self.data).join("\n");
+        if data == "[DONE]" {
+            self.done = true;
+            return None;
+        }
+        Some(SseEvent {
+            event,
+            id: self.id.clone(),
+            data,
+        })
+    }
+}
@@ -1,17 +1,34 @@
 mod agent;
 mod app;
+mod checkpoint;
 mod config;
+mod context;
+mod edit;
+mod index;
 mod llm;
+mod memory;
+mod patch;
+mod permissions;
+mod process;
+mod read;
+mod repomap;
+mod search;
+mod semantic;
+mod symbols;
 mod ui;
+mod verify;
+mod workspace;
 
 use agent::Agent;
 use app::App;
+use checkpoint::{Checkpoint, Checkpoints};
 use config::Config;
 use crossterm::{
     event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use llm::LlmError;
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     Terminal,
@@ -22,10 +39,14 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
 use tokio::task;
+use tokio_util::sync::CancellationToken;
+use workspace::Workspace;
 
 fn create_default_config() -> io::Result<()> {
     let default_config = r#"# Configuration for the LLM API
 [llm]
+# Backend: "openai" (any OpenAI-compatible server), "anthropic", or "ollama"
+# (native /api/chat, reports real token counts)
 provider = "openai"
 
 api_key = ""
@@ -35,14 +56,51 @@ api_base_url = "http://localhost:11434/v1"
 # Set to a concrete model name or use AUTODETECT to pick the first model from /v1/models
 model_name = "AUTODETECT"
 
+# How tools are offered to the model: "native" uses function calling,
+# "text" falls back to TOOL: lines for servers without function calling
+tool_protocol = "native"
 
+# Maximum tokens per reply (the Anthropic API requires one, default 8192)
+# max_tokens = 8192
+
+# Context window in tokens; guessed from the model name when unset. Older
+# turns are summarized automatically (or with /compact) as it fills up
+# context_window = 128000
+
+# Retries for rate limits (honoring Retry-After), timeouts, 5xx and dropped connections
+# [llm.retry]
+# max_retries = 3
+# initial_backoff_ms = 1000
+# max_backoff_ms = 30000
+
+# Native Ollama options (provider = "ollama")
+# [llm.ollama]
+# num_ctx = 32768
+# keep_alive = "30m"
+# options = { temperature = 0.2 }
 
 # Automation options
+[agent]
+# Tool rounds per prompt before the model has to give its final answer
 max_attempts = 12
+# Directory the tools work in; empty means where rct was started. With
+# safe_fs, file paths that lead outside it (.., absolute paths, symlinks)
+# are rejected
 workspace_root = ""
+# Shell that runs RUN_COMMAND commands
 shell = "bash"
+# After writing a file, read it back and syntax-check it (cargo check,
+# python -m py_compile, node --check); problems go back to the model
 post_write_verify = true
 safe_fs = true
+# Seconds a tool's commands may run before they are stopped (0 = no limit),
+# with per-tool overrides
+command_timeout_secs = 300
+tool_timeouts = { RUN_TESTS = 900, INSTALL_PACKAGE = 600 }
+# Long command output is cut to its first and last characters before it
+# goes to the model
+output_head_chars = 6000
+output_tail_chars = 4000
 "#;
 
     fs::write("config.toml", default_config)?;
@@ -92,8 +150,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut terminal = Terminal::new(backend)?;
 
     // create app and run it
-    let app = Arc::new(Mutex::new(App::new()));
-    let agent = Agent::new();
+    let mut app = App::new();
+    for warning in &config.warnings {
+        app.add_tool_log(format!("config.toml: {}", warning));
+    }
+    let app = Arc::new(Mutex::new(app));
+    // One agent for the whole session so the conversation carries over between turns
+    let agent = Arc::new(Mutex::new(Agent::new()));
     let res = run_app(&mut terminal, app, agent, config).await;
 
     // restore terminal
@@ -108,6 +171,81 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Short status line telling the user what to do about a failed run.
+fn error_status(error: &(dyn std::error::Error + 'static)) -> String {
+    match error.downcast_ref::<LlmError>() {
+        Some(LlmError::RateLimited { .. }) => {
+            "Error: rate limited by the API, retries exhausted. Wait a moment and try again."
+                .to_string()
+        }
+        Some(LlmError::Auth(_)) => {
+            "Error: authentication failed. Check api_key in config.toml.".to_string()
+        }
+        Some(LlmError::ContextLengthExceeded(_)) => {
+            "Error: conversation is too long for the model's context window.".to_string()
+        }
+        Some(LlmError::Timeout(_)) => "Error: the request timed out.".to_string(),
+        Some(LlmError::Server { status, .. }) => {
+            format!("Error: the API server failed (HTTP {}).", status)
+        }
+        Some(LlmError::RequestFailed(_)) => {
+            "Error: could not reach the API. Check api_base_url in config.toml.".to_string()
+        }
+        _ => "Error.".to_string(),
+    }
+}
+
+const CHECKPOINT_USAGE: &str = "Usage: /undo, /undo turn, /checkpoints, /checkpoints restore <id>";
+
+/// Runs `/undo`, `/undo turn` and `/checkpoints [restore <id>]`. Returns the
+/// reply and the checkpoints that were undone, or `None` for other input.
+fn checkpoint_command(
+    config: &Config,
+    input: &str,
+) -> Option<io::Result<(String, Vec<Checkpoint>)>> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    if !matches!(words.first(), Some(&"/undo") | Some(&"/checkpoints")) {
+        return None;
+    }
+    let store = match Workspace::from_config(&config.agent) {
+        Ok(workspace) => Checkpoints::new(workspace.root()),
+        Err(e) => return Some(Err(e)),
+    };
+    let undone = match words.as_slice() {
+        ["/undo"] => store.undo_last(),
+        ["/undo", "turn"] => store.undo_turn(),
+        ["/checkpoints"] => {
+            return Some(store.list().map(|checkpoints| {
+                let reply = if checkpoints.is_empty() {
+                    "No checkpoints yet.".to_string()
+                } else {
+                    let lines: Vec<String> =
+                        checkpoints.iter().rev().map(|c| format!("  {}", c)).collect();
+                    format!(
+                        "Checkpoints, newest first (/checkpoints restore <id> undoes that one and everything after it):\n{}",
+                        lines.join("\n")
+                    )
+                };
+                (reply, Vec::new())
+            }));
+        }
+        ["/checkpoints", "restore", id] => match id.trim_start_matches('#').parse() {
+            Ok(id) => store.restore(id),
+            Err(_) => return Some(Ok((CHECKPOINT_USAGE.to_string(), Vec::new()))),
+        },
+        _ => return Some(Ok((CHECKPOINT_USAGE.to_string(), Vec::new()))),
+    };
+    Some(undone.map(|undone| {
+        let reply = if undone.is_empty() {
+            "Nothing to undo.".to_string()
+        } else {
+            let lines: Vec<String> = undone.iter().map(|c| format!("  {}", c)).collect();
+            format!("Undid {} change(s):\n{}", undone.len(), lines.join("\n"))
+        };
+        (reply, undone)
+    }))
+}
+
 // Type alias for cleaner code
 type AgentTaskResult = Result<(String, Vec<String>), Box<dyn std::error::Error + Send + Sync>>;
 type AgentTask = task::JoinHandle<AgentTaskResult>;
@@ -115,11 +253,13 @@ type AgentTask = task::JoinHandle<AgentTaskResult>;
 async fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     app: Arc<Mutex<App>>,
-    agent: Agent,
-    config: Config,
+    agent: Arc<Mutex<Agent>>,
+    mut config: Config,
 ) -> io::Result<()> {
     // Track if there's an ongoing agent task
     let mut current_agent_task: Option<AgentTask> = None;
+    // Cancels the running task when Esc is pressed
+    let mut current_cancel = CancellationToken::new();
 
     loop {
         // Always draw the UI first
@@ -139,12 +279,16 @@ async fn run_app<B: Backend>(
                             app_guard.add_tool_log(log);
                         }
                         // Response is already added to conversation in finish_streaming()
-                        app_guard.status_message = "Done.".to_string();
+                        app_guard.status_message = if current_cancel.is_cancelled() {
+                            "Cancelled.".to_string()
+                        } else {
+                            "Done.".to_string()
+                        };
                     }
-                    Ok(Err(_e)) => {
+                    Ok(Err(e)) => {
                         let mut app_guard = app.lock().await;
-                        // Error is handled in finish_streaming() or through agent error handling
-                        app_guard.status_message = "Error.".to_string();
+                        // The error text itself is shown by finish_streaming()
+                        app_guard.status_message = error_status(e.as_ref());
                     }
                     Err(_) => {
                         let mut app_guard = app.lock().await;
@@ -161,11 +305,27 @@ async fn run_app<B: Backend>(
                 if let Ok(Event::Key(key)) = event::read() {
                     // Only process key events (ignore mouse events, resize events, etc.)
                     if key.kind == KeyEventKind::Press {
+                        // An open approval prompt takes the keys it understands
+                        if !key.modifiers.contains(KeyModifiers::CONTROL) {
+                            let mut app_guard = app.lock().await;
+                            if app_guard.handle_approval_key(key.code) {
+                                continue;
+                            }
+                        }
                         match key.code {
                             KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                                 // Ctrl+C to quit
                                 return Ok(());
                             }
+                            KeyCode::Esc
+                                if current_agent_task.is_some()
+                                    && !current_cancel.is_cancelled() =>
+                            {
+                                // Esc stops the running generation or tool
+                                current_cancel.cancel();
+                                let mut app_guard = app.lock().await;
+                                app_guard.status_message = "Cancelling...".to_string();
+                            }
                             KeyCode::Char(c) => {
                                 // Handle character input
                                 let mut app_guard = app.lock().await;
@@ -261,27 +421,142 @@ async fn run_app<B: Backend>(
                                     continue;
                                 }
 
+                                // List the endpoint's models, marking the active one
+                                if user_input.trim() == "/models" {
+                                    let llm_config = config.llm.clone();
+                                    let app_clone = Arc::clone(&app);
+                                    current_cancel = CancellationToken::new();
+                                    let cancel = current_cancel.clone();
+                                    current_agent_task = Some(task::spawn(async move {
+                                        let models = tokio::select! {
+                                            _ = cancel.cancelled() => return Ok((String::new(), Vec::new())),
+                                            models = llm::list_models(&llm_config) => models?,
+                                        };
+                                        let active = llm::active_model(&llm_config)
+                                            .await
+                                            .unwrap_or_default();
+                                        let listing: Vec<String> = models
+                                            .iter()
+                                            .map(|model| {
+                                                let marker =
+                                                    if *model == active { "*" } else { " " };
+                                                format!("  {} {}", marker, model)
+                                            })
+                                            .collect();
+                                        let mut app_guard = app_clone.lock().await;
+                                        app_guard.conversation.push(format!(
+                                            "System: Models available at {}:\n{}",
+                                            llm_config.api_base_url,
+                                            listing.join("\n")
+                                        ));
+                                        Ok((String::new(), Vec::new()))
+                                    }));
+                                    continue;
+                                }
+
+                                // Show or switch the active model
+                                if user_input.trim() == "/model" {
+                                    // AUTODETECT may have to ask the endpoint
+                                    let llm_config = config.llm.clone();
+                                    let app_clone = Arc::clone(&app);
+                                    current_cancel = CancellationToken::new();
+                                    let cancel = current_cancel.clone();
+                                    current_agent_task = Some(task::spawn(async move {
+                                        let active = tokio::select! {
+                                            _ = cancel.cancelled() => return Ok((String::new(), Vec::new())),
+                                            active = llm::active_model(&llm_config) => active?,
+                                        };
+                                        let mut app_guard = app_clone.lock().await;
+                                        app_guard.conversation.push(format!(
+                                            "System: Active model: {} (use /model <id> to switch, /models to list)",
+                                            active
+                                        ));
+                                        Ok((String::new(), Vec::new()))
+                                    }));
+                                    continue;
+                                }
+                                if let Some(model) = user_input
+                                    .trim()
+                                    .strip_prefix("/model")
+                                    .filter(|rest| rest.starts_with(char::is_whitespace))
+                                {
+                                    let model = model.trim();
+                                    config.llm.model_name = model.to_string();
+                                    let mut app_guard = app.lock().await;
+                                    app_guard
+                                        .conversation
+                                        .push(format!("System: Switched to model {}", model));
+                                    app_guard.status_message = format!("Model: {}", model);
+                                    continue;
+                                }
+
+                                // Check for compact command
+                                if user_input.trim() == "/compact" {
+                                    {
+                                        let mut app_guard = app.lock().await;
+                                        app_guard.status_message =
+                                            "Compacting conversation...".to_string();
+                                    }
+                                    let agent = Arc::clone(&agent);
+                                    let config_clone = config.clone();
+                                    let app_clone = Arc::clone(&app);
+                                    current_cancel = CancellationToken::new();
+                                    let cancel = current_cancel.clone();
+                                    current_agent_task = Some(task::spawn(async move {
+                                        let mut agent = agent.lock().await;
+                                        // Compaction only touches the messages once the
+                                        // summary is back, so dropping it midway is safe
+                                        let compacted = tokio::select! {
+                                            _ = cancel.cancelled() => return Ok((String::new(), Vec::new())),
+                                            compacted = agent.compact(&config_clone, &app_clone, true) => compacted,
+                                        };
+                                        let log = compacted?.unwrap_or_default();
+                                        let mut app_guard = app_clone.lock().await;
+                                        app_guard.conversation.push(format!("System: {}", log));
+                                        Ok((log, Vec::new()))
+                                    }));
+                                    continue;
+                                }
+
+                                // Undo agent edits from their checkpoints
+                                if let Some(result) = checkpoint_command(&config, &user_input) {
+                                    let reply = match result {
+                                        Ok((reply, undone)) => {
+                                            if !undone.is_empty() {
+                                                agent.lock().await.note_undone(&undone);
+                                            }
+                                            reply
+                                        }
+                                        Err(e) => format!("Error: {}", e),
+                                    };
+                                    let mut app_guard = app.lock().await;
+                                    app_guard.conversation.push(format!("System: {}", reply));
+                                    continue;
+                                }
+
                                 {
                                     let mut app_guard = app.lock().await;
                                     app_guard.conversation.push(format!("User: {}", user_input));
                                     app_guard.status_message =
-                                        "Thinking... (streaming response will appear live)"
+                                        "Thinking... (streaming response will appear live, Esc to cancel)"
                                             .to_string();
                                     terminal.draw(|f| ui::ui(f, &app_guard))?;
                                 }
 
                                 // Spawn the agent task in the background so the UI can continue updating
-                                let mut agent_clone = agent.clone();
+                                let agent = Arc::clone(&agent);
                                 let config_clone = config.clone();
                                 let user_input_clone = user_input.clone();
                                 let app_clone = Arc::clone(&app);
+                                current_cancel = CancellationToken::new();
+                                let cancel = current_cancel.clone();
                                 current_agent_task = Some(task::spawn(async move {
                                     // Run the agent with access to the shared app state
                                     // The agent will handle its own locking/unlocking to allow UI updates
-                                    let result = agent_clone
-                                        .run(&config_clone, user_input_clone, app_clone)
-                                        .await;
-                                    result
+                                    let mut agent = agent.lock().await;
+                                    agent
+                                        .run(&config_clone, user_input_clone, app_clone, cancel)
+                                        .await
                                 }));
                             }
                             _ => {}
@@ -0,0 +1,426 @@
+use crate::semantic;
+use crate::workspace;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where project memories live, relative to the workspace, and user
+/// memories, relative to the home directory.
+pub const MEMORY_FILE: &str = ".rct/memory.json";
+
+/// Where REMEMBER used to append markdown. It is imported once, the first
+/// time the project store is read.
+pub const LEGACY_MEMORY_FILE: &str = ".agent_memory.md";
+
+/// Starts the memories recalled for a user turn, so the ones from the
+/// previous turn can be found and removed.
+pub const RECALL_HEADER: &str = "## RELEVANT MEMORIES";
+
+/// Ends the recalled memories, where the user's own words begin.
+pub const RECALL_FOOTER: &str = "## END OF MEMORIES";
+
+/// Memories RECALL lists at most.
+pub const DEFAULT_RECALL_LIMIT: usize = 20;
+
+/// Memories that have gone unused longest are dropped once a store holds
+/// more than this.
+const MAX_MEMORIES: usize = 500;
+
+/// How much more a query word counts when it is one of the tags.
+const TAG_WEIGHT: f64 = 2.0;
+
+/// `prompt` with the `recalled` memories in front of it. They go in the user
+/// message because many chat templates reject a system message anywhere
+/// but first.
+pub fn prepend_recalled(recalled: &str, prompt: &str) -> String {
+    format!("{}\n{}\n\n{}", recalled.trim_end(), RECALL_FOOTER, prompt)
+}
+
+/// A user message without the memories `prepend_recalled` put in front.
+pub fn strip_recalled(message: &str) -> &str {
+    if !message.starts_with(RECALL_HEADER) {
+        return message;
+    }
+    match message.split_once(&format!("\n{}\n\n", RECALL_FOOTER)) {
+        Some((_, prompt)) => prompt,
+        None => message,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Facts about the workspace, kept in it
+    #[default]
+    Project,
+    /// Facts about the user that hold in every workspace
+    User,
+}
+
+impl Scope {
+    /// Ids are numbered per store, so the prefix tells which one to look in.
+    fn prefix(self) -> char {
+        match self {
+            Scope::Project => 'p',
+            Scope::User => 'u',
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Project => write!(f, "project"),
+            Scope::User => write!(f, "user"),
+        }
+    }
+}
+
+/// One remembered fact.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    /// `p` or `u` for the store, then a number that is never reused
+    pub id: String,
+    pub fact: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// What recorded it: a tool, or the file it was imported from
+    pub source: String,
+    pub created: String,
+    /// When it was last recalled, on request or with a user turn
+    #[serde(default)]
+    pub used: Option<String>,
+}
+
+impl Memory {
+    /// When it last mattered, for pruning and for breaking ties.
+    fn last_used(&self) -> &str {
+        self.used.as_deref().unwrap_or(&self.created)
+    }
+}
+
+impl fmt::Display for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.id, self.fact)?;
+        if !self.tags.is_empty() {
+            write!(f, " (tags: {})", self.tags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The contents of one memory file.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Store {
+    next_id: u64,
+    memories: Vec<Memory>,
+}
+
+impl Store {
+    fn load(path: &Path) -> io::Result<Store> {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Store::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            workspace::create_state_dir(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        // A write that is cut short must not leave a store that can't be read
+        let partial = path.with_extension("json.tmp");
+        fs::write(&partial, json)?;
+        fs::rename(partial, path)
+    }
+
+    fn add(
+        &mut self,
+        scope: Scope,
+        fact: String,
+        tags: Vec<String>,
+        source: &str,
+        created: String,
+    ) -> Memory {
+        self.next_id += 1;
+        let memory = Memory {
+            id: format!("{}{}", scope.prefix(), self.next_id),
+            fact,
+            tags,
+            source: source.to_string(),
+            created,
+            used: None,
+        };
+        self.memories.push(memory.clone());
+        memory
+    }
+
+    /// Drops the memories unused longest until at most MAX_MEMORIES are left.
+    fn prune(&mut self) {
+        let excess = self.memories.len().saturating_sub(MAX_MEMORIES);
+        if excess == 0 {
+            return;
+        }
+        let mut by_use: Vec<(String, String)> = self
+            .memories
+            .iter()
+            .map(|memory| (memory.last_used().to_string(), memory.id.clone()))
+            .collect();
+        by_use.sort();
+        let dropped: HashSet<String> = by_use.into_iter().take(excess).map(|(_, id)| id).collect();
+        self.memories.retain(|memory| !dropped.contains(&memory.id));
+    }
+}
+
+/// The project store of a workspace and, unless it is turned off, the
+/// user's own store shared by all workspaces.
+#[derive(Debug, Clone)]
+pub struct Memories {
+    root: PathBuf,
+    user: Option<PathBuf>,
+}
+
+impl Memories {
+    /// `user` is the user store's file, usually [`Memories::user_file`].
+    pub fn new(root: &Path, user: Option<PathBuf>) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            user,
+        }
+    }
+
+    /// `~/.rct/memory.json`, if there is a home directory.
+    pub fn user_file() -> Option<PathBuf> {
+        env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .filter(|home| !home.is_empty())
+            .map(|home| PathBuf::from(home).join(MEMORY_FILE))
+    }
+
+    fn path(&self, scope: Scope) -> io::Result<PathBuf> {
+        match scope {
+            Scope::Project => Ok(self.root.join(MEMORY_FILE)),
+            Scope::User => self.user.clone().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "There is no user memory store")
+            }),
+        }
+    }
+
+    fn load(&self, scope: Scope) -> io::Result<Store> {
+        let path = self.path(scope)?;
+        let mut store = Store::load(&path)?;
+        if scope == Scope::Project && !path.exists() {
+            if let Ok(markdown) = fs::read_to_string(self.root.join(LEGACY_MEMORY_FILE)) {
+                for (created, fact) in parse_legacy(&markdown) {
+                    store.add(scope, fact, Vec::new(), LEGACY_MEMORY_FILE, created);
+                }
+                store.save(&path)?;
+            }
+        }
+        Ok(store)
+    }
+
+    /// The stores there are, project first.
+    fn scopes(&self) -> Vec<Scope> {
+        let mut scopes = vec![Scope::Project];
+        if self.user.is_some() {
+            scopes.push(Scope::User);
+        }
+        scopes
+    }
+
+    /// Saves `fact` in the store of `scope`. A fact the store already holds
+    /// is not added again; it gets the new tags instead.
+    pub fn remember(
+        &self,
+        fact: &str,
+        tags: &[String],
+        scope: Scope,
+        source: &str,
+    ) -> io::Result<Memory> {
+        let fact = fact.trim();
+        if fact.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "There is no fact to remember",
+            ));
+        }
+        let mut tags: Vec<String> = tags
+            .iter()
+            .map(|tag| tag.trim().trim_start_matches('#').to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        let mut seen = HashSet::new();
+        tags.retain(|tag| seen.insert(tag.clone()));
+
+        let path = self.path(scope)?;
+        let mut store = self.load(scope)?;
+        let existing = store
+            .memories
+            .iter_mut()
+            .find(|memory| memory.fact.eq_ignore_ascii_case(fact));
+        let memory = match existing {
+            Some(memory) => {
+                for tag in tags {
+                    if !memory.tags.contains(&tag) {
+                        memory.tags.push(tag);
+                    }
+                }
+                memory.clone()
+            }
+            None => {
+                let memory = store.add(scope, fact.to_string(), tags, source, now());
+                store.prune();
+                memory
+            }
+        };
+        store.save(&path)?;
+        Ok(memory)
+    }
+
+    /// Removes the memory with `id` from whichever store it is in. None if
+    /// there is no such memory.
+    pub fn forget(&self, id: &str) -> io::Result<Option<Memory>> {
+        for scope in self.scopes() {
+            if !id.starts_with(scope.prefix()) {
+                continue;
+            }
+            let mut store = self.load(scope)?;
+            let Some(i) = store.memories.iter().position(|memory| memory.id == id) else {
+                return Ok(None);
+            };
+            let memory = store.memories.remove(i);
+            store.save(&self.path(scope)?)?;
+            return Ok(Some(memory));
+        }
+        Ok(None)
+    }
+
+    /// The memories of both stores that share words with `query`, best
+    /// first, or with an empty query all of them, most recently used first.
+    /// At most `limit` are returned, and those are marked as used.
+    pub fn recall(&self, query: &str, limit: usize) -> io::Result<Vec<(Scope, Memory)>> {
+        let mut stores = Vec::new();
+        for scope in self.scopes() {
+            stores.push((scope, self.load(scope)?));
+        }
+        let all: Vec<(Scope, &Memory)> = stores
+            .iter()
+            .flat_map(|(scope, store)| store.memories.iter().map(move |memory| (*scope, memory)))
+            .collect();
+
+        let mut ranked: Vec<(f64, Scope, &Memory)> = if query.trim().is_empty() {
+            all.iter()
+                .map(|&(scope, memory)| (0.0, scope, memory))
+                .collect()
+        } else {
+            score(query, &all)
+                .into_iter()
+                .zip(&all)
+                .filter(|(score, _)| *score > 0.0)
+                .map(|(score, &(scope, memory))| (score, scope, memory))
+                .collect()
+        };
+        ranked.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then_with(|| b.2.last_used().cmp(a.2.last_used()))
+        });
+        ranked.truncate(limit);
+        let used = now();
+        let recalled: Vec<(Scope, Memory)> = ranked
+            .into_iter()
+            .map(|(_, scope, memory)| {
+                let mut memory = memory.clone();
+                memory.used = Some(used.clone());
+                (scope, memory)
+            })
+            .collect();
+
+        for (scope, mut store) in stores {
+            let mut changed = false;
+            for memory in &mut store.memories {
+                if recalled
+                    .iter()
+                    .any(|(_, recalled)| recalled.id == memory.id)
+                {
+                    memory.used = Some(used.clone());
+                    changed = true;
+                }
+            }
+            if changed {
+                store.save(&self.path(scope)?)?;
+            }
+        }
+        Ok(recalled)
+    }
+}
+
+/// How well each memory matches `query`: every query word it contains adds
+/// that word's rarity among the memories, twice over when it is a tag.
+fn score(query: &str, memories: &[(Scope, &Memory)]) -> Vec<f64> {
+    let wanted: HashSet<String> = semantic::terms(query).into_iter().collect();
+    let documents: Vec<(HashSet<String>, HashSet<String>)> = memories
+        .iter()
+        .map(|(_, memory)| {
+            let words = semantic::terms(&memory.fact).into_iter().collect();
+            let tags = semantic::terms(&memory.tags.join(" "))
+                .into_iter()
+                .collect();
+            (words, tags)
+        })
+        .collect();
+
+    let mut frequency: HashMap<&str, usize> = HashMap::new();
+    for (words, tags) in &documents {
+        for term in words.union(tags) {
+            *frequency.entry(term.as_str()).or_default() += 1;
+        }
+    }
+    let count = documents.len() as f64;
+    documents
+        .iter()
+        .map(|(words, tags)| {
+            wanted
+                .iter()
+                .map(|term| {
+                    let Some(&found) = frequency.get(term.as_str()) else {
+                        return 0.0;
+                    };
+                    let rarity = (1.0 + count / found as f64).ln();
+                    if tags.contains(term) {
+                        TAG_WEIGHT * rarity
+                    } else if words.contains(term) {
+                        rarity
+                    } else {
+                        0.0
+                    }
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// The entries of an old `.agent_memory.md`: a `## [timestamp]` heading
+/// followed by the fact.
+fn parse_legacy(markdown: &str) -> Vec<(String, String)> {
+    markdown
+        .split("\n## [")
+        .skip(1)
+        .filter_map(|entry| {
+            let (created, fact) = entry.split_once("]\n")?;
+            let fact = fact.trim();
+            (!fact.is_empty()).then(|| (created.to_string(), fact.to_string()))
+        })
+        .collect()
+}
+
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
@@ -0,0 +1,488 @@
+use std::fmt;
+use std::io;
+
+/// Context lines that may be dropped from each end of a hunk that doesn't
+/// apply as written, like `patch --fuzz=2`.
+const MAX_FUZZ: usize = 2;
+
+/// Lines of the file shown around the spot a rejected hunk was meant for.
+const NEARBY_LINES: usize = 3;
+
+/// The changes a patch makes to one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    /// None when the patch creates the file
+    pub old_path: Option<String>,
+    /// None when the patch deletes the file
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// 1-based line the hunk starts at in the old file, as its header says
+    pub old_start: usize,
+    pub lines: Vec<Line>,
+    /// The new text has no newline at the end of the file
+    pub no_newline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// A file patched in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patched {
+    pub content: String,
+    /// Hunks that applied somewhere other than where their header said
+    pub moved: usize,
+    /// Hunks that only applied after dropping context or ignoring whitespace
+    pub fuzzy: usize,
+}
+
+/// A hunk that could not be applied, with the file lines where it was meant to go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    /// 1-based position of the hunk in its file's patch
+    pub index: usize,
+    pub hunk: String,
+    pub nearby: String,
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Hunk {} does not match the file:\n{}\nThe file around that spot:\n{}",
+            self.index, self.hunk, self.nearby
+        )
+    }
+}
+
+impl FilePatch {
+    /// The path the patch is about, for messages.
+    pub fn path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or("")
+    }
+
+    pub fn is_creation(&self) -> bool {
+        self.old_path.is_none()
+    }
+
+    pub fn is_deletion(&self) -> bool {
+        self.new_path.is_none()
+    }
+
+    /// Applies every hunk to `old`, in order. A hunk that doesn't match
+    /// where its header says is looked for elsewhere in the file, then
+    /// retried ignoring trailing whitespace and with up to `MAX_FUZZ`
+    /// context lines dropped from each end.
+    pub fn apply(&self, old: &str) -> Result<Patched, Vec<Rejected>> {
+        let crlf = old.contains("\r\n");
+        let mut lines: Vec<&str> = old.split('\n').map(|l| l.trim_end_matches('\r')).collect();
+        let mut newline = old.ends_with('\n');
+        if newline || old.is_empty() {
+            lines.pop();
+        }
+
+        let mut out: Vec<&str> = Vec::with_capacity(lines.len());
+        let mut rejected = Vec::new();
+        let (mut cursor, mut delta) = (0usize, 0isize);
+        let (mut moved, mut fuzzy) = (0, 0);
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            let Some(found) = hunk.locate(&lines, cursor, delta) else {
+                rejected.push(hunk.reject(i + 1, &lines, delta));
+                continue;
+            };
+            let planned = hunk.expected(found.skip, 0);
+            if found.at as isize != planned {
+                moved += 1;
+            }
+            if found.fuzzy || found.skip > 0 {
+                fuzzy += 1;
+            }
+            let (old_block, new_block) = hunk.blocks(found.skip);
+            out.extend_from_slice(&lines[cursor..found.at]);
+            out.extend(new_block);
+            cursor = found.at + old_block.len();
+            // Later hunks are probably shifted the same way
+            delta = found.at as isize - planned;
+            if cursor == lines.len() && found.skip == 0 {
+                newline = !hunk.no_newline;
+            }
+        }
+        if !rejected.is_empty() {
+            return Err(rejected);
+        }
+        out.extend_from_slice(&lines[cursor..]);
+        if self.is_creation() {
+            newline = !self.hunks.last().is_some_and(|hunk| hunk.no_newline);
+        }
+
+        let eol = if crlf { "\r\n" } else { "\n" };
+        let mut content = out.join(eol);
+        if newline && !out.is_empty() {
+            content.push_str(eol);
+        }
+        Ok(Patched {
+            content,
+            moved,
+            fuzzy,
+        })
+    }
+}
+
+/// Where a hunk matched.
+struct Found {
+    at: usize,
+    /// Context lines dropped from each end
+    skip: usize,
+    fuzzy: bool,
+}
+
+impl Hunk {
+    /// The lines the hunk replaces and the lines it puts there, without
+    /// `skip` context lines at either end.
+    fn blocks(&self, skip: usize) -> (Vec<&str>, Vec<&str>) {
+        let leading = self
+            .lines
+            .iter()
+            .take_while(|l| matches!(l, Line::Context(_)))
+            .count();
+        let trailing = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|l| matches!(l, Line::Context(_)))
+            .count();
+        let end = self.lines.len() - skip.min(trailing);
+        let lines = &self.lines[skip.min(leading).min(end)..end];
+        let old = lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Remove(text) => Some(text.as_str()),
+                Line::Add(_) => None,
+            })
+            .collect();
+        let new = lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Add(text) => Some(text.as_str()),
+                Line::Remove(_) => None,
+            })
+            .collect();
+        (old, new)
+    }
+
+    /// 0-based line the (trimmed) hunk should start at, after earlier hunks
+    /// shifted the file by `delta`.
+    fn expected(&self, skip: usize, delta: isize) -> isize {
+        let leading = self
+            .lines
+            .iter()
+            .take_while(|l| matches!(l, Line::Context(_)))
+            .count();
+        let has_old = self.lines.iter().any(|l| !matches!(l, Line::Add(_)));
+        // A pure insertion's header names the line it goes after
+        let start = if has_old {
+            self.old_start.saturating_sub(1)
+        } else {
+            self.old_start
+        };
+        (start + skip.min(leading)) as isize + delta
+    }
+
+    fn locate(&self, lines: &[&str], from: usize, delta: isize) -> Option<Found> {
+        let mut tried = None;
+        for skip in 0..=MAX_FUZZ {
+            let (old, _) = self.blocks(skip);
+            if tried == Some(old.len()) {
+                continue;
+            }
+            tried = Some(old.len());
+            let expected = self.expected(skip, delta);
+            for fuzzy in [false, true] {
+                if let Some(at) = search(lines, &old, from, expected, fuzzy) {
+                    return Some(Found { at, skip, fuzzy });
+                }
+            }
+        }
+        None
+    }
+
+    fn reject(&self, index: usize, lines: &[&str], delta: isize) -> Rejected {
+        let hunk = self
+            .lines
+            .iter()
+            .map(|line| match line {
+                Line::Context(text) => format!(" {}", text),
+                Line::Remove(text) => format!("-{}", text),
+                Line::Add(text) => format!("+{}", text),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (old, _) = self.blocks(0);
+        let at = self.expected(0, delta).clamp(0, lines.len() as isize) as usize;
+        let start = at.saturating_sub(NEARBY_LINES);
+        let end = (at + old.len() + NEARBY_LINES).min(lines.len());
+        let nearby = if start >= end {
+            "(the file ends before that line)".to_string()
+        } else {
+            (start..end)
+                .map(|i| format!("{:>5}| {}", i + 1, lines[i]))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        Rejected {
+            index,
+            hunk: format!("@@ -{} @@\n{}", self.old_start, hunk),
+            nearby,
+        }
+    }
+}
+
+/// The position at or after `from` where `block` matches `lines`, closest
+/// to `expected` first.
+fn search(
+    lines: &[&str],
+    block: &[&str],
+    from: usize,
+    expected: isize,
+    fuzzy: bool,
+) -> Option<usize> {
+    if block.len() > lines.len().saturating_sub(from) {
+        return None;
+    }
+    let last = lines.len() - block.len();
+    let expected = expected.clamp(from as isize, last as isize) as usize;
+    let matches = |at: usize| {
+        block.iter().zip(&lines[at..]).all(|(want, have)| {
+            if fuzzy {
+                want.trim_end() == have.trim_end()
+            } else {
+                want == have
+            }
+        })
+    };
+    (0..=last - from).find_map(|distance| {
+        let after = expected + distance;
+        if after <= last && matches(after) {
+            return Some(after);
+        }
+        let before = expected
+            .checked_sub(distance)
+            .filter(|&at| at >= from && distance > 0)?;
+        matches(before).then_some(before)
+    })
+}
+
+/// Parses a unified diff, plain or as written by `git diff`, into one
+/// `FilePatch` per file. Text around the diff is ignored.
+pub fn parse(text: &str) -> io::Result<Vec<FilePatch>> {
+    let lines: Vec<&str> = text.lines().map(|l| l.trim_end_matches('\r')).collect();
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut current: Option<FilePatch> = None;
+    let mut git_header = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(rest) = line.strip_prefix("diff --git ") {
+            files.extend(current.take());
+            let (old, new) = rest.split_once(" b/").unwrap_or((rest, rest));
+            current = Some(FilePatch {
+                old_path: Some(old.to_string()),
+                new_path: Some(format!("b/{}", new)),
+                hunks: Vec::new(),
+            });
+            git_header = true;
+        } else if line.starts_with("new file mode") {
+            if let Some(file) = current.as_mut() {
+                file.old_path = None;
+            }
+        } else if line.starts_with("deleted file mode") {
+            if let Some(file) = current.as_mut() {
+                file.new_path = None;
+            }
+        } else if let Some(path) = line.strip_prefix("rename from ") {
+            if let Some(file) = current.as_mut() {
+                file.old_path = Some(format!("a/{}", path));
+            }
+        } else if let Some(path) = line.strip_prefix("rename to ") {
+            if let Some(file) = current.as_mut() {
+                file.new_path = Some(format!("b/{}", path));
+            }
+        } else if line.starts_with("GIT binary patch") || line.starts_with("Binary files ") {
+            return Err(invalid("Binary patches are not supported".to_string()));
+        } else if line.starts_with("--- ")
+            && lines
+                .get(i + 1)
+                .is_some_and(|next| next.starts_with("+++ "))
+        {
+            // Starts a new file unless it belongs to the preceding `diff --git` line
+            if !git_header || current.as_ref().is_none_or(|file| !file.hunks.is_empty()) {
+                files.extend(current.take());
+                current = Some(FilePatch {
+                    old_path: None,
+                    new_path: None,
+                    hunks: Vec::new(),
+                });
+            }
+            let file = current.as_mut().expect("a file was just started");
+            file.old_path = header_path(&line[4..]);
+            file.new_path = header_path(&lines[i + 1][4..]);
+            git_header = false;
+            i += 1;
+        } else if line.starts_with("@@") {
+            let Some(file) = current.as_mut() else {
+                return Err(invalid(format!(
+                    "Hunk at line {} comes before any file header",
+                    i + 1
+                )));
+            };
+            let (hunk, next) = parse_hunk(&lines, i)?;
+            file.hunks.push(hunk);
+            git_header = false;
+            i = next;
+            continue;
+        }
+        i += 1;
+    }
+    files.extend(current);
+
+    if files.is_empty() {
+        return Err(invalid(
+            "No file headers found; expected a unified diff with ---/+++ lines and @@ hunks"
+                .to_string(),
+        ));
+    }
+    for file in &mut files {
+        strip_git_prefixes(file);
+        let renamed =
+            file.old_path.is_some() && file.new_path.is_some() && file.old_path != file.new_path;
+        if file.hunks.is_empty() && !file.is_creation() && !file.is_deletion() && !renamed {
+            return Err(invalid(format!(
+                "The patch for '{}' has no hunks",
+                file.path()
+            )));
+        }
+    }
+    Ok(files)
+}
+
+/// Reads the hunk whose header is at `start`. Line counts in the header are
+/// a guide rather than a rule, since hand-written diffs often get them
+/// wrong, and a bare `@@` means the hunk may be anywhere in the file.
+fn parse_hunk(lines: &[&str], start: usize) -> io::Result<(Hunk, usize)> {
+    let header = parse_header(lines[start]);
+    let (old_start, old_count, new_count) = header.unwrap_or((0, usize::MAX, usize::MAX));
+    let mut hunk = Hunk {
+        old_start,
+        lines: Vec::new(),
+        no_newline: false,
+    };
+    let (mut old_left, mut new_left) = (old_count, new_count);
+    let mut i = start + 1;
+    while let Some(&line) = lines.get(i) {
+        let counted = old_left == 0 && new_left == 0;
+        if line.starts_with("@@") || line.starts_with("diff --git ") {
+            break;
+        }
+        if counted
+            && (line.is_empty()
+                || (line.starts_with("--- ")
+                    && lines
+                        .get(i + 1)
+                        .is_some_and(|next| next.starts_with("+++ "))))
+        {
+            break;
+        }
+        match line.chars().next() {
+            Some('+') => {
+                hunk.lines.push(Line::Add(line[1..].to_string()));
+                new_left = new_left.saturating_sub(1);
+            }
+            Some('-') => {
+                hunk.lines.push(Line::Remove(line[1..].to_string()));
+                old_left = old_left.saturating_sub(1);
+            }
+            Some(' ') | None => {
+                hunk.lines
+                    .push(Line::Context(line.get(1..).unwrap_or("").to_string()));
+                old_left = old_left.saturating_sub(1);
+                new_left = new_left.saturating_sub(1);
+            }
+            Some('\\') => {
+                // Only the new side's missing newline changes the result
+                if !matches!(hunk.lines.last(), Some(Line::Remove(_))) {
+                    hunk.no_newline = true;
+                }
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    if header.is_none() {
+        // Without counts, blank lines at the end can't be told from context
+        while hunk.lines.last() == Some(&Line::Context(String::new())) {
+            hunk.lines.pop();
+        }
+    }
+    if hunk.lines.is_empty() {
+        return Err(invalid(format!("Empty hunk at line {}", start + 1)));
+    }
+    Ok((hunk, i))
+}
+
+/// `@@ -old_start[,old_count] +new_start[,new_count] @@`
+fn parse_header(header: &str) -> Option<(usize, usize, usize)> {
+    let mut parts = header.trim_start_matches('@').split_whitespace();
+    let range = |part: Option<&str>, sign: char| -> Option<(usize, usize)> {
+        let range = part?.strip_prefix(sign)?;
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_count) = range(parts.next(), '-')?;
+    let (_, new_count) = range(parts.next(), '+')?;
+    Some((old_start, old_count, new_count))
+}
+
+/// Path from a `---`/`+++` line, or None for /dev/null.
+fn header_path(rest: &str) -> Option<String> {
+    // Drop a trailing timestamp, as written by `diff -u`
+    let path = rest
+        .split('\t')
+        .next()
+        .unwrap_or(rest)
+        .trim()
+        .trim_matches('"');
+    (path != "/dev/null").then(|| path.to_string())
+}
+
+/// Removes the `a/` and `b/` prefixes git puts on paths, but leaves paths
+/// alone when the diff doesn't use them.
+fn strip_git_prefixes(file: &mut FilePatch) {
+    let prefixed =
+        |path: &Option<String>, prefix: &str| path.as_ref().is_none_or(|p| p.starts_with(prefix));
+    if !prefixed(&file.old_path, "a/") || !prefixed(&file.new_path, "b/") {
+        return;
+    }
+    for path in [&mut file.old_path, &mut file.new_path]
+        .into_iter()
+        .flatten()
+    {
+        path.drain(..2);
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
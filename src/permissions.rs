@@ -0,0 +1,292 @@
+use crate::workspace::{self, Workspace};
+use globset::GlobBuilder;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::sync::oneshot;
+
+/// Where saved rules live, relative to the workspace.
+pub const PERMISSIONS_FILE: &str = ".rct/permissions.toml";
+
+/// What makes one command line run more than the program it starts with:
+/// `cargo *` must not let `cargo build && rm -rf ~` or `cargo x; curl ... | sh`
+/// through.
+const SHELL_OPERATORS: &[&str] = &[";", "&", "|", "`", "$(", ">", "<", "\n"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Allow,
+    Deny,
+}
+
+/// A saved decision for every action of `tool` whose subject matches
+/// `pattern`, e.g. allow `RUN_COMMAND` for `cargo *`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub action: RuleAction,
+    /// Tool name as in tool calls (`RUN_COMMAND`), or `*` for any tool
+    pub tool: String,
+    /// Glob over the command or path. `*` stays within one path component
+    /// for paths, `**` crosses them, and a leading `!` inverts the match
+    /// (deny `!src/**` denies everything outside `src/`)
+    #[serde(default = "match_everything")]
+    pub pattern: String,
+}
+
+fn match_everything() -> String {
+    "**".to_string()
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            RuleAction::Allow => "allow",
+            RuleAction::Deny => "deny",
+        };
+        write!(f, "{} {} \"{}\"", action, self.tool, self.pattern)
+    }
+}
+
+impl Rule {
+    fn matches_subject(&self, subject: &str, is_path: bool) -> bool {
+        let (negated, pattern) = match self.pattern.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, self.pattern.as_str()),
+        };
+        // A broken pattern matches nothing, so the action falls back to a prompt
+        let Ok(glob) = GlobBuilder::new(pattern).literal_separator(is_path).build() else {
+            return false;
+        };
+        glob.compile_matcher().is_match(subject) != negated
+    }
+
+    /// Deny rules fire if any subject matches; allow rules need all of them,
+    /// so moving a file out of an allowed directory still asks.
+    fn matches(&self, action: &Action) -> bool {
+        if self.tool != "*" && !self.tool.eq_ignore_ascii_case(action.tool) {
+            return false;
+        }
+        let mut subjects = action.subjects.iter();
+        match self.action {
+            RuleAction::Allow if action.always_asks() => false,
+            RuleAction::Allow => subjects.all(|s| self.matches_subject(s, action.is_path)),
+            RuleAction::Deny => subjects.any(|s| self.matches_subject(s, action.is_path)),
+        }
+    }
+}
+
+/// A tool call that needs permission, described for rules and the prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Action {
+    /// Tool name as in tool calls
+    pub tool: &'static str,
+    /// What rules match against: the command line, or every path touched
+    pub subjects: Vec<String>,
+    /// Whether the subjects are paths, which changes how `*` matches
+    pub is_path: bool,
+    /// Whether the subject is a command line run by a shell
+    pub is_shell: bool,
+    /// Whether no allow rule may cover the action: code for an interpreter,
+    /// and changes to rct's own rules and checkpoints
+    pub never_allowed_by_rule: bool,
+    /// The exact action, shown in the approval prompt
+    pub description: String,
+}
+
+impl Action {
+    pub fn command(tool: &'static str, command: &str, description: String) -> Self {
+        Self {
+            tool,
+            subjects: vec![command.trim().to_string()],
+            is_path: false,
+            is_shell: false,
+            never_allowed_by_rule: false,
+            description,
+        }
+    }
+
+    /// Source code run by an interpreter; asked about every time.
+    pub fn script(tool: &'static str, code: &str, description: String) -> Self {
+        Self {
+            never_allowed_by_rule: true,
+            ..Self::command(tool, code, description)
+        }
+    }
+
+    /// A command line for the shell, as opposed to arguments passed to one
+    /// program.
+    pub fn shell(tool: &'static str, command: &str, description: String) -> Self {
+        Self {
+            is_shell: true,
+            ..Self::command(tool, command, description)
+        }
+    }
+
+    /// Paths are matched as `workspace` resolves them, relative to its root.
+    pub fn paths(
+        tool: &'static str,
+        workspace: &Workspace,
+        paths: &[&str],
+        description: String,
+    ) -> Self {
+        Self {
+            tool,
+            subjects: paths.iter().map(|path| workspace.rule_path(path)).collect(),
+            is_path: true,
+            is_shell: false,
+            never_allowed_by_rule: false,
+            description,
+        }
+    }
+
+    /// Changes to paths under the workspace's state directory, asked about
+    /// every time: a rule written there could allow anything.
+    pub fn state_paths(
+        tool: &'static str,
+        workspace: &Workspace,
+        paths: &[&str],
+        description: String,
+    ) -> Self {
+        Self {
+            never_allowed_by_rule: true,
+            ..Self::paths(tool, workspace, paths, description)
+        }
+    }
+
+    /// A shell command line that chains, pipes, substitutes or redirects.
+    /// No allow rule covers these, so they are asked about every time.
+    pub fn is_chained(&self) -> bool {
+        self.is_shell
+            && self.subjects.iter().any(|subject| {
+                SHELL_OPERATORS
+                    .iter()
+                    .any(|operator| subject.contains(operator))
+            })
+    }
+
+    /// Whether the user must approve this action every time, because no
+    /// allow rule covers it.
+    pub fn always_asks(&self) -> bool {
+        self.never_allowed_by_rule || self.is_chained()
+    }
+
+    /// Pattern offered when saving a rule from the prompt: the program and
+    /// any arguments for commands, the exact path (or shared directory) for
+    /// files.
+    pub fn suggested_pattern(&self) -> String {
+        let first = self.subjects.first().map(String::as_str).unwrap_or("");
+        if !self.is_path {
+            return match first.split_once(' ') {
+                Some((program, _)) => format!("{} *", program),
+                None => first.to_string(),
+            };
+        }
+        if self.subjects.len() == 1 {
+            return first.to_string();
+        }
+        let parent = |path: &str| Path::new(path).parent().map(Path::to_path_buf);
+        let shared = parent(first).filter(|dir| {
+            self.subjects
+                .iter()
+                .all(|subject| parent(subject).as_ref() == Some(dir))
+        });
+        match shared {
+            Some(dir) if !dir.as_os_str().is_empty() => format!("{}/*", dir.display()),
+            Some(_) => "*".to_string(),
+            None => "**".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allow,
+    Deny(Rule),
+    Ask,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+/// Allow/deny rules, loaded from and saved to a TOML file of `[[rule]]`
+/// tables.
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    pub rules: Vec<Rule>,
+    path: Option<PathBuf>,
+}
+
+impl Permissions {
+    /// Reads the rules in `path`; a missing file means no rules.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let rules = match fs::read_to_string(path) {
+            Ok(text) => toml::from_str::<RulesFile>(&text)?.rules,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            rules,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// The file the rules were loaded from.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Deny rules win over allow rules; without a match the user is asked.
+    pub fn check(&self, action: &Action) -> Decision {
+        let matching = |kind: RuleAction| {
+            self.rules
+                .iter()
+                .find(|rule| rule.action == kind && rule.matches(action))
+        };
+        if let Some(rule) = matching(RuleAction::Deny) {
+            return Decision::Deny(rule.clone());
+        }
+        if matching(RuleAction::Allow).is_some() {
+            return Decision::Allow;
+        }
+        Decision::Ask
+    }
+
+    /// Adds `rule` and writes the file this was loaded from, if any.
+    pub fn add(&mut self, rule: Rule) -> io::Result<()> {
+        self.rules.push(rule);
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            workspace::create_state_dir(dir)?;
+        }
+        let file = RulesFile {
+            rules: self.rules.clone(),
+        };
+        let text = toml::to_string(&file).map_err(io::Error::other)?;
+        fs::write(path, text)
+    }
+}
+
+/// The user's answer to an approval prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Approval {
+    AllowOnce,
+    /// The reason is passed on to the model
+    Deny(String),
+    /// Save the rule, then apply it
+    Save(Rule),
+}
+
+/// An action waiting for the user, shown as a modal by the TUI.
+#[derive(Debug)]
+pub struct ApprovalRequest {
+    pub action: Action,
+    pub reply: oneshot::Sender<Approval>,
+}
@@ -0,0 +1,255 @@
+use std::io;
+use std::process::{Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// How long to wait for the last output after killing a timed-out child,
+/// or after a child exits while something it started in the background
+/// still holds its pipes open.
+const DRAIN_GRACE: Duration = Duration::from_secs(1);
+
+/// Added to the output of a child that left background processes holding
+/// its pipes, whose output is not waited for.
+const STILL_OPEN_NOTE: &str =
+    "[output cut off: processes it started in the background still hold the output open]";
+
+/// Characters of output kept in a timeout error.
+const TIMEOUT_OUTPUT_CHARS: usize = 2_000;
+
+/// Limits and hooks for a child process.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Kill the child once it has run this long
+    pub timeout: Option<Duration>,
+    /// Receives every line the child prints, stdout and stderr alike
+    pub lines: Option<UnboundedSender<String>>,
+}
+
+/// Like `Command::output`, but kills the child and everything it started
+/// as soon as `cancel` fires, returning an `Interrupted` error.
+#[allow(dead_code)]
+pub async fn output(command: &mut Command, cancel: &CancellationToken) -> io::Result<Output> {
+    run(command, cancel, &RunOptions::default()).await
+}
+
+/// `output` with a timeout and live output. A timed-out child is killed and
+/// a `TimedOut` error carrying the end of its output is returned.
+///
+/// Dropping the future kills the child and everything it started as well.
+pub async fn run(
+    command: &mut Command,
+    cancel: &CancellationToken,
+    options: &RunOptions,
+) -> io::Result<Output> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    // A separate process group lets us kill grandchildren (e.g. the program
+    // a `sh -c` wrapper started) along with the child
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let started = tokio::time::Instant::now();
+    let mut child = command.spawn()?;
+    let mut group = KillOnDrop(child.id());
+    // Drain both pipes while waiting so a chatty child can't fill them and block
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| Reader::spawn(pipe, options.lines.clone()));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| Reader::spawn(pipe, options.lines.clone()));
+
+    let deadline = async {
+        match options.timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    let status = tokio::select! {
+        status = child.wait() => status?,
+        _ = cancel.cancelled() => {
+            kill_tree(&mut child).await;
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "cancelled by user",
+            ));
+        }
+        _ = deadline => {
+            kill_tree(&mut child).await;
+            let mut so_far = String::new();
+            for reader in stdout.into_iter().chain(stderr) {
+                so_far.push_str(&String::from_utf8_lossy(&reader.wait_briefly().await));
+            }
+            let so_far = clip(so_far.trim(), 0, TIMEOUT_OUTPUT_CHARS);
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "timed out after {}s and was stopped. Output so far:\n{}",
+                    options.timeout.unwrap_or_default().as_secs(),
+                    so_far
+                ),
+            ));
+        }
+    };
+
+    // It exited by itself; anything it left running in the background stays,
+    // but is only waited for briefly, and never past the timeout
+    group.0 = None;
+    let mut until = tokio::time::Instant::now() + DRAIN_GRACE;
+    if let Some(timeout) = options.timeout {
+        until = until.min(started + timeout);
+    }
+    let ((stdout, stdout_closed), (mut stderr, stderr_closed)) = tokio::join!(
+        Reader::drain(stdout, until, cancel),
+        Reader::drain(stderr, until, cancel)
+    );
+    if !(stdout_closed && stderr_closed) {
+        if !stderr.is_empty() && !stderr.ends_with(b"\n") {
+            stderr.push(b'\n');
+        }
+        stderr.extend_from_slice(STILL_OPEN_NOTE.as_bytes());
+    }
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Collects a pipe in a background task, passing complete lines on.
+struct Reader {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    handle: JoinHandle<()>,
+}
+
+impl Reader {
+    fn spawn<R>(mut pipe: R, lines: Option<UnboundedSender<String>>) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let shared = Arc::clone(&buffer);
+        let handle = tokio::spawn(async move {
+            let mut chunk = [0u8; 8192];
+            let mut line = Vec::new();
+            loop {
+                let n = match pipe.read(&mut chunk).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                shared.lock().unwrap().extend_from_slice(&chunk[..n]);
+                if let Some(lines) = &lines {
+                    for &byte in &chunk[..n] {
+                        if byte == b'\n' {
+                            let _ =
+                                lines.send(String::from_utf8_lossy(&line).trim_end().to_string());
+                            line.clear();
+                        } else {
+                            line.push(byte);
+                        }
+                    }
+                }
+            }
+            if let Some(lines) = &lines {
+                if !line.is_empty() {
+                    let _ = lines.send(String::from_utf8_lossy(&line).trim_end().to_string());
+                }
+            }
+        });
+        Self { buffer, handle }
+    }
+
+    /// What the pipe, if any, delivers until it closes, `until` passes or `cancel`
+    /// fires, and whether it closed.
+    async fn drain(
+        reader: Option<Self>,
+        until: tokio::time::Instant,
+        cancel: &CancellationToken,
+    ) -> (Vec<u8>, bool) {
+        let Some(mut reader) = reader else {
+            return (Vec::new(), true);
+        };
+        let closed = tokio::select! {
+            _ = &mut reader.handle => true,
+            _ = tokio::time::sleep_until(until) => false,
+            _ = cancel.cancelled() => false,
+        };
+        if !closed {
+            reader.handle.abort();
+        }
+        let so_far = reader.buffer.lock().unwrap().clone();
+        (so_far, closed)
+    }
+
+    /// What was read so far, after giving the pipe a moment to close. A
+    /// grandchild that escaped the kill may keep it open indefinitely.
+    async fn wait_briefly(mut self) -> Vec<u8> {
+        if tokio::time::timeout(DRAIN_GRACE, &mut self.handle)
+            .await
+            .is_err()
+        {
+            self.handle.abort();
+        }
+        let so_far = self.buffer.lock().unwrap().clone();
+        so_far
+    }
+}
+
+/// Keeps the first `head` and last `tail` characters of `text`, with a note
+/// on how much was cut in between.
+pub fn clip(text: &str, head: usize, tail: usize) -> String {
+    let total = text.chars().count();
+    if total <= head + tail {
+        return text.to_string();
+    }
+    let first: String = text.chars().take(head).collect();
+    let last: String = text.chars().skip(total - tail).collect();
+    let cut: String = text.chars().skip(head).take(total - head - tail).collect();
+    format!(
+        "{}\n[... {} characters ({} lines) cut ...]\n{}",
+        first,
+        total - head - tail,
+        cut.lines().count(),
+        last
+    )
+}
+
+/// Kills a process group when dropped, unless the pid was cleared, so a run
+/// that is abandoned midway leaves nothing behind.
+struct KillOnDrop(Option<u32>);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.0 {
+            kill_group(pid);
+        }
+    }
+}
+
+async fn kill_tree(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        kill_group(pid);
+    }
+    let _ = child.start_kill();
+    let _ = child.wait().await;
+}
+
+#[cfg(unix)]
+fn kill_group(pid: u32) {
+    // The child leads its own process group, so a negative pid hits all of it
+    unsafe {
+        libc::kill(-(pid as i32), libc::SIGKILL);
+    }
+}
@@ -0,0 +1,241 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Lines READ_FILE shows when no limit is given.
+pub const DEFAULT_LIMIT: usize = 2_000;
+
+/// Longer lines (minified code, logs) are cut to this many characters.
+const MAX_LINE_CHARS: usize = 2_000;
+
+/// Bytes looked at to decide whether a file is binary.
+const SNIFF_BYTES: usize = 8_192;
+
+/// Bytes shown in the hex summary of a binary file.
+const HEX_BYTES: usize = 64;
+
+/// Bytes of a line kept in memory: enough for `MAX_LINE_CHARS` characters
+/// of any width.
+const MAX_LINE_BYTES: usize = (MAX_LINE_CHARS + 1) * 4;
+
+/// Renders the file at `file` for READ_FILE: `limit` lines starting at the
+/// 1-based line `offset`, each prefixed with its line number. Binary files
+/// get a short summary instead, and text that isn't valid UTF-8 is decoded
+/// lossily rather than failing.
+///
+/// Only a prefix is read to tell binary files apart, and the lines are
+/// streamed: nothing past the last line shown is kept, it is only counted
+/// for the "more lines" hint.
+pub fn read_file(
+    path: &str,
+    file: &Path,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> io::Result<String> {
+    let mut file = File::open(file)?;
+    let size = file.metadata()?.len();
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    (&mut file)
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut head)?;
+    if is_binary(&head) {
+        return Ok(describe_binary(path, &head, size));
+    }
+    let reader = BufReader::new(io::Cursor::new(head).chain(file));
+    render_lines(path, reader, offset, limit)
+}
+
+fn render_lines(
+    path: &str,
+    mut reader: impl BufRead,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> io::Result<String> {
+    let start = offset.unwrap_or(1).max(1);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).max(1);
+    let last = (start - 1).saturating_add(limit);
+
+    let mut shown = Vec::new();
+    let mut lossy = false;
+    let mut end = 0;
+    while end < last {
+        let Some((bytes, cut)) = read_line(&mut reader)? else {
+            break;
+        };
+        end += 1;
+        if end >= start {
+            let line = String::from_utf8_lossy(&bytes);
+            lossy |= matches!(line, Cow::Owned(_));
+            shown.push((line.into_owned(), cut));
+        }
+    }
+    let total = end + count_lines(&mut reader)?;
+    if total == 0 {
+        return Ok(format!("File: {} (empty)", path));
+    }
+    if start > total {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "offset {} is past the end of '{}', which has {} lines",
+                start, path, total
+            ),
+        ));
+    }
+
+    let mut out = if start == 1 && end == total {
+        format!("File: {} ({} lines)", path, total)
+    } else {
+        format!("File: {} (lines {}-{} of {})", path, start, end, total)
+    };
+    if lossy {
+        out.push_str(
+            "\nNote: the file is not valid UTF-8; undecodable bytes are shown as \u{FFFD}",
+        );
+    }
+    out.push_str("\n\n");
+
+    let width = end.to_string().len();
+    for (number, (line, cut)) in (start..=end).zip(&shown) {
+        if *cut || line.chars().count() > MAX_LINE_CHARS {
+            let cut: String = line.chars().take(MAX_LINE_CHARS).collect();
+            out.push_str(&format!("{:>width$}\t{} [line cut]\n", number, cut));
+        } else {
+            out.push_str(&format!("{:>width$}\t{}\n", number, line));
+        }
+    }
+    if end < total {
+        out.push_str(&format!(
+            "\n... file truncated, {} more lines. Read on with offset: {}\n",
+            total - end,
+            end + 1
+        ));
+    }
+    Ok(out)
+}
+
+/// The next line without its ending, as `str::lines` splits them, keeping
+/// at most `MAX_LINE_BYTES` of it; the flag tells whether it was longer.
+/// `None` at the end of the input.
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<(Vec<u8>, bool)>> {
+    let mut line = Vec::new();
+    let mut cut = false;
+    let mut read_any = false;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        read_any = true;
+        let newline = buf.iter().position(|&b| b == b'\n');
+        let chunk = &buf[..newline.unwrap_or(buf.len())];
+        let room = MAX_LINE_BYTES.saturating_sub(line.len());
+        cut |= chunk.len() > room;
+        line.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        let used = newline.map_or(buf.len(), |i| i + 1);
+        reader.consume(used);
+        if newline.is_some() {
+            break;
+        }
+    }
+    if !read_any {
+        return Ok(None);
+    }
+    if cut {
+        // Don't let a character split by the cut look like invalid UTF-8
+        if let Err(e) = std::str::from_utf8(&line) {
+            if e.error_len().is_none() {
+                line.truncate(e.valid_up_to());
+            }
+        }
+    } else if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(Some((line, cut)))
+}
+
+/// Lines left in `reader`, counted as `read_line` reads them.
+fn count_lines(reader: &mut impl BufRead) -> io::Result<usize> {
+    let mut count = 0;
+    let mut unterminated = false;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        count += buf.iter().filter(|&&b| b == b'\n').count();
+        unterminated = buf.last() != Some(&b'\n');
+        let used = buf.len();
+        reader.consume(used);
+    }
+    Ok(count + usize::from(unterminated))
+}
+
+/// A file is binary if its first bytes contain a NUL, or if more than a
+/// tenth of them are control characters that text doesn't use.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    if head.contains(&0) {
+        return true;
+    }
+    let control = head
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0c | 0x1b))
+        .count();
+    control * 10 > head.len()
+}
+
+/// A summary of a binary file of `size` bytes that starts with `bytes`.
+fn describe_binary(path: &str, bytes: &[u8], size: u64) -> String {
+    let kind = kind(bytes).unwrap_or("unknown type");
+    let head = &bytes[..bytes.len().min(HEX_BYTES)];
+    let mut hex = String::new();
+    for (row, chunk) in head.chunks(16).enumerate() {
+        let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        hex.push_str(&format!(
+            "{:08x}  {:<47}  {}\n",
+            row * 16,
+            bytes.join(" "),
+            ascii
+        ));
+    }
+    format!(
+        "File: {} is binary ({}, {} bytes), so its contents are not shown.\n\nFirst {} bytes:\n{}",
+        path,
+        kind,
+        size,
+        head.len(),
+        hex
+    )
+}
+
+/// Names common file formats from their magic numbers.
+fn kind(bytes: &[u8]) -> Option<&'static str> {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "PNG image"),
+        (b"\xff\xd8\xff", "JPEG image"),
+        (b"GIF8", "GIF image"),
+        (b"%PDF", "PDF document"),
+        (b"PK\x03\x04", "zip archive"),
+        (b"\x1f\x8b", "gzip archive"),
+        (b"\x7fELF", "ELF executable"),
+        (b"MZ", "Windows executable"),
+        (b"\0asm", "WebAssembly module"),
+        (b"SQLite format 3\0", "SQLite database"),
+    ];
+    MAGIC
+        .iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+        .map(|&(_, kind)| kind)
+}
@@ -0,0 +1,249 @@
+use crate::index::Index;
+use crate::llm::estimate_token_count;
+use crate::search;
+use crate::symbols::{Kind, Symbol};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+
+/// Workspaces with more files than this get no map; indexing them on every
+/// turn would cost more than the map saves.
+const MAX_FILES: usize = 20_000;
+
+/// Files named at the workspace root before the rest are counted.
+const MAX_ROOT_FILES: usize = 12;
+
+/// Types or functions listed per file.
+const MAX_ENTRIES_PER_FILE: usize = 6;
+
+/// Methods listed per type.
+const MAX_MEMBERS: usize = 6;
+
+/// What a call from the file a symbol is defined in counts for, next to a
+/// call from another file. Helpers used all over one file are less of a
+/// landmark than what the rest of the code calls.
+const LOCAL_CALL_WEIGHT: f64 = 0.25;
+
+/// Brings `index` up to date with the workspace at `root` and renders its
+/// map in at most `max_tokens`. The index is only updated in memory; saving
+/// it is left to INDEX_CODEBASE. None for workspaces that are empty or too
+/// large.
+pub fn refresh(root: &Path, index: &mut Index, max_tokens: usize) -> io::Result<Option<String>> {
+    let files = search::files(root, root, &search::Filter::default())?;
+    if files.is_empty() || files.len() > MAX_FILES {
+        return Ok(None);
+    }
+    index.update_files(root, root, &files);
+    Ok(render(&files, index, max_tokens))
+}
+
+/// A map of the workspace for the system prompt: its top-level layout,
+/// then the files whose symbols are called most, each with its main types
+/// and functions. Files are added in that order until `max_tokens` is used
+/// up. `files` are all the workspace's paths, relative to its root.
+pub fn render(files: &[String], index: &Index, max_tokens: usize) -> Option<String> {
+    let mut map = String::from("## REPOSITORY MAP\n\nLayout:\n");
+    map.push_str(&layout(files));
+    if estimate_token_count(&map) as usize > max_tokens {
+        return None;
+    }
+
+    let ranked = rank(index);
+    if !ranked.is_empty() {
+        map.push_str("\nKey files and their main symbols, most used first:\n");
+    }
+    let mut shown = 0;
+    for (path, refs, entries) in &ranked {
+        let mut block = path.to_string();
+        if *refs > 0 {
+            block.push_str(&format!(" ({} refs)", refs));
+        }
+        block.push('\n');
+        for entry in entries.iter().take(MAX_ENTRIES_PER_FILE) {
+            block.push_str(&format!("  {}\n", entry));
+        }
+        if entries.len() > MAX_ENTRIES_PER_FILE {
+            block.push_str(&format!(
+                "  ... {} more\n",
+                entries.len() - MAX_ENTRIES_PER_FILE
+            ));
+        }
+        if estimate_token_count(&map) + estimate_token_count(&block) > max_tokens as u64 {
+            break;
+        }
+        map.push_str(&block);
+        shown += 1;
+    }
+    if shown < ranked.len() {
+        map.push_str(&format!(
+            "... {} more files with symbols; use SEARCH_INDEX or SEMANTIC_SEARCH to find them\n",
+            ranked.len() - shown
+        ));
+    }
+    Some(map)
+}
+
+/// Files at the root by name, directories with how many files they hold
+/// and their subdirectories.
+fn layout(files: &[String]) -> String {
+    let mut root_files: Vec<&str> = Vec::new();
+    let mut dirs: BTreeMap<&str, (usize, Vec<&str>)> = BTreeMap::new();
+    for path in files {
+        match path.split_once('/') {
+            None => root_files.push(path),
+            Some((dir, rest)) => {
+                let (count, subdirs) = dirs.entry(dir).or_default();
+                *count += 1;
+                if let Some((sub, _)) = rest.split_once('/') {
+                    if !subdirs.contains(&sub) {
+                        subdirs.push(sub);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut out = String::new();
+    if !root_files.is_empty() {
+        let mut names = root_files
+            .iter()
+            .take(MAX_ROOT_FILES)
+            .copied()
+            .collect::<Vec<_>>()
+            .join(", ");
+        if root_files.len() > MAX_ROOT_FILES {
+            names.push_str(&format!(" and {} more", root_files.len() - MAX_ROOT_FILES));
+        }
+        out.push_str(&format!("  {}\n", names));
+    }
+    for (dir, (count, subdirs)) in dirs {
+        out.push_str(&format!("  {}/ ({} files", dir, count));
+        if !subdirs.is_empty() {
+            let subdirs: Vec<String> = subdirs.iter().map(|sub| format!("{}/", sub)).collect();
+            out.push_str(&format!("; {}", subdirs.join(", ")));
+        }
+        out.push_str(")\n");
+    }
+    out
+}
+
+/// A type with its methods, or a free function, as shown in the map.
+struct Entry<'a> {
+    symbol: Option<&'a Symbol>,
+    name: &'a str,
+    score: f64,
+    members: Vec<(&'a str, f64)>,
+}
+
+impl std::fmt::Display for Entry<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.symbol {
+            Some(symbol) => {
+                let signature = symbol.signature.trim_end_matches([';', ':']);
+                write!(f, "{}: {}", symbol.line, signature)?
+            }
+            None => write!(f, "impl {}", self.name)?,
+        }
+        if !self.members.is_empty() {
+            let names: Vec<&str> = self
+                .members
+                .iter()
+                .take(MAX_MEMBERS)
+                .map(|(name, _)| *name)
+                .collect();
+            write!(f, " {{ {}", names.join(", "))?;
+            if self.members.len() > MAX_MEMBERS {
+                write!(f, ", ...")?;
+            }
+            write!(f, " }}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Files with the weighted number of calls of their symbols, best first,
+/// and their entries, best first. A call counts toward every definition of
+/// the name it calls, split evenly, so a `new` defined in twenty files adds
+/// little to each of them.
+fn rank(index: &Index) -> Vec<(&str, usize, Vec<Entry<'_>>)> {
+    let mut calls: HashMap<&str, usize> = HashMap::new();
+    let mut local_calls: HashMap<(&str, &str), usize> = HashMap::new();
+    for (path, file) in &index.files {
+        for reference in &file.references {
+            *calls.entry(reference.name.as_str()).or_default() += 1;
+            *local_calls
+                .entry((path.as_str(), reference.name.as_str()))
+                .or_default() += 1;
+        }
+    }
+    let mut definitions: HashMap<&str, usize> = HashMap::new();
+    for file in index.files.values() {
+        for symbol in &file.symbols {
+            *definitions.entry(symbol.name.as_str()).or_default() += 1;
+        }
+    }
+    let score = |path: &str, symbol: &Symbol| {
+        let name = symbol.name.as_str();
+        let all = calls.get(name).copied().unwrap_or(0) as f64;
+        let local = local_calls.get(&(path, name)).copied().unwrap_or(0) as f64;
+        (all - local + LOCAL_CALL_WEIGHT * local) / definitions[name] as f64
+    };
+
+    let mut ranked = Vec::new();
+    for (path, file) in &index.files {
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut by_name: HashMap<&str, usize> = HashMap::new();
+        for symbol in file.symbols.iter().filter(|s| s.parent.is_none()) {
+            if let Some(&i) = by_name.get(symbol.name.as_str()) {
+                // `impl Index` after `struct Index` adds nothing of its own
+                if symbol.kind != Kind::Impl && entries[i].symbol.is_none() {
+                    entries[i].symbol = Some(symbol);
+                }
+                continue;
+            }
+            by_name.insert(&symbol.name, entries.len());
+            entries.push(Entry {
+                symbol: (symbol.kind != Kind::Impl).then_some(symbol),
+                name: &symbol.name,
+                score: score(path, symbol),
+                members: Vec::new(),
+            });
+        }
+        for symbol in &file.symbols {
+            let Some(parent) = symbol.parent.as_deref() else {
+                continue;
+            };
+            let i = *by_name.entry(parent).or_insert_with(|| {
+                entries.push(Entry {
+                    symbol: None,
+                    name: parent,
+                    score: 0.0,
+                    members: Vec::new(),
+                });
+                entries.len() - 1
+            });
+            let member = score(path, symbol);
+            entries[i].score += member;
+            entries[i].members.push((&symbol.name, member));
+        }
+        if entries.is_empty() {
+            continue;
+        }
+
+        for entry in &mut entries {
+            entry.members.sort_by(|a, b| b.1.total_cmp(&a.1));
+        }
+        entries.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(a.symbol.map(|s| s.line).cmp(&b.symbol.map(|s| s.line)))
+        });
+        let total: f64 = entries.iter().map(|entry| entry.score).sum();
+        ranked.push((path.as_str(), total, entries));
+    }
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+    ranked
+        .into_iter()
+        .map(|(path, total, entries)| (path, total.round() as usize, entries))
+        .collect()
+}
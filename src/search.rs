@@ -0,0 +1,216 @@
+use crate::read;
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::types::TypesBuilder;
+use ignore::WalkBuilder;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Results shown when no max_results is given.
+pub const DEFAULT_MAX_RESULTS: usize = 200;
+
+/// Matching lines longer than this are cut.
+const MAX_LINE_CHARS: usize = 300;
+
+/// Order of search results.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Path,
+    /// Most recently modified first
+    Modified,
+}
+
+/// Which files a search looks at, and in what order.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Only files whose path below the search directory matches this glob.
+    /// A glob without a `/` is matched against file names alone
+    pub glob: Option<String>,
+    /// Only files of this type, using ripgrep's names (rust, py, js, ...)
+    pub file_type: Option<String>,
+    pub sort: SortBy,
+}
+
+/// Matches of a GREP_SEARCH.
+#[derive(Debug, Clone, Default)]
+pub struct Grep {
+    /// `path:line:text` for matches and `path-line-text` for context,
+    /// with `--` between groups that aren't next to each other
+    pub lines: Vec<String>,
+    pub matches: usize,
+    pub files: usize,
+    /// Stopped at `max_results` matches
+    pub truncated: bool,
+}
+
+/// Files under `start` that pass `filter`, as paths relative to `root`.
+/// Anything .gitignore, .ignore or git's exclude files leave out is
+/// skipped, inside a git repository or not, and so is `.git` itself.
+pub fn files(root: &Path, start: &Path, filter: &Filter) -> io::Result<Vec<String>> {
+    let glob = filter.glob.as_deref().map(compile_glob).transpose()?;
+    let mut builder = WalkBuilder::new(start);
+    builder
+        .hidden(false)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git");
+    if let Some(name) = &filter.file_type {
+        let mut types = TypesBuilder::new();
+        types.add_defaults();
+        types.select(name);
+        let types = types.build().map_err(|e| {
+            invalid(format!(
+                "file_type '{}' is not known ({}); try rust, py, js, ts, go, java, c, cpp, md or toml",
+                name, e
+            ))
+        })?;
+        builder.types(types);
+    }
+
+    let mut found: Vec<(String, Option<SystemTime>)> = Vec::new();
+    for entry in builder.build().flatten() {
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        if let Some(glob) = &glob {
+            let below = match path.strip_prefix(start) {
+                Ok(below) if !below.as_os_str().is_empty() => below,
+                _ => Path::new(path.file_name().unwrap_or_default()),
+            };
+            if !glob.is_match(below) {
+                continue;
+            }
+        }
+        let modified = match filter.sort {
+            SortBy::Modified => entry.metadata().ok().and_then(|m| m.modified().ok()),
+            SortBy::Path => None,
+        };
+        found.push((display(root, path), modified));
+    }
+    match filter.sort {
+        SortBy::Path => found.sort_by(|a, b| a.0.cmp(&b.0)),
+        SortBy::Modified => {
+            found.sort_by_key(|(path, modified)| (Reverse(*modified), path.clone()))
+        }
+    }
+    Ok(found.into_iter().map(|(path, _)| path).collect())
+}
+
+/// Lines matching `pattern` in the files under `start`, with `context`
+/// lines around each. Binary files are skipped and other files that
+/// aren't UTF-8 are searched lossily.
+pub fn grep(
+    root: &Path,
+    start: &Path,
+    pattern: &Regex,
+    context: usize,
+    max_results: usize,
+    filter: &Filter,
+) -> io::Result<Grep> {
+    let mut grep = Grep::default();
+    for path in files(root, start, filter)? {
+        let Ok(bytes) = fs::read(root.join(&path)) else {
+            continue;
+        };
+        if read::is_binary(&bytes) {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        let lines: Vec<&str> = text.lines().collect();
+        let mut hits: Vec<usize> = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            if pattern.is_match(line) {
+                if grep.matches + hits.len() == max_results {
+                    grep.truncated = true;
+                    break;
+                }
+                hits.push(i);
+            }
+        }
+        if hits.is_empty() {
+            if grep.truncated {
+                break;
+            }
+            continue;
+        }
+
+        // Everything within `context` lines of a hit, merged into groups
+        let mut groups: Vec<(usize, usize)> = Vec::new();
+        for &hit in &hits {
+            let from = hit.saturating_sub(context);
+            let to = (hit + context).min(lines.len() - 1);
+            match groups.last_mut() {
+                Some(last) if from <= last.1 + 1 => last.1 = to,
+                _ => groups.push((from, to)),
+            }
+        }
+        if context > 0 && !grep.lines.is_empty() {
+            grep.lines.push("--".to_string());
+        }
+        for (g, &(from, to)) in groups.iter().enumerate() {
+            if g > 0 {
+                grep.lines.push("--".to_string());
+            }
+            for (i, line) in lines.iter().enumerate().take(to + 1).skip(from) {
+                let separator = if hits.binary_search(&i).is_ok() {
+                    ':'
+                } else {
+                    '-'
+                };
+                grep.lines.push(format!(
+                    "{}{}{}{}{}",
+                    path,
+                    separator,
+                    i + 1,
+                    separator,
+                    clip(line)
+                ));
+            }
+        }
+        grep.matches += hits.len();
+        grep.files += 1;
+        if grep.truncated {
+            break;
+        }
+    }
+    Ok(grep)
+}
+
+/// Compiles a glob where `*` stays within one directory and `**` spans
+/// any number of them.
+fn compile_glob(pattern: &str) -> io::Result<GlobMatcher> {
+    let pattern = if pattern.contains('/') {
+        pattern.trim_start_matches("./").to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+    GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .map(|glob| glob.compile_matcher())
+        .map_err(|e| invalid(format!("Invalid glob '{}': {}", pattern, e)))
+}
+
+fn display(root: &Path, path: &Path) -> String {
+    let shown = path.strip_prefix(root).unwrap_or(path);
+    shown.to_string_lossy().replace('\\', "/")
+}
+
+fn clip(line: &str) -> String {
+    if line.chars().count() > MAX_LINE_CHARS {
+        let cut: String = line.chars().take(MAX_LINE_CHARS).collect();
+        format!("{}...", cut)
+    } else {
+        line.to_string()
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
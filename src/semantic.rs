@@ -0,0 +1,453 @@
+use crate::index::{self, Update};
+use crate::read;
+use crate::search;
+use crate::symbols;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Where SEMANTIC_SEARCH keeps its chunk index, next to the symbol index.
+pub const SEARCH_FILE: &str = ".agent_search.json";
+
+/// Results shown when no max_results is given.
+pub const DEFAULT_MAX_RESULTS: usize = 10;
+
+/// BM25 results handed to the embedding model for re-ranking.
+pub const RERANK_CANDIDATES: usize = 30;
+
+/// Bumped whenever chunking or tokenizing changes, so that an old index is
+/// rebuilt instead of misread.
+const VERSION: u32 = 1;
+
+/// Chunks longer than this are split.
+const MAX_CHUNK_LINES: usize = 60;
+
+/// Larger files, typically generated or minified, are left out.
+const MAX_FILE_BYTES: u64 = 512 * 1024;
+
+/// Characters of a chunk sent to the embedding model.
+const MAX_EMBEDDING_CHARS: usize = 2_000;
+
+/// Share of the cosine similarity in a re-ranked score; BM25 makes up the
+/// rest, so exact word matches still count.
+const EMBEDDING_WEIGHT: f64 = 0.7;
+
+// BM25 term frequency saturation and length normalization
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// Words too common in questions and code to say anything about a chunk.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "do", "does", "else", "fn", "for", "from",
+    "how", "if", "in", "is", "it", "let", "mut", "of", "on", "or", "pub", "return", "self", "that",
+    "the", "this", "to", "use", "we", "what", "where", "which", "who", "with",
+];
+
+/// A stretch of a file, usually one definition with its doc comment.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Chunk {
+    /// 1-based, inclusive
+    pub start: usize,
+    pub end: usize,
+    /// The definition or heading the chunk starts with, like
+    /// `function Config::from_file`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Occurrences of each term
+    terms: BTreeMap<String, u32>,
+    /// Number of terms
+    length: u32,
+}
+
+/// The chunks of one file, refreshed like an index entry.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileChunks {
+    /// Milliseconds since the Unix epoch
+    pub modified: u64,
+    pub size: u64,
+    pub hash: String,
+    pub chunks: Vec<Chunk>,
+}
+
+/// A ranked chunk, with its text as it is on disk now.
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub path: String,
+    pub start: usize,
+    pub end: usize,
+    pub title: Option<String>,
+    pub score: f64,
+    pub text: String,
+}
+
+impl Hit {
+    /// What the embedding model sees of the chunk.
+    pub fn embedding_input(&self) -> String {
+        let text: String = self.text.chars().take(MAX_EMBEDDING_CHARS).collect();
+        format!("{}\n{}", self.path, text)
+    }
+
+    /// Up to `count` lines of the chunk with a query term in them, or its
+    /// first lines if none has, as `line: text`.
+    pub fn excerpt(&self, query: &str, count: usize) -> Vec<String> {
+        let wanted: HashSet<String> = terms(query).into_iter().collect();
+        let lines = self
+            .text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let mut shown: Vec<(usize, &str)> = lines
+            .clone()
+            .filter(|(_, line)| terms(line).iter().any(|term| wanted.contains(term)))
+            .take(count)
+            .collect();
+        if shown.is_empty() {
+            shown = lines.take(count).collect();
+        }
+        shown
+            .into_iter()
+            .map(|(i, line)| format!("{}: {}", self.start + i, line.trim_end()))
+            .collect()
+    }
+}
+
+/// Chunked source files of a workspace with their term counts, keyed by
+/// path relative to its root.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Corpus {
+    version: u32,
+    pub files: BTreeMap<String, FileChunks>,
+}
+
+impl Default for Corpus {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+impl Corpus {
+    /// The saved corpus, or an empty one if there is none or it was written
+    /// by an older version.
+    pub fn load(root: &Path) -> Corpus {
+        fs::read_to_string(root.join(SEARCH_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Corpus>(&json).ok())
+            .filter(|corpus| corpus.version == VERSION)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(root.join(SEARCH_FILE), json)
+    }
+
+    /// Brings the corpus up to date with the text files under `start`,
+    /// skipping whatever .gitignore leaves out, binary files and very large
+    /// ones. Files are only chunked again when their content changed.
+    pub fn update(&mut self, root: &Path, start: &Path) -> io::Result<Update> {
+        let mut update = Update::default();
+        let mut seen = HashSet::new();
+        for path in search::files(root, start, &search::Filter::default())? {
+            if is_noise(&path) {
+                continue;
+            }
+            let full = root.join(&path);
+            let Ok(metadata) = fs::metadata(&full) else {
+                continue;
+            };
+            if metadata.len() > MAX_FILE_BYTES {
+                continue;
+            }
+            let modified = index::modified_millis(&metadata);
+            let size = metadata.len();
+            let known = self.files.get_mut(&path);
+            if known
+                .as_ref()
+                .is_some_and(|entry| entry.modified == modified && entry.size == size)
+            {
+                update.unchanged += 1;
+                seen.insert(path);
+                continue;
+            }
+
+            let Ok(bytes) = fs::read(&full) else {
+                continue;
+            };
+            if read::is_binary(&bytes) {
+                continue;
+            }
+            let hash = format!("{:016x}", index::fnv1a(&bytes));
+            match known {
+                Some(entry) if entry.hash == hash => {
+                    entry.modified = modified;
+                    entry.size = size;
+                    update.unchanged += 1;
+                }
+                known => {
+                    if known.is_some() {
+                        update.changed += 1;
+                    } else {
+                        update.added += 1;
+                    }
+                    let chunks = chunk(&path, &String::from_utf8_lossy(&bytes));
+                    self.files.insert(
+                        path.clone(),
+                        FileChunks {
+                            modified,
+                            size,
+                            hash,
+                            chunks,
+                        },
+                    );
+                }
+            }
+            seen.insert(path);
+        }
+
+        let below = relative(root, start);
+        let gone: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| !seen.contains(*path) && index::is_below(path, &below))
+            .cloned()
+            .collect();
+        update.removed = gone.len();
+        for path in gone {
+            self.files.remove(&path);
+        }
+        Ok(update)
+    }
+
+    /// The `limit` chunks under `start` that rank highest for `query` by
+    /// BM25, best first. Query and chunks are compared by word stems, so
+    /// "parsing configs" finds `parse_config`.
+    pub fn search(&self, root: &Path, start: &Path, query: &str, limit: usize) -> Vec<Hit> {
+        let dir = relative(root, start);
+        let mut wanted = terms(query);
+        wanted.sort();
+        wanted.dedup();
+        let chunks: Vec<(&str, &Chunk)> = self
+            .files
+            .iter()
+            .filter(|(path, _)| index::is_below(path, &dir))
+            .flat_map(|(path, file)| file.chunks.iter().map(move |c| (path.as_str(), c)))
+            .collect();
+        if wanted.is_empty() || chunks.is_empty() {
+            return Vec::new();
+        }
+
+        let total = chunks.len() as f64;
+        let average = chunks.iter().map(|(_, c)| c.length as f64).sum::<f64>() / total;
+        let idf: Vec<f64> = wanted
+            .iter()
+            .map(|term| {
+                let found = chunks
+                    .iter()
+                    .filter(|(_, c)| c.terms.contains_key(term))
+                    .count() as f64;
+                (1.0 + (total - found + 0.5) / (found + 0.5)).ln()
+            })
+            .collect();
+        let mut scored: Vec<(f64, &str, &Chunk)> = chunks
+            .into_iter()
+            .filter_map(|(path, chunk)| {
+                let norm = K1 * (1.0 - B + B * chunk.length as f64 / average.max(1.0));
+                let score: f64 = wanted
+                    .iter()
+                    .zip(&idf)
+                    .filter_map(|(term, idf)| {
+                        let tf = *chunk.terms.get(term)? as f64;
+                        Some(idf * tf * (K1 + 1.0) / (tf + norm))
+                    })
+                    .sum();
+                (score > 0.0).then_some((score, path, chunk))
+            })
+            .collect();
+        scored.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then(a.1.cmp(b.1))
+                .then(a.2.start.cmp(&b.2.start))
+        });
+
+        let mut hits = Vec::new();
+        let mut contents: BTreeMap<&str, Option<String>> = BTreeMap::new();
+        for (score, path, chunk) in scored {
+            if hits.len() == limit {
+                break;
+            }
+            let content = contents
+                .entry(path)
+                .or_insert_with(|| fs::read(root.join(path)).ok().map(lossy));
+            let Some(content) = content else {
+                continue;
+            };
+            let text: Vec<&str> = content
+                .lines()
+                .skip(chunk.start - 1)
+                .take(chunk.end + 1 - chunk.start)
+                .collect();
+            hits.push(Hit {
+                path: path.to_string(),
+                start: chunk.start,
+                end: chunk.end,
+                title: chunk.title.clone(),
+                score,
+                text: text.join("\n"),
+            });
+        }
+        hits
+    }
+}
+
+/// Orders `hits` by a blend of their cosine similarity to the query and
+/// their BM25 score relative to the best one. `chunks` holds the
+/// embeddings of the hits, in the same order.
+pub fn rerank(hits: &mut [Hit], query: &[f32], chunks: &[Vec<f32>]) {
+    let best = hits.iter().map(|hit| hit.score).fold(0.0, f64::max);
+    for (hit, embedding) in hits.iter_mut().zip(chunks) {
+        let lexical = if best > 0.0 { hit.score / best } else { 0.0 };
+        hit.score =
+            EMBEDDING_WEIGHT * cosine(query, embedding) + (1.0 - EMBEDDING_WEIGHT) * lexical;
+    }
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+}
+
+pub fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// The stemmed, lowercase words of `text`, with identifiers split at
+/// underscores and case changes and stop words left out.
+pub fn terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .flat_map(index::words)
+        .filter(|word| word.len() > 1 && !word.chars().all(|c| c.is_ascii_digit()))
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .map(|word| stem(&word))
+        .collect()
+}
+
+/// Strips a plural and then one common suffix, so that "parser",
+/// "parses" and "parsing" all become "pars".
+fn stem(word: &str) -> String {
+    let mut word = word;
+    if word.len() > 3 && word.ends_with('s') && !word.ends_with("ss") {
+        word = &word[..word.len() - 1];
+    }
+    for suffix in ["ing", "er", "ed", "e"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            if stem.len() >= 3 {
+                return stem.to_string();
+            }
+        }
+    }
+    word.to_string()
+}
+
+/// Files that are text but not worth searching.
+fn is_noise(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name == index::INDEX_FILE
+        || name == SEARCH_FILE
+        || name.ends_with(".lock")
+        || name.ends_with("-lock.json")
+        || name.ends_with(".min.js")
+}
+
+/// Splits a file at its definitions, or at its headings for Markdown. A
+/// definition's chunk starts at the comments, attributes or decorators
+/// right above it; files with neither are cut into fixed-size pieces.
+fn chunk(path: &str, content: &str) -> Vec<Chunk> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut starts: Vec<(usize, Option<String>)> = vec![(0, None)];
+    if path.ends_with(".md") || path.ends_with(".markdown") {
+        for (i, line) in lines.iter().enumerate() {
+            if line.starts_with('#') {
+                starts.push((i, Some(line.trim_start_matches('#').trim().to_string())));
+            }
+        }
+    } else {
+        for symbol in symbols::extract(path, content).symbols {
+            let mut first = symbol.line - 1;
+            while first > 0 && is_preamble(lines[first - 1]) {
+                first -= 1;
+            }
+            let title = format!("{} {}", symbol.kind, symbol.qualified_name());
+            starts.push((first, Some(title)));
+        }
+    }
+    starts.sort_by_key(|(line, _)| *line);
+    // Of several starts on one line, a named one wins
+    let mut merged: Vec<(usize, Option<String>)> = Vec::new();
+    for (line, title) in starts {
+        match merged.last_mut() {
+            Some(last) if last.0 == line => last.1 = title.or(last.1.take()),
+            _ => merged.push((line, title)),
+        }
+    }
+
+    let context = terms(path);
+    let mut chunks = Vec::new();
+    for (i, (first, title)) in merged.iter().enumerate() {
+        let end = merged.get(i + 1).map_or(lines.len(), |next| next.0);
+        let mut from = *first;
+        while from < end {
+            let to = (from + MAX_CHUNK_LINES).min(end);
+            let text = lines[from..to].join("\n");
+            if !text.trim().is_empty() {
+                let mut words = terms(&text);
+                words.extend(context.iter().cloned());
+                if let Some(title) = title {
+                    // A match in the name counts for more than one in the body
+                    for _ in 0..2 {
+                        words.extend(terms(title));
+                    }
+                }
+                let mut counts: BTreeMap<String, u32> = BTreeMap::new();
+                for word in &words {
+                    *counts.entry(word.clone()).or_default() += 1;
+                }
+                chunks.push(Chunk {
+                    start: from + 1,
+                    end: to,
+                    title: title.clone(),
+                    terms: counts,
+                    length: words.len() as u32,
+                });
+            }
+            from = to;
+        }
+    }
+    chunks
+}
+
+fn is_preamble(line: &str) -> bool {
+    let line = line.trim_start();
+    ["//", "#", "/*", "*", "@"]
+        .iter()
+        .any(|start| line.starts_with(start))
+}
+
+fn relative(root: &Path, dir: &Path) -> String {
+    dir.strip_prefix(root)
+        .unwrap_or(dir)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+fn lossy(bytes: Vec<u8>) -> String {
+    String::from_utf8_lossy(&bytes).into_owned()
+}
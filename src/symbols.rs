@@ -0,0 +1,493 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use tree_sitter::{Node, Parser, Query, QueryCursor, StreamingIterator};
+
+/// Longer signatures are cut to this many characters.
+const MAX_SIGNATURE_CHARS: usize = 160;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Function,
+    Method,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    Type,
+    Const,
+    Static,
+    Module,
+    Macro,
+    Class,
+    Interface,
+    Variable,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = serde_json::to_value(self).ok();
+        write!(
+            f,
+            "{}",
+            name.as_ref().and_then(|v| v.as_str()).unwrap_or("symbol")
+        )
+    }
+}
+
+/// A definition found in a source file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: Kind,
+    /// 1-based
+    pub line: usize,
+    /// The declaration's first line, without its body
+    pub signature: String,
+    /// The impl, trait or class the symbol is declared in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+impl Symbol {
+    /// `Parent::name` for members, `name` otherwise.
+    pub fn qualified_name(&self) -> String {
+        match &self.parent {
+            Some(parent) => format!("{}::{}", parent, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// A call of a function, method or macro, or a `new` of a class.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    /// The name called, without any receiver or path before it
+    pub name: String,
+    /// 1-based
+    pub line: usize,
+    /// The function or method the call is made from, as `Parent::name`
+    /// for methods
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caller: Option<String>,
+}
+
+/// Definitions and references found in one file.
+#[derive(Debug, Clone, Default)]
+pub struct Extracted {
+    pub symbols: Vec<Symbol>,
+    pub references: Vec<Reference>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    Tsx,
+    Go,
+    Java,
+    C,
+}
+
+impl Language {
+    fn of(path: &str) -> Option<Language> {
+        let extension = Path::new(path).extension()?.to_str()?;
+        match extension {
+            "rs" => Some(Language::Rust),
+            "py" | "pyi" => Some(Language::Python),
+            "js" | "jsx" | "mjs" | "cjs" => Some(Language::JavaScript),
+            "ts" | "mts" | "cts" => Some(Language::TypeScript),
+            "tsx" => Some(Language::Tsx),
+            "go" => Some(Language::Go),
+            "java" => Some(Language::Java),
+            "c" | "h" => Some(Language::C),
+            _ => None,
+        }
+    }
+}
+
+/// Whether symbols can be extracted from `path`, going by its extension.
+pub fn is_supported(path: &str) -> bool {
+    Language::of(path).is_some()
+}
+
+// Each query captures definitions as @definition and calls as @reference,
+// with the name in @name. What kind of symbol a definition is comes from
+// its node, not from the pattern that matched it.
+
+const RUST_QUERY: &str = r#"
+(function_item name: (identifier) @name) @definition
+(function_signature_item name: (identifier) @name) @definition
+(struct_item name: (type_identifier) @name) @definition
+(enum_item name: (type_identifier) @name) @definition
+(union_item name: (type_identifier) @name) @definition
+(trait_item name: (type_identifier) @name) @definition
+(type_item name: (type_identifier) @name) @definition
+(mod_item name: (identifier) @name) @definition
+(const_item name: (identifier) @name) @definition
+(static_item name: (identifier) @name) @definition
+(macro_definition name: (identifier) @name) @definition
+(impl_item type: (_) @name) @definition
+
+(call_expression function: [
+  (identifier) @name
+  (field_expression field: (field_identifier) @name)
+  (scoped_identifier name: (identifier) @name)
+  (generic_function function: [
+    (identifier) @name
+    (field_expression field: (field_identifier) @name)
+    (scoped_identifier name: (identifier) @name)
+  ])
+]) @reference
+(macro_invocation macro: [
+  (identifier) @name
+  (scoped_identifier name: (identifier) @name)
+]) @reference
+; Macro arguments aren't parsed, so `name(...)` is all there is to go by
+(token_tree (identifier) @name . (token_tree) @reference)
+"#;
+
+const PYTHON_QUERY: &str = r#"
+(class_definition name: (identifier) @name) @definition
+(function_definition name: (identifier) @name) @definition
+(module (expression_statement (assignment left: (identifier) @name) @definition))
+
+(call function: [
+  (identifier) @name
+  (attribute attribute: (identifier) @name)
+]) @reference
+"#;
+
+const SCRIPT_QUERY: &str = r#"
+(function_declaration name: (identifier) @name) @definition
+(generator_function_declaration name: (identifier) @name) @definition
+(class_declaration name: (_) @name) @definition
+(method_definition name: [(property_identifier) (private_property_identifier)] @name) @definition
+(variable_declarator
+  name: (identifier) @name
+  value: [(arrow_function) (function_expression)]) @definition
+(program (lexical_declaration (variable_declarator name: (identifier) @name) @definition))
+(program (variable_declaration (variable_declarator name: (identifier) @name) @definition))
+(program (export_statement declaration: (lexical_declaration
+  (variable_declarator name: (identifier) @name) @definition)))
+
+(call_expression function: [
+  (identifier) @name
+  (member_expression property: (property_identifier) @name)
+]) @reference
+(new_expression constructor: (identifier) @name) @reference
+"#;
+
+const TYPESCRIPT_QUERY: &str = r#"
+(abstract_class_declaration name: (type_identifier) @name) @definition
+(interface_declaration name: (type_identifier) @name) @definition
+(type_alias_declaration name: (type_identifier) @name) @definition
+(enum_declaration name: (identifier) @name) @definition
+(internal_module name: (identifier) @name) @definition
+(method_signature name: (property_identifier) @name) @definition
+(abstract_method_signature name: (property_identifier) @name) @definition
+(function_signature name: (identifier) @name) @definition
+"#;
+
+const GO_QUERY: &str = r#"
+(function_declaration name: (identifier) @name) @definition
+(method_declaration name: (field_identifier) @name) @definition
+(type_spec name: (type_identifier) @name) @definition
+(source_file (const_declaration (const_spec name: (identifier) @name) @definition))
+(source_file (var_declaration (var_spec name: (identifier) @name) @definition))
+
+(call_expression function: [
+  (identifier) @name
+  (selector_expression field: (field_identifier) @name)
+]) @reference
+"#;
+
+const JAVA_QUERY: &str = r#"
+(class_declaration name: (identifier) @name) @definition
+(interface_declaration name: (identifier) @name) @definition
+(enum_declaration name: (identifier) @name) @definition
+(record_declaration name: (identifier) @name) @definition
+(method_declaration name: (identifier) @name) @definition
+(constructor_declaration name: (identifier) @name) @definition
+
+(method_invocation name: (identifier) @name) @reference
+(object_creation_expression type: (type_identifier) @name) @reference
+"#;
+
+const C_QUERY: &str = r#"
+(function_definition
+  declarator: (function_declarator declarator: (identifier) @name)) @definition
+(function_definition
+  declarator: (pointer_declarator
+    declarator: (function_declarator declarator: (identifier) @name))) @definition
+(struct_specifier name: (type_identifier) @name body: (_)) @definition
+(union_specifier name: (type_identifier) @name body: (_)) @definition
+(enum_specifier name: (type_identifier) @name body: (_)) @definition
+(type_definition declarator: (type_identifier) @name) @definition
+(preproc_function_def name: (identifier) @name) @definition
+(preproc_def name: (identifier) @name) @definition
+
+(call_expression function: (identifier) @name) @reference
+"#;
+
+/// A grammar with its compiled query.
+struct Grammar {
+    language: tree_sitter::Language,
+    query: Query,
+    name: u32,
+    definition: u32,
+}
+
+impl Grammar {
+    fn new(language: tree_sitter::Language, source: &str) -> Grammar {
+        // The queries are fixed, so one that doesn't compile is a bug
+        let query =
+            Query::new(&language, source).unwrap_or_else(|e| panic!("invalid symbol query: {}", e));
+        let index = |name| query.capture_index_for_name(name).unwrap_or(u32::MAX);
+        Grammar {
+            name: index("name"),
+            definition: index("definition"),
+            language,
+            query,
+        }
+    }
+}
+
+fn grammar(language: Language) -> &'static Grammar {
+    static RUST: Lazy<Grammar> =
+        Lazy::new(|| Grammar::new(tree_sitter_rust::LANGUAGE.into(), RUST_QUERY));
+    static PYTHON: Lazy<Grammar> =
+        Lazy::new(|| Grammar::new(tree_sitter_python::LANGUAGE.into(), PYTHON_QUERY));
+    static JAVASCRIPT: Lazy<Grammar> =
+        Lazy::new(|| Grammar::new(tree_sitter_javascript::LANGUAGE.into(), SCRIPT_QUERY));
+    static TYPESCRIPT: Lazy<Grammar> = Lazy::new(|| {
+        Grammar::new(
+            tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            &format!("{}{}", SCRIPT_QUERY, TYPESCRIPT_QUERY),
+        )
+    });
+    static TSX: Lazy<Grammar> = Lazy::new(|| {
+        Grammar::new(
+            tree_sitter_typescript::LANGUAGE_TSX.into(),
+            &format!("{}{}", SCRIPT_QUERY, TYPESCRIPT_QUERY),
+        )
+    });
+    static GO: Lazy<Grammar> =
+        Lazy::new(|| Grammar::new(tree_sitter_go::LANGUAGE.into(), GO_QUERY));
+    static JAVA: Lazy<Grammar> =
+        Lazy::new(|| Grammar::new(tree_sitter_java::LANGUAGE.into(), JAVA_QUERY));
+    static C: Lazy<Grammar> = Lazy::new(|| Grammar::new(tree_sitter_c::LANGUAGE.into(), C_QUERY));
+    match language {
+        Language::Rust => &RUST,
+        Language::Python => &PYTHON,
+        Language::JavaScript => &JAVASCRIPT,
+        Language::TypeScript => &TYPESCRIPT,
+        Language::Tsx => &TSX,
+        Language::Go => &GO,
+        Language::Java => &JAVA,
+        Language::C => &C,
+    }
+}
+
+/// Definitions and calls in a source file, in the order they appear. The
+/// file is parsed with the tree-sitter grammar for its extension; files of
+/// other languages have neither.
+pub fn extract(path: &str, content: &str) -> Extracted {
+    let Some(language) = Language::of(path) else {
+        return Extracted::default();
+    };
+    let grammar = grammar(language);
+    let mut parser = Parser::new();
+    if parser.set_language(&grammar.language).is_err() {
+        return Extracted::default();
+    }
+    let Some(tree) = parser.parse(content, None) else {
+        return Extracted::default();
+    };
+
+    // Definitions by node id, so calls can be traced to the one they're in
+    let mut definitions: Vec<(Node, Symbol)> = Vec::new();
+    let mut ids: HashMap<usize, usize> = HashMap::new();
+    let mut calls: Vec<(Node, String)> = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&grammar.query, tree.root_node(), content.as_bytes());
+    while let Some(found) = matches.next() {
+        let Some(name) = found.captures.iter().find(|c| c.index == grammar.name) else {
+            continue;
+        };
+        let Some(outer) = found.captures.iter().find(|c| c.index != grammar.name) else {
+            continue;
+        };
+        let node = outer.node;
+        if outer.index != grammar.definition {
+            calls.push((node, text(name.node, content).to_string()));
+            continue;
+        }
+        if ids.contains_key(&node.id()) {
+            continue;
+        }
+        let parent = parent_of(node, content);
+        let name = match node.kind() {
+            "impl_item" => type_name(name.node, content),
+            _ => text(name.node, content).to_string(),
+        };
+        let symbol = Symbol {
+            kind: kind_of(node, parent.is_some(), &name),
+            line: node.start_position().row + 1,
+            signature: signature(node, content),
+            name,
+            parent,
+        };
+        ids.insert(node.id(), definitions.len());
+        definitions.push((node, symbol));
+    }
+
+    let references = calls
+        .into_iter()
+        .map(|(node, name)| {
+            let caller = ancestors(node)
+                .filter_map(|ancestor| ids.get(&ancestor.id()))
+                .map(|&i| &definitions[i].1)
+                .find(|symbol| matches!(symbol.kind, Kind::Function | Kind::Method))
+                .map(Symbol::qualified_name);
+            Reference {
+                name,
+                line: node.start_position().row + 1,
+                caller,
+            }
+        })
+        .collect();
+    definitions.sort_by_key(|(node, _)| node.start_byte());
+    Extracted {
+        symbols: definitions.into_iter().map(|(_, symbol)| symbol).collect(),
+        references,
+    }
+}
+
+fn text<'a>(node: Node, content: &'a str) -> &'a str {
+    &content[node.byte_range()]
+}
+
+fn ancestors(node: Node) -> impl Iterator<Item = Node> {
+    std::iter::successors(node.parent(), |n| n.parent())
+}
+
+/// The impl, trait, class, interface or enum a definition is a direct
+/// member of. Anything declared inside a function body has none.
+fn parent_of(node: Node, content: &str) -> Option<String> {
+    if node.kind() == "method_declaration" {
+        // A Go method names its type in the receiver
+        if let Some(receiver) = node.child_by_field_name("receiver") {
+            let mut cursor = receiver.walk();
+            let parameter = receiver.named_children(&mut cursor).next()?;
+            return Some(type_name(parameter.child_by_field_name("type")?, content));
+        }
+    }
+    for ancestor in ancestors(node) {
+        match ancestor.kind() {
+            // Bodies between a member and what it's a member of
+            "declaration_list"
+            | "block"
+            | "decorated_definition"
+            | "class_body"
+            | "interface_body"
+            | "object_type"
+            | "enum_body"
+            | "enum_body_declarations"
+            | "export_statement" => continue,
+            "impl_item" => {
+                return Some(type_name(ancestor.child_by_field_name("type")?, content));
+            }
+            "trait_item"
+            | "class_definition"
+            | "class_declaration"
+            | "class"
+            | "abstract_class_declaration"
+            | "interface_declaration"
+            | "enum_declaration"
+            | "record_declaration" => {
+                return ancestor
+                    .child_by_field_name("name")
+                    .map(|name| text(name, content).to_string());
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// The bare name of a type: `Wrapper` for `Wrapper<T>`, `io::Error` or
+/// `*Wrapper`.
+fn type_name(node: Node, content: &str) -> String {
+    let inner = match node.kind() {
+        "generic_type" | "reference_type" => node.child_by_field_name("type"),
+        "scoped_type_identifier" => node.child_by_field_name("name"),
+        "pointer_type" => node.named_child(0),
+        _ => None,
+    };
+    match inner {
+        Some(inner) => type_name(inner, content),
+        None => text(node, content).to_string(),
+    }
+}
+
+fn kind_of(node: Node, has_parent: bool, name: &str) -> Kind {
+    let value = |field| node.child_by_field_name(field).map(|n| n.kind());
+    match node.kind() {
+        "method_definition"
+        | "method_declaration"
+        | "method_signature"
+        | "abstract_method_signature"
+        | "constructor_declaration" => Kind::Method,
+        "struct_item" | "union_item" | "struct_specifier" | "union_specifier"
+        | "record_declaration" => Kind::Struct,
+        "enum_item" | "enum_declaration" | "enum_specifier" => Kind::Enum,
+        "trait_item" => Kind::Trait,
+        "impl_item" => Kind::Impl,
+        "type_item" | "type_alias_declaration" | "type_definition" => Kind::Type,
+        "type_spec" => match value("type") {
+            Some("struct_type") => Kind::Struct,
+            Some("interface_type") => Kind::Interface,
+            _ => Kind::Type,
+        },
+        "mod_item" | "internal_module" => Kind::Module,
+        "const_item" | "const_spec" => Kind::Const,
+        "static_item" => Kind::Static,
+        "macro_definition" | "preproc_function_def" | "preproc_def" => Kind::Macro,
+        "class_definition" | "class_declaration" | "abstract_class_declaration" => Kind::Class,
+        "interface_declaration" => Kind::Interface,
+        "variable_declarator" => match value("value") {
+            Some("arrow_function" | "function_expression" | "function") => Kind::Function,
+            _ => Kind::Variable,
+        },
+        "assignment" if name.chars().all(|c| !c.is_lowercase()) => Kind::Const,
+        "assignment" | "var_spec" => Kind::Variable,
+        _ if has_parent => Kind::Method,
+        _ => Kind::Function,
+    }
+}
+
+/// The line a definition starts on, up to where its body opens.
+fn signature(node: Node, content: &str) -> String {
+    let start = node.start_byte();
+    let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+    let mut end = content[start..]
+        .find('\n')
+        .map_or(content.len(), |i| start + i);
+    if let Some(body) = node.child_by_field_name("body") {
+        if body.start_byte() > start && body.start_byte() < end {
+            end = body.start_byte();
+        }
+    }
+    let signature = content[line_start..end].trim();
+    let mut cut: String = signature.chars().take(MAX_SIGNATURE_CHARS).collect();
+    if cut.len() < signature.len() {
+        cut.push_str("...");
+    }
+    cut
+}
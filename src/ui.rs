@@ -1,10 +1,13 @@
-use crate::app::App;
+use crate::app::{App, ApprovalInput, ApprovalPrompt};
+use crate::permissions::RuleAction;
 use once_cell::sync::Lazy;
 use ratatui::{
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style, Stylize},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState},
+    widgets::{
+        Block, Borders, Clear, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, Wrap,
+    },
     Frame,
 };
 use syntect::easy::HighlightLines;
@@ -351,4 +354,101 @@ pub fn ui(f: &mut Frame, app: &App) {
     let status_block = Block::default().title("Status").borders(Borders::ALL);
     let status = Paragraph::new(app.status_message.as_str()).block(status_block);
     f.render_widget(status, chunks[3]);
+
+    if let Some(prompt) = &app.approval {
+        render_approval(f, prompt);
+    }
+}
+
+// Centered rectangle taking the given share of `area`
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+// Modal asking whether a guarded tool call may run
+fn render_approval(f: &mut Frame, prompt: &ApprovalPrompt) {
+    let action = &prompt.request.action;
+    // Keep long code short enough that the choices stay visible
+    const MAX_DESCRIPTION_LINES: usize = 12;
+    let description: Vec<&str> = action.description.lines().collect();
+    let mut lines: Vec<Line> = description
+        .iter()
+        .take(MAX_DESCRIPTION_LINES)
+        .map(|line| Line::from(line.to_string()))
+        .collect();
+    if description.len() > MAX_DESCRIPTION_LINES {
+        lines.push(
+            Line::from(format!(
+                "... ({} more lines)",
+                description.len() - MAX_DESCRIPTION_LINES
+            ))
+            .italic(),
+        );
+    }
+    lines.push(Line::from(""));
+
+    let key = |k: &'static str| Span::styled(k, Style::default().fg(Color::Yellow).bold());
+    match &prompt.input {
+        ApprovalInput::Choosing => {
+            let mut choices = vec![key("[y]"), Span::raw(" allow once  ")];
+            choices.extend([key("[n]"), Span::raw(" deny  ")]);
+            // No allow rule covers chained commands, code or rct's own files, so none is offered
+            if !prompt.request.action.always_asks() {
+                choices.extend([key("[a]"), Span::raw(" always allow  ")]);
+            }
+            choices.extend([key("[d]"), Span::raw(" always deny  ")]);
+            choices.extend([key("[Esc]"), Span::raw(" cancel run")]);
+            lines.push(Line::from(choices));
+        }
+        ApprovalInput::Reason(reason) => {
+            lines.push(Line::from("Reason for denying (sent to the model):"));
+            lines.push(Line::from(format!("> {}_", reason)).cyan());
+            lines.push(Line::from(vec![
+                key("[Enter]"),
+                Span::raw(" deny  "),
+                key("[Esc]"),
+                Span::raw(" back"),
+            ]));
+        }
+        ApprovalInput::Pattern(rule_action, pattern) => {
+            let verb = match rule_action {
+                RuleAction::Allow => "Always allow",
+                RuleAction::Deny => "Always deny",
+            };
+            lines.push(Line::from(format!("{} {} matching:", verb, action.tool)));
+            lines.push(Line::from(format!("> {}_", pattern)).cyan());
+            lines.push(Line::from(vec![
+                key("[Enter]"),
+                Span::raw(" save rule  "),
+                key("[Esc]"),
+                Span::raw(" back"),
+            ]));
+        }
+    }
+
+    let area = centered_rect(70, 50, f.size());
+    let block = Block::default()
+        .title(format!("Approve {}?", action.tool))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Red));
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false });
+    f.render_widget(Clear, area);
+    f.render_widget(paragraph, area);
 }
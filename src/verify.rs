@@ -0,0 +1,161 @@
+use crate::process::{self, RunOptions};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+/// Longest checker output passed back to the model.
+const MAX_REPORT_CHARS: usize = 4_000;
+
+/// A quick language check of a written file.
+struct Checker {
+    label: &'static str,
+    command: Command,
+    /// The crate `cargo check` covers, shared by all of its files
+    crate_dir: Option<PathBuf>,
+}
+
+/// Reads every file a tool wrote back and runs a quick language check on
+/// it, for at most `timeout` each. Returns the notes for the tool result,
+/// none when there was nothing to check.
+///
+/// Each crate is checked once however many of its files changed.
+/// Checkers that aren't installed are skipped rather than reported, and
+/// checks that could not run are noted, since they say nothing about the
+/// write that happened.
+pub async fn verify_written_files(
+    files: &[(&Path, Option<&str>)],
+    root: &Path,
+    timeout: Option<Duration>,
+    cancel: &CancellationToken,
+) -> Vec<String> {
+    let mut notes = Vec::new();
+    let mut checked_crates = HashSet::new();
+    for &(path, expected) in files {
+        match verify_file(path, expected, root, timeout, cancel, &mut checked_crates).await {
+            Ok(note) => notes.extend(note),
+            Err(e) => notes.push(format!(
+                "Verification of '{}' could not run: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+    notes
+}
+
+/// Verifies one file, skipping the check of crates in `checked_crates` and
+/// adding the one it checks.
+async fn verify_file(
+    path: &Path,
+    expected: Option<&str>,
+    root: &Path,
+    timeout: Option<Duration>,
+    cancel: &CancellationToken,
+    checked_crates: &mut HashSet<PathBuf>,
+) -> io::Result<Option<String>> {
+    if let Some(note) = read_back(path, expected).await? {
+        return Ok(Some(note));
+    }
+    let Some(checker) = checker(path, root) else {
+        return Ok(None);
+    };
+    if let Some(dir) = &checker.crate_dir {
+        if !checked_crates.insert(dir.clone()) {
+            return Ok(None);
+        }
+    }
+    run_checker(checker, timeout, cancel).await
+}
+
+/// A note if `path` doesn't hold what was written to it.
+async fn read_back(path: &Path, expected: Option<&str>) -> io::Result<Option<String>> {
+    // Compared as bytes, since the file needn't be UTF-8
+    let written = fs::read(path).await?;
+    match expected {
+        Some(expected) if written != expected.as_bytes() => Ok(Some(format!(
+            "Verification failed: '{}' reads back {} bytes, {} were written.",
+            path.display(),
+            written.len(),
+            expected.len()
+        ))),
+        _ => Ok(None),
+    }
+}
+
+/// The check for `path`, if its language has one.
+fn checker(path: &Path, root: &Path) -> Option<Checker> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let (label, command, crate_dir) = match extension {
+        "rs" => {
+            // Only worth it inside a crate; a lone .rs file has nothing to check against
+            let manifest_dir = path
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(root))
+                .find(|dir| dir.join("Cargo.toml").is_file())?;
+            let mut command = Command::new("cargo");
+            command
+                .args(["check", "--quiet", "--message-format", "short"])
+                .current_dir(manifest_dir);
+            ("cargo check", command, Some(manifest_dir.to_path_buf()))
+        }
+        "py" => {
+            let mut command = Command::new("python3");
+            command.args(["-m", "py_compile"]).arg(path);
+            ("python -m py_compile", command, None)
+        }
+        "js" | "mjs" | "cjs" => {
+            let mut command = Command::new("node");
+            command.arg("--check").arg(path);
+            ("node --check", command, None)
+        }
+        _ => return None,
+    };
+    Some(Checker {
+        label,
+        command,
+        crate_dir,
+    })
+}
+
+/// Runs `checker` and describes the outcome; `None` if it isn't installed.
+async fn run_checker(
+    mut checker: Checker,
+    timeout: Option<Duration>,
+    cancel: &CancellationToken,
+) -> io::Result<Option<String>> {
+    let label = checker.label;
+    let options = RunOptions {
+        timeout,
+        ..Default::default()
+    };
+    let output = match process::run(&mut checker.command, cancel, &options).await {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        // A slow checker says nothing about the file that was written
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+            return Ok(Some(format!("Verification ({}) timed out.", label)))
+        }
+        Err(e) => return Err(e),
+    };
+    if output.status.success() {
+        return Ok(Some(format!("Verification ({}): OK", label)));
+    }
+
+    let mut report = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if report.is_empty() {
+        report = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    }
+    if report.chars().count() > MAX_REPORT_CHARS {
+        report = report.chars().take(MAX_REPORT_CHARS).collect();
+        report.push_str("\n[... output truncated ...]");
+    }
+    Ok(Some(format!(
+        "Verification ({}) FAILED, fix these errors:\n{}",
+        label, report
+    )))
+}
@@ -0,0 +1,172 @@
+use crate::config::AgentConfig;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Where rct keeps checkpoints, memories and rules, relative to the
+/// workspace.
+pub const STATE_DIR: &str = ".rct";
+
+/// Gives up on symlink chains longer than this, like the kernel does.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// The directory tools work in. Relative paths are taken from its root and,
+/// with `safe_fs`, anything that ends up outside it (through `..`, an
+/// absolute path or a symlink) is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    root: PathBuf,
+    safe_fs: bool,
+}
+
+impl Default for Workspace {
+    /// The current directory, sandboxed.
+    fn default() -> Self {
+        let root = env::current_dir()
+            .and_then(|dir| dir.canonicalize())
+            .unwrap_or_else(|_| PathBuf::from("."));
+        Self {
+            root,
+            safe_fs: true,
+        }
+    }
+}
+
+impl Workspace {
+    pub fn new(root: &Path, safe_fs: bool) -> io::Result<Self> {
+        let root = root.canonicalize().map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Workspace root '{}' is not usable: {}", root.display(), e),
+            )
+        })?;
+        if !root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Workspace root '{}' is not a directory", root.display()),
+            ));
+        }
+        Ok(Self { root, safe_fs })
+    }
+
+    /// `workspace_root` from config, or the current directory when empty.
+    pub fn from_config(config: &AgentConfig) -> io::Result<Self> {
+        let root = match config.workspace_root.trim() {
+            "" => env::current_dir()?,
+            root => PathBuf::from(root),
+        };
+        Self::new(&root, config.safe_fs)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// How permission rules see a path from a tool call: relative to the
+    /// root once `.`, `..` and symlinks are resolved, so `src/**` can't be
+    /// dodged with `src/../Cargo.toml`. Paths outside the root stay
+    /// absolute.
+    pub fn rule_path(&self, path: &str) -> String {
+        let resolved = self
+            .resolve(path)
+            .unwrap_or_else(|_| normalize(&self.root.join(path)));
+        match resolved.strip_prefix(&self.root) {
+            Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+            Ok(relative) => relative.to_string_lossy().to_string(),
+            Err(_) => resolved.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Whether a path from a tool call is in the state directory, where
+    /// rct keeps its rules and checkpoints.
+    pub fn is_state_path(&self, path: &str) -> bool {
+        Path::new(&self.rule_path(path)).starts_with(STATE_DIR)
+    }
+
+    /// Turns a path from a tool call into the path to operate on.
+    ///
+    /// The parent directory is resolved but the last component is kept, so
+    /// deleting or renaming a symlink affects the link itself. With
+    /// `safe_fs` both the link and whatever it points to must be inside
+    /// the root.
+    pub fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let path = normalize(&self.root.join(path));
+        if !self.safe_fs {
+            return Ok(path);
+        }
+
+        let entry = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => real_path(parent, 0)?.join(name),
+            _ => real_path(&path, 0)?,
+        };
+        let target = real_path(&entry, 0)?;
+        if !entry.starts_with(&self.root) || !target.starts_with(&self.root) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "Path '{}' is outside the workspace '{}'; only files inside it can be used",
+                    path.display(),
+                    self.root.display()
+                ),
+            ));
+        }
+        Ok(entry)
+    }
+}
+
+/// Creates `dir` and, in the `.rct` directory it is in, a `.gitignore` that
+/// ignores everything, so GIT_COMMIT's `git add .` leaves rct's own files
+/// out of the repository.
+pub fn create_state_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let Some(state) = dir
+        .ancestors()
+        .find(|ancestor| ancestor.file_name().is_some_and(|name| name == STATE_DIR))
+    else {
+        return Ok(());
+    };
+    let ignore = state.join(".gitignore");
+    if !ignore.exists() {
+        fs::write(ignore, "*\n")?;
+    }
+    Ok(())
+}
+
+/// Removes `.` and resolves `..` without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            other => normal.push(other),
+        }
+    }
+    normal
+}
+
+/// Where `path` really is once every symlink is followed, for paths that
+/// don't exist (yet) as well.
+fn real_path(path: &Path, depth: usize) -> io::Result<PathBuf> {
+    if let Ok(real) = path.canonicalize() {
+        return Ok(real);
+    }
+    if depth > MAX_SYMLINK_DEPTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Too many levels of symbolic links in '{}'", path.display()),
+        ));
+    }
+    // A dangling symlink: writing through it would create its target
+    if let Ok(target) = fs::read_link(path) {
+        let base = path.parent().unwrap_or(Path::new("/"));
+        return real_path(&normalize(&base.join(target)), depth + 1);
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => Ok(real_path(parent, depth)?.join(name)),
+        _ => Ok(path.to_path_buf()),
+    }
+}
@@ -1,6 +1,15 @@
-use rust_tui_coder::agent::{Agent, Tool};
+mod common;
+
+use common::TempRoot;
+use rust_tui_coder::agent::{tool_definitions, Agent, Tool};
+use rust_tui_coder::app::App;
+use rust_tui_coder::config::{Config, ToolProtocol};
+use serde_json::json;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
 #[test]
 fn test_agent_new() {
@@ -14,24 +23,28 @@ fn test_agent_default() {
     // Agent default constructor works
 }
 
-#[test]
-fn test_tool_read_file() {
+#[tokio::test]
+async fn test_tool_read_file() {
     let test_file = "tmp_rovodev_test_read.txt";
     fs::write(test_file, "Test content").unwrap();
 
     let tool = Tool::ReadFile {
         path: test_file.to_string(),
+        offset: None,
+        limit: None,
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(result.unwrap().contains("Test content"));
 
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_tool_write_file() {
+#[tokio::test]
+async fn test_tool_write_file() {
     let test_file = "tmp_rovodev_test_write.txt";
 
     let tool = Tool::WriteFile {
@@ -39,7 +52,9 @@ fn test_tool_write_file() {
         content: "Hello World!".to_string(),
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(Path::new(test_file).exists());
 
@@ -49,8 +64,8 @@ fn test_tool_write_file() {
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_tool_append_file() {
+#[tokio::test]
+async fn test_tool_append_file() {
     let test_file = "tmp_rovodev_test_append.txt";
     fs::write(test_file, "Initial content\n").unwrap();
 
@@ -59,7 +74,9 @@ fn test_tool_append_file() {
         content: "Appended content".to_string(),
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
 
     let content = fs::read_to_string(test_file).unwrap();
@@ -69,8 +86,8 @@ fn test_tool_append_file() {
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_tool_search_replace() {
+#[tokio::test]
+async fn test_tool_search_replace() {
     let test_file = "tmp_rovodev_test_search_replace.txt";
     fs::write(test_file, "Hello World! World is great.").unwrap();
 
@@ -78,9 +95,13 @@ fn test_tool_search_replace() {
         path: test_file.to_string(),
         old_string: "World".to_string(),
         new_string: "Rust".to_string(),
+        replace_all: true,
+        ignore_whitespace: false,
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
 
     let content = fs::read_to_string(test_file).unwrap();
@@ -89,8 +110,8 @@ fn test_tool_search_replace() {
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_tool_delete_file() {
+#[tokio::test]
+async fn test_tool_delete_file() {
     let test_file = "tmp_rovodev_test_delete.txt";
     fs::write(test_file, "To be deleted").unwrap();
 
@@ -98,28 +119,32 @@ fn test_tool_delete_file() {
         path: test_file.to_string(),
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(!Path::new(test_file).exists());
 }
 
-#[test]
-fn test_tool_create_directory() {
+#[tokio::test]
+async fn test_tool_create_directory() {
     let test_dir = "tmp_rovodev_test_dir";
 
     let tool = Tool::CreateDirectory {
         path: test_dir.to_string(),
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(Path::new(test_dir).is_dir());
 
     fs::remove_dir_all(test_dir).ok();
 }
 
-#[test]
-fn test_tool_list_files() {
+#[tokio::test]
+async fn test_tool_list_files() {
     let test_dir = "tmp_rovodev_test_list";
     fs::create_dir_all(test_dir).unwrap();
     fs::write(format!("{}/file1.txt", test_dir), "content1").unwrap();
@@ -130,7 +155,9 @@ fn test_tool_list_files() {
         path: test_dir.to_string(),
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     let output = result.unwrap();
     assert!(output.contains("file1.txt"));
@@ -140,8 +167,8 @@ fn test_tool_list_files() {
     fs::remove_dir_all(test_dir).ok();
 }
 
-#[test]
-fn test_tool_list_files_recursive() {
+#[tokio::test]
+async fn test_tool_list_files_recursive() {
     let test_dir = "tmp_rovodev_test_recursive";
     fs::create_dir_all(format!("{}/subdir", test_dir)).unwrap();
     fs::write(format!("{}/file1.txt", test_dir), "content1").unwrap();
@@ -151,7 +178,9 @@ fn test_tool_list_files_recursive() {
         path: test_dir.to_string(),
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     let output = result.unwrap();
     assert!(output.contains("file1.txt"));
@@ -160,49 +189,296 @@ fn test_tool_list_files_recursive() {
     fs::remove_dir_all(test_dir).ok();
 }
 
-#[test]
-fn test_tool_run_command() {
+#[tokio::test]
+async fn test_tool_run_command() {
     let tool = Tool::RunCommand {
         command: "echo 'Hello from test'".to_string(),
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(result.unwrap().contains("Hello from test"));
 }
 
-#[test]
-fn test_tool_execute_code_python() {
+#[tokio::test]
+async fn test_tool_execute_code_python() {
     let tool = Tool::ExecuteCode {
         language: "python".to_string(),
         code: "print('Python test')".to_string(),
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     // Python might not be available in all test environments
-    if result.is_ok() {
-        assert!(result.unwrap().contains("Python test"));
+    if let Ok(output) = result {
+        assert!(output.contains("Python test"));
     }
 }
 
-#[test]
-fn test_tool_execute_code_bash() {
+#[tokio::test]
+async fn test_tool_execute_code_bash() {
     let tool = Tool::ExecuteCode {
         language: "bash".to_string(),
         code: "echo 'Bash test'".to_string(),
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(result.unwrap().contains("Bash test"));
 }
 
 // Plan tests moved to tests/plan_tests.rs to avoid race conditions
 
-#[test]
-fn test_tool_git_status() {
+#[tokio::test]
+async fn test_tool_git_status() {
     let tool = Tool::GitStatus;
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     // Git might not be available or this might not be a git repo
     assert!(result.is_ok());
 }
+
+#[test]
+fn test_tool_from_call_keeps_multiline_content() {
+    let arguments = serde_json::json!({
+        "path": "notes.txt",
+        "content": "first line\nsecond line\n"
+    })
+    .to_string();
+
+    match Tool::from_call("WRITE_FILE", &arguments) {
+        Some(Tool::WriteFile { path, content }) => {
+            assert_eq!(path, "notes.txt");
+            assert_eq!(content, "first line\nsecond line\n");
+        }
+        other => panic!("unexpected tool: {:?}", other),
+    }
+    assert!(Tool::from_call("GET_TIME", "").is_some());
+    assert!(Tool::from_call("NOT_A_TOOL", "{}").is_none());
+    assert!(Tool::from_call("READ_FILE", "{}").is_none());
+}
+
+#[test]
+fn test_tool_definitions_match_tool_calls() {
+    let definitions = tool_definitions();
+    assert!(definitions.len() >= 34);
+
+    // Every advertised tool must be constructible from its required parameters
+    for definition in definitions {
+        let schema = &definition.function.parameters;
+        let mut arguments = serde_json::Map::new();
+        for name in schema["required"].as_array().unwrap() {
+            let name = name.as_str().unwrap();
+            let value = match schema["properties"][name]["type"].as_str().unwrap() {
+                "integer" => serde_json::json!(1),
+                "array" if schema["properties"][name]["items"]["type"] == "object" => {
+                    serde_json::json!([{ "old_string": "value", "new_string": "value" }])
+                }
+                "array" => serde_json::json!(["step"]),
+                _ => serde_json::json!("value"),
+            };
+            arguments.insert(name.to_string(), value);
+        }
+        let arguments = serde_json::Value::Object(arguments).to_string();
+        assert!(
+            Tool::from_call(&definition.function.name, &arguments).is_some(),
+            "{} could not be built from its schema",
+            definition.function.name
+        );
+    }
+}
+
+#[test]
+fn test_system_prompt_lists_tools_in_their_sections() {
+    let prompt = Agent::get_system_prompt(ToolProtocol::Text);
+    let section = |tool: &str| {
+        let at = prompt.find(&format!("**{}**", tool)).unwrap();
+        let heading = prompt[..at].rfind("### ").unwrap();
+        prompt[heading..].lines().next().unwrap().to_string()
+    };
+    assert_eq!(section("MULTI_EDIT"), "### File Operations");
+    assert_eq!(section("APPLY_PATCH"), "### File Operations");
+    assert_eq!(section("SEMANTIC_SEARCH"), "### Search & Navigation");
+    assert_eq!(section("FORGET"), "### Memory");
+    assert_eq!(section("REMEMBER"), "### Memory");
+
+    // Every tool the model can call is listed once
+    for definition in tool_definitions() {
+        let name = &definition.function.name;
+        assert_eq!(
+            prompt.matches(&format!(". **{}**", name)).count(),
+            1,
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_system_prompt_follows_tool_protocol() {
+    let native = Agent::get_system_prompt(ToolProtocol::Native);
+    assert!(!native.contains("Prefer `TOOL:"));
+    assert!(native.contains("Never write `TOOL:` lines"));
+    let text = Agent::get_system_prompt(ToolProtocol::Text);
+    assert!(text.contains("Prefer `TOOL:"));
+    assert!(!text.contains("Never write `TOOL:` lines"));
+}
+
+#[test]
+fn test_parse_tool_calls_collects_every_line() {
+    let response = "REASONING: I need three files.\n\
+TOOL: {\"name\": \"READ_FILE\", \"parameters\": {\"path\": \"a.rs\"}}\n\
+TOOL: {\"name\": \"READ_FILE\", \"parameters\": {\"path\": \"b.rs\"}}\n\
+TOOL: LIST_FILES src\n\
+Not a tool line";
+
+    let tools = Agent::parse_tool_calls(response);
+    assert_eq!(tools.len(), 3);
+    assert!(matches!(&tools[0], Tool::ReadFile { path, .. } if path == "a.rs"));
+    assert!(matches!(&tools[1], Tool::ReadFile { path, .. } if path == "b.rs"));
+    assert!(matches!(&tools[2], Tool::ListFiles { path } if path == "src"));
+    assert!(Agent::parse_tool_calls("no tools here").is_empty());
+}
+
+#[test]
+fn test_tool_read_only_classification() {
+    assert!(Tool::ReadFile {
+        path: "a".to_string(),
+        offset: None,
+        limit: None,
+    }
+    .is_read_only());
+    assert!(Tool::GitStatus.is_read_only());
+    assert!(!Tool::WriteFile {
+        path: "a".to_string(),
+        content: String::new()
+    }
+    .is_read_only());
+    assert!(!Tool::RunCommand {
+        command: "ls".to_string()
+    }
+    .is_read_only());
+    // These write index or memory files, so two at once could corrupt them
+    assert!(!Tool::SearchIndex {
+        query: "main".to_string(),
+        callers: false,
+    }
+    .is_read_only());
+    assert!(!Tool::SemanticSearch {
+        query: "config".to_string(),
+        path: None,
+        max_results: None,
+    }
+    .is_read_only());
+    assert!(!Tool::Recall { query: None }.is_read_only());
+}
+
+/// A Messages API stand-in that calls GET_TIME on every streamed turn and,
+/// like the real API, rejects tool blocks in a request that offers no tools.
+async fn mock_anthropic() -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut calls = 0;
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 8192];
+            let body = loop {
+                let n = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break body.to_string();
+                    }
+                }
+            };
+            let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+            let messages = body["messages"].to_string();
+
+            let (status, content_type, reply) = if body["stream"] == true {
+                calls += 1;
+                let events = [
+                    json!({ "type": "message_start", "message": { "usage": { "input_tokens": 10 } } }),
+                    json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "tool_use", "id": format!("toolu_{}", calls), "name": "GET_TIME", "input": {} } }),
+                    json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "input_json_delta", "partial_json": "{}" } }),
+                    json!({ "type": "message_delta", "usage": { "output_tokens": 5 } }),
+                    json!({ "type": "message_stop" }),
+                ];
+                let stream: String = events
+                    .iter()
+                    .map(|event| {
+                        format!(
+                            "event: {}\ndata: {}\n\n",
+                            event["type"].as_str().unwrap(),
+                            event
+                        )
+                    })
+                    .collect();
+                ("200 OK", "text/event-stream", stream)
+            } else if body.get("tools").is_none()
+                && (messages.contains("tool_use") || messages.contains("tool_result"))
+            {
+                let error = json!({ "type": "error", "error": { "type": "invalid_request_error", "message": "tool_use blocks need tools" } });
+                ("400 Bad Request", "application/json", error.to_string())
+            } else {
+                let answer = json!({ "content": [{ "type": "text", "text": "Final answer" }], "usage": { "input_tokens": 10, "output_tokens": 2 } });
+                ("200 OK", "application/json", answer.to_string())
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                content_type,
+                reply.len(),
+                reply
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.ok();
+        }
+    });
+    format!("http://{}/v1", address)
+}
+
+#[tokio::test]
+async fn test_max_attempts_ends_with_an_answer() {
+    let root = TempRoot::new();
+    let config: Config = toml::from_str(&format!(
+        "[llm]\nprovider = \"anthropic\"\napi_key = \"test\"\napi_base_url = \"{}\"\nmodel_name = \"claude-test\"\n\n\
+         [agent]\nmax_attempts = 2\nworkspace_root = {:?}\nrepo_map_tokens = 0\nrecall_memories = 0\nuser_memory = false\n",
+        mock_anthropic().await,
+        root.to_string_lossy()
+    ))
+    .unwrap();
+
+    let mut agent = Agent::new();
+    let app = Arc::new(Mutex::new(App::new()));
+    let (answer, logs) = agent
+        .run_with_streaming(
+            &config,
+            "What time is it?".to_string(),
+            app,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(answer, "Final answer");
+    assert!(logs
+        .iter()
+        .any(|log| log.contains("Reached maximum attempts (2)")));
+}
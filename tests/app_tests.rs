@@ -1,4 +1,9 @@
-use rust_tui_coder::app::App;
+use crossterm::event::KeyCode;
+use rust_tui_coder::agent::Tool;
+use rust_tui_coder::app::{App, ApprovalInput};
+use rust_tui_coder::llm::TokenUsage;
+use rust_tui_coder::permissions::{Approval, ApprovalRequest, Rule, RuleAction};
+use rust_tui_coder::workspace::Workspace;
 
 #[test]
 fn test_app_new() {
@@ -31,6 +36,25 @@ fn test_add_tool_log() {
     assert_eq!(app.tool_logs[1], "Test log 2");
 }
 
+#[test]
+fn test_record_usage_tracks_prompt_and_completion() {
+    let mut app = App::new();
+    app.record_usage(TokenUsage {
+        prompt_tokens: 300,
+        completion_tokens: 45,
+    });
+    app.record_usage(TokenUsage {
+        prompt_tokens: 200,
+        completion_tokens: 5,
+    });
+    assert_eq!(app.prompt_tokens, 500);
+    assert_eq!(app.completion_tokens, 50);
+    assert_eq!(app.tokens_used, 550);
+    assert!(app
+        .get_usage_summary()
+        .contains("Tokens Used: 550 (500 prompt, 50 completion)"));
+}
+
 #[test]
 fn test_increment_tokens() {
     let mut app = App::new();
@@ -139,3 +163,98 @@ fn test_session_duration() {
     let duration = app.get_session_duration();
     assert!(duration.as_millis() >= 100);
 }
+
+fn open_approval(app: &mut App) -> tokio::sync::oneshot::Receiver<Approval> {
+    open_command_approval(app, "cargo build --release")
+}
+
+fn open_command_approval(app: &mut App, command: &str) -> tokio::sync::oneshot::Receiver<Approval> {
+    let (reply, answer) = tokio::sync::oneshot::channel();
+    let action = Tool::RunCommand {
+        command: command.to_string(),
+    }
+    .permission_action(&Workspace::default())
+    .unwrap();
+    app.request_approval(ApprovalRequest { action, reply });
+    answer
+}
+
+#[test]
+fn test_approval_allow_once() {
+    let mut app = App::new();
+    let mut answer = open_approval(&mut app);
+    assert!(app.handle_approval_key(KeyCode::Char('y')));
+    assert!(app.approval.is_none());
+    assert_eq!(answer.try_recv().unwrap(), Approval::AllowOnce);
+}
+
+#[test]
+fn test_approval_deny_with_reason() {
+    let mut app = App::new();
+    let mut answer = open_approval(&mut app);
+    app.handle_approval_key(KeyCode::Char('n'));
+    for c in "use cargo check".chars() {
+        app.handle_approval_key(KeyCode::Char(c));
+    }
+    app.handle_approval_key(KeyCode::Backspace);
+    app.handle_approval_key(KeyCode::Enter);
+    assert_eq!(
+        answer.try_recv().unwrap(),
+        Approval::Deny("use cargo chec".to_string())
+    );
+}
+
+#[test]
+fn test_approval_always_allow_saves_suggested_pattern() {
+    let mut app = App::new();
+    let mut answer = open_approval(&mut app);
+    app.handle_approval_key(KeyCode::Char('a'));
+    assert_eq!(
+        app.approval.as_ref().unwrap().input,
+        ApprovalInput::Pattern(RuleAction::Allow, "cargo *".to_string())
+    );
+    app.handle_approval_key(KeyCode::Enter);
+    assert_eq!(
+        answer.try_recv().unwrap(),
+        Approval::Save(Rule {
+            action: RuleAction::Allow,
+            tool: "RUN_COMMAND".to_string(),
+            pattern: "cargo *".to_string(),
+        })
+    );
+}
+
+#[test]
+fn test_approval_always_allow_refused_for_chained_command() {
+    let mut app = App::new();
+    let mut answer = open_command_approval(&mut app, "cargo build && rm -rf ~");
+    app.handle_approval_key(KeyCode::Char('a'));
+    assert_eq!(
+        app.approval.as_ref().unwrap().input,
+        ApprovalInput::Choosing
+    );
+    assert!(answer.try_recv().is_err());
+    app.handle_approval_key(KeyCode::Char('y'));
+    assert_eq!(answer.try_recv().unwrap(), Approval::AllowOnce);
+}
+
+#[test]
+fn test_approval_esc_goes_back_then_passes_through() {
+    let mut app = App::new();
+    let _answer = open_approval(&mut app);
+    app.handle_approval_key(KeyCode::Char('d'));
+    assert!(app.handle_approval_key(KeyCode::Esc));
+    assert_eq!(
+        app.approval.as_ref().unwrap().input,
+        ApprovalInput::Choosing
+    );
+    // Esc while choosing is left to the caller, which cancels the run
+    assert!(!app.handle_approval_key(KeyCode::Esc));
+    assert!(app.approval.is_some());
+}
+
+#[test]
+fn test_keys_ignored_without_approval() {
+    let mut app = App::new();
+    assert!(!app.handle_approval_key(KeyCode::Char('y')));
+}
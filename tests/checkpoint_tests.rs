@@ -0,0 +1,257 @@
+mod common;
+
+use common::TempRoot;
+use rust_tui_coder::agent::{Tool, ToolContext};
+use rust_tui_coder::checkpoint::Checkpoints;
+use rust_tui_coder::workspace::Workspace;
+use std::fs;
+
+/// A fresh workspace with checkpoints turned on.
+fn sandbox() -> (TempRoot, ToolContext) {
+    let root = TempRoot::new();
+    let workspace = Workspace::new(&root, true).unwrap();
+    let ctx = ToolContext {
+        checkpoints: Some(Checkpoints::new(workspace.root())),
+        workspace,
+        turn: 1,
+        ..Default::default()
+    };
+    (root, ctx)
+}
+
+fn write(path: &str, content: &str) -> Tool {
+    Tool::WriteFile {
+        path: path.to_string(),
+        content: content.to_string(),
+    }
+}
+
+fn store(ctx: &ToolContext) -> &Checkpoints {
+    ctx.checkpoints.as_ref().unwrap()
+}
+
+#[tokio::test]
+async fn test_undo_restores_overwritten_and_removes_new_files() {
+    let (root, ctx) = sandbox();
+    fs::write(root.join("a.txt"), "original").unwrap();
+
+    write("a.txt", "changed").execute_with(&ctx).await.unwrap();
+    write("b.txt", "new").execute_with(&ctx).await.unwrap();
+
+    let undone = store(&ctx).undo_last().unwrap();
+    assert_eq!(undone.len(), 1);
+    assert!(!root.join("b.txt").exists());
+    assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "changed");
+
+    store(&ctx).undo_last().unwrap();
+    assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "original");
+    assert!(store(&ctx).undo_last().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_undo_turn_stops_at_the_previous_turn() {
+    let (root, mut ctx) = sandbox();
+
+    write("a.txt", "turn 1").execute_with(&ctx).await.unwrap();
+    ctx.turn = 2;
+    write("a.txt", "turn 2, first")
+        .execute_with(&ctx)
+        .await
+        .unwrap();
+    Tool::SearchReplace {
+        path: "a.txt".to_string(),
+        old_string: "first".to_string(),
+        new_string: "second".to_string(),
+        replace_all: false,
+        ignore_whitespace: false,
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+
+    let undone = store(&ctx).undo_turn().unwrap();
+    assert_eq!(undone.len(), 2);
+    assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "turn 1");
+    assert_eq!(store(&ctx).list().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_deleted_directory_is_restored() {
+    let (root, ctx) = sandbox();
+    fs::create_dir_all(root.join("src/nested")).unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(root.join("src/nested/lib.rs"), "// lib").unwrap();
+
+    Tool::DeleteFile {
+        path: "src".to_string(),
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+    assert!(!root.join("src").exists());
+
+    store(&ctx).undo_last().unwrap();
+    assert_eq!(
+        fs::read_to_string(root.join("src/main.rs")).unwrap(),
+        "fn main() {}"
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("src/nested/lib.rs")).unwrap(),
+        "// lib"
+    );
+}
+
+#[tokio::test]
+async fn test_move_is_undone() {
+    let (root, ctx) = sandbox();
+    fs::write(root.join("old.txt"), "content").unwrap();
+
+    Tool::MoveFile {
+        source: "old.txt".to_string(),
+        destination: "new.txt".to_string(),
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+
+    store(&ctx).undo_last().unwrap();
+    assert_eq!(fs::read_to_string(root.join("old.txt")).unwrap(), "content");
+    assert!(!root.join("new.txt").exists());
+}
+
+#[tokio::test]
+async fn test_restore_undoes_later_checkpoints_too() {
+    let (root, ctx) = sandbox();
+
+    for content in ["one", "two", "three"] {
+        write("a.txt", content).execute_with(&ctx).await.unwrap();
+    }
+    let checkpoints = store(&ctx).list().unwrap();
+    assert_eq!(checkpoints.len(), 3);
+
+    let undone = store(&ctx).restore(checkpoints[1].id).unwrap();
+    assert_eq!(undone.len(), 2);
+    assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "one");
+    assert!(store(&ctx).restore(99).is_err());
+}
+
+#[tokio::test]
+async fn test_failed_tool_leaves_no_checkpoint() {
+    let (root, ctx) = sandbox();
+    fs::write(root.join("a.txt"), "content").unwrap();
+
+    let result = Tool::SearchReplace {
+        path: "a.txt".to_string(),
+        old_string: "missing".to_string(),
+        new_string: "x".to_string(),
+        replace_all: false,
+        ignore_whitespace: false,
+    }
+    .execute_with(&ctx)
+    .await;
+
+    assert!(result.is_err());
+    assert!(store(&ctx).list().unwrap().is_empty());
+}
+
+#[test]
+fn test_unchanged_notices_partial_changes() {
+    let (root, ctx) = sandbox();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(root.join("src/a.rs"), "a").unwrap();
+    fs::write(root.join("src/b.rs"), "b").unwrap();
+    let paths = [ctx.workspace.root().join("src")];
+    let checkpoint = store(&ctx).save(1, "delete", &paths).unwrap();
+    assert!(store(&ctx).unchanged(&checkpoint).unwrap());
+
+    // A delete that stopped halfway
+    fs::remove_file(root.join("src/a.rs")).unwrap();
+    assert!(!store(&ctx).unchanged(&checkpoint).unwrap());
+    store(&ctx).undo_last().unwrap();
+    let checkpoint = store(&ctx).save(2, "delete", &paths).unwrap();
+    assert!(store(&ctx).unchanged(&checkpoint).unwrap());
+
+    fs::write(root.join("src/new.rs"), "").unwrap();
+    assert!(!store(&ctx).unchanged(&checkpoint).unwrap());
+    fs::remove_file(root.join("src/new.rs")).unwrap();
+    fs::write(root.join("src/b.rs"), "changed").unwrap();
+    assert!(!store(&ctx).unchanged(&checkpoint).unwrap());
+}
+
+#[tokio::test]
+async fn test_copy_over_existing_file_is_undone() {
+    let (root, ctx) = sandbox();
+    fs::write(root.join("a.txt"), "new").unwrap();
+    fs::write(root.join("b.txt"), "old").unwrap();
+
+    Tool::CopyFile {
+        source: "a.txt".to_string(),
+        destination: "b.txt".to_string(),
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+    assert_eq!(fs::read_to_string(root.join("b.txt")).unwrap(), "new");
+
+    store(&ctx).undo_last().unwrap();
+    assert_eq!(fs::read_to_string(root.join("b.txt")).unwrap(), "old");
+}
+
+#[tokio::test]
+async fn test_oversized_snapshot_is_refused() {
+    let (root, ctx) = sandbox();
+    let ctx = ToolContext {
+        checkpoints: Some(Checkpoints::new(ctx.workspace.root()).with_limits(3, 1024)),
+        ..ctx
+    };
+    fs::create_dir_all(root.join("target")).unwrap();
+    for i in 0..5 {
+        fs::write(root.join(format!("target/{}.o", i)), "object").unwrap();
+    }
+    fs::write(root.join("big.bin"), vec![0u8; 4096]).unwrap();
+
+    for path in ["target", "big.bin"] {
+        let error = Tool::DeleteFile {
+            path: path.to_string(),
+        }
+        .execute_with(&ctx)
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("too big to checkpoint"));
+    }
+    assert!(root.join("target/4.o").exists());
+    assert!(root.join("big.bin").exists());
+    assert!(store(&ctx).list().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_state_dir_is_gitignored_and_not_snapshotted() {
+    let (root, ctx) = sandbox();
+    write("a.txt", "one").execute_with(&ctx).await.unwrap();
+    assert_eq!(
+        fs::read_to_string(root.join(".rct/.gitignore")).unwrap(),
+        "*\n"
+    );
+
+    Tool::DeleteFile {
+        path: ".rct".to_string(),
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+    assert!(!root.join(".rct").exists());
+    fs::create_dir_all(root.join(".rct")).unwrap();
+    fs::write(root.join(".rct/permissions.toml"), "").unwrap();
+
+    // The checkpoint of the delete was inside what it deleted
+    assert!(store(&ctx).list().unwrap().is_empty());
+    write("a.txt", "two").execute_with(&ctx).await.unwrap();
+    let checkpoint = store(&ctx)
+        .save(2, "snapshot", &[ctx.workspace.root().join(".rct")])
+        .unwrap();
+    let paths: Vec<_> = checkpoint.entries.iter().map(|entry| &entry.path).collect();
+    assert!(paths.iter().any(|path| path.ends_with("permissions.toml")));
+    assert!(paths
+        .iter()
+        .all(|path| !path.starts_with(".rct/checkpoints")));
+}
@@ -0,0 +1,48 @@
+//! Helpers shared by the integration tests. Each test file uses some of
+//! them, so the rest would be dead code there.
+#![allow(dead_code)]
+
+use rust_tui_coder::agent::ToolContext;
+use rust_tui_coder::workspace::Workspace;
+use std::ops::Deref;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// An empty directory of its own that is removed when dropped. It derefs to
+/// its (canonical) path, so `root.join(..)` and `&root` work as for a path.
+pub struct TempRoot {
+    dir: TempDir,
+    path: std::path::PathBuf,
+}
+
+impl Deref for TempRoot {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempRoot {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl TempRoot {
+    pub fn new() -> Self {
+        let dir = tempfile::Builder::new().prefix("rct_").tempdir().unwrap();
+        let path = dir.path().canonicalize().unwrap();
+        Self { dir, path }
+    }
+}
+
+/// A fresh workspace with `safe_fs` on, and a tool context working in it.
+pub fn sandbox() -> (TempRoot, ToolContext) {
+    let root = TempRoot::new();
+    let ctx = ToolContext {
+        workspace: Workspace::new(&root, true).unwrap(),
+        ..Default::default()
+    };
+    (root, ctx)
+}
@@ -2,8 +2,8 @@ use rust_tui_coder::agent::Tool;
 use rust_tui_coder::app::App;
 use std::fs;
 
-#[test]
-fn test_end_to_end_file_operations() {
+#[tokio::test]
+async fn test_end_to_end_file_operations() {
     let test_dir = "tmp_rovodev_e2e_test";
 
     // Create directory
@@ -12,6 +12,7 @@ fn test_end_to_end_file_operations() {
     };
     assert!(create_dir
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Write multiple files
@@ -22,6 +23,7 @@ fn test_end_to_end_file_operations() {
         };
         assert!(write
             .execute(&rust_tui_coder::config::WebConfig::default())
+            .await
             .is_ok());
     }
 
@@ -29,7 +31,9 @@ fn test_end_to_end_file_operations() {
     let list = Tool::ListFiles {
         path: test_dir.to_string(),
     };
-    let result = list.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = list
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     let output = result.unwrap();
     assert!(output.contains("file1.txt"));
@@ -39,8 +43,12 @@ fn test_end_to_end_file_operations() {
     // Read and verify
     let read = Tool::ReadFile {
         path: format!("{}/file1.txt", test_dir),
+        offset: None,
+        limit: None,
     };
-    let result = read.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = read
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(result.unwrap().contains("Content for file 1"));
 
@@ -49,16 +57,23 @@ fn test_end_to_end_file_operations() {
         path: format!("{}/file1.txt", test_dir),
         old_string: "Content".to_string(),
         new_string: "Modified".to_string(),
+        replace_all: false,
+        ignore_whitespace: false,
     };
     assert!(replace
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Verify modification
     let read2 = Tool::ReadFile {
         path: format!("{}/file1.txt", test_dir),
+        offset: None,
+        limit: None,
     };
-    let result2 = read2.execute(&rust_tui_coder::config::WebConfig::default());
+    let result2 = read2
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result2.is_ok());
     assert!(result2.unwrap().contains("Modified for file 1"));
 
@@ -107,8 +122,8 @@ fn test_app_state_transitions() {
     assert!(summary.contains("1"));
 }
 
-#[test]
-fn test_nested_directory_operations() {
+#[tokio::test]
+async fn test_nested_directory_operations() {
     let base_dir = "tmp_rovodev_nested";
     let nested_path = format!("{}/level1/level2/level3", base_dir);
 
@@ -118,6 +133,7 @@ fn test_nested_directory_operations() {
     };
     assert!(create
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Write file in nested location
@@ -127,13 +143,16 @@ fn test_nested_directory_operations() {
     };
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // List recursively
     let list_recursive = Tool::ListFilesRecursive {
         path: base_dir.to_string(),
     };
-    let result = list_recursive.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = list_recursive
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(result.unwrap().contains("deep_file.txt"));
 
@@ -141,13 +160,15 @@ fn test_nested_directory_operations() {
     fs::remove_dir_all(base_dir).ok();
 }
 
-#[test]
-fn test_command_execution() {
+#[tokio::test]
+async fn test_command_execution() {
     // Test simple command
     let cmd = Tool::RunCommand {
         command: "echo 'test output'".to_string(),
     };
-    let result = cmd.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = cmd
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(result.unwrap().contains("test output"));
 
@@ -155,13 +176,15 @@ fn test_command_execution() {
     let cmd2 = Tool::RunCommand {
         command: "echo 'hello' | tr 'h' 'H'".to_string(),
     };
-    let result2 = cmd2.execute(&rust_tui_coder::config::WebConfig::default());
+    let result2 = cmd2
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result2.is_ok());
     assert!(result2.unwrap().contains("Hello"));
 }
 
-#[test]
-fn test_file_append_multiple_times() {
+#[tokio::test]
+async fn test_file_append_multiple_times() {
     let test_file = "tmp_rovodev_append_multi.txt";
 
     // Create initial file
@@ -171,6 +194,7 @@ fn test_file_append_multiple_times() {
     };
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Append multiple times
@@ -181,14 +205,19 @@ fn test_file_append_multiple_times() {
         };
         assert!(append
             .execute(&rust_tui_coder::config::WebConfig::default())
+            .await
             .is_ok());
     }
 
     // Verify all lines present
     let read = Tool::ReadFile {
         path: test_file.to_string(),
+        offset: None,
+        limit: None,
     };
-    let result = read.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = read
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     let content = result.unwrap();
 
@@ -199,8 +228,8 @@ fn test_file_append_multiple_times() {
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_search_replace_multiple_occurrences() {
+#[tokio::test]
+async fn test_search_replace_multiple_occurrences() {
     let test_file = "tmp_rovodev_replace_multi.txt";
 
     let content = "foo bar foo baz foo";
@@ -210,6 +239,7 @@ fn test_search_replace_multiple_occurrences() {
     };
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Replace all occurrences
@@ -217,16 +247,23 @@ fn test_search_replace_multiple_occurrences() {
         path: test_file.to_string(),
         old_string: "foo".to_string(),
         new_string: "FOO".to_string(),
+        replace_all: true,
+        ignore_whitespace: false,
     };
     assert!(replace
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Verify all replaced
     let read = Tool::ReadFile {
         path: test_file.to_string(),
+        offset: None,
+        limit: None,
     };
-    let result = read.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = read
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     let new_content = result.unwrap();
     assert!(new_content.contains("FOO bar FOO baz FOO"));
@@ -235,14 +272,17 @@ fn test_search_replace_multiple_occurrences() {
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_tool_error_handling() {
+#[tokio::test]
+async fn test_tool_error_handling() {
     // Try to read non-existent file
     let read = Tool::ReadFile {
         path: "tmp_rovodev_nonexistent.txt".to_string(),
+        offset: None,
+        limit: None,
     };
     assert!(read
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_err());
 
     // Try to append to non-existent file
@@ -252,6 +292,7 @@ fn test_tool_error_handling() {
     };
     assert!(append
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_err());
 
     // Try to replace in non-existent file
@@ -259,9 +300,12 @@ fn test_tool_error_handling() {
         path: "tmp_rovodev_nonexistent.txt".to_string(),
         old_string: "old".to_string(),
         new_string: "new".to_string(),
+        replace_all: false,
+        ignore_whitespace: false,
     };
     assert!(replace
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_err());
 }
 
@@ -293,8 +337,8 @@ fn test_usage_tracking_accuracy() {
     assert!(summary.contains("Average Tokens/Request: 300"));
 }
 
-#[test]
-fn test_concurrent_file_operations() {
+#[tokio::test]
+async fn test_concurrent_file_operations() {
     let test_dir = "tmp_rovodev_concurrent";
     fs::create_dir_all(test_dir).ok();
 
@@ -310,6 +354,7 @@ fn test_concurrent_file_operations() {
         };
         assert!(write
             .execute(&rust_tui_coder::config::WebConfig::default())
+            .await
             .is_ok());
     }
 
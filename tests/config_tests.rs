@@ -66,3 +66,123 @@ model_name = "test-model"
 
     fs::remove_file(test_config_path).ok();
 }
+
+#[test]
+fn test_config_agent_settings() {
+    let test_config_path = "tmp_rovodev_test_config_agent.toml";
+    let config_content = r#"
+[llm]
+api_key = ""
+api_base_url = "http://localhost:11434/v1"
+model_name = "test-model"
+
+[agent]
+max_attempts = 5
+shell = "bash"
+post_write_verify = false
+workspace_root = "/srv/project"
+safe_fs = false
+command_timeout_secs = 60
+tool_timeouts = { RUN_TESTS = 900 }
+output_head_chars = 100
+output_tail_chars = 50
+"#;
+    fs::write(test_config_path, config_content).unwrap();
+    let config = Config::from_file(test_config_path).unwrap();
+    fs::remove_file(test_config_path).ok();
+
+    assert_eq!(config.agent.max_attempts, 5);
+    assert_eq!(config.agent.shell, "bash");
+    assert!(!config.agent.post_write_verify);
+    assert_eq!(config.agent.workspace_root, "/srv/project");
+    assert!(!config.agent.safe_fs);
+    assert_eq!(config.agent.command_timeout_secs, 60);
+    assert_eq!(config.agent.tool_timeouts.get("RUN_TESTS"), Some(&900));
+    assert_eq!(config.agent.output_head_chars, 100);
+    assert_eq!(config.agent.output_tail_chars, 50);
+}
+
+#[test]
+fn test_config_agent_defaults() {
+    let test_config_path = "tmp_rovodev_test_config_agent_defaults.toml";
+    let config_content = r#"
+[llm]
+api_key = ""
+api_base_url = "http://localhost:11434/v1"
+model_name = "test-model"
+"#;
+    fs::write(test_config_path, config_content).unwrap();
+    let config = Config::from_file(test_config_path).unwrap();
+    fs::remove_file(test_config_path).ok();
+
+    assert_eq!(config.agent.max_attempts, 12);
+    assert!(config.agent.post_write_verify);
+    assert_eq!(config.agent.workspace_root, "");
+    assert!(config.agent.safe_fs);
+    assert_eq!(config.agent.command_timeout_secs, 300);
+    assert!(config.agent.tool_timeouts.is_empty());
+}
+
+#[test]
+fn test_config_reads_legacy_llm_workspace_keys() {
+    let config = Config::parse(
+        r#"
+[llm]
+api_key = ""
+api_base_url = "http://localhost:11434/v1"
+model_name = "test-model"
+max_attempts = 7
+shell = "bash"
+post_write_verify = false
+workspace_root = "/srv/project"
+safe_fs = false
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.agent.max_attempts, 7);
+    assert_eq!(config.agent.shell, "bash");
+    assert!(!config.agent.post_write_verify);
+    assert_eq!(config.agent.workspace_root, "/srv/project");
+    assert!(!config.agent.safe_fs);
+    assert_eq!(config.warnings.len(), 5);
+    for key in [
+        "max_attempts",
+        "shell",
+        "post_write_verify",
+        "workspace_root",
+        "safe_fs",
+    ] {
+        assert!(
+            config
+                .warnings
+                .iter()
+                .any(|warning| warning.contains(&format!("`{}` belongs under [agent]", key))),
+            "{}",
+            key
+        );
+    }
+
+    // [agent] wins when both set a key
+    let config = Config::parse(
+        r#"
+[llm]
+api_key = ""
+api_base_url = "http://localhost:11434/v1"
+model_name = "test-model"
+safe_fs = false
+
+[agent]
+max_attempts = 3
+safe_fs = true
+"#,
+    )
+    .unwrap();
+    assert!(config.agent.safe_fs);
+    assert_eq!(config.agent.max_attempts, 3);
+    assert!(config.warnings[0].contains("ignored"));
+
+    assert!(Config::parse("[llm]\napi_key = \"\"\n")
+        .unwrap_err()
+        .to_string()
+        .contains("missing field"));
+}
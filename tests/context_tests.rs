@@ -0,0 +1,111 @@
+use rust_tui_coder::config::{LlmConfig, OllamaConfig, RetryConfig, ToolProtocol};
+use rust_tui_coder::context::{
+    context_window_for_model, recent_turns_start, replace_with_summary, summary_request,
+    truncate_tool_results, ContextBudget,
+};
+use rust_tui_coder::llm::Message;
+
+fn message(role: &str, content: &str) -> Message {
+    Message {
+        role: role.to_string(),
+        content: content.to_string(),
+        ..Default::default()
+    }
+}
+
+fn llm_config(model_name: &str) -> LlmConfig {
+    LlmConfig {
+        provider: None,
+        api_key: String::new(),
+        api_base_url: "http://localhost:11434/v1".to_string(),
+        model_name: model_name.to_string(),
+        tool_protocol: ToolProtocol::Native,
+        max_tokens: None,
+        context_window: None,
+        embedding_model: None,
+        ollama: OllamaConfig::default(),
+        retry: RetryConfig::default(),
+    }
+}
+
+/// system prompt, then two finished turns and the current one
+fn conversation() -> Vec<Message> {
+    vec![
+        message("system", "You are a coding assistant."),
+        message("user", "Read main.rs"),
+        message("assistant", "TOOL: READ_FILE main.rs"),
+        message("user", &format!("Tool result: {}", "x".repeat(5000))),
+        message("assistant", "Done."),
+        message("user", "Now add a test"),
+        message("assistant", "Added."),
+        message("user", "Run the tests"),
+    ]
+}
+
+#[test]
+fn test_context_window_guessed_from_model_name() {
+    assert_eq!(context_window_for_model("claude-sonnet-4-5"), 200_000);
+    assert_eq!(context_window_for_model("gpt-4o-mini"), 128_000);
+    assert_eq!(context_window_for_model("gpt-4"), 8_192);
+    assert_eq!(context_window_for_model("llama3.1:8b"), 128_000);
+    assert_eq!(context_window_for_model("AUTODETECT"), 32_768);
+}
+
+#[test]
+fn test_context_budget_prefers_configured_window() {
+    // The window is guessed from the resolved model, not `AUTODETECT`
+    let mut config = llm_config("AUTODETECT");
+    assert_eq!(ContextBudget::for_config(&config, "gpt-4o").window, 128_000);
+
+    config.ollama.num_ctx = Some(16_384);
+    assert_eq!(ContextBudget::for_config(&config, "gpt-4o").window, 16_384);
+
+    config.context_window = Some(50_000);
+    config.max_tokens = Some(8_000);
+    let budget = ContextBudget::for_config(&config, "gpt-4o");
+    assert_eq!(budget.window, 50_000);
+    assert_eq!(budget.limit(), 42_000);
+    assert!(budget.threshold() < budget.limit());
+}
+
+#[test]
+fn test_recent_turns_skip_tool_results() {
+    let messages = conversation();
+    assert_eq!(recent_turns_start(&messages, 1), 7);
+    assert_eq!(recent_turns_start(&messages, 2), 5);
+    // The tool result at index 3 belongs to the first turn
+    assert_eq!(recent_turns_start(&messages, 3), 1);
+    assert_eq!(recent_turns_start(&messages, 10), 1);
+}
+
+#[test]
+fn test_truncate_tool_results_keeps_head_and_tail() {
+    let mut messages = conversation();
+    let original = messages[3].content.clone();
+    assert_eq!(truncate_tool_results(&mut messages, 5, 1000), 1);
+    assert!(messages[3].content.len() < 1100);
+    assert!(messages[3].content.starts_with("Tool result: "));
+    assert!(messages[3].content.contains("characters removed"));
+    assert!(original.ends_with(messages[3].content.lines().last().unwrap()));
+
+    // Nothing left to shorten, and recent turns are never touched
+    assert_eq!(truncate_tool_results(&mut messages, 5, 1000), 0);
+    assert_eq!(messages[7].content, "Run the tests");
+}
+
+#[test]
+fn test_replace_with_summary_keeps_system_prompt_and_recent_turns() {
+    let mut messages = conversation();
+    let request = summary_request(&messages[1..5]);
+    assert_eq!(request.len(), 2);
+    assert!(request[1].content.contains("Read main.rs"));
+    // Long tool output is shortened in the summary request too
+    assert!(request[1].content.len() < 5000);
+
+    replace_with_summary(&mut messages, 1, 5, "Summary: read main.rs".to_string());
+    assert_eq!(messages.len(), 5);
+    assert_eq!(messages[0].role, "system");
+    assert_eq!(messages[1].content, "Summary: read main.rs");
+    assert_eq!(messages[2].content, "Now add a test");
+    assert_eq!(messages[4].content, "Run the tests");
+}
@@ -3,8 +3,8 @@ use rust_tui_coder::app::App;
 use rust_tui_coder::llm::estimate_token_count;
 use std::fs;
 
-#[test]
-fn test_empty_file_operations() {
+#[tokio::test]
+async fn test_empty_file_operations() {
     let test_file = "tmp_rovodev_empty.txt";
 
     // Write empty file
@@ -14,13 +14,18 @@ fn test_empty_file_operations() {
     };
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Read empty file
     let read = Tool::ReadFile {
         path: test_file.to_string(),
+        offset: None,
+        limit: None,
     };
-    let result = read.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = read
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
 
     // Append to empty file
@@ -30,13 +35,14 @@ fn test_empty_file_operations() {
     };
     assert!(append
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_special_characters_in_content() {
+#[tokio::test]
+async fn test_special_characters_in_content() {
     let test_file = "tmp_rovodev_special_chars.txt";
 
     let special_content =
@@ -48,20 +54,25 @@ fn test_special_characters_in_content() {
     };
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     let read = Tool::ReadFile {
         path: test_file.to_string(),
+        offset: None,
+        limit: None,
     };
-    let result = read.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = read
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(result.unwrap().contains("$pecial"));
 
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_unicode_content() {
+#[tokio::test]
+async fn test_unicode_content() {
     let test_file = "tmp_rovodev_unicode.txt";
 
     let unicode_content = "Hello 世界 🌍 🦀 Rust\nΔημοκρατία\nԱզգություն";
@@ -72,12 +83,17 @@ fn test_unicode_content() {
     };
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     let read = Tool::ReadFile {
         path: test_file.to_string(),
+        offset: None,
+        limit: None,
     };
-    let result = read.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = read
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     let content = result.unwrap();
     assert!(content.contains("世界"));
@@ -86,8 +102,8 @@ fn test_unicode_content() {
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_very_long_filenames() {
+#[tokio::test]
+async fn test_very_long_filenames() {
     let test_dir = "tmp_rovodev_long_name";
     fs::create_dir_all(test_dir).ok();
 
@@ -101,6 +117,7 @@ fn test_very_long_filenames() {
     // This might fail on some filesystems, which is acceptable
     if write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok()
     {
         assert!(std::path::Path::new(&long_filename).exists());
@@ -110,8 +127,8 @@ fn test_very_long_filenames() {
     fs::remove_dir_all(test_dir).ok();
 }
 
-#[test]
-fn test_path_with_spaces() {
+#[tokio::test]
+async fn test_path_with_spaces() {
     let test_dir = "tmp_rovodev_with spaces";
     fs::create_dir_all(test_dir).ok();
 
@@ -123,20 +140,24 @@ fn test_path_with_spaces() {
     };
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     let read = Tool::ReadFile {
         path: file_path.clone(),
+        offset: None,
+        limit: None,
     };
     assert!(read
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     fs::remove_dir_all(test_dir).ok();
 }
 
-#[test]
-fn test_search_replace_with_special_chars() {
+#[tokio::test]
+async fn test_search_replace_with_special_chars() {
     let test_file = "tmp_rovodev_special_replace.txt";
 
     let content = "Price: $100.00\nDiscount: 20%\nTotal: $80.00";
@@ -147,29 +168,37 @@ fn test_search_replace_with_special_chars() {
     };
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     let replace = Tool::SearchReplace {
         path: test_file.to_string(),
         old_string: "$100.00".to_string(),
         new_string: "$150.00".to_string(),
+        replace_all: false,
+        ignore_whitespace: false,
     };
     assert!(replace
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     let read = Tool::ReadFile {
         path: test_file.to_string(),
+        offset: None,
+        limit: None,
     };
-    let result = read.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = read
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(result.unwrap().contains("$150.00"));
 
     fs::remove_file(test_file).ok();
 }
 
-#[test]
-fn test_search_replace_not_found() {
+#[tokio::test]
+async fn test_search_replace_not_found() {
     let test_file = "tmp_rovodev_not_found.txt";
 
     let write = Tool::WriteFile {
@@ -178,17 +207,21 @@ fn test_search_replace_not_found() {
     };
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     let replace = Tool::SearchReplace {
         path: test_file.to_string(),
         old_string: "NonExistent".to_string(),
         new_string: "Replacement".to_string(),
+        replace_all: false,
+        ignore_whitespace: false,
     };
 
     // Should fail because old string doesn't exist
     assert!(replace
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_err());
 
     fs::remove_file(test_file).ok();
@@ -276,8 +309,8 @@ fn test_concurrent_scroll_and_update() {
     assert!(app.conversation_scroll_position > 0);
 }
 
-#[test]
-fn test_delete_directory_with_content() {
+#[tokio::test]
+async fn test_delete_directory_with_content() {
     let test_dir = "tmp_rovodev_delete_dir";
 
     // Create directory with files
@@ -291,31 +324,36 @@ fn test_delete_directory_with_content() {
     };
     assert!(delete
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     assert!(!std::path::Path::new(test_dir).exists());
 }
 
-#[test]
-fn test_command_with_error() {
+#[tokio::test]
+async fn test_command_with_error() {
     let cmd = Tool::RunCommand {
         command: "exit 1".to_string(),
     };
 
-    let result = cmd.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = cmd
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     // Command executes but returns failure status
     assert!(result.is_ok());
     assert!(result.unwrap().contains("failed"));
 }
 
-#[test]
-fn test_plan_with_empty_steps() {
+#[tokio::test]
+async fn test_plan_with_empty_steps() {
     let create = Tool::CreatePlan {
         task: "Empty task".to_string(),
         steps: vec![],
     };
 
-    let result = create.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = create
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
 
     let content = fs::read_to_string("plan.md").unwrap();
@@ -324,8 +362,8 @@ fn test_plan_with_empty_steps() {
     fs::remove_file("plan.md").ok();
 }
 
-#[test]
-fn test_update_nonexistent_plan_step() {
+#[tokio::test]
+async fn test_update_nonexistent_plan_step() {
     // Create plan with 2 steps
     let create = Tool::CreatePlan {
         task: "Test".to_string(),
@@ -333,11 +371,14 @@ fn test_update_nonexistent_plan_step() {
     };
     create
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .ok();
 
     // Try to update step 5 (doesn't exist)
     let update = Tool::UpdatePlan { completed_step: 5 };
-    let result = update.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = update
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
 
     // Should still succeed but won't find the step
     assert!(result.is_ok());
@@ -371,8 +412,8 @@ fn test_scroll_position_overflow_protection() {
     assert!(app.conversation_scroll_position < usize::MAX);
 }
 
-#[test]
-fn test_nested_path_creation() {
+#[tokio::test]
+async fn test_nested_path_creation() {
     let nested_path = "tmp_rovodev_a/b/c/d/e/f/file.txt";
 
     let write = Tool::WriteFile {
@@ -382,14 +423,15 @@ fn test_nested_path_creation() {
 
     assert!(write
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
     assert!(std::path::Path::new(nested_path).exists());
 
     fs::remove_dir_all("tmp_rovodev_a").ok();
 }
 
-#[test]
-fn test_list_empty_directory() {
+#[tokio::test]
+async fn test_list_empty_directory() {
     let test_dir = "tmp_rovodev_empty_dir";
     fs::create_dir_all(test_dir).ok();
 
@@ -397,7 +439,9 @@ fn test_list_empty_directory() {
         path: test_dir.to_string(),
     };
 
-    let result = list.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = list
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
 
     fs::remove_dir_all(test_dir).ok();
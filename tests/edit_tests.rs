@@ -0,0 +1,133 @@
+mod common;
+
+use common::sandbox;
+use rust_tui_coder::agent::Tool;
+use rust_tui_coder::edit::{self, Edit};
+use std::fs;
+
+fn edit(old: &str, new: &str) -> Edit {
+    Edit {
+        old_string: old.to_string(),
+        new_string: new.to_string(),
+        replace_all: false,
+        ignore_whitespace: false,
+    }
+}
+
+#[test]
+fn test_unique_match_reports_its_line() {
+    let (replaced, applied) = edit::replace("a\nb\nc\n", "b", "B", false, false).unwrap();
+    assert_eq!(replaced, "a\nB\nc\n");
+    assert_eq!(applied.lines, vec![2]);
+    assert_eq!(applied.describe(), "1 occurrence (line 2)");
+}
+
+#[test]
+fn test_ambiguous_match_fails_with_line_numbers() {
+    let content = "let x = 1;\nlet y = 2;\nlet x = 3;\n";
+    let err = edit::replace(content, "let x", "let z", false, false).unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("matches 2 times"), "{}", message);
+    assert!(message.contains("lines 1, 3"), "{}", message);
+    assert!(message.contains("replace_all"), "{}", message);
+
+    let (replaced, applied) = edit::replace(content, "let x", "let z", true, false).unwrap();
+    assert_eq!(replaced, "let z = 1;\nlet y = 2;\nlet z = 3;\n");
+    assert_eq!(applied.describe(), "2 occurrences (lines 1, 3)");
+}
+
+#[test]
+fn test_whitespace_drift_needs_ignore_whitespace() {
+    let content = "fn main() {\n        println!(\"hi\");\n}\n";
+    let old = "fn main() {\n    println!(\"hi\");\n}";
+
+    let err = edit::replace(content, old, "fn main() {}", false, false).unwrap_err();
+    assert!(err.to_string().contains("ignore_whitespace"), "{}", err);
+
+    let (replaced, applied) = edit::replace(content, old, "fn main() {}", false, true).unwrap();
+    assert_eq!(replaced, "fn main() {}\n");
+    assert!(applied.loose);
+    assert_eq!(
+        applied.describe(),
+        "1 occurrence (line 1, ignoring whitespace)"
+    );
+}
+
+#[test]
+fn test_exact_match_wins_over_loose_match() {
+    let content = "a  b\na b\n";
+    let (replaced, applied) = edit::replace(content, "a b", "x", false, true).unwrap();
+    assert_eq!(replaced, "a  b\nx\n");
+    assert_eq!(applied.lines, vec![2]);
+    assert!(!applied.loose);
+}
+
+#[test]
+fn test_missing_and_empty_old_string_fail() {
+    assert!(edit::replace("abc", "xyz", "", false, true).is_err());
+    assert!(edit::replace("abc", "", "x", false, false).is_err());
+}
+
+#[tokio::test]
+async fn test_multi_edit_applies_edits_in_order() {
+    let (root, ctx) = sandbox();
+    fs::write(
+        root.join("lib.rs"),
+        "fn old() {}\nfn main() { old(); old(); }\n",
+    )
+    .unwrap();
+
+    let tool = Tool::MultiEdit {
+        path: "lib.rs".to_string(),
+        edits: vec![
+            edit("fn old()", "fn new()"),
+            Edit {
+                replace_all: true,
+                ..edit("old();", "new();")
+            },
+        ],
+    };
+    let result = tool.execute_with(&ctx).await.unwrap();
+
+    assert!(result.contains("Applied 2 edits"), "{}", result);
+    assert_eq!(
+        fs::read_to_string(root.join("lib.rs")).unwrap(),
+        "fn new() {}\nfn main() { new(); new(); }\n"
+    );
+}
+
+#[tokio::test]
+async fn test_multi_edit_is_all_or_nothing() {
+    let (root, ctx) = sandbox();
+    fs::write(root.join("a.txt"), "one\ntwo\n").unwrap();
+
+    let tool = Tool::MultiEdit {
+        path: "a.txt".to_string(),
+        edits: vec![edit("one", "1"), edit("three", "3")],
+    };
+    let err = tool.execute_with(&ctx).await.unwrap_err();
+
+    assert!(err.to_string().contains("Edit 2 of 2"), "{}", err);
+    assert_eq!(
+        fs::read_to_string(root.join("a.txt")).unwrap(),
+        "one\ntwo\n"
+    );
+}
+
+#[test]
+fn test_multi_edit_from_call() {
+    let tool = Tool::from_call(
+        "MULTI_EDIT",
+        r#"{"path": "a.rs", "edits": [{"old_string": "a", "new_string": "b", "replace_all": true}]}"#,
+    )
+    .unwrap();
+    match tool {
+        Tool::MultiEdit { path, edits } => {
+            assert_eq!(path, "a.rs");
+            assert_eq!(edits.len(), 1);
+            assert!(edits[0].replace_all);
+            assert!(!edits[0].ignore_whitespace);
+        }
+        other => panic!("unexpected tool {:?}", other),
+    }
+}
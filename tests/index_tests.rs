@@ -0,0 +1,374 @@
+mod common;
+
+use common::TempRoot;
+use rust_tui_coder::agent::{Tool, ToolContext};
+use rust_tui_coder::index::Index;
+use rust_tui_coder::symbols::{self, Kind};
+use std::fs;
+
+fn sandbox() -> (TempRoot, ToolContext) {
+    let (root, ctx) = common::sandbox();
+    fs::create_dir_all(root.join("src")).unwrap();
+    (root, ctx)
+}
+
+const RUST: &str = r#"// fn commented_out() {}
+/* fn also_commented() {} */
+pub struct Parser {
+    text: String,
+}
+
+impl Parser {
+    pub fn new(text: &str) -> Self {
+        let message = "fn in_a_string() {}";
+        Self { text: text.to_string() }
+    }
+
+    fn parse_item(&self) -> Option<char> {
+        let c = '{';
+        self.text.chars().next()
+    }
+}
+
+impl<T: Clone> Default for Wrapper<T> {
+    fn default() -> Self {
+        todo!()
+    }
+}
+
+pub async fn run<'a>(input: &'a str) {}
+"#;
+
+#[test]
+fn test_rust_symbols_have_lines_kinds_and_parents() {
+    let symbols = symbols::extract("src/parser.rs", RUST).symbols;
+    let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "Parser",
+            "Parser",
+            "new",
+            "parse_item",
+            "Wrapper",
+            "default",
+            "run"
+        ]
+    );
+
+    let new = &symbols[2];
+    assert_eq!(new.kind, Kind::Method);
+    assert_eq!(new.line, 8);
+    assert_eq!(new.parent.as_deref(), Some("Parser"));
+    assert_eq!(new.signature, "pub fn new(text: &str) -> Self");
+
+    assert_eq!(symbols[3].parent.as_deref(), Some("Parser"));
+    assert_eq!(symbols[5].parent.as_deref(), Some("Wrapper"));
+    let run = &symbols[6];
+    assert_eq!(run.kind, Kind::Function);
+    assert_eq!(run.parent, None);
+    assert_eq!(run.line, 25);
+}
+
+#[test]
+fn test_python_and_typescript_symbols() {
+    let python = "class Shape:\n    \"\"\"def not_a_method(): pass\"\"\"\n    def area(self):\n        pass\n\ndef helper():\n    # def commented(): pass\n    pass\n";
+    let symbols = symbols::extract("shapes.py", python).symbols;
+    let found: Vec<(Kind, &str, Option<&str>, usize)> = symbols
+        .iter()
+        .map(|s| (s.kind, s.name.as_str(), s.parent.as_deref(), s.line))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Kind::Class, "Shape", None, 1),
+            (Kind::Method, "area", Some("Shape"), 3),
+            (Kind::Function, "helper", None, 6),
+        ]
+    );
+
+    let script = "export class Store {\n  async load(id: string) {\n    if (id) {}\n  }\n}\nexport const handler = async (event) => {};\nconst LIMIT = 10;\nexport interface Options {}\n";
+    let symbols = symbols::extract("store.ts", script).symbols;
+    let found: Vec<(Kind, &str)> = symbols.iter().map(|s| (s.kind, s.name.as_str())).collect();
+    assert_eq!(
+        found,
+        vec![
+            (Kind::Class, "Store"),
+            (Kind::Method, "load"),
+            (Kind::Function, "handler"),
+            (Kind::Variable, "LIMIT"),
+            (Kind::Interface, "Options"),
+        ]
+    );
+
+    let view =
+        "export function View(props: Props) {\n  return <div>{format(props.name)}</div>;\n}\n";
+    let extracted = symbols::extract("view.tsx", view);
+    assert_eq!(extracted.symbols[0].name, "View");
+    assert_eq!(extracted.references[0].caller.as_deref(), Some("View"));
+}
+
+#[test]
+fn test_nested_decorated_and_restricted_definitions() {
+    let rust = "mod inner {\n    pub(crate) struct Cache;\n    impl crate::Store for Cache {\n        fn get(&self) {}\n    }\n}\ntrait Store {\n    fn get(&self);\n}\n";
+    let found: Vec<(Kind, String, Option<String>)> = symbols::extract("lib.rs", rust)
+        .symbols
+        .into_iter()
+        .map(|s| (s.kind, s.name, s.parent))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Kind::Module, "inner".to_string(), None),
+            (Kind::Struct, "Cache".to_string(), None),
+            (Kind::Impl, "Cache".to_string(), None),
+            (Kind::Method, "get".to_string(), Some("Cache".to_string())),
+            (Kind::Trait, "Store".to_string(), None),
+            (Kind::Method, "get".to_string(), Some("Store".to_string())),
+        ]
+    );
+
+    let python = "class Outer:\n    class Inner:\n        @property\n        def size(self):\n            def local():\n                pass\n            return local()\n";
+    let found: Vec<(Kind, String, Option<String>, usize)> = symbols::extract("a.py", python)
+        .symbols
+        .into_iter()
+        .map(|s| (s.kind, s.name, s.parent, s.line))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Kind::Class, "Outer".to_string(), None, 1),
+            (
+                Kind::Class,
+                "Inner".to_string(),
+                Some("Outer".to_string()),
+                2
+            ),
+            (
+                Kind::Method,
+                "size".to_string(),
+                Some("Inner".to_string()),
+                4
+            ),
+            (Kind::Function, "local".to_string(), None, 5),
+        ]
+    );
+}
+
+#[test]
+fn test_go_java_and_c_symbols() {
+    let go = "package main\n\ntype Server struct {\n\tport int\n}\n\nfunc (s *Server) Start() error {\n\treturn listen(s.port)\n}\n\nfunc listen(port int) error { return nil }\n";
+    let found: Vec<(Kind, String, Option<String>)> = symbols::extract("main.go", go)
+        .symbols
+        .into_iter()
+        .map(|s| (s.kind, s.name, s.parent))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Kind::Struct, "Server".to_string(), None),
+            (
+                Kind::Method,
+                "Start".to_string(),
+                Some("Server".to_string())
+            ),
+            (Kind::Function, "listen".to_string(), None),
+        ]
+    );
+
+    let java = "public class Shop {\n    public Shop() {}\n    private int total(List<Item> items) {\n        return sum(items);\n    }\n    enum Size { SMALL, LARGE }\n}\n";
+    let extracted = symbols::extract("Shop.java", java);
+    let found: Vec<(Kind, &str, Option<&str>)> = extracted
+        .symbols
+        .iter()
+        .map(|s| (s.kind, s.name.as_str(), s.parent.as_deref()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Kind::Class, "Shop", None),
+            (Kind::Method, "Shop", Some("Shop")),
+            (Kind::Method, "total", Some("Shop")),
+            (Kind::Enum, "Size", Some("Shop")),
+        ]
+    );
+    assert_eq!(
+        extracted.symbols[2].signature,
+        "private int total(List<Item> items)"
+    );
+
+    let c = "#include <stdio.h>\nstruct point { int x; };\nint area(int w);\nstatic int area(int w) {\n    return square(w);\n}\n";
+    let extracted = symbols::extract("geo.c", c);
+    let found: Vec<(Kind, &str, usize)> = extracted
+        .symbols
+        .iter()
+        .map(|s| (s.kind, s.name.as_str(), s.line))
+        .collect();
+    assert_eq!(
+        found,
+        vec![(Kind::Struct, "point", 2), (Kind::Function, "area", 4)]
+    );
+}
+
+#[test]
+fn test_calls_are_recorded_with_their_caller() {
+    let rust = "fn main() {\n    let p = Parser::new(\"x\");\n    p.parse::<u8>();\n    println!(\"{}\", helper(1));\n}\n";
+    let references = symbols::extract("main.rs", rust).references;
+    let found: Vec<(&str, usize, Option<&str>)> = references
+        .iter()
+        .map(|r| (r.name.as_str(), r.line, r.caller.as_deref()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("new", 2, Some("main")),
+            ("parse", 3, Some("main")),
+            ("println", 4, Some("main")),
+            ("helper", 4, Some("main")),
+        ]
+    );
+
+    let script = "class Api {\n  fetch() {\n    return request();\n  }\n}\nconst start = () => new Api().fetch();\nstart();\n";
+    let references = symbols::extract("api.js", script).references;
+    let found: Vec<(&str, Option<&str>)> = references
+        .iter()
+        .map(|r| (r.name.as_str(), r.caller.as_deref()))
+        .collect();
+    assert!(
+        found.contains(&("request", Some("Api::fetch"))),
+        "{:?}",
+        found
+    );
+    assert!(found.contains(&("Api", Some("start"))), "{:?}", found);
+    assert!(found.contains(&("fetch", Some("start"))), "{:?}", found);
+    assert!(found.contains(&("start", None)), "{:?}", found);
+}
+
+#[test]
+fn test_index_is_incremental_and_honours_gitignore() {
+    let (root, _ctx) = sandbox();
+    fs::write(root.join(".gitignore"), "target/\n").unwrap();
+    fs::create_dir_all(root.join("target/package")).unwrap();
+    fs::write(root.join("target/package/copy.rs"), "fn copied() {}\n").unwrap();
+    fs::write(root.join("src/a.rs"), "fn a() {}\n").unwrap();
+    fs::write(root.join("src/b.rs"), "fn b() {}\n").unwrap();
+
+    let mut index = Index::default();
+    let first = index.update(&root, &root).unwrap();
+    assert_eq!((first.added, first.changed, first.removed), (2, 0, 0));
+    assert!(!index.files.contains_key("target/package/copy.rs"));
+    index.save(&root).unwrap();
+
+    let mut index = Index::load(&root);
+    fs::write(root.join("src/a.rs"), "fn a() {}\nfn a2() {}\n").unwrap();
+    fs::remove_file(root.join("src/b.rs")).unwrap();
+    let second = index.update(&root, &root).unwrap();
+    assert_eq!(
+        (
+            second.added,
+            second.changed,
+            second.removed,
+            second.unchanged
+        ),
+        (0, 1, 1, 0)
+    );
+    assert_eq!(index.symbol_count(), 2);
+
+    let third = index.update(&root, &root).unwrap();
+    assert_eq!(third.unchanged, 1);
+}
+
+#[test]
+fn test_search_ranks_exact_names_first() {
+    let (root, _ctx) = sandbox();
+    fs::write(
+        root.join("src/lib.rs"),
+        "fn read_file_lines() {}\nfn read() {}\nfn spread() {}\nstruct Reader;\nimpl Reader {\n    fn read(&self) {}\n}\n",
+    )
+    .unwrap();
+    let mut index = Index::default();
+    index.update(&root, &root).unwrap();
+
+    let hits: Vec<String> = index
+        .search("read")
+        .iter()
+        .map(|hit| hit.to_string())
+        .collect();
+    assert_eq!(hits[0], "src/lib.rs:2 function read: fn read()");
+    assert_eq!(
+        hits[1],
+        "src/lib.rs:6 method read (in Reader): fn read(&self)"
+    );
+    assert!(
+        hits.iter().position(|h| h.contains("read_file_lines"))
+            < hits.iter().position(|h| h.contains("spread"))
+    );
+
+    let qualified = index.search("Reader::read");
+    assert_eq!(qualified.len(), 1);
+    assert_eq!(qualified[0].symbol.line, 6);
+}
+
+#[tokio::test]
+async fn test_search_index_sees_edits_without_reindexing() {
+    let (root, ctx) = sandbox();
+    fs::write(root.join("src/lib.rs"), "fn old_name() {}\n").unwrap();
+
+    let result = Tool::IndexCodebase {
+        path: ".".to_string(),
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+    assert!(
+        result.contains("Indexed 1 files with 1 symbols"),
+        "{}",
+        result
+    );
+
+    fs::write(root.join("src/lib.rs"), "\nfn new_name() {}\n").unwrap();
+    let result = Tool::SearchIndex {
+        query: "new_name".to_string(),
+        callers: false,
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+    assert!(
+        result.contains("src/lib.rs:2 function new_name"),
+        "{}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn test_search_index_lists_callers() {
+    let (root, ctx) = sandbox();
+    fs::write(
+        root.join("src/lib.rs"),
+        "pub fn helper() {}\n\nfn first() {\n    helper();\n}\n",
+    )
+    .unwrap();
+    fs::write(root.join("src/other.py"), "def second():\n    helper()\n").unwrap();
+    Tool::IndexCodebase {
+        path: ".".to_string(),
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+
+    let tool = Tool::from_call("SEARCH_INDEX", r#"{"query": "helper", "callers": true}"#).unwrap();
+    assert_eq!(tool.label(), "SEARCH_INDEX callers of helper");
+    let result = tool.execute_with(&ctx).await.unwrap();
+    assert!(result.starts_with("2 calls of 'helper'"), "{}", result);
+    assert!(result.contains("src/lib.rs:4 in first"), "{}", result);
+    assert!(result.contains("src/other.py:2 in second"), "{}", result);
+
+    let tool = Tool::from_call("SEARCH_INDEX", r#"{"query": "helper"}"#).unwrap();
+    let result = tool.execute_with(&ctx).await.unwrap();
+    assert!(
+        result.contains("src/lib.rs:1 function helper"),
+        "{}",
+        result
+    );
+}
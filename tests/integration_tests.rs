@@ -2,8 +2,8 @@ use rust_tui_coder::agent::Tool;
 use rust_tui_coder::app::App;
 use std::fs;
 
-#[test]
-fn test_integration_file_workflow() {
+#[tokio::test]
+async fn test_integration_file_workflow() {
     let test_dir = "tmp_rovodev_integration_test";
     let test_file = format!("{}/test.txt", test_dir);
 
@@ -13,6 +13,7 @@ fn test_integration_file_workflow() {
     };
     assert!(create_dir
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Write file
@@ -22,13 +23,18 @@ fn test_integration_file_workflow() {
     };
     assert!(write_file
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Read file
     let read_file = Tool::ReadFile {
         path: test_file.clone(),
+        offset: None,
+        limit: None,
     };
-    let result = read_file.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = read_file
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     assert!(result.unwrap().contains("Initial content"));
 
@@ -39,13 +45,18 @@ fn test_integration_file_workflow() {
     };
     assert!(append_file
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Read again
     let read_file2 = Tool::ReadFile {
         path: test_file.clone(),
+        offset: None,
+        limit: None,
     };
-    let result2 = read_file2.execute(&rust_tui_coder::config::WebConfig::default());
+    let result2 = read_file2
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result2.is_ok());
     let content = result2.unwrap();
     assert!(content.contains("Initial content"));
@@ -56,16 +67,23 @@ fn test_integration_file_workflow() {
         path: test_file.clone(),
         old_string: "Initial".to_string(),
         new_string: "Modified".to_string(),
+        replace_all: false,
+        ignore_whitespace: false,
     };
     assert!(search_replace
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Verify replacement
     let read_file3 = Tool::ReadFile {
         path: test_file.clone(),
+        offset: None,
+        limit: None,
     };
-    let result3 = read_file3.execute(&rust_tui_coder::config::WebConfig::default());
+    let result3 = read_file3
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result3.is_ok());
     assert!(result3.unwrap().contains("Modified content"));
 
@@ -112,8 +130,8 @@ fn test_integration_app_workflow() {
     assert_eq!(app.conversation_scroll_position, 0);
 }
 
-#[test]
-fn test_integration_plan_workflow() {
+#[tokio::test]
+async fn test_integration_plan_workflow() {
     // Create a plan
     let create_plan = Tool::CreatePlan {
         task: "Integration Test Task".to_string(),
@@ -125,22 +143,26 @@ fn test_integration_plan_workflow() {
     };
     assert!(create_plan
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Update plan for each step
     let update_step1 = Tool::UpdatePlan { completed_step: 1 };
     assert!(update_step1
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     let update_step2 = Tool::UpdatePlan { completed_step: 2 };
     assert!(update_step2
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     let update_step3 = Tool::UpdatePlan { completed_step: 3 };
     assert!(update_step3
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
 
     // Verify all steps are completed
@@ -151,6 +173,7 @@ fn test_integration_plan_workflow() {
     let clear_plan = Tool::ClearPlan;
     assert!(clear_plan
         .execute(&rust_tui_coder::config::WebConfig::default())
+        .await
         .is_ok());
     assert!(!std::path::Path::new("plan.md").exists());
 }
@@ -181,8 +204,8 @@ fn test_integration_streaming_workflow() {
     assert_eq!(app.conversation_scroll_position, usize::MAX);
 }
 
-#[test]
-fn test_integration_multiple_file_operations() {
+#[tokio::test]
+async fn test_integration_multiple_file_operations() {
     let test_dir = "tmp_rovodev_multi_ops";
     fs::create_dir_all(test_dir).ok();
 
@@ -194,6 +217,7 @@ fn test_integration_multiple_file_operations() {
         };
         assert!(write_tool
             .execute(&rust_tui_coder::config::WebConfig::default())
+            .await
             .is_ok());
     }
 
@@ -201,7 +225,9 @@ fn test_integration_multiple_file_operations() {
     let list_tool = Tool::ListFiles {
         path: test_dir.to_string(),
     };
-    let result = list_tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = list_tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok());
     let output = result.unwrap();
 
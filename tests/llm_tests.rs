@@ -1,4 +1,18 @@
-use rust_tui_coder::llm::{estimate_token_count, Message};
+use async_trait::async_trait;
+use rust_tui_coder::config::{LlmConfig, OllamaConfig, RetryConfig, ToolProtocol};
+use rust_tui_coder::llm::anthropic::convert_messages;
+use rust_tui_coder::llm::anthropic::AnthropicDecoder;
+use rust_tui_coder::llm::ollama::{self, OllamaDecoder};
+use rust_tui_coder::llm::openai::OpenAiDecoder;
+use rust_tui_coder::llm::sse::{SseDecoder, SseEvent};
+use rust_tui_coder::llm::{
+    backoff_delay, estimate_token_count, estimate_usage, flatten_tool_calls, resolve_model,
+    with_retry, ChunkDecoder, EventStream, FunctionCall, FunctionCallDelta, LineBuffer, LlmError,
+    LlmProvider, Message, StreamEvent, TokenUsage, ToolCallAccumulator, ToolCallDelta,
+    ToolCallRequest, ToolDefinition,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 #[test]
 fn test_estimate_token_count_empty() {
@@ -18,6 +32,13 @@ fn test_estimate_token_count_long() {
     assert!(count > 10);
 }
 
+#[test]
+fn test_estimate_token_count_uses_bpe_vocabulary() {
+    // cl100k splits these into whole-word tokens, unlike a chars/4 guess
+    assert_eq!(estimate_token_count("hello world"), 2);
+    assert_eq!(estimate_token_count("fn main() {}"), 4);
+}
+
 #[test]
 fn test_estimate_token_count_whitespace() {
     let count = estimate_token_count("   ");
@@ -36,6 +57,7 @@ fn test_message_creation() {
     let message = Message {
         role: "user".to_string(),
         content: "Hello, AI!".to_string(),
+        ..Default::default()
     };
 
     assert_eq!(message.role, "user");
@@ -47,6 +69,7 @@ fn test_message_clone() {
     let message = Message {
         role: "assistant".to_string(),
         content: "Hello, human!".to_string(),
+        ..Default::default()
     };
 
     let cloned = message.clone();
@@ -59,6 +82,7 @@ fn test_message_serialization() {
     let message = Message {
         role: "system".to_string(),
         content: "You are a helpful assistant.".to_string(),
+        ..Default::default()
     };
 
     let json = serde_json::to_string(&message);
@@ -79,3 +103,573 @@ fn test_message_deserialization() {
     assert_eq!(message.role, "user");
     assert_eq!(message.content, "Test message");
 }
+
+#[test]
+fn test_message_without_tool_fields_serializes_plainly() {
+    let message = Message {
+        role: "user".to_string(),
+        content: "Hi".to_string(),
+        ..Default::default()
+    };
+
+    let json_str = serde_json::to_string(&message).unwrap();
+    assert_eq!(json_str, r#"{"role":"user","content":"Hi"}"#);
+}
+
+#[test]
+fn test_message_deserialization_with_tool_calls_and_null_content() {
+    let json_str = r#"{"role":"assistant","content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"READ_FILE","arguments":"{\"path\":\"src/main.rs\"}"}}]}"#;
+    let message: Message = serde_json::from_str(json_str).unwrap();
+
+    assert_eq!(message.content, "");
+    let calls = message.tool_calls.unwrap();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].id, "call_1");
+    assert_eq!(calls[0].function.name, "READ_FILE");
+    assert!(calls[0].function.arguments.contains("src/main.rs"));
+}
+
+#[test]
+fn test_flatten_tool_calls_turns_tool_turns_into_text() {
+    let json_str = r#"[{"role":"user","content":"What time is it?"},{"role":"assistant","content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"GET_TIME","arguments":"{}"}}]},{"role":"tool","content":"12:00","tool_call_id":"call_1"}]"#;
+    let messages: Vec<Message> = serde_json::from_str(json_str).unwrap();
+
+    let flat = flatten_tool_calls(&messages);
+    assert_eq!(flat.len(), 3);
+    assert_eq!(flat[0].content, "What time is it?");
+    assert_eq!(flat[1].role, "assistant");
+    assert_eq!(flat[1].content, "(called GET_TIME {})");
+    assert_eq!(flat[2].role, "user");
+    assert_eq!(flat[2].content, "Tool result: 12:00");
+    assert!(flat
+        .iter()
+        .all(|message| message.tool_calls.is_none() && message.tool_call_id.is_none()));
+}
+
+#[test]
+fn test_tool_call_accumulator_joins_streamed_fragments() {
+    let mut accumulator = ToolCallAccumulator::default();
+    accumulator.push(ToolCallDelta {
+        index: 0,
+        id: Some("call_a".to_string()),
+        function: Some(FunctionCallDelta {
+            name: Some("WRITE_FILE".to_string()),
+            arguments: Some("{\"path\": \"a.txt\", ".to_string()),
+        }),
+    });
+    accumulator.push(ToolCallDelta {
+        index: 1,
+        id: None,
+        function: Some(FunctionCallDelta {
+            name: Some("GET_TIME".to_string()),
+            arguments: None,
+        }),
+    });
+    accumulator.push(ToolCallDelta {
+        index: 0,
+        id: None,
+        function: Some(FunctionCallDelta {
+            name: None,
+            arguments: Some("\"content\": \"line1\\nline2\"}".to_string()),
+        }),
+    });
+
+    let calls = accumulator.finish();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].id, "call_a");
+    assert_eq!(calls[0].function.name, "WRITE_FILE");
+    let args: serde_json::Value = serde_json::from_str(&calls[0].function.arguments).unwrap();
+    assert_eq!(args["content"], "line1\nline2");
+    assert_eq!(calls[1].id, "call_1");
+    assert_eq!(calls[1].function.name, "GET_TIME");
+}
+
+#[test]
+fn test_anthropic_conversion_splits_system_and_groups_tool_results() {
+    let call = |id: &str| ToolCallRequest {
+        id: id.to_string(),
+        kind: "function".to_string(),
+        function: FunctionCall {
+            name: "READ_FILE".to_string(),
+            arguments: format!("{{\"path\": \"{}.rs\"}}", id),
+        },
+    };
+    let messages = vec![
+        Message {
+            role: "system".to_string(),
+            content: "Be helpful.".to_string(),
+            ..Default::default()
+        },
+        Message {
+            role: "system".to_string(),
+            content: "Plan first.".to_string(),
+            ..Default::default()
+        },
+        Message {
+            role: "user".to_string(),
+            content: "Read a and b".to_string(),
+            ..Default::default()
+        },
+        Message {
+            role: "assistant".to_string(),
+            content: String::new(),
+            tool_calls: Some(vec![call("a"), call("b")]),
+            ..Default::default()
+        },
+        Message {
+            role: "tool".to_string(),
+            content: "contents of a".to_string(),
+            tool_call_id: Some("a".to_string()),
+            ..Default::default()
+        },
+        Message {
+            role: "tool".to_string(),
+            content: "contents of b".to_string(),
+            tool_call_id: Some("b".to_string()),
+            ..Default::default()
+        },
+    ];
+
+    let (system, converted) = convert_messages(&messages);
+    assert_eq!(system.as_deref(), Some("Be helpful.\n\nPlan first."));
+    assert_eq!(converted.len(), 3);
+
+    assert_eq!(converted[1]["role"], "assistant");
+    let tool_uses = converted[1]["content"].as_array().unwrap();
+    assert_eq!(tool_uses.len(), 2);
+    assert_eq!(tool_uses[0]["type"], "tool_use");
+    assert_eq!(tool_uses[0]["input"]["path"], "a.rs");
+
+    // Both results must arrive in the single user turn that follows
+    assert_eq!(converted[2]["role"], "user");
+    let results = converted[2]["content"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["type"], "tool_result");
+    assert_eq!(results[1]["tool_use_id"], "b");
+}
+
+#[test]
+fn test_line_buffer_keeps_partial_lines_and_utf8() {
+    let mut buffer = LineBuffer::default();
+    let text = "héllo\r\nwörld\n".as_bytes();
+    // Split inside the two-byte 'é'
+    assert!(buffer.push(&text[..2]).is_empty());
+    assert_eq!(buffer.push(&text[2..10]), vec!["héllo".to_string()]);
+    assert_eq!(buffer.push(&text[10..]), vec!["wörld".to_string()]);
+    assert_eq!(buffer.finish(), None);
+
+    buffer.push(b"tail");
+    assert_eq!(buffer.finish(), Some("tail".to_string()));
+}
+
+#[test]
+fn test_ollama_decoder_handles_split_ndjson_and_usage() {
+    let body = concat!(
+        r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#,
+        "\n",
+        r#"{"message":{"role":"assistant","content":"lo","tool_calls":[{"function":{"name":"READ_FILE","arguments":{"path":"a.rs"}}}]},"done":false}"#,
+        "\n",
+        r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":42,"eval_count":7}"#,
+    );
+
+    let mut decoder = OllamaDecoder::default();
+    let mut events = Vec::new();
+    for chunk in body.as_bytes().chunks(9) {
+        events.extend(decoder.decode(chunk));
+    }
+    events.extend(decoder.finish());
+    let events: Vec<StreamEvent> = events.into_iter().map(|e| e.unwrap()).collect();
+
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0], StreamEvent::Content("Hel".to_string()));
+    assert_eq!(events[1], StreamEvent::Content("lo".to_string()));
+    match &events[2] {
+        StreamEvent::ToolCall(delta) => {
+            let function = delta.function.as_ref().unwrap();
+            assert_eq!(function.name.as_deref(), Some("READ_FILE"));
+            assert_eq!(function.arguments.as_deref(), Some(r#"{"path":"a.rs"}"#));
+        }
+        other => panic!("expected a tool call, got {:?}", other),
+    }
+    assert_eq!(
+        events[3],
+        StreamEvent::Usage(TokenUsage {
+            prompt_tokens: 42,
+            completion_tokens: 7,
+        })
+    );
+}
+
+#[test]
+fn test_ollama_decoder_reports_stream_errors() {
+    let mut decoder = OllamaDecoder::default();
+    let events = decoder.decode(b"{\"error\":\"model not found\"}\n");
+    assert_eq!(events.len(), 1);
+    assert!(events[0].is_err());
+}
+
+#[test]
+fn test_ollama_conversion_sends_arguments_as_objects() {
+    let messages = vec![Message {
+        role: "assistant".to_string(),
+        content: String::new(),
+        tool_calls: Some(vec![ToolCallRequest {
+            id: "call_0".to_string(),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: "READ_FILE".to_string(),
+                arguments: r#"{"path":"a.rs"}"#.to_string(),
+            },
+        }]),
+        ..Default::default()
+    }];
+
+    let converted = ollama::convert_messages(&messages);
+    assert_eq!(
+        converted[0]["tool_calls"][0]["function"]["arguments"]["path"],
+        "a.rs"
+    );
+}
+
+#[test]
+fn test_sse_decoder_reassembles_events_across_chunks() {
+    let body = "event: message\nid: 7\ndata: {\"text\":\"ünï\"}\n\n: keep-alive\n\ndata: line one\ndata: line two\n\ndata: [DONE]\n\ndata: ignored\n\n";
+    let mut decoder = SseDecoder::default();
+    let mut events = Vec::new();
+    for chunk in body.as_bytes().chunks(5) {
+        events.extend(decoder.push(chunk));
+    }
+    events.extend(decoder.finish());
+
+    assert_eq!(
+        events,
+        vec![
+            SseEvent {
+                event: Some("message".to_string()),
+                id: Some("7".to_string()),
+                data: r#"{"text":"ünï"}"#.to_string(),
+            },
+            SseEvent {
+                event: None,
+                id: Some("7".to_string()),
+                data: "line one\nline two".to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_sse_decoder_flushes_unterminated_event() {
+    let mut decoder = SseDecoder::default();
+    assert!(decoder.push(b"data: partial").is_empty());
+    let events = decoder.finish();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].data, "partial");
+}
+
+#[test]
+fn test_openai_decoder_surfaces_mid_stream_errors() {
+    let mut decoder = OpenAiDecoder::default();
+    let events = decoder.decode(
+        b"data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\ndata: {\"error\":{\"message\":\"overloaded\"}}\n\n",
+    );
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        events[0].as_ref().unwrap(),
+        &StreamEvent::Content("Hi".to_string())
+    );
+    match &events[1] {
+        Err(e) => assert!(e.to_string().contains("overloaded")),
+        Ok(event) => panic!("expected an error, got {:?}", event),
+    }
+}
+
+#[test]
+fn test_openai_decoder_reports_malformed_chunks() {
+    let mut decoder = OpenAiDecoder::default();
+    let events = decoder.decode(b"data: {not json\n\n");
+    assert_eq!(events.len(), 1);
+    assert!(events[0].is_err());
+}
+
+#[test]
+fn test_anthropic_decoder_maps_events() {
+    let body = concat!(
+        "event: content_block_start\n",
+        r#"data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"READ_FILE","input":{}}}"#,
+        "\n\nevent: ping\ndata: {\"type\":\"ping\"}\n\n",
+        "event: error\n",
+        r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+        "\n\n",
+    );
+    let mut decoder = AnthropicDecoder::default();
+    let mut events = Vec::new();
+    for chunk in body.as_bytes().chunks(16) {
+        events.extend(decoder.decode(chunk));
+    }
+
+    assert_eq!(events.len(), 2);
+    match events[0].as_ref().unwrap() {
+        StreamEvent::ToolCall(delta) => {
+            assert_eq!(delta.index, 1);
+            assert_eq!(delta.id.as_deref(), Some("toolu_1"));
+        }
+        other => panic!("expected a tool call, got {:?}", other),
+    }
+    assert!(events[1].is_err());
+}
+
+#[test]
+fn test_llm_error_classification_by_status() {
+    assert!(matches!(
+        LlmError::from_status(429, Some(Duration::from_secs(2)), String::new()),
+        LlmError::RateLimited {
+            retry_after: Some(_),
+            ..
+        }
+    ));
+    assert!(matches!(
+        LlmError::from_status(401, None, "bad key".to_string()),
+        LlmError::Auth(_)
+    ));
+    assert!(matches!(
+        LlmError::from_status(
+            400,
+            None,
+            r#"{"error":{"code":"context_length_exceeded"}}"#.to_string()
+        ),
+        LlmError::ContextLengthExceeded(_)
+    ));
+    assert!(matches!(
+        LlmError::from_status(400, None, "bad request".to_string()),
+        LlmError::ApiError(_)
+    ));
+    assert!(matches!(
+        LlmError::from_status(503, None, String::new()),
+        LlmError::Server { status: 503, .. }
+    ));
+    assert!(matches!(
+        LlmError::from_status(504, None, String::new()),
+        LlmError::Timeout(_)
+    ));
+}
+
+#[test]
+fn test_llm_error_retryability() {
+    assert!(LlmError::from_status(429, None, String::new()).is_retryable());
+    assert!(LlmError::from_status(502, None, String::new()).is_retryable());
+    assert!(!LlmError::from_status(401, None, String::new()).is_retryable());
+    assert!(!LlmError::ParseError("x".to_string()).is_retryable());
+}
+
+#[test]
+fn test_backoff_delay_grows_and_is_capped() {
+    let policy = RetryConfig {
+        max_retries: 5,
+        initial_backoff_ms: 100,
+        max_backoff_ms: 1000,
+    };
+    for retry in 0..8 {
+        let base = (100u64 << retry).min(1000);
+        let delay = backoff_delay(&policy, retry).as_millis() as u64;
+        assert!(
+            delay >= base / 2 && delay <= base,
+            "retry {}: {}",
+            retry,
+            delay
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_with_retry_retries_transient_errors_only() {
+    let policy = RetryConfig {
+        max_retries: 3,
+        initial_backoff_ms: 1,
+        max_backoff_ms: 2,
+    };
+
+    let mut calls = 0;
+    let result = with_retry(&policy, || {
+        calls += 1;
+        let attempt = calls;
+        async move {
+            if attempt < 3 {
+                Err(LlmError::from_status(502, None, String::new()))
+            } else {
+                Ok(attempt)
+            }
+        }
+    })
+    .await;
+    assert_eq!(result.unwrap(), 3);
+
+    let mut calls = 0;
+    let result: Result<(), LlmError> = with_retry(&policy, || {
+        calls += 1;
+        async { Err(LlmError::from_status(401, None, String::new())) }
+    })
+    .await;
+    assert!(matches!(result, Err(LlmError::Auth(_))));
+    assert_eq!(calls, 1);
+
+    let mut calls = 0;
+    let result: Result<(), LlmError> = with_retry(&policy, || {
+        calls += 1;
+        async {
+            Err(LlmError::RateLimited {
+                retry_after: Some(Duration::from_millis(1)),
+                message: String::new(),
+            })
+        }
+    })
+    .await;
+    assert!(result.is_err());
+    assert_eq!(calls, 4);
+
+    // Waiting a day is not a retry
+    let mut calls = 0;
+    let result: Result<(), LlmError> = with_retry(&policy, || {
+        calls += 1;
+        async {
+            Err(LlmError::RateLimited {
+                retry_after: Some(Duration::from_secs(86_400)),
+                message: String::new(),
+            })
+        }
+    })
+    .await;
+    assert!(matches!(result, Err(LlmError::RateLimited { .. })));
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn test_openai_decoder_reports_final_usage_chunk() {
+    let mut decoder = OpenAiDecoder::default();
+    let events = decoder.decode(
+        b"data: {\"choices\":[],\"usage\":{\"prompt_tokens\":120,\"completion_tokens\":30,\"total_tokens\":150}}\n\ndata: [DONE]\n\n",
+    );
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+        events[0].as_ref().unwrap(),
+        &StreamEvent::Usage(TokenUsage {
+            prompt_tokens: 120,
+            completion_tokens: 30,
+        })
+    );
+}
+
+#[test]
+fn test_anthropic_decoder_reports_usage() {
+    let body = concat!(
+        "event: message_start\n",
+        r#"data: {"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":50,"cache_read_input_tokens":10,"output_tokens":1}}}"#,
+        "\n\nevent: message_delta\n",
+        r#"data: {"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":25}}"#,
+        "\n\n",
+    );
+    let mut decoder = AnthropicDecoder::default();
+    let total = decoder
+        .decode(body.as_bytes())
+        .into_iter()
+        .map(|event| match event.unwrap() {
+            StreamEvent::Usage(usage) => usage,
+            other => panic!("expected usage, got {:?}", other),
+        })
+        .fold(TokenUsage::default(), |acc, usage| acc + usage);
+    assert_eq!(
+        total,
+        TokenUsage {
+            prompt_tokens: 60,
+            completion_tokens: 25,
+        }
+    );
+}
+
+#[test]
+fn test_estimate_usage_counts_prompt_and_reply() {
+    let messages = vec![Message {
+        role: "user".to_string(),
+        content: "hello world".to_string(),
+        ..Default::default()
+    }];
+    let reply = Message {
+        role: "assistant".to_string(),
+        content: "hello world".to_string(),
+        ..Default::default()
+    };
+    let usage = estimate_usage(&messages, &reply);
+    assert!(usage.prompt_tokens > usage.completion_tokens);
+    assert_eq!(usage.completion_tokens, 2);
+}
+
+/// Provider that only answers `list_models`, counting how often it's asked.
+struct ModelListProvider {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl LlmProvider for ModelListProvider {
+    async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok(vec!["qwen2.5-coder".to_string(), "llama3.1".to_string()])
+    }
+
+    async fn complete(
+        &self,
+        _messages: &[Message],
+        _tools: &[ToolDefinition],
+    ) -> Result<(Message, TokenUsage), LlmError> {
+        unimplemented!()
+    }
+
+    async fn stream(
+        &self,
+        _messages: &[Message],
+        _tools: &[ToolDefinition],
+    ) -> Result<EventStream, LlmError> {
+        unimplemented!()
+    }
+}
+
+fn autodetect_config(base_url: &str) -> LlmConfig {
+    LlmConfig {
+        provider: None,
+        api_key: String::new(),
+        api_base_url: base_url.to_string(),
+        model_name: "AUTODETECT".to_string(),
+        tool_protocol: ToolProtocol::Native,
+        max_tokens: None,
+        context_window: None,
+        embedding_model: None,
+        ollama: OllamaConfig::default(),
+        retry: RetryConfig::default(),
+    }
+}
+
+#[tokio::test]
+async fn test_autodetected_model_is_cached_per_endpoint() {
+    let provider = ModelListProvider {
+        calls: AtomicUsize::new(0),
+    };
+    let config = autodetect_config("http://tmp-rovodev-autodetect.invalid/v1");
+
+    assert_eq!(
+        resolve_model(&provider, &config).await.unwrap(),
+        "qwen2.5-coder"
+    );
+    assert_eq!(
+        resolve_model(&provider, &config).await.unwrap(),
+        "qwen2.5-coder"
+    );
+    assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+
+    // An explicit model never hits the endpoint
+    let mut explicit = config.clone();
+    explicit.model_name = "llama3.1".to_string();
+    assert_eq!(
+        resolve_model(&provider, &explicit).await.unwrap(),
+        "llama3.1"
+    );
+    assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+}
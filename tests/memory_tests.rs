@@ -0,0 +1,175 @@
+mod common;
+
+use common::TempRoot;
+use rust_tui_coder::agent::{Tool, ToolContext};
+use rust_tui_coder::memory::{self, Memories, Scope, MEMORY_FILE, RECALL_HEADER};
+use rust_tui_coder::workspace::Workspace;
+use std::fs;
+
+fn tags(tags: &[&str]) -> Vec<String> {
+    tags.iter().map(|tag| tag.to_string()).collect()
+}
+
+#[test]
+fn test_recall_ranks_matches_across_both_stores() {
+    let root = TempRoot::new();
+    let memories = Memories::new(&root, Some(root.join("home").join(MEMORY_FILE)));
+    memories
+        .remember(
+            "Run the tests with cargo nextest",
+            &tags(&["testing"]),
+            Scope::Project,
+            "REMEMBER",
+        )
+        .unwrap();
+    memories
+        .remember(
+            "The API server listens on port 8080",
+            &[],
+            Scope::Project,
+            "REMEMBER",
+        )
+        .unwrap();
+    let user = memories
+        .remember(
+            "Prefers small commits",
+            &tags(&["git"]),
+            Scope::User,
+            "REMEMBER",
+        )
+        .unwrap();
+    assert_eq!(user.id, "u1");
+
+    let recalled = memories.recall("how do I run the tests?", 5).unwrap();
+    assert_eq!(recalled.len(), 1);
+    assert_eq!(recalled[0].1.id, "p1");
+    assert!(recalled[0].1.used.is_some());
+
+    let recalled = memories.recall("git commits", 5).unwrap();
+    assert_eq!(recalled[0].0, Scope::User);
+    assert_eq!(recalled[0].1.fact, "Prefers small commits");
+
+    // Without a query everything comes back
+    assert_eq!(memories.recall("", 10).unwrap().len(), 3);
+}
+
+#[test]
+fn test_remember_merges_duplicates_and_forget_removes() {
+    let root = TempRoot::new();
+    let memories = Memories::new(&root, None);
+    let first = memories
+        .remember(
+            "Use tabs in Makefiles",
+            &tags(&["style"]),
+            Scope::Project,
+            "REMEMBER",
+        )
+        .unwrap();
+    let again = memories
+        .remember(
+            "use tabs in makefiles",
+            &tags(&["#Make", "style"]),
+            Scope::Project,
+            "REMEMBER",
+        )
+        .unwrap();
+    assert_eq!(again.id, first.id);
+    assert_eq!(again.tags, tags(&["style", "make"]));
+    assert!(memories
+        .remember("x", &[], Scope::User, "REMEMBER")
+        .is_err());
+
+    assert_eq!(
+        memories.forget("p1").unwrap().unwrap().fact,
+        "Use tabs in Makefiles"
+    );
+    assert!(memories.forget("p1").unwrap().is_none());
+    let next = memories
+        .remember("Docs live in docs/", &[], Scope::Project, "REMEMBER")
+        .unwrap();
+    assert_eq!(next.id, "p2");
+}
+
+#[test]
+fn test_legacy_markdown_memory_is_imported() {
+    let root = TempRoot::new();
+    fs::write(
+        root.join(".agent_memory.md"),
+        "# Agent Memory\n\n## [2024-05-01 10:00:00]\nThe database is Postgres 15\n\n## [2024-05-02 11:30:00]\nDeploy with make release\n",
+    )
+    .unwrap();
+    let memories = Memories::new(&root, None);
+    let all = memories.recall("", 10).unwrap();
+    assert_eq!(all.len(), 2);
+    let postgres = all.iter().find(|(_, m)| m.id == "p1").unwrap();
+    assert_eq!(postgres.1.fact, "The database is Postgres 15");
+    assert_eq!(postgres.1.created, "2024-05-01 10:00:00");
+    assert_eq!(postgres.1.source, ".agent_memory.md");
+    assert!(root.join(MEMORY_FILE).exists());
+}
+
+#[tokio::test]
+async fn test_memory_tools() {
+    let root = TempRoot::new();
+    let ctx = ToolContext {
+        workspace: Workspace::new(&root, true).unwrap(),
+        ..Default::default()
+    };
+    let remembered = Tool::Remember {
+        fact: "Migrations are in db/migrate".to_string(),
+        tags: tags(&["database"]),
+        scope: Scope::Project,
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+    assert!(remembered.contains("[p1] Migrations are in db/migrate"));
+
+    let recalled = Tool::Recall {
+        query: Some("database migrations".to_string()),
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+    assert!(recalled.contains("1 memories matching"));
+    assert!(recalled.contains("tags: database"));
+
+    // There is no user store in this context
+    assert!(Tool::Remember {
+        fact: "Likes tabs".to_string(),
+        tags: Vec::new(),
+        scope: Scope::User,
+    }
+    .execute_with(&ctx)
+    .await
+    .is_err());
+
+    Tool::Forget {
+        id: "p1".to_string(),
+    }
+    .execute_with(&ctx)
+    .await
+    .unwrap();
+    assert!(Tool::Forget {
+        id: "p1".to_string()
+    }
+    .execute_with(&ctx)
+    .await
+    .is_err());
+    let empty = Tool::Recall { query: None }
+        .execute_with(&ctx)
+        .await
+        .unwrap();
+    assert!(empty.contains("No memories saved yet"));
+}
+
+#[test]
+fn test_recalled_memories_are_stripped_from_the_prompt() {
+    let recalled = format!("{}\n\nSaved facts:\n- Uses tabs\n", RECALL_HEADER);
+    let message = memory::prepend_recalled(&recalled, "Fix the build");
+    assert!(message.starts_with(RECALL_HEADER));
+    assert!(message.contains("- Uses tabs"));
+    assert!(message.ends_with("Fix the build"));
+    assert_eq!(memory::strip_recalled(&message), "Fix the build");
+    assert_eq!(memory::strip_recalled("Fix the build"), "Fix the build");
+}
@@ -0,0 +1,171 @@
+mod common;
+
+use common::sandbox;
+use rust_tui_coder::agent::Tool;
+use rust_tui_coder::patch;
+use std::fs;
+
+fn apply_patch(patch: &str) -> Tool {
+    Tool::ApplyPatch {
+        patch: patch.to_string(),
+    }
+}
+
+const GIT_DIFF: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@
+ fn one() {}
+-fn two() {}
++fn two() -> u8 { 2 }
+ fn three() {}
+diff --git a/notes.txt b/notes.txt
+new file mode 100644
+--- /dev/null
++++ b/notes.txt
+@@ -0,0 +1,2 @@
++first
++second
+diff --git a/old.txt b/old.txt
+deleted file mode 100644
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+";
+
+#[test]
+fn test_parse_git_diff() {
+    let files = patch::parse(GIT_DIFF).unwrap();
+    assert_eq!(files.len(), 3);
+
+    assert_eq!(files[0].old_path.as_deref(), Some("src/lib.rs"));
+    assert_eq!(files[0].new_path.as_deref(), Some("src/lib.rs"));
+    assert_eq!(files[0].hunks.len(), 1);
+    assert_eq!(files[0].hunks[0].old_start, 1);
+
+    assert!(files[1].is_creation());
+    assert_eq!(files[1].path(), "notes.txt");
+    assert!(files[2].is_deletion());
+    assert_eq!(files[2].path(), "old.txt");
+}
+
+#[test]
+fn test_parse_rejects_text_without_hunks() {
+    assert!(patch::parse("just some text\n").is_err());
+    assert!(patch::parse("--- a.txt\n+++ a.txt\n").is_err());
+}
+
+#[test]
+fn test_hunk_applies_at_an_offset() {
+    let files = patch::parse("--- a.txt\n+++ a.txt\n@@ -2,3 +2,3 @@\n b\n-c\n+C\n d\n").unwrap();
+    // Two lines were added above the spot the diff was made against
+    let patched = files[0].apply("x\ny\na\nb\nc\nd\ne\n").unwrap();
+    assert_eq!(patched.content, "x\ny\na\nb\nC\nd\ne\n");
+    assert_eq!(patched.moved, 1);
+    assert_eq!(patched.fuzzy, 0);
+}
+
+#[test]
+fn test_hunk_applies_with_fuzz() {
+    let files = patch::parse("--- a.txt\n+++ a.txt\n@@ -1,5 +1,5 @@\n a\n b\n-c\n+C\n d\n stale\n")
+        .unwrap();
+    let patched = files[0].apply("a\nb\nc\nd\ne\n").unwrap();
+    assert_eq!(patched.content, "a\nb\nC\nd\ne\n");
+    assert_eq!(patched.fuzzy, 1);
+}
+
+#[test]
+fn test_rejected_hunk_shows_the_file_around_it() {
+    let files =
+        patch::parse("--- a.txt\n+++ a.txt\n@@ -2,3 +2,3 @@\n b\n-missing\n+new\n d\n").unwrap();
+    let rejected = files[0].apply("a\nb\nc\nd\n").unwrap_err();
+    assert_eq!(rejected.len(), 1);
+
+    let report = rejected[0].to_string();
+    assert!(report.contains("Hunk 1 does not match"), "{}", report);
+    assert!(report.contains("-missing"), "{}", report);
+    assert!(report.contains("c"), "{}", report);
+}
+
+#[tokio::test]
+async fn test_apply_patch_changes_creates_and_deletes_files() {
+    let (root, ctx) = sandbox();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::write(
+        root.join("src/lib.rs"),
+        "fn one() {}\nfn two() {}\nfn three() {}\n",
+    )
+    .unwrap();
+    fs::write(root.join("old.txt"), "gone\n").unwrap();
+
+    let result = apply_patch(GIT_DIFF).execute_with(&ctx).await.unwrap();
+
+    assert!(result.contains("Patched 3 files"), "{}", result);
+    assert_eq!(
+        fs::read_to_string(root.join("src/lib.rs")).unwrap(),
+        "fn one() {}\nfn two() -> u8 { 2 }\nfn three() {}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("notes.txt")).unwrap(),
+        "first\nsecond\n"
+    );
+    assert!(!root.join("old.txt").exists());
+}
+
+#[tokio::test]
+async fn test_apply_patch_is_all_or_nothing() {
+    let (root, ctx) = sandbox();
+    fs::write(root.join("a.txt"), "one\ntwo\n").unwrap();
+    fs::write(root.join("b.txt"), "three\nfour\n").unwrap();
+
+    let diff = "\
+--- a/a.txt
++++ b/a.txt
+@@ -1,2 +1,2 @@
+-one
++1
+ two
+--- a/b.txt
++++ b/b.txt
+@@ -1,2 +1,2 @@
+-five
++5
+ four
+";
+    let err = apply_patch(diff).execute_with(&ctx).await.unwrap_err();
+    let message = err.to_string();
+
+    assert!(message.contains("no files were changed"), "{}", message);
+    assert!(message.contains("b.txt"), "{}", message);
+    assert_eq!(
+        fs::read_to_string(root.join("a.txt")).unwrap(),
+        "one\ntwo\n"
+    );
+    assert_eq!(
+        fs::read_to_string(root.join("b.txt")).unwrap(),
+        "three\nfour\n"
+    );
+}
+
+#[tokio::test]
+async fn test_failed_write_removes_directories_it_created() {
+    let (root, ctx) = sandbox();
+
+    // The second file needs a directory where the first one was just written
+    let diff = "\
+--- /dev/null
++++ b/a/b/new.rs
+@@ -0,0 +1 @@
++fn new() {}
+--- /dev/null
++++ b/a/b/new.rs/inner.rs
+@@ -0,0 +1 @@
++fn inner() {}
+";
+    apply_patch(diff).execute_with(&ctx).await.unwrap_err();
+
+    assert!(!root.join("a").exists());
+}
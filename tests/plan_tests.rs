@@ -3,14 +3,14 @@
 use rust_tui_coder::agent::Tool;
 use std::fs;
 use std::path::Path;
-use std::sync::Mutex;
+use tokio::sync::Mutex;
 
 // Global mutex to ensure plan tests run one at a time
-static PLAN_TEST_MUTEX: Mutex<()> = Mutex::new(());
+static PLAN_TEST_MUTEX: Mutex<()> = Mutex::const_new(());
 
-#[test]
-fn test_tool_create_plan() {
-    let _lock = PLAN_TEST_MUTEX.lock().unwrap();
+#[tokio::test]
+async fn test_tool_create_plan() {
+    let _lock = PLAN_TEST_MUTEX.lock().await;
 
     // Ensure plan.md doesn't exist before test
     let _ = fs::remove_file("plan.md");
@@ -24,7 +24,9 @@ fn test_tool_create_plan() {
         ],
     };
 
-    let result = tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok(), "CreatePlan should succeed: {:?}", result);
 
     // Add a small delay to ensure file system operations complete
@@ -48,9 +50,9 @@ fn test_tool_create_plan() {
     fs::remove_file("plan.md").ok();
 }
 
-#[test]
-fn test_tool_update_plan() {
-    let _lock = PLAN_TEST_MUTEX.lock().unwrap();
+#[tokio::test]
+async fn test_tool_update_plan() {
+    let _lock = PLAN_TEST_MUTEX.lock().await;
 
     // Ensure clean state
     let _ = fs::remove_file("plan.md");
@@ -60,7 +62,9 @@ fn test_tool_update_plan() {
         task: "Test Task".to_string(),
         steps: vec!["Step 1".to_string(), "Step 2".to_string()],
     };
-    let create_result = create_tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let create_result = create_tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(
         create_result.is_ok(),
         "CreatePlan should succeed: {:?}",
@@ -77,7 +81,9 @@ fn test_tool_update_plan() {
 
     // Then update it
     let update_tool = Tool::UpdatePlan { completed_step: 1 };
-    let result = update_tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = update_tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok(), "UpdatePlan should succeed: {:?}", result);
 
     let content = fs::read_to_string("plan.md").unwrap();
@@ -90,9 +96,9 @@ fn test_tool_update_plan() {
     fs::remove_file("plan.md").ok();
 }
 
-#[test]
-fn test_tool_clear_plan() {
-    let _lock = PLAN_TEST_MUTEX.lock().unwrap();
+#[tokio::test]
+async fn test_tool_clear_plan() {
+    let _lock = PLAN_TEST_MUTEX.lock().await;
 
     // Ensure clean state
     let _ = fs::remove_file("plan.md");
@@ -102,7 +108,9 @@ fn test_tool_clear_plan() {
         task: "Test Task".to_string(),
         steps: vec!["Step 1".to_string()],
     };
-    let create_result = create_tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let create_result = create_tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(
         create_result.is_ok(),
         "CreatePlan should succeed: {:?}",
@@ -119,7 +127,9 @@ fn test_tool_clear_plan() {
 
     // Then clear it
     let clear_tool = Tool::ClearPlan;
-    let result = clear_tool.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = clear_tool
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok(), "ClearPlan should succeed: {:?}", result);
 
     // Add a small delay to ensure file system operations complete
@@ -131,9 +141,9 @@ fn test_tool_clear_plan() {
     );
 }
 
-#[test]
-fn test_plan_lifecycle() {
-    let _lock = PLAN_TEST_MUTEX.lock().unwrap();
+#[tokio::test]
+async fn test_plan_lifecycle() {
+    let _lock = PLAN_TEST_MUTEX.lock().await;
 
     // Ensure clean state
     let _ = fs::remove_file("plan.md");
@@ -151,6 +161,7 @@ fn test_plan_lifecycle() {
     assert!(
         create
             .execute(&rust_tui_coder::config::WebConfig::default())
+            .await
             .is_ok(),
         "CreatePlan should succeed"
     );
@@ -163,6 +174,7 @@ fn test_plan_lifecycle() {
         assert!(
             update
                 .execute(&rust_tui_coder::config::WebConfig::default())
+                .await
                 .is_ok(),
             "UpdatePlan step {} should succeed",
             i
@@ -181,6 +193,7 @@ fn test_plan_lifecycle() {
     assert!(
         clear
             .execute(&rust_tui_coder::config::WebConfig::default())
+            .await
             .is_ok(),
         "ClearPlan should succeed"
     );
@@ -193,9 +206,9 @@ fn test_plan_lifecycle() {
     );
 }
 
-#[test]
-fn test_plan_with_empty_steps() {
-    let _lock = PLAN_TEST_MUTEX.lock().unwrap();
+#[tokio::test]
+async fn test_plan_with_empty_steps() {
+    let _lock = PLAN_TEST_MUTEX.lock().await;
 
     // Ensure clean state
     let _ = fs::remove_file("plan.md");
@@ -205,7 +218,9 @@ fn test_plan_with_empty_steps() {
         task: "Task with no steps".to_string(),
         steps: vec![],
     };
-    let result = create.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = create
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(result.is_ok(), "CreatePlan with empty steps should succeed");
 
     std::thread::sleep(std::time::Duration::from_millis(50));
@@ -222,9 +237,9 @@ fn test_plan_with_empty_steps() {
     fs::remove_file("plan.md").ok();
 }
 
-#[test]
-fn test_plan_update_nonexistent_step() {
-    let _lock = PLAN_TEST_MUTEX.lock().unwrap();
+#[tokio::test]
+async fn test_plan_update_nonexistent_step() {
+    let _lock = PLAN_TEST_MUTEX.lock().await;
 
     // Ensure clean state
     let _ = fs::remove_file("plan.md");
@@ -237,6 +252,7 @@ fn test_plan_update_nonexistent_step() {
     assert!(
         create
             .execute(&rust_tui_coder::config::WebConfig::default())
+            .await
             .is_ok(),
         "CreatePlan should succeed"
     );
@@ -245,7 +261,9 @@ fn test_plan_update_nonexistent_step() {
 
     // Try to update step 5 (doesn't exist)
     let update = Tool::UpdatePlan { completed_step: 5 };
-    let result = update.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = update
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     // Should still succeed but not mark anything
     assert!(
         result.is_ok(),
@@ -256,16 +274,18 @@ fn test_plan_update_nonexistent_step() {
     fs::remove_file("plan.md").ok();
 }
 
-#[test]
-fn test_clear_nonexistent_plan() {
-    let _lock = PLAN_TEST_MUTEX.lock().unwrap();
+#[tokio::test]
+async fn test_clear_nonexistent_plan() {
+    let _lock = PLAN_TEST_MUTEX.lock().await;
 
     // Ensure no plan exists
     let _ = fs::remove_file("plan.md");
 
     // Try to clear non-existent plan
     let clear = Tool::ClearPlan;
-    let result = clear.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = clear
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(
         result.is_ok(),
         "ClearPlan should succeed even if plan doesn't exist"
@@ -276,25 +296,27 @@ fn test_clear_nonexistent_plan() {
     );
 }
 
-#[test]
-fn test_update_plan_before_create() {
-    let _lock = PLAN_TEST_MUTEX.lock().unwrap();
+#[tokio::test]
+async fn test_update_plan_before_create() {
+    let _lock = PLAN_TEST_MUTEX.lock().await;
 
     // Ensure no plan exists
     let _ = fs::remove_file("plan.md");
 
     // Try to update without creating first
     let update = Tool::UpdatePlan { completed_step: 1 };
-    let result = update.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = update
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(
         result.is_err(),
         "UpdatePlan should fail if plan doesn't exist"
     );
 }
 
-#[test]
-fn test_plan_with_special_characters() {
-    let _lock = PLAN_TEST_MUTEX.lock().unwrap();
+#[tokio::test]
+async fn test_plan_with_special_characters() {
+    let _lock = PLAN_TEST_MUTEX.lock().await;
 
     // Ensure clean state
     let _ = fs::remove_file("plan.md");
@@ -308,7 +330,9 @@ fn test_plan_with_special_characters() {
             "Step with 'quotes'".to_string(),
         ],
     };
-    let result = create.execute(&rust_tui_coder::config::WebConfig::default());
+    let result = create
+        .execute(&rust_tui_coder::config::WebConfig::default())
+        .await;
     assert!(
         result.is_ok(),
         "CreatePlan with special characters should succeed"
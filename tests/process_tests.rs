@@ -0,0 +1,275 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::TempRoot;
+use rust_tui_coder::agent::{Tool, ToolContext};
+use rust_tui_coder::process::{self, RunOptions};
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+fn cancel_after(token: &CancellationToken, delay: Duration) {
+    let token = token.clone();
+    thread::spawn(move || {
+        thread::sleep(delay);
+        token.cancel();
+    });
+}
+
+#[tokio::test]
+async fn test_output_captures_stdout_and_stderr() {
+    let cancel = CancellationToken::new();
+    let output = process::output(
+        Command::new("sh").args(["-c", "echo out; echo err >&2; exit 3"]),
+        &cancel,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "err\n");
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[tokio::test]
+async fn test_output_cancel_kills_child() {
+    let cancel = CancellationToken::new();
+    cancel_after(&cancel, Duration::from_millis(100));
+
+    let started = Instant::now();
+    let result = process::output(Command::new("sleep").arg("10"), &cancel).await;
+
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Interrupted);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_output_cancel_kills_grandchildren() {
+    let cancel = CancellationToken::new();
+    cancel_after(&cancel, Duration::from_millis(100));
+
+    // The grandchild keeps the pipes open; if it survived, reading them would block
+    let started = Instant::now();
+    let result = process::output(Command::new("sh").args(["-c", "sleep 10; true"]), &cancel).await;
+
+    assert!(result.is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_run_command_honors_cancel() {
+    let ctx = ToolContext::default();
+    cancel_after(&ctx.cancel, Duration::from_millis(100));
+
+    let tool = Tool::RunCommand {
+        command: "sleep 10".to_string(),
+    };
+    let started = Instant::now();
+    let result = tool.execute_with(&ctx).await;
+
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Interrupted);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_run_timeout_kills_child() {
+    let cancel = CancellationToken::new();
+    let options = RunOptions {
+        timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+
+    let started = Instant::now();
+    let result = process::run(
+        Command::new("sh").args(["-c", "echo started; sleep 10; true"]),
+        &cancel,
+        &options,
+    )
+    .await;
+
+    let error = result.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    assert!(error.to_string().contains("started"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_background_process_does_not_hold_up_run() {
+    let cancel = CancellationToken::new();
+    let options = RunOptions {
+        timeout: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+
+    let started = Instant::now();
+    let output = process::run(
+        Command::new("sh").args(["-c", "echo ready; sleep 30 &"]),
+        &cancel,
+        &options,
+    )
+    .await
+    .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ready\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("in the background"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_run_command_returns_with_background_process() {
+    let ctx = ToolContext {
+        timeout: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    let tool = Tool::RunCommand {
+        command: "sh -c 'sleep 30 &'".to_string(),
+    };
+
+    let started = Instant::now();
+    let result = tool.execute_with(&ctx).await.unwrap();
+    assert!(result.contains("in the background"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_run_streams_lines() {
+    let cancel = CancellationToken::new();
+    let (lines, mut received) = mpsc::unbounded_channel();
+    let options = RunOptions {
+        lines: Some(lines),
+        ..Default::default()
+    };
+
+    let output = process::run(
+        Command::new("sh").args(["-c", "echo one; echo two; printf three"]),
+        &cancel,
+        &options,
+    )
+    .await
+    .unwrap();
+
+    let mut streamed = Vec::new();
+    while let Ok(line) = received.try_recv() {
+        streamed.push(line);
+    }
+    assert_eq!(streamed, ["one", "two", "three"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "one\ntwo\nthree");
+}
+
+#[test]
+fn test_clip_keeps_head_and_tail() {
+    let text = (1..=100)
+        .map(|i| format!("line {}", i))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let clipped = process::clip(&text, 20, 20);
+
+    assert!(clipped.starts_with("line 1\nline 2\n"));
+    assert!(clipped.ends_with("line 99\nline 100"));
+    assert!(clipped.contains(&format!("[... {} characters", text.len() - 40)));
+    assert_eq!(process::clip("short", 20, 20), "short");
+}
+
+#[tokio::test]
+async fn test_run_command_output_is_clipped() {
+    let ctx = ToolContext {
+        output_head_chars: 100,
+        output_tail_chars: 100,
+        ..Default::default()
+    };
+
+    let tool = Tool::RunCommand {
+        command: "seq 1 10000".to_string(),
+    };
+    let result = tool.execute_with(&ctx).await.unwrap();
+
+    assert!(result.contains("characters ("));
+    assert!(result.contains("\n10000"));
+    assert!(result.len() < 1_000);
+}
+
+#[tokio::test]
+async fn test_tool_timeout_overrides_default() {
+    let mut ctx = ToolContext {
+        timeout: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+    ctx.tool_timeouts
+        .insert("RUN_COMMAND".to_string(), Some(Duration::from_millis(200)));
+
+    let tool = Tool::RunCommand {
+        command: "sleep 10".to_string(),
+    };
+    let started = Instant::now();
+    let result = tool.execute_with(&ctx).await;
+
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_dropping_a_tool_kills_its_command() {
+    let dir = TempRoot::new();
+    let marker = dir.join("marker");
+    let tool = Tool::RunCommand {
+        command: format!("sleep 1; touch '{}'", marker.display()),
+    };
+
+    let ctx = ToolContext::default();
+    let result = tokio::time::timeout(Duration::from_millis(200), tool.execute_with(&ctx)).await;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    assert!(result.is_err());
+    assert!(!marker.exists());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_running_tool_does_not_block_the_runtime() {
+    let tool = Tool::RunCommand {
+        command: "sleep 1".to_string(),
+    };
+    let ctx = ToolContext::default();
+    let started = Instant::now();
+    // A blocking tool would hold the only worker thread until the command ends
+    let ticked = async {
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        started.elapsed()
+    };
+
+    let (result, ticked) = tokio::join!(tool.execute_with(&ctx), ticked);
+
+    assert!(result.is_ok());
+    assert!(ticked < Duration::from_millis(800));
+}
+
+#[tokio::test]
+async fn test_timed_out_code_leaves_no_temp_files() {
+    let ctx = ToolContext {
+        timeout: Some(Duration::from_millis(500)),
+        ..Default::default()
+    };
+    let tool = Tool::ExecuteCode {
+        language: "python".to_string(),
+        code: "import os, sys, time\nprint(os.path.dirname(os.path.abspath(sys.argv[0])), flush=True)\ntime.sleep(10)".to_string(),
+    };
+
+    let error = match tool.execute_with(&ctx).await {
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => e,
+        // Python might not be available in all test environments
+        _ => return,
+    };
+    let message = error.to_string();
+    let dir = message
+        .lines()
+        .find(|line| line.contains("rct_code_"))
+        .expect("the timeout error carries the output");
+    assert!(!std::path::Path::new(dir.trim()).exists());
+}
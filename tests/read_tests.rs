@@ -0,0 +1,113 @@
+mod common;
+
+use common::{sandbox, TempRoot};
+use rust_tui_coder::agent::Tool;
+use rust_tui_coder::read;
+use std::fs;
+
+fn numbered(count: usize) -> String {
+    (1..=count).map(|i| format!("line {}\n", i)).collect()
+}
+
+/// `read::read_file` on a file holding `bytes`.
+fn render(
+    name: &str,
+    bytes: &[u8],
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> std::io::Result<String> {
+    let dir = TempRoot::new();
+    let file = dir.join(name);
+    fs::write(&file, bytes).unwrap();
+    read::read_file(name, &file, offset, limit)
+}
+
+#[test]
+fn test_lines_are_numbered() {
+    let shown = render("a.txt", b"first\nsecond\n", None, None).unwrap();
+    assert!(shown.starts_with("File: a.txt (2 lines)"), "{}", shown);
+    assert!(shown.contains("1\tfirst\n2\tsecond\n"), "{}", shown);
+}
+
+#[test]
+fn test_offset_and_limit_pick_a_range() {
+    let content = numbered(10);
+    let shown = render("a.txt", content.as_bytes(), Some(4), Some(3)).unwrap();
+    assert!(shown.contains("(lines 4-6 of 10)"), "{}", shown);
+    assert!(shown.contains("\n4\tline 4\n"), "{}", shown);
+    assert!(shown.contains("\n6\tline 6\n"), "{}", shown);
+    assert!(!shown.contains("line 7"), "{}", shown);
+    assert!(
+        shown.contains("4 more lines. Read on with offset: 7"),
+        "{}",
+        shown
+    );
+
+    assert!(render("a.txt", content.as_bytes(), Some(11), None).is_err());
+}
+
+#[test]
+fn test_long_files_are_capped() {
+    let content = numbered(read::DEFAULT_LIMIT + 50);
+    let shown = render("big.log", content.as_bytes(), None, None).unwrap();
+    assert!(shown.contains("file truncated, 50 more lines"), "{}", shown);
+    assert!(!shown.contains(&format!("line {}\n", read::DEFAULT_LIMIT + 1)));
+}
+
+#[test]
+fn test_binary_files_are_summarised() {
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x10";
+    assert!(read::is_binary(png));
+    let shown = render("logo.png", png, None, None).unwrap();
+    assert!(
+        shown.contains("is binary (PNG image, 20 bytes)"),
+        "{}",
+        shown
+    );
+    assert!(shown.contains("89 50 4e 47"), "{}", shown);
+    assert!(!read::is_binary("plain text\twith tabs\n".as_bytes()));
+}
+
+#[test]
+fn test_invalid_utf8_is_decoded_lossily() {
+    let shown = render("latin1.txt", b"caf\xe9\n", None, None).unwrap();
+    assert!(shown.contains("not valid UTF-8"), "{}", shown);
+    assert!(shown.contains("caf\u{FFFD}"), "{}", shown);
+}
+
+#[test]
+fn test_long_lines_are_cut() {
+    let line = "é".repeat(10_000);
+    let content = format!("{}\r\nshort\r\n", line);
+    let shown = render("min.js", content.as_bytes(), None, None).unwrap();
+    assert!(shown.contains("[line cut]"), "{}", shown);
+    assert!(!shown.contains("not valid UTF-8"), "{}", shown);
+    assert!(shown.contains("2\tshort\n"), "{:?}", shown);
+    assert!(shown.len() < 10_000);
+}
+
+#[test]
+fn test_lines_after_the_range_are_counted() {
+    let content = format!("{}last line without newline", numbered(99));
+    let shown = render("a.txt", content.as_bytes(), Some(1), Some(1)).unwrap();
+    assert!(shown.contains("(lines 1-1 of 100)"), "{}", shown);
+    assert!(shown.contains("99 more lines"), "{}", shown);
+    assert_eq!(
+        render("empty.txt", b"", None, None).unwrap(),
+        "File: empty.txt (empty)"
+    );
+}
+
+#[tokio::test]
+async fn test_read_file_tool_uses_offset_and_limit() {
+    let (root, ctx) = sandbox();
+    fs::write(root.join("a.txt"), numbered(5)).unwrap();
+
+    let tool =
+        Tool::from_call("READ_FILE", r#"{"path": "a.txt", "offset": 2, "limit": 2}"#).unwrap();
+    assert_eq!(tool.label(), "READ_FILE a.txt (lines 2-3)");
+    let result = tool.execute_with(&ctx).await.unwrap();
+
+    assert!(result.contains("2\tline 2\n3\tline 3\n"), "{}", result);
+    assert!(!result.contains("line 4"), "{}", result);
+}
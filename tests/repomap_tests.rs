@@ -0,0 +1,84 @@
+mod common;
+
+use common::TempRoot;
+use rust_tui_coder::index::{self, Index};
+use rust_tui_coder::repomap;
+use std::fs;
+
+fn sandbox() -> TempRoot {
+    let root = TempRoot::new();
+    fs::create_dir_all(root.join("src/net")).unwrap();
+    fs::create_dir_all(root.join("docs")).unwrap();
+    fs::write(root.join("README.md"), "# Demo\n").unwrap();
+    fs::write(root.join("docs/guide.md"), "Guide\n").unwrap();
+    fs::write(
+        root.join("src/store.rs"),
+        "pub struct Store;\n\nimpl Store {\n    pub fn get(&self) {}\n    pub fn put(&self) {}\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("src/main.rs"),
+        "fn main() {\n    let store = Store;\n    store.get();\n    store.get();\n    store.put();\n    log();\n}\n\nfn log() {}\n",
+    )
+    .unwrap();
+    fs::write(root.join("src/net/client.rs"), "pub fn connect() {}\n").unwrap();
+    root
+}
+
+#[test]
+fn test_map_lists_layout_and_ranks_files_by_use() {
+    let root = sandbox();
+    let mut index = Index::default();
+    let map = repomap::refresh(&root, &mut index, 1000).unwrap().unwrap();
+
+    assert!(map.contains("Layout:\n  README.md\n"), "{}", map);
+    assert!(map.contains("  docs/ (1 files)\n"), "{}", map);
+    assert!(map.contains("  src/ (3 files; net/)\n"), "{}", map);
+
+    let store = map.find("src/store.rs (3 refs)").expect(&map);
+    let main = map.find("src/main.rs").expect(&map);
+    assert!(store < main, "{}", map);
+    assert!(
+        map.contains("  1: pub struct Store { get, put }\n"),
+        "{}",
+        map
+    );
+    assert!(map.contains("  9: fn log()\n"), "{}", map);
+
+    // Built from the symbol index, which is kept in memory for the next
+    // turn but not saved into the workspace
+    assert_eq!(index.files.len(), 3);
+    assert!(!root.join(index::INDEX_FILE).exists());
+}
+
+#[test]
+fn test_map_follows_file_changes() {
+    let root = sandbox();
+    let mut index = Index::default();
+    repomap::refresh(&root, &mut index, 1000).unwrap();
+
+    fs::write(root.join("src/net/client.rs"), "pub fn reconnect() {}\n").unwrap();
+    let map = repomap::refresh(&root, &mut index, 1000).unwrap().unwrap();
+    assert!(map.contains("pub fn reconnect()"), "{}", map);
+    assert!(!map.contains("pub fn connect()"), "{}", map);
+}
+
+#[test]
+fn test_map_stays_within_its_token_budget() {
+    let root = sandbox();
+    let files: Vec<String> = ["README.md", "src/main.rs", "src/store.rs"]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+    let mut index = Index::default();
+    index.update(&root, &root).unwrap();
+
+    let small = repomap::render(&files, &index, 60).unwrap();
+    assert!(small.contains("src/store.rs"), "{}", small);
+    assert!(
+        small.contains("more files with symbols; use SEARCH_INDEX"),
+        "{}",
+        small
+    );
+    assert!(repomap::render(&files, &index, 5).is_none());
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use common::TempRoot;
+use regex::Regex;
+use rust_tui_coder::agent::{Tool, ToolContext};
+use rust_tui_coder::search::{self, Filter, SortBy};
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+/// A workspace with a .gitignore'd target/ and node_modules/ and a .git dir.
+fn sandbox() -> (TempRoot, ToolContext) {
+    let (root, ctx) = common::sandbox();
+    for dir in ["src/nested", "target/debug", "node_modules/dep", ".git"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    fs::write(root.join(".gitignore"), "target/\nnode_modules/\n").unwrap();
+    fs::write(root.join("src/main.rs"), "fn main() {\n    helper();\n}\n").unwrap();
+    fs::write(
+        root.join("src/nested/helper.rs"),
+        "// one\n// two\npub fn helper() {}\n// four\n// five\n",
+    )
+    .unwrap();
+    fs::write(root.join("src/notes.md"), "helper docs\n").unwrap();
+    fs::write(root.join("target/debug/out.rs"), "fn helper() {}\n").unwrap();
+    fs::write(root.join("node_modules/dep/index.js"), "helper()\n").unwrap();
+    fs::write(root.join(".git/config"), "helper\n").unwrap();
+    (root, ctx)
+}
+
+#[test]
+fn test_walk_skips_ignored_files_and_git() {
+    let (root, _ctx) = sandbox();
+    let files = search::files(&root, &root, &Filter::default()).unwrap();
+    assert_eq!(
+        files,
+        vec![
+            ".gitignore",
+            "src/main.rs",
+            "src/nested/helper.rs",
+            "src/notes.md"
+        ]
+    );
+}
+
+#[test]
+fn test_globs_and_file_types() {
+    let (root, _ctx) = sandbox();
+    let filter = |glob: &str| Filter {
+        glob: Some(glob.to_string()),
+        ..Default::default()
+    };
+
+    let by_name = search::files(&root, &root, &filter("*.rs")).unwrap();
+    assert_eq!(by_name, vec!["src/main.rs", "src/nested/helper.rs"]);
+    let one_level = search::files(&root, &root, &filter("src/*.rs")).unwrap();
+    assert_eq!(one_level, vec!["src/main.rs"]);
+    let any_depth = search::files(&root, &root, &filter("src/**/*.rs")).unwrap();
+    assert_eq!(any_depth, vec!["src/main.rs", "src/nested/helper.rs"]);
+
+    let markdown = Filter {
+        file_type: Some("md".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        search::files(&root, &root, &markdown).unwrap(),
+        vec!["src/notes.md"]
+    );
+    let unknown = Filter {
+        file_type: Some("not-a-type".to_string()),
+        ..Default::default()
+    };
+    assert!(search::files(&root, &root, &unknown).is_err());
+}
+
+#[test]
+fn test_sort_by_modified_lists_newest_first() {
+    let (root, _ctx) = sandbox();
+    let old = SystemTime::now() - Duration::from_secs(3600);
+    for file in ["src/main.rs", "src/notes.md", ".gitignore"] {
+        fs::File::options()
+            .write(true)
+            .open(root.join(file))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+    }
+    let filter = Filter {
+        sort: SortBy::Modified,
+        ..Default::default()
+    };
+    let files = search::files(&root, &root, &filter).unwrap();
+    assert_eq!(files[0], "src/nested/helper.rs");
+}
+
+#[test]
+fn test_grep_context_and_limit() {
+    let (root, _ctx) = sandbox();
+    let pattern = Regex::new("helper").unwrap();
+
+    let found = search::grep(&root, &root, &pattern, 1, 100, &Filter::default()).unwrap();
+    assert_eq!(found.matches, 3);
+    assert_eq!(found.files, 3);
+    assert!(!found.truncated);
+    assert!(found
+        .lines
+        .contains(&"src/main.rs:2:    helper();".to_string()));
+    assert!(found
+        .lines
+        .contains(&"src/nested/helper.rs-2-// two".to_string()));
+    assert!(found
+        .lines
+        .contains(&"src/nested/helper.rs:3:pub fn helper() {}".to_string()));
+    assert!(found.lines.contains(&"--".to_string()));
+
+    let capped = search::grep(&root, &root, &pattern, 0, 2, &Filter::default()).unwrap();
+    assert_eq!(capped.matches, 2);
+    assert!(capped.truncated);
+}
+
+#[tokio::test]
+async fn test_grep_search_tool() {
+    let (_root, ctx) = sandbox();
+
+    let tool = Tool::from_call(
+        "GREP_SEARCH",
+        r#"{"pattern": "HELPER", "file_type": "rust"}"#,
+    )
+    .unwrap();
+    let result = tool.execute_with(&ctx).await.unwrap();
+    assert!(result.starts_with("2 matches in 2 files"), "{}", result);
+    assert!(!result.contains("target/"), "{}", result);
+    assert!(!result.contains("notes.md"), "{}", result);
+
+    let tool = Tool::from_call(
+        "GREP_SEARCH",
+        r#"{"pattern": "HELPER", "case_sensitive": true}"#,
+    )
+    .unwrap();
+    let result = tool.execute_with(&ctx).await.unwrap();
+    assert!(result.starts_with("No matches"), "{}", result);
+}
+
+#[tokio::test]
+async fn test_glob_search_tool_limits_results() {
+    let (_root, ctx) = sandbox();
+
+    let tool =
+        Tool::from_call("GLOB_SEARCH", r#"{"pattern": "**/*.rs", "max_results": 1}"#).unwrap();
+    let result = tool.execute_with(&ctx).await.unwrap();
+    assert!(result.contains("src/main.rs"), "{}", result);
+    assert!(!result.contains("helper.rs"), "{}", result);
+    assert!(result.contains("1 more files not shown"), "{}", result);
+}
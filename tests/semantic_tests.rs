@@ -0,0 +1,167 @@
+mod common;
+
+use common::TempRoot;
+use rust_tui_coder::agent::{Tool, ToolContext};
+use rust_tui_coder::semantic::{self, Corpus, Hit};
+use std::fs;
+
+const CONFIG: &str = r#"use std::fs;
+
+/// Settings read from config.toml.
+pub struct Settings {
+    pub name: String,
+}
+
+/// Reads and parses the TOML config file.
+pub fn load_settings(path: &str) -> Settings {
+    let text = fs::read_to_string(path).unwrap();
+    parse_toml(&text)
+}
+
+fn parse_toml(text: &str) -> Settings {
+    Settings { name: text.trim().to_string() }
+}
+"#;
+
+const UI: &str = r#"/// Draws the status bar.
+pub fn render_status(width: u16) -> String {
+    "-".repeat(width as usize)
+}
+
+pub fn render_messages(lines: &[String]) -> String {
+    lines.join("\n")
+}
+"#;
+
+fn sandbox() -> (TempRoot, ToolContext) {
+    let (root, ctx) = common::sandbox();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("target")).unwrap();
+    fs::write(root.join(".gitignore"), "target/\n").unwrap();
+    fs::write(root.join("src/config.rs"), CONFIG).unwrap();
+    fs::write(root.join("src/ui.rs"), UI).unwrap();
+    fs::write(
+        root.join("README.md"),
+        "# Demo\n\nA demo app.\n\n## Configuration\n\nPut your settings in config.toml.\n",
+    )
+    .unwrap();
+    fs::write(root.join("target/config.rs"), CONFIG).unwrap();
+    (root, ctx)
+}
+
+#[test]
+fn test_terms_are_split_and_stemmed() {
+    assert_eq!(
+        semantic::terms("where do we parse the ConfigFiles?"),
+        vec!["pars", "config", "fil"]
+    );
+    assert_eq!(
+        semantic::terms("parser parsing parse_toml"),
+        vec!["pars", "pars", "pars", "toml"]
+    );
+}
+
+#[test]
+fn test_files_are_chunked_by_definition() {
+    let (root, _ctx) = sandbox();
+    let mut corpus = Corpus::default();
+    let update = corpus.update(&root, &root).unwrap();
+    assert_eq!(update.added, 4);
+    assert!(!corpus.files.contains_key("target/config.rs"));
+
+    let chunks: Vec<(usize, usize, Option<&str>)> = corpus.files["src/config.rs"]
+        .chunks
+        .iter()
+        .map(|c| (c.start, c.end, c.title.as_deref()))
+        .collect();
+    assert_eq!(
+        chunks,
+        vec![
+            (1, 2, None),
+            (3, 7, Some("struct Settings")),
+            (8, 13, Some("function load_settings")),
+            (14, 16, Some("function parse_toml")),
+        ]
+    );
+    let headings: Vec<Option<&str>> = corpus.files["README.md"]
+        .chunks
+        .iter()
+        .map(|c| c.title.as_deref())
+        .collect();
+    assert_eq!(headings, vec![Some("Demo"), Some("Configuration")]);
+
+    let again = corpus.update(&root, &root).unwrap();
+    assert_eq!((again.added, again.changed, again.unchanged), (0, 0, 4));
+}
+
+#[test]
+fn test_questions_find_the_right_definition() {
+    let (root, _ctx) = sandbox();
+    let mut corpus = Corpus::default();
+    corpus.update(&root, &root).unwrap();
+
+    let hits = corpus.search(&root, &root, "where do we parse the config", 3);
+    assert_eq!(hits[0].path, "src/config.rs");
+    assert_eq!(hits[0].title.as_deref(), Some("function parse_toml"));
+    assert!(
+        hits[0].text.starts_with("fn parse_toml"),
+        "{}",
+        hits[0].text
+    );
+
+    let hits = corpus.search(&root, &root, "drawing the status bar", 1);
+    assert_eq!(hits[0].title.as_deref(), Some("function render_status"));
+
+    assert!(corpus
+        .search(&root, &root, "kubernetes deployment", 5)
+        .is_empty());
+}
+
+#[test]
+fn test_rerank_blends_embeddings_with_bm25() {
+    let hit = |path: &str, score: f64| Hit {
+        path: path.to_string(),
+        start: 1,
+        end: 1,
+        title: None,
+        score,
+        text: String::new(),
+    };
+    let mut hits = vec![hit("a.rs", 4.0), hit("b.rs", 3.0)];
+    let query = [1.0, 0.0];
+    semantic::rerank(&mut hits, &query, &[vec![0.0, 1.0], vec![1.0, 0.1]]);
+    assert_eq!(hits[0].path, "b.rs");
+    assert!(hits[0].score > hits[1].score);
+    assert!((semantic::cosine(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_semantic_search_tool_works_offline() {
+    let (root, ctx) = sandbox();
+
+    let tool = Tool::from_call(
+        "SEMANTIC_SEARCH",
+        r#"{"query": "load settings from the config file", "max_results": 2}"#,
+    )
+    .unwrap();
+    let result = tool.execute_with(&ctx).await.unwrap();
+    assert!(result.starts_with("Top 2 chunks"), "{}", result);
+    assert!(result.contains("(BM25)"), "{}", result);
+    assert!(
+        result.contains("src/config.rs:8-13 function load_settings"),
+        "{}",
+        result
+    );
+    assert!(result.contains("    9: pub fn load_settings"), "{}", result);
+    assert!(root.join(semantic::SEARCH_FILE).exists());
+
+    fs::write(root.join("src/ui.rs"), "pub fn load_theme_settings() {}\n").unwrap();
+    let tool = Tool::from_call(
+        "SEMANTIC_SEARCH",
+        r#"{"query": "theme settings", "path": "src"}"#,
+    )
+    .unwrap();
+    let result = tool.execute_with(&ctx).await.unwrap();
+    assert!(result.contains("src/ui.rs:1-1"), "{}", result);
+    assert!(!result.contains("README.md"), "{}", result);
+}
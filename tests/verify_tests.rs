@@ -0,0 +1,211 @@
+mod common;
+
+use common::TempRoot;
+use rust_tui_coder::agent::{Tool, ToolContext};
+use rust_tui_coder::verify::verify_written_files;
+use rust_tui_coder::workspace::Workspace;
+use std::fs;
+use std::process::Command;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+fn installed(program: &str) -> bool {
+    Command::new(program).arg("--version").output().is_ok()
+}
+
+#[tokio::test]
+async fn test_plain_files_are_not_checked() {
+    let dir = TempRoot::new();
+    let file = dir.join("notes.txt");
+    fs::write(&file, "hello").unwrap();
+
+    let notes = verify_written_files(
+        &[(&file, Some("hello"))],
+        &dir,
+        None,
+        &CancellationToken::new(),
+    )
+    .await;
+    assert!(notes.is_empty());
+}
+
+#[tokio::test]
+async fn test_read_back_mismatch_is_reported() {
+    let dir = TempRoot::new();
+    let file = dir.join("notes.txt");
+    fs::write(&file, "hello").unwrap();
+
+    let note = verify_written_files(
+        &[(&file, Some("hello!"))],
+        &dir,
+        None,
+        &CancellationToken::new(),
+    )
+    .await
+    .remove(0);
+    assert!(note.contains("Verification failed"));
+}
+
+#[tokio::test]
+async fn test_non_utf8_files_are_read_back() {
+    let dir = TempRoot::new();
+    let file = dir.join("image.bin");
+    fs::write(&file, [0xff, 0xfe, 0x00]).unwrap();
+
+    let notes = verify_written_files(&[(&file, None)], &dir, None, &CancellationToken::new()).await;
+    assert!(notes.is_empty());
+}
+
+#[tokio::test]
+async fn test_slow_checker_times_out() {
+    if !installed("cargo") {
+        return;
+    }
+    let dir = TempRoot::new();
+    fs::create_dir(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"slow\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+    let file = dir.join("src/lib.rs");
+    fs::write(&file, "pub fn f() {}\n").unwrap();
+
+    let timeout = Some(Duration::from_millis(1));
+    let note = verify_written_files(&[(&file, None)], &dir, timeout, &CancellationToken::new())
+        .await
+        .remove(0);
+    assert_eq!(note, "Verification (cargo check) timed out.");
+}
+
+#[tokio::test]
+async fn test_each_crate_is_checked_once() {
+    if !installed("cargo") {
+        return;
+    }
+    let dir = TempRoot::new();
+    fs::create_dir(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"twice\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+    let lib = dir.join("src/lib.rs");
+    let other = dir.join("src/other.rs");
+    fs::write(&lib, "mod other;\n").unwrap();
+    fs::write(&other, "pub fn f() {}\n").unwrap();
+
+    let timeout = Some(Duration::from_millis(1));
+    let notes = verify_written_files(
+        &[(&lib, None), (&other, None)],
+        &dir,
+        timeout,
+        &CancellationToken::new(),
+    )
+    .await;
+    assert_eq!(
+        notes,
+        vec!["Verification (cargo check) timed out.".to_string()]
+    );
+}
+
+#[tokio::test]
+async fn test_verification_errors_become_notes() {
+    let dir = TempRoot::new();
+    let missing = dir.join("gone.txt");
+
+    let notes =
+        verify_written_files(&[(&missing, None)], &dir, None, &CancellationToken::new()).await;
+    assert_eq!(notes.len(), 1);
+    assert!(notes[0].contains("could not run"), "{}", notes[0]);
+}
+
+#[tokio::test]
+async fn test_python_syntax_errors_are_reported() {
+    if !installed("python3") {
+        return;
+    }
+    let dir = TempRoot::new();
+    let good = dir.join("good.py");
+    let bad = dir.join("bad.py");
+    fs::write(&good, "print('ok')\n").unwrap();
+    fs::write(&bad, "def broken(:\n    pass\n").unwrap();
+    let cancel = CancellationToken::new();
+
+    let note = verify_written_files(&[(&good, None)], &dir, None, &cancel)
+        .await
+        .remove(0);
+    assert!(note.contains("OK"), "{}", note);
+    let note = verify_written_files(&[(&bad, None)], &dir, None, &cancel)
+        .await
+        .remove(0);
+    assert!(note.contains("FAILED"), "{}", note);
+    assert!(note.contains("SyntaxError"), "{}", note);
+}
+
+#[tokio::test]
+async fn test_javascript_syntax_errors_are_reported() {
+    if !installed("node") {
+        return;
+    }
+    let dir = TempRoot::new();
+    let bad = dir.join("bad.js");
+    fs::write(&bad, "function (\n").unwrap();
+
+    let note = verify_written_files(&[(&bad, None)], &dir, None, &CancellationToken::new())
+        .await
+        .remove(0);
+    assert!(note.contains("node --check"), "{}", note);
+    assert!(note.contains("FAILED"), "{}", note);
+}
+
+#[tokio::test]
+async fn test_rust_file_outside_a_crate_is_not_checked() {
+    let dir = TempRoot::new();
+    let file = dir.join("lonely.rs");
+    fs::write(&file, "fn main() {").unwrap();
+
+    let notes = verify_written_files(&[(&file, None)], &dir, None, &CancellationToken::new()).await;
+    assert!(notes.is_empty());
+}
+
+#[tokio::test]
+async fn test_write_file_reports_verification() {
+    if !installed("python3") {
+        return;
+    }
+    let dir = TempRoot::new();
+    let ctx = ToolContext {
+        workspace: Workspace::new(&dir, true).unwrap(),
+        post_write_verify: true,
+        ..Default::default()
+    };
+    let tool = Tool::WriteFile {
+        path: "script.py".to_string(),
+        content: "if True\n    pass\n".to_string(),
+    };
+
+    let result = tool.execute_with(&ctx).await.unwrap();
+    assert!(result.contains("written successfully"));
+    assert!(result.contains("Verification (python -m py_compile) FAILED"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_command_uses_configured_shell() {
+    if !installed("bash") {
+        return;
+    }
+    let ctx = ToolContext {
+        shell: "bash".to_string(),
+        ..Default::default()
+    };
+    let tool = Tool::RunCommand {
+        command: "echo \"shell:${BASH_VERSION:+bash}\"".to_string(),
+    };
+    assert!(tool
+        .execute_with(&ctx)
+        .await
+        .unwrap()
+        .contains("shell:bash"));
+}
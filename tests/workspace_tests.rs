@@ -0,0 +1,158 @@
+mod common;
+
+use common::TempRoot;
+use rust_tui_coder::agent::{Tool, ToolContext};
+use rust_tui_coder::workspace::Workspace;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// A fresh directory with a `project` workspace and an `outside` sibling.
+fn sandbox() -> (TempRoot, Workspace) {
+    let base = TempRoot::new();
+    fs::create_dir_all(base.join("project/src")).unwrap();
+    fs::create_dir_all(base.join("outside")).unwrap();
+    fs::write(base.join("outside/secret.txt"), "secret").unwrap();
+    let workspace = Workspace::new(&base.join("project"), true).unwrap();
+    (base, workspace)
+}
+
+fn is_denied(result: io::Result<PathBuf>) -> bool {
+    matches!(result, Err(e) if e.kind() == io::ErrorKind::PermissionDenied)
+}
+
+#[test]
+fn test_relative_paths_resolve_inside_root() {
+    let (_base, workspace) = sandbox();
+    let root = workspace.root().to_path_buf();
+
+    assert_eq!(
+        workspace.resolve("src/main.rs").unwrap(),
+        root.join("src/main.rs")
+    );
+    assert_eq!(
+        workspace.resolve("./src/../Cargo.toml").unwrap(),
+        root.join("Cargo.toml")
+    );
+    assert_eq!(
+        workspace.resolve("new/dir/file.txt").unwrap(),
+        root.join("new/dir/file.txt")
+    );
+    assert_eq!(workspace.resolve(".").unwrap(), root);
+}
+
+#[test]
+fn test_paths_outside_root_are_rejected() {
+    let (_base, workspace) = sandbox();
+    let inside = workspace.root().join("src/lib.rs");
+
+    assert!(is_denied(workspace.resolve("../outside/secret.txt")));
+    assert!(is_denied(workspace.resolve("src/../../outside")));
+    assert!(is_denied(workspace.resolve("/etc/passwd")));
+    assert!(workspace.resolve(inside.to_str().unwrap()).is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_escapes_are_rejected() {
+    let (base, workspace) = sandbox();
+    let root = workspace.root().to_path_buf();
+    std::os::unix::fs::symlink(base.join("outside"), root.join("escape")).unwrap();
+    std::os::unix::fs::symlink(base.join("outside/new.txt"), root.join("dangling")).unwrap();
+    std::os::unix::fs::symlink(root.join("src"), root.join("source")).unwrap();
+
+    assert!(is_denied(workspace.resolve("escape/secret.txt")));
+    assert!(is_denied(workspace.resolve("escape")));
+    assert!(is_denied(workspace.resolve("dangling")));
+    // Links that stay inside are fine, and resolve to the link itself
+    assert_eq!(workspace.resolve("source").unwrap(), root.join("source"));
+}
+
+#[test]
+fn test_unsafe_workspace_allows_outside_paths() {
+    let (_base, workspace) = sandbox();
+    let workspace = Workspace::new(workspace.root(), false).unwrap();
+    assert!(workspace.resolve("../outside/secret.txt").is_ok());
+}
+
+#[test]
+fn test_missing_root_is_an_error() {
+    assert!(Workspace::new(std::path::Path::new("tmp_rovodev_no_such_root"), true).is_err());
+}
+
+#[tokio::test]
+async fn test_file_tools_work_in_root_and_reject_escapes() {
+    let (base, workspace) = sandbox();
+    let ctx = ToolContext {
+        workspace: workspace.clone(),
+        ..Default::default()
+    };
+
+    let write = Tool::WriteFile {
+        path: "src/hello.txt".to_string(),
+        content: "hi".to_string(),
+    };
+    write.execute_with(&ctx).await.unwrap();
+    assert_eq!(
+        fs::read_to_string(workspace.root().join("src/hello.txt")).unwrap(),
+        "hi"
+    );
+
+    let read = Tool::ReadFile {
+        path: "../outside/secret.txt".to_string(),
+        offset: None,
+        limit: None,
+    };
+    let error = read.execute_with(&ctx).await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    assert!(error.to_string().contains("outside the workspace"));
+
+    let delete = Tool::DeleteFile {
+        path: base.join("outside").to_string_lossy().to_string(),
+    };
+    assert!(delete.execute_with(&ctx).await.is_err());
+    assert!(base.join("outside/secret.txt").exists());
+
+    let delete_root = Tool::DeleteFile {
+        path: ".".to_string(),
+    };
+    assert!(delete_root.execute_with(&ctx).await.is_err());
+    assert!(workspace.root().exists());
+
+    let rename = Tool::RenameFile {
+        old_name: "src/hello.txt".to_string(),
+        new_name: "../outside/hello.txt".to_string(),
+    };
+    assert!(rename.execute_with(&ctx).await.is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_command_starts_in_root() {
+    let (_base, workspace) = sandbox();
+    let ctx = ToolContext {
+        workspace: workspace.clone(),
+        ..Default::default()
+    };
+    let tool = Tool::RunCommand {
+        command: "pwd -P".to_string(),
+    };
+    let output = tool.execute_with(&ctx).await.unwrap();
+    assert!(output.contains(workspace.root().to_str().unwrap()));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_execute_code_starts_in_root() {
+    let (_base, workspace) = sandbox();
+    let ctx = ToolContext {
+        workspace: workspace.clone(),
+        ..Default::default()
+    };
+    let tool = Tool::ExecuteCode {
+        language: "bash".to_string(),
+        code: "pwd -P".to_string(),
+    };
+    let output = tool.execute_with(&ctx).await.unwrap();
+    assert!(output.contains(workspace.root().to_str().unwrap()));
+}